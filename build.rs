@@ -16,7 +16,7 @@ fn main() -> std::io::Result<()> {
             "rustls"
         };
 
-        println!("cargo:warning=Multiple tls backends are activated (through the '*-tls' features). Consider to activate only one as it is not possible to change the backend during runtime. The active backend for this build will be '{}'.", active_tls_backend)
+        println!("cargo:warning=Multiple tls backends are activated (through the '*-tls' features). Pick between them at runtime with '--tls-backend <rustls|native|openssl>'; without it, '{}' is used by default.", active_tls_backend)
     }
 
     if cfg!(feature = "openssl") {
@@ -121,6 +121,7 @@ fn generate_manpages(out_dir: PathBuf) -> std::io::Result<()> {
 
     generate_command_manpage(crunchy_cli_core::Cli::command(), &out_dir, "")?;
     generate_command_manpage(crunchy_cli_core::Archive::command(), &out_dir, "archive")?;
+    generate_command_manpage(crunchy_cli_core::Browse::command(), &out_dir, "browse")?;
     generate_command_manpage(crunchy_cli_core::Download::command(), &out_dir, "download")?;
     generate_command_manpage(crunchy_cli_core::Login::command(), &out_dir, "login")?;
     generate_command_manpage(crunchy_cli_core::Search::command(), &out_dir, "search")?;
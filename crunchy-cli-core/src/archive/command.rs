@@ -1,42 +1,79 @@
 use crate::archive::filter::ArchiveFilter;
+use crate::utils::accelerate::AccelerateFactors;
 use crate::utils::context::Context;
 use crate::utils::download::{
-    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, MergeBehavior,
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, Downloader, MergeBehavior,
+    SubtitleKind, SubtitleSyncMode,
 };
-use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::ffmpeg::{resolve_ffmpeg, FFmpegAudioChannel, FFmpegPreset};
 use crate::utils::filter::Filter;
-use crate::utils::format::{Format, SingleFormat};
-use crate::utils::locale::{all_locale_in_locales, resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
+use crate::utils::format::{group_formats_by_season, Format, PrintFormatsOutput, SingleFormat};
+use crate::utils::interactive_select::VersionSelector;
+use crate::utils::locale::{
+    all_locale_in_locales, locale_position, resolve_locales, LanguageTagging,
+};
+use crate::utils::log::progress_unless;
+use crate::utils::os::{
+    ffmpeg_command, ffprobe_command, free_file, has_ffmpeg, is_special_file, set_ffmpeg_binary,
+    tempfile, AtomicOutput,
+};
+use crate::utils::package::{package_season, Compression};
 use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::subtitle_export::{SubtitleFormat, SubtitleOutput, SubtitleStyleOverrides};
+use crate::utils::video::{
+    format_resolution_preferences, stream_data_from_stream, ResolutionPreference,
+    ResolutionStrategy, StreamProtocol,
+};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
-use chrono::Duration;
-use crunchyroll_rs::media::{Resolution, Subtitle};
+use chrono::{Duration, TimeDelta};
+use crunchyroll_rs::media::{StreamData, Subtitle};
 use crunchyroll_rs::Locale;
 use log::{debug, warn};
-use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::fs;
 use std::iter::zip;
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::Stdio;
+
+/// Output containers the muxing pipeline can produce, in addition to the default '.mkv'. '.mp4'
+/// and '.webm' can't carry font/info-json attachments, and '.webm' only supports a single audio
+/// and subtitle track, so `pre_check` downgrades/validates around those gaps.
+const SUPPORTED_CONTAINERS: [&str; 3] = ["mkv", "mp4", "webm"];
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Archive a video")]
 #[command(arg_required_else_help(true))]
 pub struct Archive {
-    #[arg(help = format!("Audio languages. Can be used multiple times. \
+    #[arg(help = format!("Audio languages. Can be used multiple times, or set to 'original' to always include the title's original-language audio. \
     Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
     #[arg(long_help = format!("Audio languages. Can be used multiple times. \
+    Set to 'original' to always include whichever audio the stream itself marks as the original language, which varies per title (e.g. Japanese for most anime, but Korean or Chinese for others), instead of having to know and pass the right locale for every series. \
     Available languages are:\n  {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| format!("{:<6} → {}", l.to_string(), l.to_human_readable())).collect::<Vec<String>>().join("\n  ")))]
-    #[arg(short, long, default_values_t = vec![Locale::ja_JP, crate::utils::locale::system_locale()])]
+    #[arg(short, long, default_values_t = vec![Locale::Custom("original".to_string()), crate::utils::locale::system_locale()])]
     pub(crate) audio: Vec<Locale>,
     #[arg(skip)]
     output_audio_locales: Vec<String>,
+    #[arg(
+        help = "Which re-release to keep when a season has more than one (e.g. uncut vs. broadcast). Accepts a 1-based position (as shown by the interactive prompt) or a keyword matched against each release's title"
+    )]
+    #[arg(long_help = "Crunchyroll sometimes lists the same season more than once for alternate cuts (e.g. an uncut release alongside the broadcast version), which by default are disambiguated by '--audio' alone. \
+    Pass a 1-based position (the order the interactive duplicate-season prompt would show them in) or a keyword (matched case-insensitively as a substring of each release's title, e.g. 'uncut') to prefer a specific one instead. \
+    Has no effect if none of the duplicates match; '--audio' is used as the fallback")]
+    #[arg(long, value_parser = VersionSelector::parse)]
+    pub(crate) version: Option<VersionSelector>,
+    #[arg(
+        help = "Acoustically verify duplicated seasons before auto-resolving them, instead of trusting metadata alone"
+    )]
+    #[arg(long_help = "Before auto-resolving duplicated seasons (see '--version'), downloads a short audio sample of every candidate release and compares it against the one '--audio'/'--version' would otherwise pick via the same fingerprinting '--merge sync' uses. \
+    A candidate whose audio doesn't match closely enough is kept alongside the pick instead of being dropped, since it's likely a distinct release (recap edition, re-dub, regional re-cut) that just happens to share a season number. \
+    Costs one extra audio sample download per duplicate; has no effect if a series has no duplicated seasons")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_duplicates: bool,
     #[arg(help = format!("Subtitle languages. Can be used multiple times. \
     Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
     #[arg(long_help = format!("Subtitle languages. Can be used multiple times. \
@@ -65,7 +102,10 @@ pub struct Archive {
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n  \
+    Since the path is written as-is, subdirectories can be used to build a library layout, e.g. \
+    '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mkv' \
+    for a layout Kodi/Jellyfin/Plex can scan directly (use together with '--nfo')")]
     #[arg(short, long, default_value = "{title}.mkv")]
     pub(crate) output: String,
     #[arg(help = "Name of the output file if the episode is a special")]
@@ -79,15 +119,74 @@ pub struct Archive {
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
 
+    #[arg(help = "Order episodes within a season by air date instead of episode number")]
+    #[arg(long_help = "Order episodes within a season by their air date instead of their episode \
+    number. Episodes sharing an air date (down to the month) or an episode number fall back to the \
+    other key, so entries are never left in an arbitrary order")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) sort_by_air_date: bool,
+
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    Multiple fallbacks can be chained with a comma, tried in order until one resolves, e.g. 'best<=720p,480p,worst'. \
+    'best<=H'/'best>=H' pick the highest/lowest-bandwidth variant at most/at least 'H' pixels tall")]
     #[arg(short, long, default_value = "best")]
-    #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
-    pub(crate) resolution: Resolution,
+    #[arg(value_parser = crate::utils::clap::clap_parse_resolution_preferences)]
+    pub(crate) resolution: Vec<ResolutionPreference>,
+
+    #[arg(
+        help = "How to pick a variant when '--resolution' isn't 'best'/'worst' and no variant matches its height exactly. Valid values are 'exact', 'nearest', 'max-bitrate:<bps>' and 'budget:<bytes>:<seconds>'"
+    )]
+    #[arg(long_help = "How to pick a variant when '--resolution' isn't 'best'/'worst' and no variant matches its height exactly. \
+    'exact' only accepts an exact height match and fails with an error otherwise (the default, and the only behavior before this flag existed). \
+    'nearest' picks the variant whose height is closest to the requested one. \
+    'max-bitrate:<bps>' picks the highest-bandwidth variant under the given bits/second ceiling, falling back to the lowest-bandwidth variant if none qualify. \
+    'budget:<bytes>:<seconds>' is the same as 'max-bitrate', but derives the ceiling from a total byte budget spread evenly over a duration, e.g. to fit an episode within a storage quota")]
+    #[arg(long, default_value = "exact")]
+    #[arg(value_parser = ResolutionStrategy::parse)]
+    pub(crate) resolution_strategy: ResolutionStrategy,
+
+    #[arg(help = "How long (in seconds) a series' seasons / a season's episodes are cached on disk before being re-fetched")]
+    #[arg(long, default_value_t = 3600)]
+    pub(crate) cache_ttl: u64,
+
+    #[arg(help = "Disable the on-disk season/episode cache entirely")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_cache: bool,
+
+    #[arg(help = "Ignore cached season/episode lists and re-fetch them, refreshing the cache")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) refresh_cache: bool,
+
+    #[arg(help = "Resolve series/season/episode metadata exclusively from the on-disk cache, without hitting the network")]
+    #[arg(long_help = "Resolve every series/season/episode lookup exclusively from the on-disk cache populated by previous runs, \
+    instead of calling the Crunchyroll API. Fails cleanly with an error as soon as something isn't cached, rather than silently \
+    going online. Can't be combined with '--no-cache', and ignores '--refresh-cache' and any cache entry's TTL, since there's nowhere else to get the data \
+    from while offline. Useful to resume filtering after a partial failure without hammering the API again for everything \
+    that was already resolved")]
+    #[arg(long, default_value_t = false, conflicts_with = "no_cache")]
+    pub(crate) offline: bool,
+
+    #[arg(help = "Write a structured report of missing audio/subtitles, skipped premium episodes and other filter findings to this path")]
+    #[arg(long_help = "Write a structured report of everything the filter stage noticed along the way (missing audio/subtitle tracks, \
+    premium-only episodes skipped, failed relative-episode-number lookups, duplicated seasons) as a JSON/YAML document at this path, \
+    keyed by the series/season/episode it was raised against. \
+    The format is picked from the path's extension ('.yml'/'.yaml' select YAML, anything else JSON). \
+    Lets scripts diff what was actually archived against what was requested without scraping stderr. \
+    If the path already exists, its entries are merged with the ones from this run instead of being overwritten, \
+    so archiving multiple urls in one command still ends up with a single report")]
+    #[arg(long)]
+    pub(crate) report: Option<PathBuf>,
+
+    #[arg(help = "Adaptive streaming protocol to request stream data through. Valid values are 'hls' and 'dash'")]
+    #[arg(long_help = "Adaptive streaming protocol to request stream data through. Valid values are 'hls' (the default) and 'dash'. \
+    'dash' is not supported yet by the crunchyroll-rs version this is built against and currently always errors out; the flag exists so switching over later doesn't need another CLI change")]
+    #[arg(long, default_value_t = StreamProtocol::Hls, value_parser = StreamProtocol::parse)]
+    pub(crate) stream_protocol: StreamProtocol,
 
     #[arg(
         help = "Sets the behavior of the stream merging. Valid behaviors are 'auto', 'sync', 'audio' and 'video'"
@@ -115,14 +214,33 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = 4)]
     pub(crate) merge_sync_precision: u32,
+    #[arg(
+        help = "If the merge behavior is 'sync', reject a format's offset as unreliable if it varies by more than the specified milliseconds across the determination runs instead of using it"
+    )]
+    #[arg(long, default_value_t = 250)]
+    pub(crate) merge_sync_max_offset_variance: u32,
+
+    #[arg(help = "Start the output at a specific position instead of the beginning")]
+    #[arg(long_help = "Start the output at a specific position instead of the beginning, given as 'HH:MM:SS(.ms)' or a plain number of seconds. \
+    Useful to extract a preview, a single scene or a fixed-length sample without downloading and keeping the full episode. \
+    If '--merge' is set to 'sync', the start position is applied after the per-track sync offset is computed so every audio language stays aligned inside the clipped window")]
+    #[arg(long)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_time_delta)]
+    pub(crate) start: Option<Duration>,
+    #[arg(help = "Limit the output to a fixed duration, given as 'HH:MM:SS(.ms)' or a plain number of seconds")]
+    #[arg(long_help = "Limit the output to a fixed duration, given as 'HH:MM:SS(.ms)' or a plain number of seconds, starting at '--start' (or the beginning if '--start' is not set). \
+    Chapters written via '--include-chapters' are rebased to the clipped window's zero point and dropped if they fall fully outside of it")]
+    #[arg(long)]
+    #[arg(value_parser = crate::utils::clap::clap_parse_time_delta)]
+    pub(crate) duration: Option<Duration>,
 
     #[arg(
         help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
-        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard)"
+        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard), 'bcp47' (like 'ietf' but region/script qualified, e.g. 'pt-BR' instead of 'pt')"
     )]
     #[arg(
         long_help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
-        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard; you might run in issues as there are multiple locales which resolve to the same IETF language code, e.g. 'es-LA' and 'es-ES' are both resolving to 'es')"
+        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard; you might run in issues as there are multiple locales which resolve to the same IETF language code, e.g. 'es-LA' and 'es-ES' are both resolving to 'es'), 'bcp47' (like 'ietf' but every locale gets its own region/script qualified tag instead of collapsing to the same bare subtag, e.g. 'pt-PT' and 'pt-BR' instead of both becoming 'pt')"
     )]
     #[arg(long)]
     #[arg(value_parser = LanguageTagging::parse)]
@@ -132,6 +250,11 @@ pub struct Archive {
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
     #[arg(long_help = format!("Presets for converting the video to a specific coding format. \
     If you need more specific ffmpeg customizations you can pass ffmpeg output arguments instead of a preset as value. \
+    Instead of a fixed quality level you can append `-crfN`/`-qN` (e.g. `h265-crf23`) to use an exact crf/`-q:v`/`-qp` value, \
+    or `-vmafN` (e.g. `h264-vmaf95`) to target a VMAF score; \
+    the actual crf is probed per episode, which requires an ffmpeg build with the `libvmaf` filter and makes the episode take noticeably longer to process. \
+    You can also append `-aac`, `-opus` or `-flac` (e.g. `h264-opus`) to re-encode audio instead of copying it; `flac` requires an `.mkv`/`.mov`/`.mp4` output file. \
+    Append `-pix<fmt>` (e.g. `h264-pixyuv420p10le`) to force an output pixel format, or `-scale<W>x<H>` (e.g. `h264-scale1280x720`) to resize the video; either is independent of the audio/video codec so they combine with any of the above. \
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
     #[arg(long)]
     #[arg(value_parser = FFmpegPreset::parse)]
@@ -146,6 +269,23 @@ pub struct Archive {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Extract or downmix a single audio channel instead of keeping the full track. Valid values are 'fl', 'fr', 'fc', 'lfe', 'sl', 'sr' and 'mono'"
+    )]
+    #[arg(long_help = "Extract a single channel of a multi-channel audio track, or downmix it to mono, via an ffmpeg `pan` filter. \
+    Valid values are 'fl' (front left), 'fr' (front right), 'fc' (front center), 'lfe', 'sl' (side left), 'sr' (side right) and 'mono' (downmix all channels). \
+    Since this requires an audio filter rather than a stream copy, the audio codec is switched to `aac` automatically unless `--ffmpeg-preset` already requests a re-encoding codec")]
+    #[arg(long)]
+    #[arg(value_parser = FFmpegAudioChannel::parse)]
+    pub(crate) audio_channel: Option<FFmpegAudioChannel>,
+    #[arg(help = "Use a specific ffmpeg executable instead of the one on `PATH`")]
+    #[arg(long)]
+    pub(crate) ffmpeg_path: Option<PathBuf>,
+    #[arg(help = "Download a static ffmpeg build if none is found on `PATH`")]
+    #[arg(long_help = "Download a static ffmpeg build for the host platform and cache it in the \
+    config directory if no usable ffmpeg is found on `PATH`. Has no effect if `--ffmpeg-path` is set")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) download_ffmpeg: bool,
 
     #[arg(
         help = "Set which subtitle language should be set as default / auto shown when starting a video"
@@ -170,6 +310,117 @@ pub struct Archive {
     #[arg(help = "Omit closed caption subtitles in the downloaded file")]
     #[arg(long, default_value_t = false)]
     pub(crate) no_closed_caption: bool,
+    #[arg(
+        help = "Mark the closed caption/SDH subtitle as default instead of the regular subtitle of the same language, if both exist"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) prefer_sdh: bool,
+
+    #[arg(
+        help = "Whether subtitles are embedded in the output, written as sidecar files next to it, or both. Valid values are 'embed', 'external' and 'both'"
+    )]
+    #[arg(long_help = "Whether subtitles are embedded in the output, written as sidecar files \
+    next to it (named after it plus the subtitle's language tag), or both. Valid values are \
+    'embed' (the previous, still default, behavior), 'external' and 'both'")]
+    #[arg(long, default_value_t = SubtitleOutput::Embed, value_parser = SubtitleOutput::parse)]
+    pub(crate) subtitle_output: SubtitleOutput,
+    #[arg(
+        help = "Format the sidecar files written via `--subtitle-output external`/`both` are converted to. Valid formats are 'ass' (no conversion), 'srt', 'vtt' and 'scc'"
+    )]
+    #[arg(long_help = "Format the sidecar files written via `--subtitle-output external`/`both` are converted to. Valid formats are 'ass' (written out as downloaded, no conversion), \
+    'srt' (styling/positioning is dropped, overlapping events are merged since SRT can't represent either), 'vtt' (same as 'srt' but keeps basic positioning cues) and 'scc' (Scenarist SCC, CEA-608 pop-on captions for TV/set-top box/editing tools that only read line-21 captions)")]
+    #[arg(long, default_value_t = SubtitleFormat::Srt, value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+    #[arg(
+        help = "Charset the sidecar subtitle files written via `--subtitle-output external`/`both` are encoded as"
+    )]
+    #[arg(long, default_value = "utf-8")]
+    pub(crate) subtitle_charset: String,
+    #[arg(help = "Override the font used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_font: Option<String>,
+    #[arg(help = "Override the font size used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_font_size: Option<u32>,
+    #[arg(help = "Override the outline width used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_outline: Option<f32>,
+    #[arg(help = "Override the shadow width used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_shadow: Option<f32>,
+    #[arg(help = "Override the vertical margin used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_margin_v: Option<u32>,
+    #[arg(
+        help = "Re-align downloaded subtitles to the matching audio track's voice activity instead of trusting their own timestamps. Valid modes are 'global' and 'split'"
+    )]
+    #[arg(long_help = "Re-align downloaded subtitles to the matching audio track's voice activity instead of trusting their own (sometimes region-mismatched/drifted) timestamps. \
+    'global' finds a single best offset for the whole episode; 'split' additionally lets different parts of the episode (e.g. around an ad break) pick up their own offset where that recovers enough extra alignment to be worth it")]
+    #[arg(long, value_parser = SubtitleSyncMode::parse)]
+    pub(crate) subtitle_sync: Option<SubtitleSyncMode>,
+    #[arg(
+        help = "Re-encode the video with the given ffmpeg video encoder instead of remuxing the downloaded stream as-is"
+    )]
+    #[arg(long_help = "Re-encode the video with the given ffmpeg video encoder (e.g. 'libx264', 'libx265') instead of remuxing the downloaded stream as-is. \
+    The episode is first split into scene-aligned chunks, which are encoded concurrently (bounded by `--threads`) and losslessly concatenated back together afterwards")]
+    #[arg(long)]
+    pub(crate) encode: Option<String>,
+    #[arg(
+        help = "Force this color transfer characteristic (e.g. 'smpte2084', 'arib-std-b67') onto the muxed video instead of what the source declares"
+    )]
+    #[arg(long_help = "Force this color transfer characteristic (e.g. 'smpte2084' for PQ/HDR10, 'arib-std-b67' for HLG) onto the muxed video instead of what the source declares. \
+    Useful when a source's own tag is missing or wrong and playback falls back to SDR-looking output despite HDR content")]
+    #[arg(long)]
+    pub(crate) force_color_transfer: Option<String>,
+    #[arg(
+        help = "Produce a fragmented/streamable mp4 (fMP4, CMAF-style) with fragments this many seconds long, instead of a flat faststart file. Only applies to mp4/mov output"
+    )]
+    #[arg(long_help = "Produce a fragmented/streamable mp4 (fMP4, CMAF-style) with fragments this many seconds long, instead of a flat faststart file. \
+    The result is playable/seekable before it has fully downloaded and needs no separate faststart pass. Only applies to mp4/mov output")]
+    #[arg(long)]
+    pub(crate) fragment_duration: Option<f64>,
+    #[arg(
+        help = "Override the muxed video track's timescale (samples/second timestamps are expressed in). Only applies to mp4/mov output"
+    )]
+    #[arg(long_help = "Override the muxed video track's timescale (samples/second timestamps are expressed in) instead of ffmpeg's framerate-derived default. \
+    Useful to keep a fragmented/CMAF output's video and audio durations exact instead of one rounding against the other. Only applies to mp4/mov output")]
+    #[arg(long)]
+    pub(crate) video_track_timescale: Option<u32>,
+    #[arg(
+        help = "Don't move 'moov' before 'mdat' (faststart) in progressive mp4/mov/m4a output, for a faster non-rewritten write"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_faststart: bool,
+    #[arg(
+        help = "Write a self-hosting-friendly single-rendition HLS VOD (playlists + segment files) into the output path as a directory, instead of muxing into one file"
+    )]
+    #[arg(long_help = "Write a self-hosting-friendly single-rendition HLS VOD (playlists + segment files) into the output path as a directory, instead of muxing into one file. \
+    Only the first video format and its first audio track are included; other audio/subtitle tracks and additional formats are not emitted as extra renditions")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) hls: bool,
+
+    #[arg(
+        help = "Speed the recap skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long_help = "Speed the recap skip-event range up by this factor (e.g. 4 plays it at 4x) instead of only marking it with a chapter. \
+    Requires re-encoding the video and currently only supports a single muxed audio track and no embedded soft subtitles")]
+    #[arg(long)]
+    pub(crate) accelerate_recap: Option<f64>,
+    #[arg(
+        help = "Speed the intro skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_intro: Option<f64>,
+    #[arg(
+        help = "Speed the credits skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_credits: Option<f64>,
+    #[arg(
+        help = "Speed the preview skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_preview: Option<f64>,
 
     #[arg(help = "Skip files which are already existing by their name")]
     #[arg(long, default_value_t = false)]
@@ -184,18 +435,99 @@ pub struct Archive {
     #[arg(long, default_values_t = SkipExistingMethod::default())]
     #[arg(value_parser = SkipExistingMethod::parse)]
     pub(crate) skip_existing_method: Vec<SkipExistingMethod>,
+    #[arg(
+        help = "If the output file already exists, only download and mux in the audio/subtitle locales it's missing instead of skipping or re-downloading it entirely"
+    )]
+    #[arg(long_help = "If the output file already exists, probe it and diff its audio/subtitle locales against the ones requested with `--audio`/`--subtitle`, \
+    then download and remux in only the locales it's missing (stream-copied, not re-encoded) instead of skipping the file or re-downloading everything. \
+    Only supported for a '.mkv' output with `--merge` set to 'audio', 'auto' or 'sync', since those produce a single file all requested tracks are merged into")]
+    #[arg(long, default_value_t = false, conflicts_with = "skip_existing")]
+    pub(crate) update: bool,
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
 
+    #[arg(help = "Write a Kodi/Jellyfin/Plex compatible '.nfo' metadata sidecar next to each archived file")]
+    #[arg(long_help = "Write a Kodi/Jellyfin/Plex compatible '.nfo' metadata sidecar next to each archived file. \
+    Combine this with an '--output' template like '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mkv' \
+    to get a library layout which media servers can scan without further configuration")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) nfo: bool,
+
+    #[arg(help = "Bundle each season's archived episodes into a single '.zip' or '.tar.gz' once it finishes downloading")]
+    #[arg(long_help = "Bundle each season's archived episodes into a single archive once every episode in that season has finished downloading, instead of leaving them as loose files. \
+    Valid formats are 'zip' and 'gzip' (written as a '.tar.gz'). The archive is named after the series and season and placed next to the episode files, which are removed once packaged")]
+    #[arg(long, value_parser = Compression::parse)]
+    pub(crate) compress: Option<Compression>,
+
+    #[arg(help = "Embed episode metadata (title, series, episode number, release date, synopsis) as Matroska tags")]
+    #[arg(long_help = "Embed episode metadata (title, series, episode number, release date, synopsis) as global and per-track Matroska tags. \
+    This makes the file self-describing to media servers like Jellyfin/Plex without a separate '--nfo' sidecar. Enabled by default, use '--no-metadata' to disable it")]
+    #[arg(long, default_value_t = true)]
+    pub(crate) metadata: bool,
+    #[arg(help = "Disable '--metadata'")]
+    #[arg(long, default_value_t = false, conflicts_with = "metadata")]
+    pub(crate) no_metadata: bool,
+    #[arg(help = "Attach the full episode metadata as a JSON file inside the archived file")]
+    #[arg(long_help = "Attach the full episode metadata (series/season/episode ids and titles/numbers, \
+    audio/subtitle locales, resolution, fps, release date, synopsis) as a JSON file attachment inside \
+    the '.mkv', mirroring how other downloaders attach an info-json alongside their output. Combine \
+    with '--include-chapters' for intro/credits chapter marks, covering both halves of archived \
+    provenance data")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_info_json: bool,
+
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
     pub(crate) yes: bool,
 
+    #[arg(help = "Print series/season/episode metadata as JSON instead of archiving")]
+    #[arg(long_help = "Print series/season/episode metadata as JSON to stdout instead of archiving anything. \
+    Useful to script episode selection externally: the url(s) are resolved as usual and every matching season and episode is printed, \
+    including duplicated-season information which is otherwise only shown via the interactive prompt, together with the available audio/subtitle locales and resolutions. \
+    Implies '--yes' and suppresses all progress output")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) dump_json: bool,
+
+    #[arg(help = "Print the resolved season/episode format tree as JSON/YAML instead of archiving")]
+    #[arg(long_help = "Resolve every matching episode's stream (audio tracks, subtitle tracks, resolution, fps) as it would for a real archive run, \
+    then print the whole season/episode tree to stdout instead of archiving anything. \
+    Unlike '--dump-json', which runs before any stream is resolved and can only list available resolutions, \
+    this shows exactly what would be muxed: the audio/subtitle locale tuples, resolution, fps and all id/number fields actually selected for each episode. \
+    Implies '--yes' and suppresses all progress output")]
+    #[arg(long, value_parser = PrintFormatsOutput::parse, conflicts_with = "dump_json")]
+    pub(crate) print_formats: Option<PrintFormatsOutput>,
+
     #[arg(help = "The number of threads used to download")]
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
+    #[arg(help = "How often to retry a segment before giving up on the download")]
+    #[arg(long_help = "How often to retry fetching a segment before giving up on the download. \
+    Each retry waits longer than the last (exponential backoff), and a segment whose request fails with a 4xx status is never retried since that indicates a permanently bad url rather than a transient failure")]
+    #[arg(long, default_value_t = 5)]
+    pub(crate) retries: usize,
+
+    #[arg(help = "Directory to cache in-progress downloads in, so an interrupted run can resume")]
+    #[arg(long_help = "Directory each episode's already-downloaded segments are cached in while downloading. \
+    If an episode's run gets interrupted, rerunning the same command only fetches what's still missing instead of starting over. \
+    Defaults to a hidden directory next to the episode's output file; pass this to move it somewhere else, e.g. off a network-mounted output volume")]
+    #[arg(long)]
+    pub(crate) work_dir: Option<PathBuf>,
+
+    #[arg(help = "Keep an episode's work directory after it was successfully archived")]
+    #[arg(long_help = "Normally an episode's work directory (see '--work-dir') is deleted once its mkv was generated successfully. \
+    Pass this to keep it around regardless, e.g. to inspect the raw downloaded segments")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) keep_work_dir: bool,
+
+    #[arg(help = "Verify the muxed output with ffprobe after archiving")]
+    #[arg(long_help = "After ffmpeg exits successfully, run ffprobe against the muxed output and confirm it actually has the expected \
+    number of video/audio/subtitle streams and a duration close to what was downloaded. \
+    If it doesn't, the partial file is deleted and the archive fails instead of leaving a corrupt file behind")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_integrity: bool,
+
     #[arg(help = "Crunchyroll series url(s)")]
     #[arg(required = true)]
     pub(crate) urls: Vec<String>,
@@ -203,27 +535,73 @@ pub struct Archive {
 
 impl Execute for Archive {
     fn pre_check(&mut self) -> Result<()> {
-        if !has_ffmpeg() {
-            bail!("FFmpeg is needed to run this command")
+        if self.dump_json || self.print_formats.is_some() {
+            self.yes = true;
+            // no muxing or file writing happens in this mode, so the output/ffmpeg checks below
+            // don't apply
+            return Ok(());
+        }
+
+        if self.ffmpeg_path.is_none() && !self.download_ffmpeg && !has_ffmpeg() {
+            bail!("FFmpeg is needed to run this command. Install it and make it available on `PATH`, pass its location via `--ffmpeg-path`, or use `--download-ffmpeg` to fetch a static build automatically")
         } else if PathBuf::from(&self.output)
             .extension()
-            .unwrap_or_default()
-            .to_string_lossy()
-            != "mkv"
+            .is_some_and(|ext| !SUPPORTED_CONTAINERS.contains(&ext.to_string_lossy().as_ref()))
             && !is_special_file(&self.output)
             && self.output != "-"
         {
-            bail!("File extension is not '.mkv'. Currently only matroska / '.mkv' files are supported")
+            bail!(
+                "File extension is not one of '.{}'",
+                SUPPORTED_CONTAINERS.join("', '.")
+            )
         } else if let Some(special_output) = &self.output_specials {
             if PathBuf::from(special_output)
                 .extension()
-                .unwrap_or_default()
-                .to_string_lossy()
-                != "mkv"
+                .is_some_and(|ext| !SUPPORTED_CONTAINERS.contains(&ext.to_string_lossy().as_ref()))
                 && !is_special_file(special_output)
                 && special_output != "-"
             {
-                bail!("File extension for special episodes is not '.mkv'. Currently only matroska / '.mkv' files are supported")
+                bail!(
+                    "File extension for special episodes is not one of '.{}'",
+                    SUPPORTED_CONTAINERS.join("', '.")
+                )
+            }
+        }
+
+        let container = PathBuf::from(&self.output)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if container != "mkv" {
+            if self.include_fonts {
+                warn!(
+                    "'--include-fonts' is ignored. The '.{}' container can't hold font attachments",
+                    container
+                );
+                self.include_fonts = false;
+            }
+            if self.embed_info_json {
+                warn!(
+                    "'--embed-info-json' is ignored. The '.{}' container can't hold attachments",
+                    container
+                );
+                self.embed_info_json = false;
+            }
+        }
+
+        if container == "webm" && self.include_chapters {
+            warn!(
+                "'--include-chapters' is ignored. The '.webm' container doesn't support chapters"
+            );
+            self.include_chapters = false;
+        }
+
+        if let Some(preset) = &self.ffmpeg_preset {
+            if let Some(ext) = PathBuf::from(&self.output).extension() {
+                preset
+                    .validate_audio_codec_container(&ext.to_string_lossy())
+                    .map_err(|e| anyhow::anyhow!(e))?;
             }
         }
 
@@ -234,6 +612,19 @@ impl Execute for Archive {
             bail!("`--include-chapters` can only be used if `--merge` is set to 'audio' or 'sync'")
         }
 
+        if self.update {
+            if container != "mkv" {
+                bail!("`--update` only supports a '.mkv' output")
+            }
+            if matches!(self.merge, MergeBehavior::Video) {
+                bail!("`--update` can't be used if `--merge` is set to 'video', since that already produces a separate file per audio locale")
+            }
+        }
+
+        if self.duration.is_some_and(|d| d <= Duration::zero()) {
+            bail!("`--duration` must be greater than zero")
+        }
+
         self.audio = all_locale_in_locales(self.audio.clone());
         self.subtitle = all_locale_in_locales(self.subtitle.clone());
 
@@ -257,21 +648,58 @@ impl Execute for Archive {
                 .collect();
         }
 
+        if container == "webm" {
+            if self.audio.len() > 1 && matches!(self.merge, MergeBehavior::Audio) {
+                bail!("The '.webm' container only supports a single audio track. Remove '--merge audio' or request a single '--audio' locale")
+            }
+            if self.subtitle.len() > 1 {
+                bail!("The '.webm' container only supports a single subtitle track. Request a single '--subtitle' locale")
+            }
+            if self.ffmpeg_preset.is_none() {
+                bail!("The '.webm' container only accepts VP8/VP9/AV1 video and Opus/Vorbis audio. The downloaded streams are neither, so `--ffmpeg-preset` must be set to a compatible custom preset, e.g. `--ffmpeg-preset=\"-c:v libvpx-vp9 -c:a libopus\"`")
+            }
+        }
+
         Ok(())
     }
 
     async fn execute(self, ctx: Context) -> Result<()> {
-        if !ctx.crunchy.premium().await {
+        debug!(
+            "Color output {}",
+            if ctx.color { "enabled" } else { "disabled" }
+        );
+
+        let skip_muxing = self.dump_json || self.print_formats.is_some();
+
+        if !skip_muxing {
+            let ffmpeg_path = resolve_ffmpeg(
+                &ctx.client,
+                self.ffmpeg_path.as_deref(),
+                self.download_ffmpeg,
+            )
+            .await?;
+            set_ffmpeg_binary(ffmpeg_path);
+
+            if let Some(preset) = &self.ffmpeg_preset {
+                preset
+                    .validate_encoder_availability()
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            }
+        }
+
+        if !skip_muxing && !ctx.crunchy.premium().await {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
 
         let mut parsed_urls = vec![];
 
         for (i, url) in self.urls.clone().into_iter().enumerate() {
-            let progress_handler = progress!("Parsing url {}", i + 1);
+            let progress_handler = progress_unless!(skip_muxing, "Parsing url {}", i + 1);
             match parse_url(&ctx.crunchy, url.clone(), true).await {
                 Ok((media_collection, url_filter)) => {
-                    progress_handler.stop(format!("Parsed url {}", i + 1));
+                    if let Some(p) = progress_handler {
+                        p.stop(format!("Parsed url {}", i + 1))
+                    }
                     parsed_urls.push((media_collection, url_filter))
                 }
                 Err(e) => bail!("url {} could not be parsed: {}", url, e),
@@ -279,22 +707,43 @@ impl Execute for Archive {
         }
 
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
-            let progress_handler = progress!("Fetching series details");
+            let progress_handler = progress_unless!(skip_muxing, "Fetching series details");
             let single_format_collection = ArchiveFilter::new(
                 url_filter,
                 self.clone(),
                 !self.yes,
                 self.skip_specials,
+                ctx.experimental_fixes,
                 ctx.crunchy.premium().await,
+                ctx.client.clone(),
+                ctx.rate_limiter.clone(),
+                if self.no_cache {
+                    TimeDelta::zero()
+                } else {
+                    TimeDelta::seconds(self.cache_ttl as i64)
+                },
+                self.refresh_cache,
             )
             .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
-                progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                if let Some(p) = progress_handler {
+                    p.stop(format!("Skipping url {} (no matching videos found)", i + 1))
+                }
+                continue;
+            }
+            if let Some(p) = progress_handler {
+                p.stop(format!("Loaded series information for url {}", i + 1))
+            }
+
+            if self.dump_json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&single_format_collection.dump_json().await)?
+                );
                 continue;
             }
-            progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
             single_format_collection.full_visual_output();
 
@@ -303,11 +752,48 @@ impl Execute for Archive {
                     .default_subtitle(self.default_subtitle.clone())
                     .download_fonts(self.include_fonts)
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
+                    .audio_channel(self.audio_channel.clone())
                     .ffmpeg_threads(self.ffmpeg_threads)
-                    .output_format(Some("matroska".to_string()))
+                    .output_format(Some(
+                        match PathBuf::from(&self.output)
+                            .extension()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .as_ref()
+                        {
+                            "mp4" => "mp4",
+                            "webm" => "webm",
+                            _ => "matroska",
+                        }
+                        .to_string(),
+                    ))
                     .audio_sort(Some(self.audio.clone()))
                     .subtitle_sort(Some(self.subtitle.clone()))
                     .no_closed_caption(self.no_closed_caption)
+                    .prefer_sdh(self.prefer_sdh)
+                    .subtitle_output(self.subtitle_output)
+                    .subtitle_format(self.subtitle_format)
+                    .subtitle_charset(self.subtitle_charset.clone())
+                    .subtitle_style(SubtitleStyleOverrides {
+                        font_name: self.subtitle_font.clone(),
+                        font_size: self.subtitle_font_size,
+                        outline: self.subtitle_outline,
+                        shadow: self.subtitle_shadow,
+                        margin_v: self.subtitle_margin_v,
+                    })
+                    .subtitle_sync(self.subtitle_sync.clone())
+                    .encode_preset(self.encode.clone())
+                    .force_color_transfer(self.force_color_transfer.clone())
+                    .fragment_duration(self.fragment_duration)
+                    .video_track_timescale(self.video_track_timescale)
+                    .disable_faststart(self.no_faststart)
+                    .hls_output(self.hls)
+                    .accelerate_skip_events(AccelerateFactors {
+                        recap: self.accelerate_recap,
+                        intro: self.accelerate_intro,
+                        credits: self.accelerate_credits,
+                        preview: self.accelerate_preview,
+                    })
                     .merge_sync_tolerance(match self.merge {
                         MergeBehavior::Sync => Some(self.merge_sync_tolerance),
                         _ => None,
@@ -316,7 +802,17 @@ impl Execute for Archive {
                         MergeBehavior::Sync => Some(self.merge_sync_precision),
                         _ => None,
                     })
+                    .merge_sync_max_offset_variance(match self.merge {
+                        MergeBehavior::Sync => Some(self.merge_sync_max_offset_variance),
+                        _ => None,
+                    })
+                    .clip_start(self.start)
+                    .clip_duration(self.duration)
                     .threads(self.threads)
+                    .retries(self.retries)
+                    .work_dir(self.work_dir.clone())
+                    .keep_work_dir(self.keep_work_dir)
+                    .verify_integrity(self.verify_integrity)
                     .audio_locale_output_map(
                         zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
                     )
@@ -324,12 +820,17 @@ impl Execute for Archive {
                         zip(self.subtitle.clone(), self.output_subtitle_locales.clone()).collect(),
                     );
 
+            let mut printed_formats = vec![];
+            // (season_id, archive name, downloaded episode paths), flushed via `package_season`
+            // whenever the season boundary changes and once more after the loop
+            let mut season_archive: Option<(String, String, Vec<PathBuf>)> = None;
+
             for single_formats in single_format_collection.into_iter() {
                 let (download_formats, mut format) = get_format(&self, &single_formats).await?;
 
-                let mut downloader = download_builder.clone().build();
-                for download_format in download_formats {
-                    downloader.add_format(download_format)
+                if self.print_formats.is_some() {
+                    printed_formats.push(format);
+                    continue;
                 }
 
                 let formatted_path = if format.is_special() {
@@ -349,13 +850,89 @@ impl Execute for Archive {
                 };
                 let (mut path, changed) = free_file(formatted_path.clone());
 
+                if changed && self.update {
+                    let Some(existing_streams) = get_video_streams(&formatted_path)? else {
+                        warn!(
+                            "Could not probe the already existing file '{}', leaving it untouched",
+                            formatted_path.to_string_lossy()
+                        );
+                        continue;
+                    };
+
+                    let missing_audios: Vec<(StreamData, Locale)> = download_formats
+                        .iter()
+                        .flat_map(|f| f.audios.clone())
+                        .filter(|(_, locale)| {
+                            !existing_streams.audio.iter().any(|t| &t.locale == locale)
+                        })
+                        .collect();
+                    let missing_subtitles: Vec<(Subtitle, SubtitleKind)> = download_formats
+                        .iter()
+                        .flat_map(|f| f.subtitles.clone())
+                        .filter(|(subtitle, kind)| {
+                            !(*kind == SubtitleKind::ClosedCaption && self.no_closed_caption)
+                                && !existing_streams
+                                    .subtitle
+                                    .iter()
+                                    .any(|t| t.locale == subtitle.locale)
+                        })
+                        .collect();
+
+                    if missing_audios.is_empty() && missing_subtitles.is_empty() {
+                        debug!(
+                            "'{}' already has every requested audio/subtitle locale, skipping",
+                            formatted_path.to_string_lossy()
+                        );
+                        continue;
+                    }
+
+                    let downloader = download_builder.clone().build();
+                    update_existing_file(
+                        &downloader,
+                        &formatted_path,
+                        &existing_streams,
+                        missing_audios,
+                        missing_subtitles,
+                        &zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
+                        &zip(self.subtitle.clone(), self.output_subtitle_locales.clone())
+                            .collect(),
+                    )
+                    .await?;
+
+                    continue;
+                }
+
+                let mut downloader = download_builder
+                    .clone()
+                    .metadata_tags(if self.metadata && !self.no_metadata {
+                        format.mkv_tags()
+                    } else {
+                        vec![]
+                    })
+                    .info_json(if self.embed_info_json {
+                        Some(format.info_json()?)
+                    } else {
+                        None
+                    })
+                    .build();
+                for download_format in download_formats {
+                    downloader.add_format(download_format)
+                }
+
                 if changed && self.skip_existing {
                     let mut skip = true;
 
                     if !self.skip_existing_method.is_empty() {
-                        if let Some((audio_locales, subtitle_locales)) =
-                            get_video_streams(&formatted_path)?
-                        {
+                        if let Some(existing_streams) = get_video_streams(&formatted_path)? {
+                            for track in
+                                existing_streams.audio.iter().chain(&existing_streams.subtitle)
+                            {
+                                debug!(
+                                    "Found existing {} track ({}, default: {}, forced: {})",
+                                    track.locale, track.codec_name, track.default, track.forced
+                                );
+                            }
+
                             let method_audio = self
                                 .skip_existing_method
                                 .contains(&SkipExistingMethod::Audio);
@@ -364,10 +941,9 @@ impl Execute for Archive {
                                 .contains(&SkipExistingMethod::Subtitle);
 
                             let audio_differ = if method_audio {
-                                format
-                                    .locales
-                                    .iter()
-                                    .any(|(a, _)| !audio_locales.contains(a))
+                                format.locales.iter().any(|(a, _)| {
+                                    !existing_streams.audio.iter().any(|t| &t.locale == a)
+                                })
                             } else {
                                 false
                             };
@@ -379,12 +955,16 @@ impl Execute for Archive {
                                     .flat_map(|(a, mut s)| {
                                         // remove the closed caption if the flag is given to omit
                                         // closed captions
-                                        if self.no_closed_caption && a != Locale::ja_JP {
+                                        if self.no_closed_caption
+                                            && Some(&a) != format.original_audio.as_ref()
+                                        {
                                             s.retain(|l| l != &a)
                                         }
                                         s
                                     })
-                                    .any(|l| !subtitle_locales.contains(&l))
+                                    .any(|l| {
+                                        !existing_streams.subtitle.iter().any(|t| t.locale == l)
+                                    })
                             } else {
                                 false
                             };
@@ -408,10 +988,12 @@ impl Execute for Archive {
                 }
 
                 format.locales.sort_by(|(a, _), (b, _)| {
-                    self.audio
-                        .iter()
-                        .position(|l| l == a)
-                        .cmp(&self.audio.iter().position(|l| l == b))
+                    locale_position(&self.audio, a, Some(a) == format.original_audio.as_ref())
+                        .cmp(&locale_position(
+                            &self.audio,
+                            b,
+                            Some(b) == format.original_audio.as_ref(),
+                        ))
                 });
                 for (_, subtitles) in format.locales.iter_mut() {
                     subtitles.sort_by(|a, b| {
@@ -424,7 +1006,42 @@ impl Execute for Archive {
 
                 format.visual_output(&path);
 
-                downloader.download(&path).await?
+                let output = AtomicOutput::new(path.clone())?;
+                downloader.download(output.path()).await?;
+                output.commit()?;
+
+                if self.nfo {
+                    format.write_nfo(&path)?
+                }
+
+                if let Some(compression) = &self.compress {
+                    if season_archive.as_ref().is_some_and(|(id, ..)| id != &format.season_id) {
+                        let (_, name, paths) = season_archive.take().unwrap();
+                        package_season(&paths, &name, compression)?;
+                    }
+                    let (_, _, paths) = season_archive.get_or_insert_with(|| {
+                        (
+                            format.season_id.clone(),
+                            format!("{} - {}", format.series_name, format.season_title),
+                            vec![],
+                        )
+                    });
+                    paths.push(path);
+                }
+            }
+
+            if let (Some(compression), Some((_, name, paths))) = (&self.compress, season_archive)
+            {
+                if !paths.is_empty() {
+                    package_season(&paths, &name, compression)?;
+                }
+            }
+
+            if let Some(print_formats) = &self.print_formats {
+                println!(
+                    "{}",
+                    print_formats.serialize(&group_formats_by_season(&printed_formats))?
+                );
             }
         }
 
@@ -471,13 +1088,19 @@ async fn get_format(
 
     for single_format in single_formats {
         let stream = single_format.stream().await?;
-        let Some((video, audio, _)) =
-            stream_data_from_stream(&stream, &archive.resolution, None).await?
+        let Some((video, audio, _)) = stream_data_from_stream(
+            &stream,
+            &archive.resolution,
+            archive.resolution_strategy,
+            None,
+            archive.stream_protocol,
+        )
+        .await?
         else {
             if single_format.is_episode() {
                 bail!(
                     "Resolution ({}) is not available for episode {} ({}) of {} season {}",
-                    archive.resolution,
+                    format_resolution_preferences(&archive.resolution),
                     single_format.episode_number,
                     single_format.title,
                     single_format.series_name,
@@ -486,35 +1109,38 @@ async fn get_format(
             } else {
                 bail!(
                     "Resolution ({}) is not available for {} ({})",
-                    archive.resolution,
+                    format_resolution_preferences(&archive.resolution),
                     single_format.source_type(),
                     single_format.title
                 )
             }
         };
 
-        let subtitles: Vec<(Subtitle, bool)> = archive
+        let subtitles: Vec<(Subtitle, SubtitleKind)> = archive
             .subtitle
             .iter()
             .flat_map(|s| {
-                let subtitles = stream
-                    .subtitles
+                let subtitles = stream.subtitles.get(s).cloned().map(|l| {
+                    // if the audio isn't the original-language track and it's the only subtitle
+                    // for this stream, it's most likely only covering the foreign dialogue/signs
+                    // the dub didn't translate, i.e. a forced subtitle
+                    let kind = if !single_format.is_original && stream.subtitles.len() == 1 {
+                        SubtitleKind::Forced
+                    } else {
+                        SubtitleKind::Regular
+                    };
+                    (l, kind)
+                });
+                let cc = stream
+                    .captions
                     .get(s)
                     .cloned()
-                    // the subtitle is probably cc if the audio is not japanese or only one
-                    // subtitle exists for this stream
-                    .map(|l| {
-                        (
-                            l,
-                            single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-                        )
-                    });
-                let cc = stream.captions.get(s).cloned().map(|l| (l, true));
+                    .map(|l| (l, SubtitleKind::ClosedCaption));
 
                 subtitles
                     .into_iter()
                     .chain(cc.into_iter())
-                    .collect::<Vec<(Subtitle, bool)>>()
+                    .collect::<Vec<(Subtitle, SubtitleKind)>>()
             })
             .collect();
 
@@ -546,10 +1172,12 @@ async fn get_format(
                 .collect(),
             // mix all subtitles together and then reduce them via a map so that only one subtitle
             // per language exists
-            subtitles: format_pairs
-                .iter()
-                .flat_map(|(_, _, _, subtitles)| subtitles.clone())
-                .collect(),
+            subtitles: dedup_subtitles(
+                format_pairs
+                    .iter()
+                    .flat_map(|(_, _, _, subtitles)| subtitles.clone())
+                    .collect(),
+            ),
             metadata: DownloadFormatMetadata {
                 skip_events: if archive.include_chapters {
                     format_pairs.first().unwrap().0.skip_events().await?
@@ -604,7 +1232,8 @@ async fn get_format(
                 };
             }
 
-            for (_, d_format) in d_formats.into_iter() {
+            for (_, mut d_format) in d_formats.into_iter() {
+                d_format.subtitles = dedup_subtitles(d_format.subtitles);
                 download_formats.push(d_format);
             }
         }
@@ -616,35 +1245,223 @@ async fn get_format(
     ))
 }
 
-fn get_video_streams(path: &Path) -> Result<Option<(Vec<Locale>, Vec<Locale>)>> {
-    let video_streams =
-        Regex::new(r"(?m)Stream\s#\d+:\d+\((?P<language>.+)\):\s(?P<type>(Audio|Subtitle))")
-            .unwrap();
+/// Keeps only the first `(locale, kind)` occurrence, so grouping multiple dubs' subtitles onto one
+/// `DownloadFormat` (`MergeBehavior::Audio`/`Auto`/`Sync`) doesn't carry the same language/variant
+/// in as many times as there are dubs.
+fn dedup_subtitles(subtitles: Vec<(Subtitle, SubtitleKind)>) -> Vec<(Subtitle, SubtitleKind)> {
+    let mut seen: Vec<(Locale, SubtitleKind)> = vec![];
+    subtitles
+        .into_iter()
+        .filter(|(subtitle, kind)| {
+            let key = (subtitle.locale.clone(), *kind);
+            if seen.contains(&key) {
+                false
+            } else {
+                seen.push(key);
+                true
+            }
+        })
+        .collect()
+}
 
-    let ffmpeg = Command::new("ffmpeg")
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .arg("-hide_banner")
-        .args(["-i", &path.to_string_lossy()])
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    streams: Vec<FfprobeStream>,
+    #[serde(default)]
+    format: FfprobeFormat,
+}
+
+#[derive(Default, Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_type: String,
+    codec_name: String,
+    #[serde(default)]
+    tags: FfprobeTags,
+    #[serde(default)]
+    disposition: FfprobeDisposition,
+}
+
+#[derive(Default, Deserialize)]
+struct FfprobeTags {
+    language: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct FfprobeDisposition {
+    #[serde(default)]
+    default: u8,
+    #[serde(default)]
+    forced: u8,
+}
+
+/// An audio or subtitle track `get_video_streams` found already muxed into a file, so callers can
+/// tell a forced-narrative subtitle from a full one and avoid re-downloading a track whose codec
+/// already matches.
+pub(crate) struct ExistingTrack {
+    pub(crate) locale: Locale,
+    pub(crate) codec_name: String,
+    pub(crate) default: bool,
+    pub(crate) forced: bool,
+}
+
+pub(crate) struct ExistingStreams {
+    pub(crate) audio: Vec<ExistingTrack>,
+    pub(crate) subtitle: Vec<ExistingTrack>,
+    pub(crate) duration: Option<TimeDelta>,
+}
+
+/// Probes an already-archived file's audio/subtitle tracks via ffprobe's structured JSON output
+/// instead of scraping `ffmpeg -i`'s human-readable stderr banner with a regex, which breaks on
+/// localized ffmpeg builds and silently drops the codec/disposition info read here.
+fn get_video_streams(path: &Path) -> Result<Option<ExistingStreams>> {
+    let ffprobe = ffprobe_command()
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .args(["-v", "quiet", "-print_format", "json"])
+        .args(["-show_streams", "-show_format"])
+        .arg(&path.to_string_lossy().to_string())
         .output()?;
-    let ffmpeg_output = String::from_utf8(ffmpeg.stderr)?;
+    let probed: FfprobeOutput = serde_json::from_slice(&ffprobe.stdout)?;
 
     let mut audio = vec![];
     let mut subtitle = vec![];
-    for cap in video_streams.captures_iter(&ffmpeg_output) {
-        let locale = cap.name("language").unwrap().as_str();
-        let type_ = cap.name("type").unwrap().as_str();
-
-        match type_ {
-            "Audio" => audio.push(Locale::from(locale.to_string())),
-            "Subtitle" => subtitle.push(Locale::from(locale.to_string())),
-            _ => unreachable!(),
+    for stream in probed.streams {
+        let Some(language) = stream.tags.language else {
+            continue;
+        };
+        let track = ExistingTrack {
+            locale: Locale::from(language),
+            codec_name: stream.codec_name,
+            default: stream.disposition.default != 0,
+            forced: stream.disposition.forced != 0,
+        };
+
+        match stream.codec_type.as_str() {
+            "audio" => audio.push(track),
+            "subtitle" => subtitle.push(track),
+            _ => {}
         }
     }
 
     if audio.is_empty() && subtitle.is_empty() {
         Ok(None)
     } else {
-        Ok(Some((audio, subtitle)))
+        let duration = probed
+            .format
+            .duration
+            .and_then(|d| d.parse::<f64>().ok())
+            .map(|secs| TimeDelta::milliseconds((secs * 1000.0) as i64));
+        Ok(Some(ExistingStreams {
+            audio,
+            subtitle,
+            duration,
+        }))
+    }
+}
+
+/// Downloads every `missing_audios`/`missing_subtitles` track and remuxes them into the already
+/// existing `path` alongside its current streams, all stream-copied so the file doesn't have to be
+/// re-encoded just to add a track `--update` found missing.
+async fn update_existing_file(
+    downloader: &Downloader,
+    path: &Path,
+    existing_streams: &ExistingStreams,
+    missing_audios: Vec<(StreamData, Locale)>,
+    missing_subtitles: Vec<(Subtitle, SubtitleKind)>,
+    audio_locale_output_map: &HashMap<Locale, String>,
+    subtitle_locale_output_map: &HashMap<Locale, String>,
+) -> Result<()> {
+    let mut new_audios = vec![];
+    for (stream_data, locale) in &missing_audios {
+        let tmp_path = downloader
+            .download_audio(stream_data, format!("Downloading {} audio", locale), None)
+            .await?;
+        new_audios.push((tmp_path, locale.clone()));
+    }
+
+    let mut new_subtitles = vec![];
+    for (subtitle, kind) in &missing_subtitles {
+        let tmp_path = downloader
+            .download_subtitle(
+                subtitle.clone(),
+                existing_streams.duration.unwrap_or_default(),
+            )
+            .await?;
+        new_subtitles.push((tmp_path, subtitle.locale.clone(), *kind));
     }
+
+    let mut input = vec!["-i".to_string(), path.to_string_lossy().to_string()];
+    let mut maps = vec!["-map".to_string(), "0".to_string()];
+    let mut metadata = vec![];
+
+    let audio_base = existing_streams.audio.len();
+    for (i, (tmp_path, locale)) in new_audios.iter().enumerate() {
+        input.extend(["-i".to_string(), tmp_path.to_string_lossy().to_string()]);
+        maps.extend(["-map".to_string(), (i + 1).to_string()]);
+        metadata.extend([
+            format!("-metadata:s:a:{}", audio_base + i),
+            format!(
+                "language={}",
+                audio_locale_output_map
+                    .get(locale)
+                    .unwrap_or(&locale.to_string())
+            ),
+        ]);
+        metadata.extend([
+            format!("-metadata:s:a:{}", audio_base + i),
+            format!("title={}", locale.to_human_readable()),
+        ]);
+    }
+
+    let subtitle_base = existing_streams.subtitle.len();
+    for (i, (tmp_path, locale, kind)) in new_subtitles.iter().enumerate() {
+        input.extend(["-i".to_string(), tmp_path.to_string_lossy().to_string()]);
+        maps.extend(["-map".to_string(), (i + 1 + new_audios.len()).to_string()]);
+        metadata.extend([
+            format!("-metadata:s:s:{}", subtitle_base + i),
+            format!(
+                "language={}",
+                subtitle_locale_output_map
+                    .get(locale)
+                    .unwrap_or(&locale.to_string())
+            ),
+        ]);
+        metadata.extend([
+            format!("-metadata:s:s:{}", subtitle_base + i),
+            format!("title={}", {
+                let mut title = locale.to_string();
+                match kind {
+                    SubtitleKind::ClosedCaption => title += " (CC)",
+                    SubtitleKind::Forced => title += " (Forced)",
+                    SubtitleKind::Regular => {}
+                }
+                title
+            }),
+        ]);
+    }
+
+    let remuxed = tempfile(".mkv")?.into_temp_path();
+
+    let ffmpeg = ffmpeg_command()
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .args(["-y", "-hide_banner"])
+        .args(input)
+        .args(maps)
+        .args(["-c", "copy"])
+        .args(metadata)
+        .arg(remuxed.to_string_lossy().to_string())
+        .output()?;
+    if !ffmpeg.status.success() {
+        bail!("{}", String::from_utf8_lossy(&ffmpeg.stderr))
+    }
+
+    fs::rename(&remuxed, path)?;
+
+    Ok(())
 }
@@ -1,11 +1,23 @@
 use crate::archive::command::Archive;
+use crate::utils::download::DownloadBuilder;
 use crate::utils::filter::{real_dedup_vec, Filter};
+use crate::utils::fingerprint::AudioFingerprint;
 use crate::utils::format::{Format, SingleFormat, SingleFormatCollection};
-use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
+use crate::utils::interactive_select::{
+    check_for_duplicated_seasons, get_duplicated_seasons, resolve_duplicated_seasons,
+};
+use crate::utils::locale::{
+    has_original_locale, locale_from_season_slug, locale_position, original_locale_of,
+};
+use crate::utils::media_cache::MediaCache;
 use crate::utils::parse::{fract, UrlFilter};
+use crate::utils::rate_limit::RateLimiterService;
+use crate::utils::report::{write_report, ReportEntry, ReportReason};
 use anyhow::Result;
+use chrono::{Datelike, TimeDelta};
 use crunchyroll_rs::{Concert, Episode, Locale, Movie, MovieListing, MusicVideo, Season, Series};
-use log::{info, warn};
+use log::{debug, info, warn};
+use reqwest::Client;
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Not;
 
@@ -20,33 +32,170 @@ pub(crate) struct ArchiveFilter {
     archive: Archive,
     interactive_input: bool,
     skip_special: bool,
+    experimental_fixes: bool,
+    /// On-disk cache for the `seasons()`/`episodes()` lookups below, see `--cache-ttl`/`--no-cache`/
+    /// `--refresh-cache`.
+    media_cache: MediaCache,
+    /// Used to build a throwaway [`crate::utils::download::Downloader`] for `--verify-duplicates`'
+    /// audio samples; not otherwise needed by the filter stage.
+    client: Client,
+    rate_limiter: Option<RateLimiterService>,
     season_episodes: HashMap<String, Vec<Episode>>,
     season_subtitles_missing: Vec<u32>,
     seasons_with_premium: Option<Vec<u32>>,
     season_sorting: Vec<String>,
     visited: Visited,
+    /// Findings accumulated for `--report`, written out in [`Self::finish`] if `archive.report` is
+    /// set.
+    report: Vec<ReportEntry>,
 }
 
 impl ArchiveFilter {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         url_filter: UrlFilter,
         archive: Archive,
         interactive_input: bool,
         skip_special: bool,
+        experimental_fixes: bool,
         is_premium: bool,
+        client: Client,
+        rate_limiter: Option<RateLimiterService>,
+        cache_ttl: TimeDelta,
+        refresh_cache: bool,
     ) -> Self {
+        let media_cache = MediaCache::new(cache_ttl, refresh_cache, archive.offline);
         Self {
             url_filter,
             archive,
             interactive_input,
             skip_special,
+            experimental_fixes,
+            media_cache,
+            client,
+            rate_limiter,
             season_episodes: HashMap::new(),
             season_subtitles_missing: vec![],
             seasons_with_premium: is_premium.not().then_some(vec![]),
             season_sorting: vec![],
             visited: Visited::None,
+            report: vec![],
         }
     }
+
+    /// Builds a [`BTreeMap`] of one [`AudioFingerprint`] per duplicated season, keyed by season id,
+    /// for `--verify-duplicates`. Samples each candidate's first episode's lowest-bitrate audio
+    /// through a throwaway [`crate::utils::download::Downloader`] and caches the result by episode
+    /// id via [`Self::media_cache`] so reruns don't re-download it. A candidate whose episode list
+    /// is empty or whose sample fails to download/fingerprint is simply left out of the map;
+    /// [`resolve_duplicated_seasons`] treats a missing entry as unverified and keeps the metadata
+    /// heuristic's decision for it.
+    async fn verify_duplicate_fingerprints(
+        &self,
+        seasons: &[Season],
+        duplicated_season_numbers: &[u32],
+    ) -> BTreeMap<String, AudioFingerprint> {
+        let downloader = DownloadBuilder::new(self.client.clone(), self.rate_limiter.clone()).build();
+
+        let mut fingerprints = BTreeMap::new();
+        for season in seasons
+            .iter()
+            .filter(|s| duplicated_season_numbers.contains(&s.season_number))
+        {
+            let episode = match self
+                .media_cache
+                .get::<Vec<Episode>>("season-episodes", &season.id)
+            {
+                Some(episodes) => episodes.into_iter().next(),
+                None => match season.episodes().await {
+                    Ok(mut episodes) if !episodes.is_empty() => Some(episodes.remove(0)),
+                    Ok(_) => None,
+                    Err(e) => {
+                        debug!(
+                            "Could not fetch episodes of season {} for duplicate verification: {}",
+                            season.season_number, e
+                        );
+                        None
+                    }
+                },
+            };
+            let Some(episode) = episode else { continue };
+
+            let fingerprint = match self
+                .media_cache
+                .get::<AudioFingerprint>("audio-fingerprint", &episode.id)
+            {
+                Some(fingerprint) => fingerprint,
+                None => match AudioFingerprint::compute_for_episode(&episode, &downloader).await {
+                    Ok(fingerprint) => {
+                        self.media_cache
+                            .set("audio-fingerprint", &episode.id, &fingerprint);
+                        fingerprint
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Could not verify season {} acoustically, falling back to metadata: {}",
+                            season.season_number, e
+                        );
+                        continue;
+                    }
+                },
+            };
+            fingerprints.insert(season.id.clone(), fingerprint);
+        }
+
+        fingerprints
+    }
+
+    /// `warn!`s `message` and, if `--report` is set, records it as a [`ReportEntry`] too.
+    fn report_warn(&mut self, identifier: impl Into<String>, reason: ReportReason, message: String) {
+        warn!("{}", message);
+        if self.archive.report.is_some() {
+            self.report.push(ReportEntry {
+                identifier: identifier.into(),
+                reason,
+                message,
+            });
+        }
+    }
+
+    /// `info!`s `message` and, if `--report` is set, records it as a [`ReportEntry`] too.
+    fn report_info(&mut self, identifier: impl Into<String>, reason: ReportReason, message: String) {
+        info!("{}", message);
+        if self.archive.report.is_some() {
+            self.report.push(ReportEntry {
+                identifier: identifier.into(),
+                reason,
+                message,
+            });
+        }
+    }
+
+    /// Replace the `original` keyword in `self.archive.audio`, if present, with the locale this
+    /// title's version metadata (`(locale, original)` pairs) actually marks as original; it can't
+    /// be resolved against a fixed locale list since the original language varies per title.
+    fn resolve_audio(
+        &self,
+        versions: &[(Locale, bool)],
+        own_locale: Option<&Locale>,
+    ) -> Vec<Locale> {
+        if !has_original_locale(&self.archive.audio) {
+            return self.archive.audio.clone();
+        }
+
+        let original_locale = original_locale_of(versions, own_locale);
+        self.archive
+            .audio
+            .iter()
+            .map(|l| {
+                if l.to_string().eq_ignore_ascii_case("original") {
+                    original_locale.clone()
+                } else {
+                    l.clone()
+                }
+            })
+            .collect()
+    }
 }
 
 impl Filter for ArchiveFilter {
@@ -54,50 +203,95 @@ impl Filter for ArchiveFilter {
     type Output = SingleFormatCollection;
 
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        // the 'original' keyword can't be checked against a fixed locale list, it's resolved per
+        // season/episode below against their version metadata instead
+        let want_original_audio = has_original_locale(&self.archive.audio);
+
         // `series.audio_locales` isn't always populated b/c of crunchyrolls api. so check if the
         // audio is matching only if the field is populated
         if !series.audio_locales.is_empty() {
-            let missing_audio = missing_locales(&series.audio_locales, &self.archive.audio);
-            if !missing_audio.is_empty() {
-                warn!(
-                    "Series {} is not available with {} audio",
-                    series.title,
-                    missing_audio
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )
+            if !want_original_audio {
+                let missing_audio = missing_locales(&series.audio_locales, &self.archive.audio);
+                if !missing_audio.is_empty() {
+                    self.report_warn(
+                        series.id.clone(),
+                        ReportReason::MissingAudio,
+                        format!(
+                            "Series {} is not available with {} audio",
+                            series.title,
+                            missing_audio
+                                .into_iter()
+                                .map(|l| l.to_string())
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                    )
+                }
             }
             let missing_subtitle =
                 missing_locales(&series.subtitle_locales, &self.archive.subtitle);
             if !missing_subtitle.is_empty() {
-                warn!(
-                    "Series {} is not available with {} subtitles",
-                    series.title,
-                    missing_subtitle
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_warn(
+                    series.id.clone(),
+                    ReportReason::MissingSubtitle,
+                    format!(
+                        "Series {} is not available with {} subtitles",
+                        series.title,
+                        missing_subtitle
+                            .into_iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
                 )
             }
             self.visited = Visited::Series
         }
 
-        let mut seasons = series.seasons().await?;
+        let mut seasons = match self
+            .media_cache
+            .get_or_offline_err::<Vec<Season>>("series-seasons", &series.id, "seasons")?
+        {
+            Some(seasons) => seasons,
+            None => {
+                let seasons = series.seasons().await?;
+                self.media_cache
+                    .set("series-seasons", &series.id, &seasons);
+                seasons
+            }
+        };
         let mut remove_ids = vec![];
         for season in seasons.iter_mut() {
+            let season_audio = self.resolve_audio(
+                &season
+                    .versions
+                    .iter()
+                    .map(|v| (v.audio_locale.clone(), v.original))
+                    .collect::<Vec<(Locale, bool)>>(),
+                season.audio_locales.first(),
+            );
+            let available_versions = match self.media_cache.get_or_offline_err::<Vec<Locale>>(
+                "season-available-versions",
+                &season.id,
+                "available versions",
+            )? {
+                Some(available_versions) => available_versions,
+                None => {
+                    let available_versions = season.available_versions().await?;
+                    self.media_cache.set(
+                        "season-available-versions",
+                        &season.id,
+                        &available_versions,
+                    );
+                    available_versions
+                }
+            };
             if !self.url_filter.is_season_valid(season.season_number)
                 || (!season
                     .audio_locales
                     .iter()
-                    .any(|l| self.archive.audio.contains(l))
-                    && !season
-                        .available_versions()
-                        .await?
-                        .iter()
-                        .any(|l| self.archive.audio.contains(l)))
+                    .any(|l| season_audio.contains(l))
+                    && !available_versions.iter().any(|l| season_audio.contains(l)))
             {
                 remove_ids.push(season.id.clone());
             }
@@ -110,13 +304,32 @@ impl Filter for ArchiveFilter {
             if self.interactive_input {
                 check_for_duplicated_seasons(&mut seasons);
             } else {
-                info!(
-                    "Found duplicated seasons: {}",
-                    duplicated_seasons
-                        .iter()
-                        .map(|d| d.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_info(
+                    series.id.clone(),
+                    ReportReason::DuplicatedSeason,
+                    format!(
+                        "Found duplicated seasons: {}, keeping the release matching the requested audio",
+                        duplicated_seasons
+                            .iter()
+                            .map(|d| d.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                );
+                let fingerprints = if self.archive.verify_duplicates {
+                    Some(
+                        self.verify_duplicate_fingerprints(&seasons, &duplicated_seasons)
+                            .await,
+                    )
+                } else {
+                    None
+                };
+                resolve_duplicated_seasons(
+                    &mut seasons,
+                    &self.archive.audio,
+                    self.archive.version.as_ref(),
+                    self.experimental_fixes,
+                    fingerprints.as_ref(),
                 )
             }
         }
@@ -129,10 +342,17 @@ impl Filter for ArchiveFilter {
             return Ok(vec![]);
         }
 
-        let mut seasons = season.version(self.archive.audio.clone()).await?;
-        if self
-            .archive
-            .audio
+        let season_audio = self.resolve_audio(
+            &season
+                .versions
+                .iter()
+                .map(|v| (v.audio_locale.clone(), v.original))
+                .collect::<Vec<(Locale, bool)>>(),
+            season.audio_locales.first(),
+        );
+
+        let mut seasons = season.version(season_audio.clone()).await?;
+        if season_audio
             .iter()
             .any(|l| season.audio_locales.contains(l))
         {
@@ -145,16 +365,20 @@ impl Filter for ArchiveFilter {
                 .flat_map(|s| s.audio_locales.clone())
                 .collect();
             real_dedup_vec(&mut audio_locales);
-            let missing_audio = missing_locales(&audio_locales, &self.archive.audio);
+            let missing_audio = missing_locales(&audio_locales, &season_audio);
             if !missing_audio.is_empty() {
-                warn!(
-                    "Season {} is not available with {} audio",
-                    season.season_number,
-                    missing_audio
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_warn(
+                    season.id.clone(),
+                    ReportReason::MissingAudio,
+                    format!(
+                        "Season {} is not available with {} audio",
+                        season.season_number,
+                        missing_audio
+                            .into_iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
                 )
             }
 
@@ -164,14 +388,18 @@ impl Filter for ArchiveFilter {
                 .collect();
             let missing_subtitle = missing_locales(&subtitle_locales, &self.archive.subtitle);
             if !missing_subtitle.is_empty() {
-                warn!(
-                    "Season {} is not available with {} subtitles",
-                    season.season_number,
-                    missing_subtitle
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_warn(
+                    season.id.clone(),
+                    ReportReason::MissingSubtitle,
+                    format!(
+                        "Season {} is not available with {} subtitles",
+                        season.season_number,
+                        missing_subtitle
+                            .into_iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
                 )
             }
             self.visited = Visited::Season
@@ -186,12 +414,31 @@ impl Filter for ArchiveFilter {
                         .audio_locales
                         .first()
                         .cloned()
+                        // `audio_locales` is sometimes empty entirely; with `--experimental-fixes`,
+                        // guess the locale from the season's slug title (e.g. `...-german`) instead
+                        // of blindly assuming Japanese
+                        .or_else(|| {
+                            self.experimental_fixes
+                                .then(|| locale_from_season_slug(&season.slug_title))
+                                .flatten()
+                        })
                         .unwrap_or(Locale::ja_JP),
                 )
             } else {
                 None
             };
-            let mut eps = season.episodes().await?;
+            let mut eps = match self.media_cache.get_or_offline_err::<Vec<Episode>>(
+                "season-episodes",
+                &season.id,
+                "episodes",
+            )? {
+                Some(eps) => eps,
+                None => {
+                    let eps = season.episodes().await?;
+                    self.media_cache.set("season-episodes", &season.id, &eps);
+                    eps
+                }
+            };
             let before_len = eps.len();
 
             for mut ep in eps.clone() {
@@ -201,33 +448,41 @@ impl Filter for ArchiveFilter {
                     }
                     eps.remove(eps.iter().position(|p| p.id == ep.id).unwrap());
                 } else {
-                    let mut requested_locales = self.archive.audio.clone();
+                    let mut requested_locales = season_audio.clone();
                     if let Some(idx) = requested_locales.iter().position(|p| p == &ep.audio_locale)
                     {
                         requested_locales.remove(idx);
                     } else {
                         eps.remove(eps.iter().position(|p| p.id == ep.id).unwrap());
                     }
-                    eps.extend(ep.version(self.archive.audio.clone()).await?);
+                    eps.extend(ep.version(season_audio.clone()).await?);
                 }
             }
             if eps.len() < before_len {
                 if eps.is_empty() {
                     if matches!(self.visited, Visited::Series) {
-                        warn!(
-                            "Season {} is not available with {} audio",
-                            season.season_number,
-                            season_locale.unwrap_or(Locale::ja_JP)
+                        self.report_warn(
+                            season.id.clone(),
+                            ReportReason::MissingAudio,
+                            format!(
+                                "Season {} is not available with {} audio",
+                                season.season_number,
+                                season_locale.unwrap_or(Locale::ja_JP)
+                            ),
                         )
                     }
                 } else {
                     let last_episode = eps.last().unwrap();
-                    warn!(
-                        "Season {} is only available with {} audio until episode {} ({})",
-                        season.season_number,
-                        season_locale.unwrap_or(Locale::ja_JP),
-                        last_episode.sequence_number,
-                        last_episode.title
+                    self.report_warn(
+                        season.id.clone(),
+                        ReportReason::MissingAudio,
+                        format!(
+                            "Season {} is only available with {} audio until episode {} ({})",
+                            season.season_number,
+                            season_locale.unwrap_or(Locale::ja_JP),
+                            last_episode.sequence_number,
+                            last_episode.title
+                        ),
                     )
                 }
             }
@@ -261,14 +516,23 @@ impl Filter for ArchiveFilter {
             return Ok(None);
         }
 
+        let episode_versions: Vec<(Locale, bool)> = episode
+            .versions
+            .iter()
+            .map(|v| (v.audio_locale.clone(), v.original))
+            .collect();
+        let original_locale = original_locale_of(&episode_versions, Some(&episode.audio_locale));
+
         let mut episodes = vec![];
         if !matches!(self.visited, Visited::Series) && !matches!(self.visited, Visited::Season) {
-            if self.archive.audio.contains(&episode.audio_locale) {
+            let episode_audio = self.resolve_audio(&episode_versions, Some(&episode.audio_locale));
+
+            if episode_audio.contains(&episode.audio_locale) {
                 episodes.push((episode.clone(), episode.subtitle_locales.clone()))
             }
             episodes.extend(
                 episode
-                    .version(self.archive.audio.clone())
+                    .version(episode_audio.clone())
                     .await?
                     .into_iter()
                     .map(|e| (e.clone(), e.subtitle_locales.clone())),
@@ -277,16 +541,20 @@ impl Filter for ArchiveFilter {
                 .iter()
                 .map(|(e, _)| e.audio_locale.clone())
                 .collect();
-            let missing_audio = missing_locales(&audio_locales, &self.archive.audio);
+            let missing_audio = missing_locales(&audio_locales, &episode_audio);
             if !missing_audio.is_empty() {
-                warn!(
-                    "Episode {} is not available with {} audio",
-                    episode.sequence_number,
-                    missing_audio
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_warn(
+                    episode.id.clone(),
+                    ReportReason::MissingAudio,
+                    format!(
+                        "Episode {} is not available with {} audio",
+                        episode.sequence_number,
+                        missing_audio
+                            .into_iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
                 )
             }
 
@@ -299,14 +567,18 @@ impl Filter for ArchiveFilter {
                     .season_subtitles_missing
                     .contains(&episode.season_number)
             {
-                warn!(
-                    "Episode {} is not available with {} subtitles",
-                    episode.sequence_number,
-                    missing_subtitles
-                        .into_iter()
-                        .map(|l| l.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
+                self.report_warn(
+                    episode.id.clone(),
+                    ReportReason::MissingSubtitle,
+                    format!(
+                        "Episode {} is not available with {} subtitles",
+                        episode.sequence_number,
+                        missing_subtitles
+                            .into_iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
                 );
                 self.season_subtitles_missing.push(episode.season_number)
             }
@@ -324,9 +596,13 @@ impl Filter for ArchiveFilter {
                     .unwrap()
                     .contains(&episode.season_number)
             {
-                warn!(
-                    "Skipping premium episodes in season {}",
-                    episode.season_number
+                self.report_warn(
+                    episode.id.clone(),
+                    ReportReason::PremiumSkipped,
+                    format!(
+                        "Skipping premium episodes in season {}",
+                        episode.season_number
+                    ),
                 );
                 self.seasons_with_premium
                     .as_mut()
@@ -345,10 +621,21 @@ impl Filter for ArchiveFilter {
             let season_eps = match self.season_episodes.get(&episode.season_id) {
                 Some(eps) => eps,
                 None => {
-                    self.season_episodes.insert(
-                        episode.season_id.clone(),
-                        episode.season().await?.episodes().await?,
-                    );
+                    let eps = match self.media_cache.get_or_offline_err::<Vec<Episode>>(
+                        "season-episodes",
+                        &episode.season_id,
+                        "episodes",
+                    )? {
+                        Some(eps) => eps,
+                        None => {
+                            let eps = episode.season().await?.episodes().await?;
+                            self.media_cache
+                                .set("season-episodes", &episode.season_id, &eps);
+                            eps
+                        }
+                    };
+                    self.season_episodes
+                        .insert(episode.season_id.clone(), eps);
                     self.season_episodes.get(&episode.season_id).unwrap()
                 }
             };
@@ -367,12 +654,16 @@ impl Filter for ArchiveFilter {
                 }
             }
             if relative_episode_number.is_none() || relative_sequence_number.is_none() {
-                warn!(
-                    "Failed to get relative episode number for episode {} ({}) of {} season {}",
-                    episode.sequence_number,
-                    episode.title,
-                    episode.series_title,
-                    episode.season_number,
+                self.report_warn(
+                    episode.id.clone(),
+                    ReportReason::RelativeNumberFailed,
+                    format!(
+                        "Failed to get relative episode number for episode {} ({}) of {} season {}",
+                        episode.sequence_number,
+                        episode.title,
+                        episode.series_title,
+                        episode.season_number,
+                    ),
                 )
             }
         }
@@ -381,11 +672,13 @@ impl Filter for ArchiveFilter {
             episodes
                 .into_iter()
                 .map(|(e, s)| {
+                    let is_original = e.audio_locale == original_locale;
                     SingleFormat::new_from_episode(
                         e,
                         s,
                         relative_episode_number.map(|n| n as u32),
                         relative_sequence_number,
+                        is_original,
                     )
                 })
                 .collect(),
@@ -393,7 +686,20 @@ impl Filter for ArchiveFilter {
     }
 
     async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
-        Ok(movie_listing.movies().await?)
+        // movie listings have no season/episode numbers of their own, so a season/episode filter
+        // treats the whole listing as season 1 and each movie's position within it as the episode
+        // number, letting e.g. `[E2]` pick a single movie out of a listing
+        if !self.url_filter.is_season_valid(1) {
+            return Ok(vec![]);
+        }
+        Ok(movie_listing
+            .movies()
+            .await?
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.url_filter.is_episode_valid((i + 1) as f32, 1))
+            .map(|(_, movie)| movie)
+            .collect())
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Option<Self::T>> {
@@ -423,38 +729,48 @@ impl Filter for ArchiveFilter {
 
         let mut sorted: Vec<(String, Self::T)> = pre_sorted.into_iter().collect();
         sorted.sort_by(|(_, a), (_, b)| {
-            self.season_sorting
-                .iter()
-                .position(|p| p == &a.first().unwrap().season_id)
-                .unwrap()
-                .cmp(
-                    &self
-                        .season_sorting
-                        .iter()
-                        .position(|p| p == &b.first().unwrap().season_id)
-                        .unwrap(),
-                )
+            let a = a.first().unwrap();
+            let b = b.first().unwrap();
+
+            // falls back to the end instead of panicking if a season_id was somehow never visited
+            let season_position = |id: &str| {
+                self.season_sorting
+                    .iter()
+                    .position(|p| p == id)
+                    .unwrap_or(usize::MAX)
+            };
+            let air_month = |f: &SingleFormat| f.release_date.map(|d| (d.year(), d.month()));
+
+            season_position(&a.season_id)
+                .cmp(&season_position(&b.season_id))
+                .then_with(|| {
+                    if self.archive.sort_by_air_date {
+                        air_month(a)
+                            .cmp(&air_month(b))
+                            .then_with(|| a.sequence_number.total_cmp(&b.sequence_number))
+                    } else {
+                        a.sequence_number
+                            .total_cmp(&b.sequence_number)
+                            .then_with(|| air_month(a).cmp(&air_month(b)))
+                    }
+                })
         });
 
         for (_, mut data) in sorted {
             data.sort_by(|a, b| {
-                self.archive
-                    .audio
-                    .iter()
-                    .position(|p| p == &a.audio)
-                    .unwrap_or(usize::MAX)
-                    .cmp(
-                        &self
-                            .archive
-                            .audio
-                            .iter()
-                            .position(|p| p == &b.audio)
-                            .unwrap_or(usize::MAX),
-                    )
+                locale_position(&self.archive.audio, &a.audio, a.is_original).cmp(&locale_position(
+                    &self.archive.audio,
+                    &b.audio,
+                    b.is_original,
+                ))
             });
             single_format_collection.add_single_formats(data)
         }
 
+        if let Some(report_path) = &self.archive.report {
+            write_report(report_path, &self.report)?;
+        }
+
         Ok(single_format_collection)
     }
 }
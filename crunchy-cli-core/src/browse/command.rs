@@ -0,0 +1,102 @@
+use crate::search::filter::FilterOptions;
+use crate::search::format::Format;
+use crate::utils::context::Context;
+use crate::utils::parse::UrlFilter;
+use crate::Execute;
+use anyhow::Result;
+use crunchyroll_rs::common::StreamExt;
+use crunchyroll_rs::search::{BrowseOptions, BrowseSortType};
+use crunchyroll_rs::Locale;
+
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(about = "Browse the catalog by category, simulcast season or dub availability")]
+pub struct Browse {
+    #[arg(help = format!("Audio languages to include. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(long, default_values_t = vec![crate::utils::locale::system_locale()])]
+    audio: Vec<Locale>,
+
+    #[arg(help = "Comma-separated list of categories to filter by (e.g. 'action,comedy')")]
+    #[arg(long, value_delimiter = ',')]
+    categories: Vec<String>,
+    #[arg(help = "Only show titles from this simulcast season (e.g. 'spring-2024')")]
+    #[arg(long)]
+    simulcast_season: Option<String>,
+    #[arg(help = "Only show dubbed (audio-translated) titles")]
+    #[arg(long, default_value_t = false)]
+    is_dubbed: bool,
+
+    #[arg(help = "Order in which browse results are returned")]
+    #[arg(long, value_parser = BrowseSort::parse, default_value = "popularity")]
+    browse_sort: BrowseSort,
+    #[arg(help = "Limit of browse results")]
+    #[arg(long, default_value_t = 20)]
+    limit: u32,
+
+    /// Format of the output text. See `crunchy-cli search --help` for the full list of keywords.
+    #[arg(short, long)]
+    #[arg(default_value = "S{{season.number}}E{{episode.number}} - {{episode.title}}")]
+    output: String,
+}
+
+#[async_trait::async_trait(?Send)]
+impl Execute for Browse {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let mut browse_options = BrowseOptions::default()
+            .sort(self.browse_sort.clone().into())
+            .is_dubbed(self.is_dubbed);
+        if !self.categories.is_empty() {
+            browse_options = browse_options.categories(self.categories.clone());
+        }
+        if let Some(simulcast_season) = &self.simulcast_season {
+            browse_options = browse_options.simulcast_season(simulcast_season.clone());
+        }
+
+        let filter_options = FilterOptions {
+            audio: self.audio.clone(),
+            url_filter: UrlFilter::default(),
+        };
+        let format = Format::new(self.output.clone(), filter_options)?;
+
+        let mut results = ctx.crunchy.browse(browse_options);
+        let mut printed = 0;
+        while let Some(media_collection) = results.next().await {
+            println!("{}", format.parse(media_collection?).await?);
+
+            printed += 1;
+            if printed >= self.limit {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum BrowseSort {
+    Popularity,
+    NewlyAdded,
+    Alphabetical,
+}
+
+impl BrowseSort {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "popularity" => Ok(Self::Popularity),
+            "newly_added" => Ok(Self::NewlyAdded),
+            "alphabetical" => Ok(Self::Alphabetical),
+            _ => Err(format!("invalid browse sort method '{}'", s)),
+        }
+    }
+}
+
+impl From<BrowseSort> for BrowseSortType {
+    fn from(value: BrowseSort) -> Self {
+        match value {
+            BrowseSort::Popularity => BrowseSortType::Popularity,
+            BrowseSort::NewlyAdded => BrowseSortType::NewlyAdded,
+            BrowseSort::Alphabetical => BrowseSortType::Alphabetical,
+        }
+    }
+}
@@ -1,42 +1,210 @@
 use crate::download::filter::DownloadFilter;
+use crate::utils::accelerate::AccelerateFactors;
 use crate::utils::context::Context;
-use crate::utils::download::{DownloadBuilder, DownloadFormat, DownloadFormatMetadata};
-use crate::utils::ffmpeg::{FFmpegPreset, SOFTSUB_CONTAINERS};
-use crate::utils::filter::Filter;
-use crate::utils::format::{Format, SingleFormat};
+use crate::utils::download::{
+    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, SubtitleKind, SubtitleSyncMode,
+};
+use crate::utils::ffmpeg::{resolve_ffmpeg, FFmpegAudioChannel, FFmpegPreset, SOFTSUB_CONTAINERS};
+use crate::utils::filter::{real_dedup_vec, Filter};
+use crate::utils::format::{
+    group_formats_by_season, Format, PrintFormatsOutput, SingleFormat, SingleFormatCollection,
+};
+use crate::utils::gc::find_orphaned_files;
+use crate::utils::interactive_select::VersionSelector;
 use crate::utils::locale::{resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
-use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
+use crate::utils::log::progress_unless;
+use crate::utils::os::{free_file, has_ffmpeg, is_special_file, set_ffmpeg_binary, AtomicOutput};
 use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::subtitle_export::{SubtitleFormat, SubtitleOutput, SubtitleStyleOverrides};
+use crate::utils::video::{
+    format_resolution_preferences, stream_data_from_stream, ResolutionPreference,
+    ResolutionStrategy, StreamProtocol,
+};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
-use crunchyroll_rs::media::Resolution;
+use chrono::TimeDelta;
 use crunchyroll_rs::Locale;
-use log::{debug, warn};
-use std::collections::HashMap;
-use std::path::Path;
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::iter::zip;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Download a video")]
 #[command(arg_required_else_help(true))]
 pub struct Download {
-    #[arg(help = format!("Audio language. Can only be used if the provided url(s) point to a series. \
+    #[arg(help = format!("Audio language(s). Can be used multiple times to mux multiple audio tracks into the output file, set to 'original' to always use the episode's original-language audio, or 'all' to mux every dub an episode has. \
     Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
-    #[arg(long_help = format!("Audio language. Can only be used if the provided url(s) point to a series. \
+    #[arg(long_help = format!("Audio language(s). Can be used multiple times to mux multiple audio tracks into the output file. \
+    Tracks which aren't available for a specific episode are skipped with a warning instead of aborting the episode. \
+    Set to 'original' to always use whichever audio the stream itself marks as the original language, which varies per title (e.g. Japanese for most anime, but Korean or Chinese for others), instead of having to know and pass the right locale for every series. \
+    Set to 'all' to mux every dub an episode actually has instead of a fixed list; unlike the other values this is resolved per episode, so the number of audio tracks in the output can vary across episodes of the same series. \
     Available languages are:\n  {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| format!("{:<6} → {}", l.to_string(), l.to_human_readable())).collect::<Vec<String>>().join("\n  ")))]
-    #[arg(short, long, default_value_t = crate::utils::locale::system_locale())]
-    pub(crate) audio: Locale,
+    #[arg(short, long, default_values_t = vec![crate::utils::locale::system_locale()])]
+    pub(crate) audio: Vec<Locale>,
     #[arg(skip)]
-    output_audio_locale: String,
-    #[arg(help = format!("Subtitle language. Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
-    #[arg(long_help = format!("Subtitle language. If set, the subtitle will be burned into the video and cannot be disabled. \
+    output_audio_locales: Vec<String>,
+    #[arg(
+        help = "Mux every requested audio locale an episode has into one output file as separate audio tracks, instead of writing one file per locale"
+    )]
+    #[arg(long_help = "Mux every requested audio locale an episode has into one output file as separate audio tracks (one per '--audio' locale the episode actually has), instead of writing one file per locale. \
+    Enabled by default, use '--no-audio-merge' to get one file per audio locale instead")]
+    #[arg(long, default_value_t = true)]
+    pub(crate) audio_merge: bool,
+    #[arg(help = "Disable '--audio-merge'")]
+    #[arg(long, default_value_t = false, conflicts_with = "audio_merge")]
+    pub(crate) no_audio_merge: bool,
+    #[arg(
+        help = "Which re-release to keep when a season has more than one (e.g. uncut vs. broadcast). Accepts a 1-based position (as shown by the interactive prompt) or a keyword matched against each release's title"
+    )]
+    #[arg(long_help = "Crunchyroll sometimes lists the same season more than once for alternate cuts (e.g. an uncut release alongside the broadcast version), which by default are disambiguated by '--audio' alone. \
+    Pass a 1-based position (the order the interactive duplicate-season prompt would show them in) or a keyword (matched case-insensitively as a substring of each release's title, e.g. 'uncut') to prefer a specific one instead. \
+    Has no effect if none of the duplicates match; '--audio' is used as the fallback")]
+    #[arg(long, value_parser = VersionSelector::parse)]
+    pub(crate) version: Option<VersionSelector>,
+    #[arg(
+        help = "Acoustically verify duplicated seasons before auto-resolving them, instead of trusting metadata alone"
+    )]
+    #[arg(long_help = "Before auto-resolving duplicated seasons (see '--version'), downloads a short audio sample of every candidate release and compares it against the one '--audio'/'--version' would otherwise pick via the same fingerprinting '--merge sync' uses. \
+    A candidate whose audio doesn't match closely enough is kept alongside the pick instead of being dropped, since it's likely a distinct release (recap edition, re-dub, regional re-cut) that just happens to share a season number. \
+    Costs one extra audio sample download per duplicate; has no effect if a series has no duplicated seasons")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_duplicates: bool,
+    #[arg(help = format!("Subtitle language(s). Can be used multiple times, or set to 'all' to embed every subtitle/closed-caption track the stream offers. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(long_help = format!("Subtitle language(s). Can be used multiple times, or set to 'all' to embed every subtitle/closed-caption track the stream offers. \
+    If the output container does not support soft subtitles (see `--force-hardsub`), only the first requested language is burned into the video instead, since a video can only show one burnt-in subtitle. \
     Available languages are: {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
     #[arg(short, long)]
-    pub(crate) subtitle: Option<Locale>,
+    pub(crate) subtitle: Vec<Locale>,
     #[arg(skip)]
-    output_subtitle_locale: String,
+    output_subtitle_locales: Vec<String>,
+    #[arg(help = "Set which subtitle language should be set as default / auto shown when starting a video")]
+    #[arg(long)]
+    pub(crate) default_subtitle: Option<Locale>,
+    #[arg(
+        help = "Prefer the closed caption/SDH subtitle over the regular one of the same language, if both exist"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) prefer_sdh: bool,
+    #[arg(
+        help = "Whether subtitles are embedded in the output, written as sidecar files next to it, or both. Valid values are 'embed', 'external' and 'both'"
+    )]
+    #[arg(long_help = "Whether subtitles are embedded in the output, written as sidecar files \
+    next to it (named after it plus the subtitle's language tag), or both. Valid values are \
+    'embed' (the previous, still default, behavior), 'external' and 'both'")]
+    #[arg(long, default_value_t = SubtitleOutput::Embed, value_parser = SubtitleOutput::parse)]
+    pub(crate) subtitle_output: SubtitleOutput,
+    #[arg(
+        help = "Format the sidecar files written via `--subtitle-output external`/`both` are converted to. Valid formats are 'ass' (no conversion), 'srt', 'vtt' and 'scc'"
+    )]
+    #[arg(long_help = "Format the sidecar files written via `--subtitle-output external`/`both` are converted to. Valid formats are 'ass' (written out as downloaded, no conversion), \
+    'srt' (styling/positioning is dropped, overlapping events are merged since SRT can't represent either), 'vtt' (same as 'srt' but keeps basic positioning cues) and 'scc' (Scenarist SCC, CEA-608 pop-on captions for TV/set-top box/editing tools that only read line-21 captions)")]
+    #[arg(long, default_value_t = SubtitleFormat::Srt, value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+    #[arg(
+        help = "Charset the sidecar subtitle files written via `--subtitle-output external`/`both` are encoded as"
+    )]
+    #[arg(long, default_value = "utf-8")]
+    pub(crate) subtitle_charset: String,
+    #[arg(help = "Override the font used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_font: Option<String>,
+    #[arg(help = "Override the font size used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_font_size: Option<u32>,
+    #[arg(help = "Override the outline width used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_outline: Option<f32>,
+    #[arg(help = "Override the shadow width used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_shadow: Option<f32>,
+    #[arg(help = "Override the vertical margin used by downloaded subtitles")]
+    #[arg(long)]
+    pub(crate) subtitle_margin_v: Option<u32>,
+    #[arg(
+        help = "Re-align downloaded subtitles to the matching audio track's voice activity instead of trusting their own timestamps. Valid modes are 'global' and 'split'"
+    )]
+    #[arg(long_help = "Re-align downloaded subtitles to the matching audio track's voice activity instead of trusting their own (sometimes region-mismatched/drifted) timestamps. \
+    'global' finds a single best offset for the whole episode; 'split' additionally lets different parts of the episode (e.g. around an ad break) pick up their own offset where that recovers enough extra alignment to be worth it")]
+    #[arg(long, value_parser = SubtitleSyncMode::parse)]
+    pub(crate) subtitle_sync: Option<SubtitleSyncMode>,
+    #[arg(
+        help = "Re-encode the video with the given ffmpeg video encoder instead of remuxing the downloaded stream as-is"
+    )]
+    #[arg(long_help = "Re-encode the video with the given ffmpeg video encoder (e.g. 'libx264', 'libx265') instead of remuxing the downloaded stream as-is. \
+    The episode is first split into scene-aligned chunks, which are encoded concurrently (bounded by `--threads`) and losslessly concatenated back together afterwards")]
+    #[arg(long)]
+    pub(crate) encode: Option<String>,
+    #[arg(
+        help = "Force this color transfer characteristic (e.g. 'smpte2084', 'arib-std-b67') onto the muxed video instead of what the source declares"
+    )]
+    #[arg(long_help = "Force this color transfer characteristic (e.g. 'smpte2084' for PQ/HDR10, 'arib-std-b67' for HLG) onto the muxed video instead of what the source declares. \
+    Useful when a source's own tag is missing or wrong and playback falls back to SDR-looking output despite HDR content")]
+    #[arg(long)]
+    pub(crate) force_color_transfer: Option<String>,
+    #[arg(
+        help = "Produce a fragmented/streamable mp4 (fMP4, CMAF-style) with fragments this many seconds long, instead of a flat faststart file. Only applies to mp4/mov output"
+    )]
+    #[arg(long_help = "Produce a fragmented/streamable mp4 (fMP4, CMAF-style) with fragments this many seconds long, instead of a flat faststart file. \
+    The result is playable/seekable before it has fully downloaded and needs no separate faststart pass. Only applies to mp4/mov output")]
+    #[arg(long)]
+    pub(crate) fragment_duration: Option<f64>,
+    #[arg(
+        help = "Override the muxed video track's timescale (samples/second timestamps are expressed in). Only applies to mp4/mov output"
+    )]
+    #[arg(long_help = "Override the muxed video track's timescale (samples/second timestamps are expressed in) instead of ffmpeg's framerate-derived default. \
+    Useful to keep a fragmented/CMAF output's video and audio durations exact instead of one rounding against the other. Only applies to mp4/mov output")]
+    #[arg(long)]
+    pub(crate) video_track_timescale: Option<u32>,
+    #[arg(
+        help = "Don't move 'moov' before 'mdat' (faststart) in progressive mp4/mov/m4a output, for a faster non-rewritten write"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_faststart: bool,
+    #[arg(
+        help = "Write a self-hosting-friendly single-rendition HLS VOD (playlists + segment files) into the output path as a directory, instead of muxing into one file"
+    )]
+    #[arg(long_help = "Write a self-hosting-friendly single-rendition HLS VOD (playlists + segment files) into the output path as a directory, instead of muxing into one file. \
+    Only the first video format and its first audio track are included; other audio/subtitle tracks and additional formats are not emitted as extra renditions")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) hls: bool,
+
+    #[arg(
+        help = "Speed the recap skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long_help = "Speed the recap skip-event range up by this factor (e.g. 4 plays it at 4x) instead of only marking it with a chapter. \
+    Requires re-encoding the video and currently only supports a single muxed audio track and no embedded soft subtitles")]
+    #[arg(long)]
+    pub(crate) accelerate_recap: Option<f64>,
+    #[arg(
+        help = "Speed the intro skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_intro: Option<f64>,
+    #[arg(
+        help = "Speed the credits skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_credits: Option<f64>,
+    #[arg(
+        help = "Speed the preview skip-event range up by this factor instead of only marking it with a chapter"
+    )]
+    #[arg(long)]
+    pub(crate) accelerate_preview: Option<f64>,
+
+    #[arg(help = "Ordered preference of server-side hardsub (pre-burned-in subtitle) variants to use instead of local subtitle burning. \
+    Can be used multiple times, 'all' to emit one file per available hardsub variant, or 'none' to always fall back to softsubs/local burning")]
+    #[arg(long_help = "Ordered preference of server-side hardsub (pre-burned-in subtitle) variants to use instead of local subtitle burning. \
+    Can be used multiple times; the list is tried in order and the first variant the stream actually offers is used, avoiding a costly local re-encode. \
+    Set to 'all' to emit one file per available hardsub variant (combine with the '{hardsub}' output path pattern to keep the files apart), or 'none' to always fall back to softsubs/local burning, even if '--force-hardsub' is set or the output container doesn't support softsubs. \
+    If not set, the first '--subtitle' language is tried as hardsub whenever burning is required anyway")]
+    #[arg(long)]
+    pub(crate) hardsub: Vec<Locale>,
 
     #[arg(help = "Name of the output file")]
     #[arg(long_help = "Name of the output file. \
@@ -45,6 +213,7 @@ pub struct Download {
       {series_name}              → Name of the series\n  \
       {season_name}              → Name of the season\n  \
       {audio}                    → Audio language of the video\n  \
+      {hardsub}                  → Server-side hardsub variant used (empty if none, see '--hardsub')\n  \
       {width}                    → Width of the video\n  \
       {height}                   → Height of the video\n  \
       {season_number}            → Number of the season\n  \
@@ -57,7 +226,10 @@ pub struct Download {
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n  \
+    Since the path is written as-is, subdirectories can be used to build a library layout, e.g. \
+    '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mp4' \
+    for a layout Kodi/Jellyfin/Plex can scan directly (use together with '--nfo')")]
     #[arg(short, long, default_value = "{title}.mp4")]
     pub(crate) output: String,
     #[arg(help = "Name of the output file if the episode is a special")]
@@ -76,19 +248,60 @@ pub struct Download {
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    Multiple fallbacks can be chained with a comma, tried in order until one resolves, e.g. 'best<=720p,480p,worst'. \
+    'best<=H'/'best>=H' pick the highest/lowest-bandwidth variant at most/at least 'H' pixels tall")]
     #[arg(short, long, default_value = "best")]
-    #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
-    pub(crate) resolution: Resolution,
+    #[arg(value_parser = crate::utils::clap::clap_parse_resolution_preferences)]
+    pub(crate) resolution: Vec<ResolutionPreference>,
+
+    #[arg(
+        help = "How to pick a variant when '--resolution' isn't 'best'/'worst' and no variant matches its height exactly. Valid values are 'exact', 'nearest', 'max-bitrate:<bps>' and 'budget:<bytes>:<seconds>'"
+    )]
+    #[arg(long_help = "How to pick a variant when '--resolution' isn't 'best'/'worst' and no variant matches its height exactly. \
+    'exact' only accepts an exact height match and fails with an error otherwise (the default, and the only behavior before this flag existed). \
+    'nearest' picks the variant whose height is closest to the requested one. \
+    'max-bitrate:<bps>' picks the highest-bandwidth variant under the given bits/second ceiling, falling back to the lowest-bandwidth variant if none qualify. \
+    'budget:<bytes>:<seconds>' is the same as 'max-bitrate', but derives the ceiling from a total byte budget spread evenly over a duration, e.g. to fit an episode within a storage quota")]
+    #[arg(long, default_value = "exact")]
+    #[arg(value_parser = ResolutionStrategy::parse)]
+    pub(crate) resolution_strategy: ResolutionStrategy,
+
+    #[arg(help = "How long (in seconds) a series' seasons / a season's episodes are cached on disk before being re-fetched")]
+    #[arg(long, default_value_t = 3600)]
+    pub(crate) cache_ttl: u64,
+
+    #[arg(help = "Disable the on-disk season/episode cache entirely")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_cache: bool,
+
+    #[arg(help = "Ignore cached season/episode lists and re-fetch them, refreshing the cache")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) refresh_cache: bool,
+
+    #[arg(help = "Resolve series/season/episode metadata exclusively from the on-disk cache, without hitting the network")]
+    #[arg(long_help = "Resolve every series/season/episode lookup exclusively from the on-disk cache populated by previous runs, \
+    instead of calling the Crunchyroll API. Fails cleanly with an error as soon as something isn't cached, rather than silently \
+    going online. Can't be combined with '--no-cache', and ignores '--refresh-cache' and any cache entry's TTL, since there's nowhere else to get the data \
+    from while offline. Useful to resume filtering after a partial failure without hammering the API again for everything \
+    that was already resolved")]
+    #[arg(long, default_value_t = false, conflicts_with = "no_cache")]
+    pub(crate) offline: bool,
+
+    #[arg(help = "Adaptive streaming protocol to request stream data through. Valid values are 'hls' and 'dash'")]
+    #[arg(long_help = "Adaptive streaming protocol to request stream data through. Valid values are 'hls' (the default) and 'dash'. \
+    'dash' is not supported yet by the crunchyroll-rs version this is built against and currently always errors out; the flag exists so switching over later doesn't need another CLI change")]
+    #[arg(long, default_value_t = StreamProtocol::Hls, value_parser = StreamProtocol::parse)]
+    pub(crate) stream_protocol: StreamProtocol,
 
     #[arg(
         long,
         help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
-        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard)"
+        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard), 'bcp47' (like 'ietf' but region/script qualified, e.g. 'pt-BR' instead of 'pt')"
     )]
     #[arg(
         long_help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
-        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard; you might run in issues as there are multiple locales which resolve to the same IETF language code, e.g. 'es-LA' and 'es-ES' are both resolving to 'es')"
+        Valid options are: 'default' (how Crunchyroll uses it internally), 'ietf' (according to the IETF standard; you might run in issues as there are multiple locales which resolve to the same IETF language code, e.g. 'es-LA' and 'es-ES' are both resolving to 'es'), 'bcp47' (like 'ietf' but every locale gets its own region/script qualified tag instead of collapsing to the same bare subtag, e.g. 'pt-PT' and 'pt-BR' instead of both becoming 'pt')"
     )]
     #[arg(value_parser = LanguageTagging::parse)]
     pub(crate) language_tagging: Option<LanguageTagging>,
@@ -97,6 +310,11 @@ pub struct Download {
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
     #[arg(long_help = format!("Presets for converting the video to a specific coding format. \
     If you need more specific ffmpeg customizations you can pass ffmpeg output arguments instead of a preset as value. \
+    Instead of a fixed quality level you can append `-crfN`/`-qN` (e.g. `h265-crf23`) to use an exact crf/`-q:v`/`-qp` value, \
+    or `-vmafN` (e.g. `h264-vmaf95`) to target a VMAF score; \
+    the actual crf is probed per episode, which requires an ffmpeg build with the `libvmaf` filter and makes the episode take noticeably longer to process. \
+    You can also append `-aac`, `-opus` or `-flac` (e.g. `h264-opus`) to re-encode audio instead of copying it; `flac` requires an `.mkv`/`.mov`/`.mp4` output file. \
+    Append `-pix<fmt>` (e.g. `h264-pixyuv420p10le`) to force an output pixel format, or `-scale<W>x<H>` (e.g. `h264-scale1280x720`) to resize the video; either is independent of the audio/video codec so they combine with any of the above. \
     Available presets: \n  {}", FFmpegPreset::available_matches_human_readable().join("\n  ")))]
     #[arg(long)]
     #[arg(value_parser = FFmpegPreset::parse)]
@@ -111,14 +329,75 @@ pub struct Download {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Extract or downmix a single audio channel instead of keeping the full track. Valid values are 'fl', 'fr', 'fc', 'lfe', 'sl', 'sr' and 'mono'"
+    )]
+    #[arg(long_help = "Extract a single channel of a multi-channel audio track, or downmix it to mono, via an ffmpeg `pan` filter. \
+    Valid values are 'fl' (front left), 'fr' (front right), 'fc' (front center), 'lfe', 'sl' (side left), 'sr' (side right) and 'mono' (downmix all channels). \
+    Since this requires an audio filter rather than a stream copy, the audio codec is switched to `aac` automatically unless `--ffmpeg-preset` already requests a re-encoding codec")]
+    #[arg(long)]
+    #[arg(value_parser = FFmpegAudioChannel::parse)]
+    pub(crate) audio_channel: Option<FFmpegAudioChannel>,
+    #[arg(help = "Use a specific ffmpeg executable instead of the one on `PATH`")]
+    #[arg(long)]
+    pub(crate) ffmpeg_path: Option<PathBuf>,
+    #[arg(help = "Download a static ffmpeg build if none is found on `PATH`")]
+    #[arg(long_help = "Download a static ffmpeg build for the host platform and cache it in the \
+    config directory if no usable ffmpeg is found on `PATH`. Has no effect if `--ffmpeg-path` is set")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) download_ffmpeg: bool,
 
-    #[arg(help = "Skip files which are already existing by their name")]
+    #[arg(help = "Skip episodes which already exist in the output directory")]
+    #[arg(long_help = "Skip episodes which already exist in the output directory. \
+    The directory implied by the literal (non-placeholder) part of '--output' is scanned and every file name in it is parsed for a season/episode number \
+    (e.g. 'S01E05', '1x05'), so a previous run with a different template, resolution or release-group-style tag around the name is still recognized. \
+    Special episodes are always (re)downloaded since they can't be reliably matched by season/episode number")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_existing: bool,
+
+    #[arg(help = "Record episodes into this file as they're downloaded, and skip anything already recorded in it on later runs")]
+    #[arg(long_help = "Record every successfully downloaded episode into this file (creating it if needed), one per line, \
+    and skip any episode already recorded in it on a later run. \
+    Unlike '--skip-existing', the check is done purely against this file before the episode's stream is requested or downloaded, \
+    so pointing a scheduled run at a whole series and re-running it periodically only pulls newly released episodes. \
+    An episode is only appended once its file has been fully written, so a run interrupted mid-download is retried next time")]
+    #[arg(long)]
+    pub(crate) archive: Option<PathBuf>,
+
+    #[arg(help = "Report files in the output directory which no longer belong to any episode")]
+    #[arg(long_help = "Report files in the output directory implied by '--output' which don't match any episode this command would download, e.g. leftovers from an interrupted download or a since-removed episode. \
+    This only reports orphaned files; pass '--gc-remove' as well to actually delete them")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) gc: bool,
+    #[arg(help = "Delete the files '--gc' finds instead of only reporting them")]
+    #[arg(long, requires = "gc", default_value_t = false)]
+    pub(crate) gc_remove: bool,
+
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
 
+    #[arg(help = "Write a Kodi/Jellyfin/Plex compatible '.nfo' metadata sidecar next to each downloaded file")]
+    #[arg(long_help = "Write a Kodi/Jellyfin/Plex compatible '.nfo' metadata sidecar next to each downloaded file. \
+    Combine this with an '--output' template like '{series_name}/Season {season_number}/{series_name} - S{season_number}E{episode_number} - {title}.mp4' \
+    to get a library layout which media servers can scan without further configuration")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) nfo: bool,
+
+    #[arg(help = "Embed episode metadata (title, series, episode number, release date, synopsis) as Matroska tags")]
+    #[arg(long_help = "Embed episode metadata (title, series, episode number, release date, synopsis) as global and per-track Matroska tags. \
+    This makes the file self-describing to media servers like Jellyfin/Plex without a separate '--nfo' sidecar. Enabled by default, use '--no-metadata' to disable it")]
+    #[arg(long, default_value_t = true)]
+    pub(crate) metadata: bool,
+    #[arg(help = "Disable '--metadata'")]
+    #[arg(long, default_value_t = false, conflicts_with = "metadata")]
+    pub(crate) no_metadata: bool,
+    #[arg(help = "Attach the full episode metadata as a JSON file inside the downloaded file")]
+    #[arg(long_help = "Attach the full episode metadata (series/season/episode titles and numbers, release date, ids, synopsis) as a JSON file attachment inside the '.mkv', \
+    mirroring how other downloaders attach an info-json alongside their output")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_info_json: bool,
+
     #[arg(help = "Includes chapters (e.g. intro, credits, ...)")]
     #[arg(long_help = "Includes chapters (e.g. intro, credits, ...). \
     Because chapters are essentially only special timeframes in episodes like the intro, most of the video timeline isn't covered by a chapter.
@@ -131,6 +410,23 @@ pub struct Download {
     #[arg(short, long, default_value_t = false)]
     pub(crate) yes: bool,
 
+    #[arg(help = "Print series/season/episode metadata as JSON instead of downloading")]
+    #[arg(long_help = "Print series/season/episode metadata as JSON to stdout instead of downloading anything. \
+    Useful to script episode selection externally: the url(s) are resolved as usual and every matching season and episode is printed, \
+    including duplicated-season information which is otherwise only shown via the interactive prompt, together with the available audio/subtitle locales and resolutions. \
+    Implies '--yes' and suppresses all progress output")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) dump_json: bool,
+
+    #[arg(help = "Print the resolved season/episode format tree as JSON/YAML instead of downloading")]
+    #[arg(long_help = "Resolve every matching episode's stream (audio track, subtitle tracks, resolution, fps) as it would for a real download, \
+    then print the whole season/episode tree to stdout instead of downloading anything. \
+    Unlike '--dump-json', which runs before any stream is resolved and can only list available resolutions, \
+    this shows exactly what would be muxed: the audio/subtitle locale tuples, resolution, fps and all id/number fields actually selected for each episode. \
+    Implies '--yes' and suppresses all progress output")]
+    #[arg(long, value_parser = PrintFormatsOutput::parse, conflicts_with = "dump_json")]
+    pub(crate) print_formats: Option<PrintFormatsOutput>,
+
     #[arg(help = "Force subtitles to be always burnt-in")]
     #[arg(long, default_value_t = false)]
     pub(crate) force_hardsub: bool,
@@ -139,6 +435,32 @@ pub struct Download {
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
+    #[arg(help = "How often to retry a segment before giving up on the download")]
+    #[arg(long_help = "How often to retry fetching a segment before giving up on the download. \
+    Each retry waits longer than the last (exponential backoff), and a segment whose request fails with a 4xx status is never retried since that indicates a permanently bad url rather than a transient failure")]
+    #[arg(long, default_value_t = 5)]
+    pub(crate) retries: usize,
+
+    #[arg(help = "Directory to cache in-progress downloads in, so an interrupted run can resume")]
+    #[arg(long_help = "Directory each episode's already-downloaded segments are cached in while downloading. \
+    If an episode's run gets interrupted, rerunning the same command only fetches what's still missing instead of starting over. \
+    Defaults to a hidden directory next to the episode's output file; pass this to move it somewhere else, e.g. off a network-mounted output volume")]
+    #[arg(long)]
+    pub(crate) work_dir: Option<PathBuf>,
+
+    #[arg(help = "Keep an episode's work directory after it was successfully downloaded")]
+    #[arg(long_help = "Normally an episode's work directory (see '--work-dir') is deleted once its output file was generated successfully. \
+    Pass this to keep it around regardless, e.g. to inspect the raw downloaded segments")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) keep_work_dir: bool,
+
+    #[arg(help = "Verify the muxed output with ffprobe after downloading")]
+    #[arg(long_help = "After ffmpeg exits successfully, run ffprobe against the muxed output and confirm it actually has the expected \
+    number of video/audio/subtitle streams and a duration close to what was downloaded. \
+    If it doesn't, the partial file is deleted and the download fails instead of leaving a corrupt file behind")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) verify_integrity: bool,
+
     #[arg(help = "Url(s) to Crunchyroll episodes or series")]
     #[arg(required = true)]
     pub(crate) urls: Vec<String>,
@@ -146,8 +468,15 @@ pub struct Download {
 
 impl Execute for Download {
     fn pre_check(&mut self) -> Result<()> {
-        if !has_ffmpeg() {
-            bail!("FFmpeg is needed to run this command")
+        if self.dump_json || self.print_formats.is_some() {
+            self.yes = true;
+            // no muxing or file writing happens in this mode, so the output/ffmpeg checks below
+            // don't apply
+            return Ok(());
+        }
+
+        if self.ffmpeg_path.is_none() && !self.download_ffmpeg && !has_ffmpeg() {
+            bail!("FFmpeg is needed to run this command. Install it and make it available on `PATH`, pass its location via `--ffmpeg-path`, or use `--download-ffmpeg` to fetch a static build automatically")
         } else if Path::new(&self.output)
             .extension()
             .unwrap_or_default()
@@ -158,7 +487,36 @@ impl Execute for Download {
             bail!("No file extension found. Please specify a file extension (via `-o`) for the output file")
         }
 
-        if self.subtitle.is_some() {
+        if let Some(preset) = &self.ffmpeg_preset {
+            if let Some(ext) = Path::new(&self.output).extension() {
+                preset
+                    .validate_audio_codec_container(&ext.to_string_lossy())
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            }
+        }
+
+        if Path::new(&self.output)
+            .extension()
+            .is_some_and(|ext| ext.to_string_lossy() == "webm")
+        {
+            if self.subtitle.len() > 1 {
+                bail!("The '.webm' container only supports a single subtitle track. Request a single '--subtitle' locale")
+            }
+            if self.ffmpeg_preset.is_none() {
+                bail!("The '.webm' container only accepts VP8/VP9/AV1 video and Opus/Vorbis audio. The downloaded streams are neither, so `--ffmpeg-preset` must be set to a compatible custom preset, e.g. `--ffmpeg-preset=\"-c:v libvpx-vp9 -c:a libopus\"`")
+            }
+        }
+
+        if self.embed_info_json
+            && Path::new(&self.output)
+                .extension()
+                .is_some_and(|ext| ext.to_string_lossy() != "mkv")
+        {
+            warn!("'--embed-info-json' is ignored. Only the '.mkv' container can hold attachments");
+            self.embed_info_json = false;
+        }
+
+        if !self.subtitle.is_empty() {
             if let Some(ext) = Path::new(&self.output).extension() {
                 if self.force_hardsub {
                     warn!("Hardsubs are forced. Adding subtitles may take a while")
@@ -187,32 +545,64 @@ impl Execute for Download {
             }
         }
 
+        // the 'all'/'none' keywords are resolved against the stream itself in `get_format`, not
+        // against a fixed locale, so they must not be touched here
+        if !self
+            .hardsub
+            .iter()
+            .any(|l| matches!(l.to_string().to_lowercase().as_str(), "all" | "none"))
+        {
+            self.hardsub = resolve_locales(&self.hardsub);
+        }
+
         if let Some(language_tagging) = &self.language_tagging {
-            self.audio = resolve_locales(&[self.audio.clone()]).remove(0);
-            self.subtitle = self
-                .subtitle
-                .as_ref()
-                .map(|s| resolve_locales(&[s.clone()]).remove(0));
-            self.output_audio_locale = language_tagging.for_locale(&self.audio);
-            self.output_subtitle_locale = self
-                .subtitle
-                .as_ref()
-                .map(|s| language_tagging.for_locale(s))
-                .unwrap_or_default()
+            // the 'original'/'all' keywords are resolved against the episode's version metadata
+            // in `DownloadFilter`, not against a fixed locale, so they must not be touched here
+            if !self
+                .audio
+                .iter()
+                .any(|l| matches!(l.to_string().to_lowercase().as_str(), "original" | "all"))
+            {
+                self.audio = resolve_locales(&self.audio);
+            }
+            if !self.subtitle.iter().any(|l| l.to_string().eq_ignore_ascii_case("all")) {
+                self.subtitle = resolve_locales(&self.subtitle);
+            }
+            self.output_audio_locales = language_tagging.convert_locales(&self.audio);
+            self.output_subtitle_locales = language_tagging.convert_locales(&self.subtitle);
         } else {
-            self.output_audio_locale = self.audio.to_string();
-            self.output_subtitle_locale = self
-                .subtitle
-                .as_ref()
-                .map(|s| s.to_string())
-                .unwrap_or_default();
+            self.output_audio_locales = self.audio.iter().map(|l| l.to_string()).collect();
+            self.output_subtitle_locales = self.subtitle.iter().map(|l| l.to_string()).collect();
         }
 
         Ok(())
     }
 
     async fn execute(self, ctx: Context) -> Result<()> {
-        if !ctx.crunchy.premium().await {
+        debug!(
+            "Color output {}",
+            if ctx.color { "enabled" } else { "disabled" }
+        );
+
+        let skip_muxing = self.dump_json || self.print_formats.is_some();
+
+        if !skip_muxing {
+            let ffmpeg_path = resolve_ffmpeg(
+                &ctx.client,
+                self.ffmpeg_path.as_deref(),
+                self.download_ffmpeg,
+            )
+            .await?;
+            set_ffmpeg_binary(ffmpeg_path);
+
+            if let Some(preset) = &self.ffmpeg_preset {
+                preset
+                    .validate_encoder_availability()
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            }
+        }
+
+        if !skip_muxing && !ctx.crunchy.premium().await {
             warn!("You may not be able to download all requested videos when logging in anonymously or using a non-premium account")
         }
 
@@ -238,10 +628,12 @@ impl Execute for Download {
         };
 
         for (i, url) in self.urls.clone().into_iter().enumerate() {
-            let progress_handler = progress!("Parsing url {}", i + 1);
+            let progress_handler = progress_unless!(skip_muxing, "Parsing url {}", i + 1);
             match parse_url(&ctx.crunchy, url.clone(), true).await {
                 Ok((media_collection, url_filter)) => {
-                    progress_handler.stop(format!("Parsed url {}", i + 1));
+                    if let Some(p) = progress_handler {
+                        p.stop(format!("Parsed url {}", i + 1))
+                    }
                     parsed_urls.push((media_collection, url_filter))
                 }
                 Err(e) => bail!("url {} could not be parsed: {}", url, e),
@@ -249,28 +641,128 @@ impl Execute for Download {
         }
 
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
-            let progress_handler = progress!("Fetching series details");
+            let progress_handler = progress_unless!(skip_muxing, "Fetching series details");
             let single_format_collection = DownloadFilter::new(
                 url_filter,
                 self.clone(),
                 !self.yes,
+                ctx.experimental_fixes,
                 self.skip_specials,
                 ctx.crunchy.premium().await,
+                ctx.client.clone(),
+                ctx.rate_limiter.clone(),
+                if self.no_cache {
+                    TimeDelta::zero()
+                } else {
+                    TimeDelta::seconds(self.cache_ttl as i64)
+                },
+                self.refresh_cache,
             )
             .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
-                progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                if let Some(p) = progress_handler {
+                    p.stop(format!("Skipping url {} (no matching videos found)", i + 1))
+                }
                 continue;
             }
-            progress_handler.stop(format!("Loaded series information for url {}", i + 1));
+            if let Some(p) = progress_handler {
+                p.stop(format!("Loaded series information for url {}", i + 1))
+            }
+
+            if self.dump_json {
+                println!(
+                    "{}",
+                    serde_json::to_string(&single_format_collection.dump_json().await)?
+                );
+                continue;
+            }
+
+            if self.gc {
+                let orphaned =
+                    gc_orphaned_files(&self.output, &single_format_collection, self.gc_remove)?;
+                for path in &orphaned {
+                    if self.gc_remove {
+                        info!("Removed orphaned file '{}'", path.to_string_lossy())
+                    } else {
+                        info!(
+                            "Found orphaned file '{}' (run with '--gc-remove' to delete it)",
+                            path.to_string_lossy()
+                        )
+                    }
+                }
+            }
+
+            if let Some(archive) = &self.archive {
+                let archived = read_archive(archive)?;
+                let removed = single_format_collection.remove_archived(&archived);
+                if removed > 0 {
+                    info!(
+                        "Skipping {} episode(s) already recorded in archive '{}'",
+                        removed,
+                        archive.to_string_lossy()
+                    )
+                }
+
+                if single_format_collection.is_empty() {
+                    continue;
+                }
+            }
+
+            if self.skip_existing {
+                if let Some(root) = Format::template_root_dir(&self.output) {
+                    let removed = single_format_collection.remove_existing(&root, None);
+                    if removed > 0 {
+                        info!(
+                            "Skipping {} already existing episode(s) found in '{}'",
+                            removed,
+                            root.to_string_lossy()
+                        )
+                    }
+
+                    if single_format_collection.is_empty() {
+                        continue;
+                    }
+                } else {
+                    warn!(
+                        "`--output`/`{}` has no literal directory component, so `--skip-existing` \
+                         doesn't know where to look for already downloaded episodes and is being \
+                         skipped - add a directory to `--output` to use it",
+                        self.output
+                    );
+                }
+            }
 
             single_format_collection.full_visual_output();
 
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
-                    .default_subtitle(self.subtitle.clone())
+                    .default_subtitle(self.default_subtitle.clone())
+                    .prefer_sdh(self.prefer_sdh)
+                    .subtitle_output(self.subtitle_output)
+                    .subtitle_format(self.subtitle_format)
+                    .subtitle_charset(self.subtitle_charset.clone())
+                    .subtitle_style(SubtitleStyleOverrides {
+                        font_name: self.subtitle_font.clone(),
+                        font_size: self.subtitle_font_size,
+                        outline: self.subtitle_outline,
+                        shadow: self.subtitle_shadow,
+                        margin_v: self.subtitle_margin_v,
+                    })
+                    .subtitle_sync(self.subtitle_sync.clone())
+                    .encode_preset(self.encode.clone())
+                    .force_color_transfer(self.force_color_transfer.clone())
+                    .fragment_duration(self.fragment_duration)
+                    .video_track_timescale(self.video_track_timescale)
+                    .disable_faststart(self.no_faststart)
+                    .hls_output(self.hls)
+                    .accelerate_skip_events(AccelerateFactors {
+                        recap: self.accelerate_recap,
+                        intro: self.accelerate_intro,
+                        credits: self.accelerate_credits,
+                        preview: self.accelerate_preview,
+                    })
                     .force_hardsub(self.force_hardsub)
                     .output_format(if is_special_file(&self.output) || self.output == "-" {
                         Some("mpegts".to_string())
@@ -278,66 +770,128 @@ impl Execute for Download {
                         None
                     })
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
+                    .audio_channel(self.audio_channel.clone())
                     .ffmpeg_threads(self.ffmpeg_threads)
                     .threads(self.threads)
-                    .audio_locale_output_map(HashMap::from([(
-                        self.audio.clone(),
-                        self.output_audio_locale.clone(),
-                    )]))
+                    .retries(self.retries)
+                    .work_dir(self.work_dir.clone())
+                    .keep_work_dir(self.keep_work_dir)
+                    .verify_integrity(self.verify_integrity)
+                    .audio_locale_output_map(
+                        zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
+                    )
                     .subtitle_locale_output_map(
-                        self.subtitle.as_ref().map_or(HashMap::new(), |s| {
-                            HashMap::from([(s.clone(), self.output_subtitle_locale.clone())])
-                        }),
+                        zip(self.subtitle.clone(), self.output_subtitle_locales.clone()).collect(),
                     );
 
-            for mut single_formats in single_format_collection.into_iter() {
-                // the vec contains always only one item
-                let single_format = single_formats.remove(0);
+            let mut printed_formats = vec![];
+
+            let hardsub_all = self
+                .hardsub
+                .iter()
+                .any(|l| l.to_string().eq_ignore_ascii_case("all"));
+            let hardsub_none = self
+                .hardsub
+                .iter()
+                .any(|l| l.to_string().eq_ignore_ascii_case("none"));
 
-                let (download_format, format) = get_format(
+            for single_formats in single_format_collection.into_iter() {
+                let primary_format = single_formats.first().unwrap();
+                let legacy_try_peer_hardsubs = if self.force_hardsub {
+                    true
+                } else if primary_format.is_special() {
+                    !special_output_supports_softsubs
+                } else {
+                    !output_supports_softsubs
+                };
+                // an explicit `--hardsub` preference list always takes priority over the implicit,
+                // container-driven hardsub behavior; `none` always disables the peer-hardsub lookup,
+                // `all` is resolved against the stream itself in `get_format`
+                let hardsub_candidates: Vec<Locale> = if hardsub_none || hardsub_all {
+                    vec![]
+                } else if !self.hardsub.is_empty() {
+                    self.hardsub.clone()
+                } else if legacy_try_peer_hardsubs {
+                    self.subtitle.first().cloned().into_iter().collect()
+                } else {
+                    vec![]
+                };
+
+                let format_pairs = get_format(
                     &self,
-                    &single_format,
-                    if self.force_hardsub {
-                        true
-                    } else if single_format.is_special() {
-                        !special_output_supports_softsubs
-                    } else {
-                        !output_supports_softsubs
-                    },
+                    &single_formats,
+                    hardsub_all,
+                    &hardsub_candidates,
                 )
                 .await?;
 
-                let mut downloader = download_builder.clone().build();
-                downloader.add_format(download_format);
+                for (download_format, format) in format_pairs {
+                    if self.print_formats.is_some() {
+                        printed_formats.push(format);
+                        continue;
+                    }
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                };
-                let (path, changed) = free_file(formatted_path.clone());
+                    let mut downloader = download_builder
+                        .clone()
+                        .metadata_tags(if self.metadata && !self.no_metadata {
+                            format.mkv_tags()
+                        } else {
+                            vec![]
+                        })
+                        .info_json(if self.embed_info_json {
+                            Some(format.info_json()?)
+                        } else {
+                            None
+                        })
+                        .build();
+                    downloader.add_format(download_format);
 
-                if changed && self.skip_existing {
-                    debug!(
-                        "Skipping already existing file '{}'",
-                        formatted_path.to_string_lossy()
-                    );
-                    continue;
-                }
+                    let formatted_path = if format.is_special() {
+                        format.format_path(
+                            self.output_specials
+                                .as_ref()
+                                .map_or((&self.output).into(), |so| so.into()),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        )
+                    } else {
+                        format.format_path(
+                            (&self.output).into(),
+                            self.universal_output,
+                            self.language_tagging.as_ref(),
+                        )
+                    };
+                    let (path, changed) = free_file(formatted_path.clone());
 
-                format.visual_output(&path);
+                    if changed && self.skip_existing {
+                        debug!(
+                            "Skipping already existing file '{}'",
+                            formatted_path.to_string_lossy()
+                        );
+                        continue;
+                    }
 
-                downloader.download(&path).await?
+                    format.visual_output(&path);
+
+                    let output = AtomicOutput::new(path.clone())?;
+                    downloader.download(output.path()).await?;
+                    output.commit()?;
+
+                    if let Some(archive) = &self.archive {
+                        append_archive(archive, &format)?
+                    }
+
+                    if self.nfo {
+                        format.write_nfo(&path)?
+                    }
+                }
+            }
+
+            if let Some(print_formats) = &self.print_formats {
+                println!(
+                    "{}",
+                    print_formats.serialize(&group_formats_by_season(&printed_formats))?
+                );
             }
         }
 
@@ -345,86 +899,292 @@ impl Execute for Download {
     }
 }
 
+/// Resolves the formats to download for a single episode (across every requested audio locale).
+/// Ordinarily this is a single `(DownloadFormat, Format)` pair muxing every requested audio locale
+/// as separate tracks of the same file, but `--no-audio-merge` asks for one output file per audio
+/// locale instead, and `--hardsub all` asks for one output file per server-side hardsub variant
+/// the stream offers, so this returns a `Vec`.
 async fn get_format(
     download: &Download,
-    single_format: &SingleFormat,
-    try_peer_hardsubs: bool,
+    single_formats: &[SingleFormat],
+    hardsub_all: bool,
+    hardsub_candidates: &[Locale],
+) -> Result<Vec<(DownloadFormat, Format)>> {
+    if !(download.audio_merge && !download.no_audio_merge) && single_formats.len() > 1 {
+        let mut format_pairs = vec![];
+        for single_format in single_formats {
+            format_pairs.extend(
+                Box::pin(get_format(
+                    download,
+                    std::slice::from_ref(single_format),
+                    hardsub_all,
+                    hardsub_candidates,
+                ))
+                .await?,
+            );
+        }
+        return Ok(format_pairs);
+    }
+
+    if hardsub_all {
+        let stream = single_formats.first().unwrap().stream().await?;
+        let mut hardsub_locales: Vec<Locale> = stream.variants.keys().cloned().collect();
+        real_dedup_vec(&mut hardsub_locales);
+
+        if hardsub_locales.is_empty() {
+            return Ok(vec![
+                build_format_pair(download, single_formats, &[]).await?,
+            ]);
+        }
+
+        let mut format_pairs = vec![];
+        for hardsub_locale in hardsub_locales {
+            format_pairs.push(
+                build_format_pair(download, single_formats, &[hardsub_locale]).await?,
+            );
+        }
+        return Ok(format_pairs);
+    }
+
+    Ok(vec![
+        build_format_pair(download, single_formats, hardsub_candidates).await?,
+    ])
+}
+
+async fn build_format_pair(
+    download: &Download,
+    single_formats: &[SingleFormat],
+    hardsub_candidates: &[Locale],
 ) -> Result<(DownloadFormat, Format)> {
-    let stream = single_format.stream().await?;
-    let Some((video, audio, contains_hardsub)) = stream_data_from_stream(
-        &stream,
-        &download.resolution,
-        if try_peer_hardsubs {
-            download.subtitle.clone()
-        } else {
-            None
-        },
-    )
-    .await?
-    else {
-        if single_format.is_episode() {
-            bail!(
-                "Resolution ({}) is not available for episode {} ({}) of {} season {}",
-                download.resolution,
-                single_format.episode_number,
-                single_format.title,
-                single_format.series_name,
-                single_format.season_number,
-            )
-        } else {
-            bail!(
-                "Resolution ({}) is not available for {} ({})",
-                download.resolution,
-                single_format.source_type(),
-                single_format.title
-            )
+    // the first entry is the primary audio (matching the first `--audio` locale which was
+    // actually resolved for this episode); its video/subtitle streams are the ones muxed into the
+    // output, every other entry only contributes an additional audio track
+    let mut format_pairs = vec![];
+    let mut used_hardsub_locale = None;
+
+    for single_format in single_formats {
+        let stream = single_format.stream().await?;
+
+        // try every candidate in order, falling through the list until one of them turns out to
+        // actually be a burned-in variant of this stream; only the primary audio's video stream
+        // carries the hardsub, so secondary audio tracks never attempt this
+        let mut peer_hardsub = None;
+        if format_pairs.is_empty() {
+            for candidate in hardsub_candidates {
+                if let Some(data @ (_, _, true)) =
+                    stream_data_from_stream(
+                        &stream,
+                        &download.resolution,
+                        download.resolution_strategy,
+                        Some(candidate.clone()),
+                        download.stream_protocol,
+                    )
+                        .await?
+                {
+                    used_hardsub_locale = Some(candidate.clone());
+                    peer_hardsub = Some(data);
+                    break;
+                }
+            }
         }
-    };
 
-    let subtitle = if contains_hardsub {
-        None
-    } else if let Some(subtitle_locale) = &download.subtitle {
-        stream
-            .subtitles
-            .get(subtitle_locale)
-            .cloned()
-            // use closed captions as fallback if no actual subtitles are found
-            .or_else(|| stream.captions.get(subtitle_locale).cloned())
-    } else {
-        None
-    };
+        let Some((video, audio, contains_hardsub)) = (match peer_hardsub {
+            Some(data) => Some(data),
+            None => {
+                stream_data_from_stream(
+                    &stream,
+                    &download.resolution,
+                    download.resolution_strategy,
+                    None,
+                    download.stream_protocol,
+                )
+                .await?
+            }
+        }) else {
+            if format_pairs.is_empty() {
+                // the primary audio must resolve, everything else can just be skipped
+                if single_format.is_episode() {
+                    bail!(
+                        "Resolution ({}) is not available for episode {} ({}) of {} season {}",
+                        format_resolution_preferences(&download.resolution),
+                        single_format.episode_number,
+                        single_format.title,
+                        single_format.series_name,
+                        single_format.season_number,
+                    )
+                } else {
+                    bail!(
+                        "Resolution ({}) is not available for {} ({})",
+                        format_resolution_preferences(&download.resolution),
+                        single_format.source_type(),
+                        single_format.title
+                    )
+                }
+            }
+            warn!(
+                "Resolution ({}) is not available for {} audio of episode {} ({}), skipping this audio track",
+                format_resolution_preferences(&download.resolution), single_format.audio, single_format.episode_number, single_format.title
+            );
+            continue;
+        };
+
+        // subtitles are a property of the output file, not of an individual audio track, so they
+        // only need to be resolved once, against the primary audio's stream
+        let subtitles = if contains_hardsub || !format_pairs.is_empty() {
+            vec![]
+        } else {
+            let requested_subtitles = if download
+                .subtitle
+                .iter()
+                .any(|l| l.to_string().eq_ignore_ascii_case("all"))
+            {
+                let mut all_subtitle_locales: Vec<Locale> = stream
+                    .subtitles
+                    .keys()
+                    .chain(stream.captions.keys())
+                    .cloned()
+                    .collect();
+                real_dedup_vec(&mut all_subtitle_locales);
+                all_subtitle_locales
+            } else {
+                download.subtitle.clone()
+            };
+
+            let missing_subtitles: Vec<&Locale> = requested_subtitles
+                .iter()
+                .filter(|l| !stream.subtitles.contains_key(l) && !stream.captions.contains_key(l))
+                .collect();
+            if !missing_subtitles.is_empty() {
+                warn!(
+                    "Episode {} ({}) is not available with {} subtitles",
+                    single_format.episode_number,
+                    single_format.title,
+                    missing_subtitles
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+
+            requested_subtitles
+                .iter()
+                .filter_map(|l| {
+                    // regular subtitles are preferred over closed captions of the same locale,
+                    // unless '--prefer-sdh' asks for the opposite
+                    if download.prefer_sdh {
+                        if let Some(s) = stream.captions.get(l) {
+                            return Some((s.clone(), SubtitleKind::ClosedCaption));
+                        }
+                    }
+                    if let Some(s) = stream.subtitles.get(l) {
+                        Some((s.clone(), SubtitleKind::Regular))
+                    } else {
+                        stream
+                            .captions
+                            .get(l)
+                            .cloned()
+                            .map(|s| (s, SubtitleKind::ClosedCaption))
+                    }
+                })
+                .collect()
+        };
+
+        format_pairs.push((single_format, video, audio, subtitles, contains_hardsub));
+    }
+
+    let (primary_single_format, primary_video, _, primary_subtitles, contains_hardsub) =
+        format_pairs.first().unwrap();
 
     let download_format = DownloadFormat {
-        video: (video.clone(), single_format.audio.clone()),
-        audios: vec![(audio, single_format.audio.clone())],
-        subtitles: subtitle.clone().map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio == Locale::ja_JP || stream.subtitles.len() > 1,
-            )]
-        }),
+        video: (primary_video.clone(), primary_single_format.audio.clone()),
+        audios: format_pairs
+            .iter()
+            .map(|(single_format, _, audio, _, _)| (audio.clone(), single_format.audio.clone()))
+            .collect(),
+        subtitles: primary_subtitles.clone(),
         metadata: DownloadFormatMetadata {
             skip_events: if download.include_chapters {
-                single_format.skip_events().await?
+                primary_single_format.skip_events().await?
             } else {
                 None
             },
         },
     };
-    let mut format = Format::from_single_formats(vec![(
-        single_format.clone(),
-        video,
-        subtitle.map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio == Locale::ja_JP || stream.subtitles.len() > 1,
-            )]
-        }),
-    )]);
+
+    let contains_hardsub = *contains_hardsub;
+    let mut format = Format::from_single_formats(
+        format_pairs
+            .into_iter()
+            .map(|(single_format, video, _, subtitles, _)| {
+                (single_format.clone(), video, subtitles)
+            })
+            .collect(),
+    );
     if contains_hardsub {
         let (_, subs) = format.locales.get_mut(0).unwrap();
-        subs.push(download.subtitle.clone().unwrap())
+        subs.push(used_hardsub_locale.clone().unwrap());
+        format.hardsub = used_hardsub_locale;
     }
 
     Ok((download_format, format))
 }
+
+/// Reports (or, if `remove` is set, deletes) files under the output directory implied by
+/// `template` which aren't among the expected final paths of `collection`. See
+/// [`crate::utils::gc::find_orphaned_files`].
+fn gc_orphaned_files(
+    template: &str,
+    collection: &SingleFormatCollection,
+    remove: bool,
+) -> Result<Vec<PathBuf>> {
+    let orphaned = find_orphaned_files(template, collection)?;
+    if remove {
+        for path in &orphaned {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(orphaned)
+}
+
+/// Reads a `--archive` file into the set of already-downloaded `episode_id`s. Each line is
+/// `episode_id` optionally followed by additional tab-separated informational fields (audio
+/// locale, resolution) which [`append_archive`] writes but which aren't needed to check whether an
+/// episode was already downloaded. A missing file is treated as an empty archive since the first
+/// run of a fresh `--archive` path has nothing to skip yet.
+fn read_archive(path: &Path) -> Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string())
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Appends `format`'s episode to a `--archive` file so it's skipped on a later run. Called only
+/// after [`crate::utils::download::Downloader::download`] returns successfully, so an aborted
+/// download is retried instead of silently skipped next time.
+fn append_archive(path: &Path, format: &Format) -> Result<()> {
+    let audio_locales = format
+        .locales
+        .iter()
+        .map(|(audio, _)| audio.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(
+        file,
+        "{}\t{}\t{}",
+        format.episode_id, audio_locales, format.resolution
+    )?;
+
+    Ok(())
+}
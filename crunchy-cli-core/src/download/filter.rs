@@ -1,82 +1,262 @@
 use crate::download::Download;
-use crate::utils::filter::Filter;
+use crate::utils::download::DownloadBuilder;
+use crate::utils::filter::{real_dedup_vec, Filter};
+use crate::utils::fingerprint::AudioFingerprint;
 use crate::utils::format::{Format, SingleFormat, SingleFormatCollection};
-use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
+use crate::utils::interactive_select::{
+    check_for_duplicated_seasons, get_duplicated_seasons, resolve_duplicated_seasons,
+};
+use crate::utils::locale::{
+    has_all_locale, has_original_locale, locale_from_season_slug, original_locale_of,
+};
+use crate::utils::media_cache::MediaCache;
 use crate::utils::parse::{fract, UrlFilter};
+use crate::utils::rate_limit::RateLimiterService;
 use anyhow::{bail, Result};
-use crunchyroll_rs::{Concert, Episode, Movie, MovieListing, MusicVideo, Season, Series};
-use log::{error, info, warn};
-use std::collections::HashMap;
+use chrono::TimeDelta;
+use crunchyroll_rs::{Concert, Episode, Locale, Movie, MovieListing, MusicVideo, Season, Series};
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use std::collections::{BTreeMap, HashMap};
 
 pub(crate) struct DownloadFilter {
     url_filter: UrlFilter,
     download: Download,
     interactive_input: bool,
+    experimental_fixes: bool,
+    /// On-disk cache for the `seasons()`/`episodes()` lookups below, see `--cache-ttl`/`--no-cache`/
+    /// `--refresh-cache`.
+    media_cache: MediaCache,
+    /// Used to build a throwaway [`crate::utils::download::Downloader`] for `--verify-duplicates`'
+    /// audio samples; not otherwise needed by the filter stage.
+    client: Client,
+    rate_limiter: Option<RateLimiterService>,
     season_episodes: HashMap<u32, Vec<Episode>>,
+    /// Each visited season's `number_of_episodes`, needed to resolve the `E-N`/`latest:N` relative
+    /// selectors in `self.url_filter` against, since that information isn't available once we're
+    /// down to looking at one `Episode` on its own in `visit_episode`.
+    season_episode_counts: HashMap<u32, u32>,
     season_subtitles_missing: Vec<u32>,
     season_visited: bool,
 }
 
 impl DownloadFilter {
-    pub(crate) fn new(url_filter: UrlFilter, download: Download, interactive_input: bool) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        url_filter: UrlFilter,
+        download: Download,
+        interactive_input: bool,
+        experimental_fixes: bool,
+        client: Client,
+        rate_limiter: Option<RateLimiterService>,
+        cache_ttl: TimeDelta,
+        refresh_cache: bool,
+    ) -> Self {
+        let media_cache = MediaCache::new(cache_ttl, refresh_cache, download.offline);
         Self {
             url_filter,
             download,
             interactive_input,
+            experimental_fixes,
+            media_cache,
+            client,
+            rate_limiter,
             season_episodes: HashMap::new(),
+            season_episode_counts: HashMap::new(),
             season_subtitles_missing: vec![],
             season_visited: false,
         }
     }
+
+    /// Same as `ArchiveFilter::verify_duplicate_fingerprints`, for `--verify-duplicates` on the
+    /// `download` command.
+    async fn verify_duplicate_fingerprints(
+        &self,
+        seasons: &[Season],
+        duplicated_season_numbers: &[u32],
+    ) -> BTreeMap<String, AudioFingerprint> {
+        let downloader = DownloadBuilder::new(self.client.clone(), self.rate_limiter.clone()).build();
+
+        let mut fingerprints = BTreeMap::new();
+        for season in seasons
+            .iter()
+            .filter(|s| duplicated_season_numbers.contains(&s.season_number))
+        {
+            let episode = match self
+                .media_cache
+                .get::<Vec<Episode>>("season-episodes", &season.id)
+            {
+                Some(episodes) => episodes.into_iter().next(),
+                None => match season.episodes().await {
+                    Ok(mut episodes) if !episodes.is_empty() => Some(episodes.remove(0)),
+                    Ok(_) => None,
+                    Err(e) => {
+                        debug!(
+                            "Could not fetch episodes of season {} for duplicate verification: {}",
+                            season.season_number, e
+                        );
+                        None
+                    }
+                },
+            };
+            let Some(episode) = episode else { continue };
+
+            let fingerprint = match self
+                .media_cache
+                .get::<AudioFingerprint>("audio-fingerprint", &episode.id)
+            {
+                Some(fingerprint) => fingerprint,
+                None => match AudioFingerprint::compute_for_episode(&episode, &downloader).await {
+                    Ok(fingerprint) => {
+                        self.media_cache
+                            .set("audio-fingerprint", &episode.id, &fingerprint);
+                        fingerprint
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Could not verify season {} acoustically, falling back to metadata: {}",
+                            season.season_number, e
+                        );
+                        continue;
+                    }
+                },
+            };
+            fingerprints.insert(season.id.clone(), fingerprint);
+        }
+
+        fingerprints
+    }
 }
 
 #[async_trait::async_trait]
 impl Filter for DownloadFilter {
-    type T = SingleFormat;
+    type T = Vec<SingleFormat>;
     type Output = SingleFormatCollection;
 
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        // the 'original'/'all' keywords can't be checked against a fixed locale list, they're
+        // resolved per season/episode below against their version metadata instead
+        let want_original_audio = has_original_locale(&self.download.audio);
+        let want_all_audio = has_all_locale(&self.download.audio);
+
         // `series.audio_locales` isn't always populated b/c of crunchyrolls api. so check if the
         // audio is matching only if the field is populated
-        if !series.audio_locales.is_empty() {
-            if !series.audio_locales.contains(&self.download.audio) {
-                error!(
-                    "Series {} is not available with {} audio",
-                    series.title, self.download.audio
-                );
-                return Ok(vec![]);
-            }
+        if !series.audio_locales.is_empty()
+            && !want_original_audio
+            && !want_all_audio
+            && !series
+                .audio_locales
+                .iter()
+                .any(|l| self.download.audio.contains(l))
+        {
+            error!(
+                "Series {} is not available with {} audio",
+                series.title,
+                self.download
+                    .audio
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+            return Ok(vec![]);
         }
 
+        let cached_seasons = match self.media_cache.get_or_offline_err::<Vec<Season>>(
+            "series-seasons",
+            &series.id,
+            "seasons",
+        )? {
+            Some(seasons) => seasons,
+            None => {
+                let seasons = series.seasons().await?;
+                self.media_cache
+                    .set("series-seasons", &series.id, &seasons);
+                seasons
+            }
+        };
+
         let mut seasons = vec![];
-        for mut season in series.seasons().await? {
+        for mut season in cached_seasons {
             if !self.url_filter.is_season_valid(season.season_number) {
                 continue;
             }
 
-            if !season
+            if want_original_audio {
+                let original_locale = original_locale_of(
+                    &season
+                        .versions
+                        .iter()
+                        .map(|v| (v.audio_locale.clone(), v.original))
+                        .collect::<Vec<(Locale, bool)>>(),
+                    season.audio_locales.first(),
+                );
+                if !season.audio_locales.contains(&original_locale) {
+                    match season.version(vec![original_locale]).await?.pop() {
+                        Some(original_season) => season = original_season,
+                        None => {
+                            error!(
+                                "Season {} - '{}' does not expose an original audio version",
+                                season.season_number, season.title,
+                            );
+                            continue;
+                        }
+                    }
+                }
+            } else if want_all_audio {
+                // keep the season as-is; every version it exposes is resolved per episode in
+                // `visit_episode` instead of narrowing to a single season-level version here
+            } else if !season
                 .audio_locales
                 .iter()
-                .any(|l| l == &self.download.audio)
+                .any(|l| self.download.audio.contains(l))
+                // `audio_locales` is sometimes empty entirely; with `--experimental-fixes`, guess
+                // it from the season's slug title (e.g. `...-german`) before giving up on it,
+                // mirroring the same fallback `ArchiveFilter`/`resolve_duplicated_seasons` already
+                // use for this API gap
+                && !(self.experimental_fixes
+                    && season.audio_locales.is_empty()
+                    && locale_from_season_slug(&season.slug_title)
+                        .is_some_and(|l| self.download.audio.contains(&l)))
             {
-                if season
-                    .available_versions()
-                    .await?
+                let available_versions = match self.media_cache.get_or_offline_err::<Vec<Locale>>(
+                    "season-available-versions",
+                    &season.id,
+                    "available versions",
+                )? {
+                    Some(available_versions) => available_versions,
+                    None => {
+                        let available_versions = season.available_versions().await?;
+                        self.media_cache.set(
+                            "season-available-versions",
+                            &season.id,
+                            &available_versions,
+                        );
+                        available_versions
+                    }
+                };
+                let matching_locales: Vec<Locale> = self
+                    .download
+                    .audio
                     .iter()
-                    .any(|l| l == &self.download.audio)
-                {
-                    season = season
-                        .version(vec![self.download.audio.clone()])
-                        .await?
-                        .remove(0)
-                } else {
+                    .filter(|l| available_versions.contains(l))
+                    .cloned()
+                    .collect();
+                if matching_locales.is_empty() {
                     error!(
                         "Season {} - '{}' is not available with {} audio",
                         season.season_number,
                         season.title,
-                        self.download.audio.clone(),
+                        self.download
+                            .audio
+                            .iter()
+                            .map(|l| l.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", "),
                     );
                     continue;
+                } else {
+                    season = season.version(matching_locales).await?.remove(0)
                 }
             }
 
@@ -89,12 +269,27 @@ impl Filter for DownloadFilter {
                 check_for_duplicated_seasons(&mut seasons);
             } else {
                 info!(
-                    "Found duplicated seasons: {}",
+                    "Found duplicated seasons: {}, keeping the release matching the requested audio",
                     duplicated_seasons
                         .iter()
                         .map(|d| d.to_string())
                         .collect::<Vec<String>>()
                         .join(", ")
+                );
+                let fingerprints = if self.download.verify_duplicates {
+                    Some(
+                        self.verify_duplicate_fingerprints(&seasons, &duplicated_seasons)
+                            .await,
+                    )
+                } else {
+                    None
+                };
+                resolve_duplicated_seasons(
+                    &mut seasons,
+                    &self.download.audio,
+                    self.download.version.as_ref(),
+                    self.experimental_fixes,
+                    fingerprints.as_ref(),
                 )
             }
         }
@@ -105,7 +300,19 @@ impl Filter for DownloadFilter {
     async fn visit_season(&mut self, season: Season) -> Result<Vec<Episode>> {
         self.season_visited = true;
 
-        let mut episodes = season.episodes().await?;
+        let mut episodes = match self.media_cache.get_or_offline_err::<Vec<Episode>>(
+            "season-episodes",
+            &season.id,
+            "episodes",
+        )? {
+            Some(episodes) => episodes,
+            None => {
+                let episodes = season.episodes().await?;
+                self.media_cache
+                    .set("season-episodes", &season.id, &episodes);
+                episodes
+            }
+        };
 
         if Format::has_relative_fmt(&self.download.output) {
             for episode in episodes.iter() {
@@ -116,77 +323,191 @@ impl Filter for DownloadFilter {
             }
         }
 
+        self.season_episode_counts
+            .insert(season.season_number, season.number_of_episodes);
+
         episodes.retain(|e| {
-            self.url_filter
-                .is_episode_valid(e.episode_number, season.season_number)
+            self.url_filter.is_episode_valid_with_count(
+                e.episode_number,
+                season.season_number,
+                Some(season.number_of_episodes),
+            )
         });
 
         Ok(episodes)
     }
 
     async fn visit_episode(&mut self, mut episode: Episode) -> Result<Option<Self::T>> {
-        if !self
-            .url_filter
-            .is_episode_valid(episode.episode_number, episode.season_number)
-        {
+        if !self.url_filter.is_episode_valid_with_count(
+            episode.episode_number,
+            episode.season_number,
+            self.season_episode_counts.get(&episode.season_number).copied(),
+        ) {
             return Ok(None);
         }
 
-        // check if the audio locale is correct.
-        // should only be incorrect if the console input was a episode url. otherwise
-        // `DownloadFilter::visit_season` returns the correct episodes with matching audio
-        if episode.audio_locale != self.download.audio {
-            // check if any other version (same episode, other language) of this episode is available
-            // with the requested audio. if not, return an error
-            if !episode
-                .available_versions()
-                .await?
-                .contains(&self.download.audio)
-            {
-                let error_message = format!(
-                    "Episode {} ({}) of {} season {} is not available with {} audio",
-                    episode.episode_number,
-                    episode.title,
-                    episode.series_title,
-                    episode.season_number,
-                    self.download.audio
+        // the locale this episode's version metadata actually marks as original, used below to
+        // replace the 'original' keyword (if requested) and to flag the resolved tracks for
+        // `SingleFormat::is_original`
+        let original_locale = original_locale_of(
+            &episode
+                .versions
+                .iter()
+                .map(|v| (v.audio_locale.clone(), v.original))
+                .collect::<Vec<(Locale, bool)>>(),
+            Some(&episode.audio_locale),
+        );
+
+        // resolve every requested audio locale for this episode. unlike the series/season checks
+        // above, a single unavailable locale doesn't abort the episode, it's just skipped, as long
+        // as at least one of the requested locales can be resolved
+        let available_versions = match self.media_cache.get_or_offline_err::<Vec<Locale>>(
+            "episode-available-versions",
+            &episode.id,
+            "available versions",
+        )? {
+            Some(available_versions) => available_versions,
+            None => {
+                let available_versions = episode.available_versions().await?;
+                self.media_cache.set(
+                    "episode-available-versions",
+                    &episode.id,
+                    &available_versions,
                 );
-                // sometimes a series randomly has episode in an other language. if this is the case,
-                // only error if the input url was a episode url
-                if self.season_visited {
-                    warn!("{}", error_message);
-                    return Ok(None);
-                } else {
-                    bail!("{}", error_message)
-                }
+                available_versions
             }
-            // overwrite the current episode with the other version episode
-            episode = episode
-                .version(vec![self.download.audio.clone()])
-                .await?
-                .remove(0)
+        };
+
+        // replace the 'original'/'all' keywords, if requested, with the actual locales they stand
+        // for before resolving the list like any other requested audio
+        let resolved_audio: Vec<Locale> = if has_all_locale(&self.download.audio) {
+            let mut all_locales: Vec<Locale> = std::iter::once(episode.audio_locale.clone())
+                .chain(available_versions.iter().cloned())
+                .collect();
+            real_dedup_vec(&mut all_locales);
+            all_locales
+        } else if has_original_locale(&self.download.audio) {
+            self.download
+                .audio
+                .iter()
+                .map(|l| {
+                    if l.to_string().eq_ignore_ascii_case("original") {
+                        original_locale.clone()
+                    } else {
+                        l.clone()
+                    }
+                })
+                .collect()
+        } else {
+            self.download.audio.clone()
+        };
+
+        let mut requested_locales: Vec<Locale> = resolved_audio
+            .iter()
+            .filter(|l| &episode.audio_locale == *l || available_versions.contains(l))
+            .cloned()
+            .collect();
+
+        let missing_locales: Vec<&Locale> = resolved_audio
+            .iter()
+            .filter(|l| &episode.audio_locale != *l && !available_versions.contains(l))
+            .collect();
+        if !missing_locales.is_empty() {
+            warn!(
+                "Episode {} ({}) of {} season {} is not available with {} audio",
+                episode.episode_number,
+                episode.title,
+                episode.series_title,
+                episode.season_number,
+                missing_locales
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
         }
 
-        // check if the subtitles are supported
-        if let Some(subtitle_locale) = &self.download.subtitle {
-            if !episode.subtitle_locales.contains(subtitle_locale) {
-                // if the episode doesn't have the requested subtitles, print a error. to print this
-                // error only once per season, it's checked if an error got printed before by looking
-                // up if the season id is present in `self.season_subtitles_missing`. if not, print
-                // the error and add the season id to `self.season_subtitles_missing`. if it is
-                // present, skip the error printing
-                if !self
-                    .season_subtitles_missing
-                    .contains(&episode.season_number)
-                {
-                    self.season_subtitles_missing.push(episode.season_number);
-                    error!(
-                        "{} season {} is not available with {} subtitles",
-                        episode.series_title, episode.season_number, subtitle_locale
-                    );
-                }
-                return Ok(None);
+        if requested_locales.is_empty() {
+            let error_message = format!(
+                "Episode {} ({}) of {} season {} is not available with any of the requested audio",
+                episode.episode_number, episode.title, episode.series_title, episode.season_number,
+            );
+            // sometimes a series randomly has episode in an other language. if this is the case,
+            // only error if the input url was a episode url
+            return if self.season_visited {
+                warn!("{}", error_message);
+                Ok(None)
+            } else {
+                bail!("{}", error_message)
+            };
+        }
+
+        // order the resolved locales like `--audio` was passed so the first entry (used for video
+        // and subtitles) matches the user's preference
+        requested_locales.sort_by_key(|l| {
+            resolved_audio
+                .iter()
+                .position(|p| p == l)
+                .unwrap_or(usize::MAX)
+        });
+
+        let mut episodes = vec![];
+        if requested_locales.first() == Some(&episode.audio_locale) {
+            episodes.push(episode.clone())
+        }
+        let other_locales: Vec<Locale> = requested_locales
+            .into_iter()
+            .filter(|l| l != &episode.audio_locale)
+            .collect();
+        if !other_locales.is_empty() {
+            episode = episode.clone();
+            episodes.extend(episode.version(other_locales).await?)
+        }
+        episodes.sort_by_key(|e| {
+            resolved_audio
+                .iter()
+                .position(|l| l == &e.audio_locale)
+                .unwrap_or(usize::MAX)
+        });
+
+        // check if at least one requested subtitle locale is supported by the primary (first
+        // requested) locale. the 'all' keyword is resolved against the stream itself later on, so
+        // it's always considered supported here
+        if !self.download.subtitle.is_empty()
+            && !self
+                .download
+                .subtitle
+                .iter()
+                .any(|l| l.to_string().eq_ignore_ascii_case("all"))
+            && !self
+                .download
+                .subtitle
+                .iter()
+                .any(|l| episodes.first().unwrap().subtitle_locales.contains(l))
+        {
+            // if the episode doesn't have any of the requested subtitles, print a error. to print
+            // this error only once per season, it's checked if an error got printed before by
+            // looking up if the season id is present in `self.season_subtitles_missing`. if not,
+            // print the error and add the season id to `self.season_subtitles_missing`. if it is
+            // present, skip the error printing
+            if !self
+                .season_subtitles_missing
+                .contains(&episode.season_number)
+            {
+                self.season_subtitles_missing.push(episode.season_number);
+                error!(
+                    "{} season {} is not available with {} subtitles",
+                    episode.series_title,
+                    episode.season_number,
+                    self.download
+                        .subtitle
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
             }
+            return Ok(None);
         }
 
         let mut relative_episode_number = None;
@@ -197,10 +518,20 @@ impl Filter for DownloadFilter {
             let season_eps = match self.season_episodes.get(&episode.season_number) {
                 Some(eps) => eps,
                 None => {
-                    self.season_episodes.insert(
-                        episode.season_number,
-                        episode.season().await?.episodes().await?,
-                    );
+                    let season = episode.season().await?;
+                    let eps = match self.media_cache.get_or_offline_err::<Vec<Episode>>(
+                        "season-episodes",
+                        &season.id,
+                        "episodes",
+                    )? {
+                        Some(eps) => eps,
+                        None => {
+                            let eps = season.episodes().await?;
+                            self.media_cache.set("season-episodes", &season.id, &eps);
+                            eps
+                        }
+                    };
+                    self.season_episodes.insert(episode.season_number, eps);
                     self.season_episodes.get(&episode.season_number).unwrap()
                 }
             };
@@ -229,41 +560,57 @@ impl Filter for DownloadFilter {
             }
         }
 
-        Ok(Some(SingleFormat::new_from_episode(
-            episode.clone(),
-            self.download.subtitle.clone().map_or(vec![], |s| {
-                if episode.subtitle_locales.contains(&s) {
-                    vec![s]
-                } else {
-                    vec![]
-                }
-            }),
-            relative_episode_number.map(|n| n as u32),
-            relative_sequence_number,
-        )))
+        Ok(Some(
+            episodes
+                .into_iter()
+                .map(|e| {
+                    let is_original = e.audio_locale == original_locale;
+                    SingleFormat::new_from_episode(
+                        e.clone(),
+                        e.subtitle_locales.clone(),
+                        relative_episode_number.map(|n| n as u32),
+                        relative_sequence_number,
+                        is_original,
+                    )
+                })
+                .collect(),
+        ))
     }
 
     async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
-        Ok(movie_listing.movies().await?)
+        // movie listings have no season/episode numbers of their own, so a season/episode filter
+        // treats the whole listing as season 1 and each movie's position within it as the episode
+        // number, letting e.g. `[E2]` pick a single movie out of a listing
+        if !self.url_filter.is_season_valid(1) {
+            return Ok(vec![]);
+        }
+        Ok(movie_listing
+            .movies()
+            .await?
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.url_filter.is_episode_valid((i + 1) as f32, 1))
+            .map(|(_, movie)| movie)
+            .collect())
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Option<Self::T>> {
-        Ok(Some(SingleFormat::new_from_movie(movie, vec![])))
+        Ok(Some(vec![SingleFormat::new_from_movie(movie, vec![])]))
     }
 
     async fn visit_music_video(&mut self, music_video: MusicVideo) -> Result<Option<Self::T>> {
-        Ok(Some(SingleFormat::new_from_music_video(music_video)))
+        Ok(Some(vec![SingleFormat::new_from_music_video(music_video)]))
     }
 
     async fn visit_concert(&mut self, concert: Concert) -> Result<Option<Self::T>> {
-        Ok(Some(SingleFormat::new_from_concert(concert)))
+        Ok(Some(vec![SingleFormat::new_from_concert(concert)]))
     }
 
     async fn finish(self, input: Vec<Self::T>) -> Result<Self::Output> {
         let mut single_format_collection = SingleFormatCollection::new();
 
         for data in input {
-            single_format_collection.add_single_formats(vec![data])
+            single_format_collection.add_single_formats(data)
         }
 
         Ok(single_format_collection)
@@ -1,6 +1,8 @@
+use crate::utils::config::Config;
 use crate::utils::context::Context;
 use crate::utils::locale::system_locale;
-use crate::utils::log::{progress, CliLogger};
+use crate::utils::log::{apply_color_choice, progress, CliLogger, ColorChoice};
+use crate::utils::os::{reap_own_temp, reap_stale_temp};
 use anyhow::bail;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -8,17 +10,30 @@ use crunchyroll_rs::crunchyroll::CrunchyrollBuilder;
 use crunchyroll_rs::error::Error;
 use crunchyroll_rs::{Crunchyroll, Locale};
 use log::{debug, error, warn, LevelFilter};
+use rand::Rng;
 use reqwest::Proxy;
+use std::time::Duration;
 use std::{env, fs};
 
+/// Base delay for the exponential backoff used when retrying after a Cloudflare block or rate
+/// limit error.
+const RETRY_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// Upper bound the backoff delay is capped at, no matter how many attempts were already made.
+const RETRY_BACKOFF_CAP: Duration = Duration::from_secs(10 * 60);
+/// How old a tempfile/cache entry without a live owning process must be before [`reap_stale_temp`]
+/// removes it at startup.
+const STALE_TEMP_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 mod archive;
+mod browse;
 mod download;
 mod login;
 mod search;
 mod utils;
 
 pub use archive::Archive;
-use dialoguer::console::Term;
+pub use browse::Browse;
+use dialoguer::console::{self, Term};
 pub use download::Download;
 pub use login::Login;
 pub use search::Search;
@@ -56,19 +71,87 @@ pub struct Cli {
     #[clap(flatten)]
     login_method: login::LoginMethod,
 
-    #[arg(help = "Use a proxy to route all traffic through")]
-    #[arg(long_help = "Use a proxy to route all traffic through. \
+    #[arg(help = "Use a named account profile for storing/restoring login sessions")]
+    #[arg(long_help = "Use a named account profile for storing/restoring login sessions. \
+    Every command that needs to be logged in (archive, download, search, browse, ...) reads and \
+    writes the session under this profile, so switching accounts (e.g. different regions) is just \
+    a matter of passing a different `--profile` instead of logging in again")]
+    #[arg(long, default_value = login::DEFAULT_PROFILE)]
+    profile: String,
+
+    #[arg(help = "Load default flag values from a TOML config file instead of the default location")]
+    #[arg(long_help = "Load default flag values from a TOML config file instead of the default \
+    location (see the 'config_file_path' used by 'utils::config::Config'). Flags given on the \
+    command line always take precedence over values from the config file")]
+    #[arg(long, global = true, env = "CRUNCHY_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    #[arg(help = "Select the TLS backend to use, if this binary was built with more than one")]
+    #[arg(long_help = "Select the TLS backend to use. Only backends this binary was actually \
+    compiled with (via the 'rustls-tls' / 'native-tls' / 'openssl-tls' cargo features) are \
+    available; requesting one that wasn't compiled in is an error")]
+    #[arg(long, global = true)]
+    tls_backend: Option<TlsBackend>,
+
+    #[arg(help = "Use a proxy (or comma-separated pool of proxies) to route all traffic through")]
+    #[arg(long_help = "Use a proxy to route all traffic through. Multiple proxies can be given, \
+            separated by a comma, and are rotated through on retry (see '--max-retries'). \
             Make sure that the proxy can either forward TLS requests, which is needed to bypass the (cloudflare) bot protection, or that it is configured so that the proxy can bypass the protection itself")]
-    #[clap(long)]
+    #[clap(long, value_delimiter = ',')]
     #[arg(value_parser = crate::utils::clap::clap_parse_proxy)]
-    proxy: Option<Proxy>,
+    proxy: Vec<Proxy>,
+
+    #[arg(help = "Load a newline-separated list of proxies from a file, added to the '--proxy' pool")]
+    #[clap(long)]
+    proxy_file: Option<std::path::PathBuf>,
+
+    #[arg(help = "Maximum retries on Cloudflare block / rate limit errors before giving up")]
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
 
     #[arg(help = "Use custom user agent")]
     #[clap(long)]
     user_agent: Option<String>,
 
+    #[arg(help = "Control if / when colored output is used")]
+    #[arg(long_help = "Control if / when colored output is used for progress bars and log messages. \
+            'auto' (the default) enables colors if stdout is a terminal, 'always' forces them on and 'never' forces them off. \
+            The conventional 'NO_COLOR' environment variable is also respected as an implicit 'never'")]
+    #[arg(long, value_parser = ColorChoice::parse, default_value = "auto")]
+    color: ColorChoice,
+
     #[clap(subcommand)]
     command: Command,
+
+    // index into the resolved proxy pool of the proxy currently in use, rotated on retry
+    #[arg(skip)]
+    proxy_pool_index: usize,
+}
+
+impl Cli {
+    /// The full proxy pool to rotate through: `--proxy` values followed by the entries of
+    /// `--proxy-file`, in order.
+    fn proxy_pool(&self) -> Result<Vec<Proxy>> {
+        let mut pool = self.proxy.clone();
+        if let Some(path) = &self.proxy_file {
+            for line in fs::read_to_string(path)?.lines() {
+                let line = line.trim();
+                if !line.is_empty() {
+                    pool.push(Proxy::all(line)?)
+                }
+            }
+        }
+        Ok(pool)
+    }
+
+    /// The proxy which should currently be used, based on `proxy_pool_index`.
+    fn current_proxy(&self) -> Result<Option<Proxy>> {
+        let pool = self.proxy_pool()?;
+        if pool.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(pool[self.proxy_pool_index % pool.len()].clone()))
+    }
 }
 
 fn version() -> String {
@@ -83,9 +166,17 @@ fn version() -> String {
     }
 }
 
-#[derive(Debug, Subcommand)]
+#[derive(Clone, Debug, clap::ValueEnum)]
+enum TlsBackend {
+    Rustls,
+    Native,
+    Openssl,
+}
+
+#[derive(Clone, Debug, Subcommand)]
 enum Command {
     Archive(Archive),
+    Browse(Browse),
     Download(Download),
     Login(Login),
     Search(Search),
@@ -105,24 +196,101 @@ struct Verbosity {
     quiet: bool,
 }
 
+/// Injects per-subcommand default flag values from the config file (`--config`/`CRUNCHY_CONFIG`,
+/// falling back to [`Config::config_file_path`]) ahead of the actual command line arguments, so
+/// clap applies them first and lets anything the user typed override them (clap keeps the last
+/// occurrence of a single-value flag). Has to work on the raw argument list rather than already
+/// parsed [`Cli`] fields, since the defaults must be in place *before* parsing happens.
+fn build_args_with_config_defaults() -> Vec<String> {
+    let args: Vec<String> = env::args().collect();
+
+    let config_path = args
+        .iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1))
+        .map(std::path::PathBuf::from)
+        .or_else(|| env::var("CRUNCHY_CONFIG").ok().map(std::path::PathBuf::from));
+
+    let config = match &config_path {
+        Some(path) => fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str::<Config>(&content).ok()),
+        None => Config::load().ok().flatten(),
+    };
+    let Some(config) = config else {
+        return args;
+    };
+
+    let Some(subcommand) = args.iter().skip(1).find(|a| !a.starts_with('-')) else {
+        return args;
+    };
+    let profile = args
+        .iter()
+        .position(|a| a == "--profile")
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str);
+    let defaults = config.defaults_for(profile);
+    let defaults_table = match subcommand.as_str() {
+        "archive" => &defaults.archive,
+        "download" => &defaults.download,
+        "search" => &defaults.search,
+        _ => return args,
+    };
+
+    let mut defaults_args = vec![];
+    for (key, value) in defaults_table {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            toml::Value::Boolean(true) => defaults_args.push(flag),
+            toml::Value::Boolean(false) => (),
+            toml::Value::Array(values) => {
+                for value in values {
+                    defaults_args.push(flag.clone());
+                    defaults_args.push(toml_value_to_arg(value));
+                }
+            }
+            value => {
+                defaults_args.push(flag);
+                defaults_args.push(toml_value_to_arg(value));
+            }
+        }
+    }
+
+    let mut result = vec![args[0].clone()];
+    result.append(&mut defaults_args);
+    result.extend(args.into_iter().skip(1));
+    result
+}
+
+fn toml_value_to_arg(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
 pub async fn cli_entrypoint() {
-    let mut cli: Cli = Cli::parse();
+    let mut cli: Cli = Cli::parse_from(build_args_with_config_defaults());
+
+    apply_color_choice(&cli.color);
 
     if let Some(verbosity) = &cli.verbosity {
         if verbosity.verbose as u8 + verbosity.quiet as u8 > 1 {
             eprintln!("Output cannot be verbose ('-v') and quiet ('-q') at the same time");
             std::process::exit(1)
         } else if verbosity.verbose {
-            CliLogger::init(LevelFilter::Debug).unwrap()
+            CliLogger::init(false, LevelFilter::Debug).unwrap()
         } else if verbosity.quiet {
-            CliLogger::init(LevelFilter::Error).unwrap()
+            CliLogger::init(false, LevelFilter::Error).unwrap()
         }
     } else {
-        CliLogger::init(LevelFilter::Info).unwrap()
+        CliLogger::init(false, LevelFilter::Info).unwrap()
     }
 
     debug!("cli input: {:?}", cli);
 
+    reap_stale_temp(STALE_TEMP_MAX_AGE);
+
     match &mut cli.command {
         Command::Archive(archive) => {
             // prevent interactive select to be shown when output should be quiet
@@ -139,52 +307,29 @@ pub async fn cli_entrypoint() {
             pre_check_executor(download).await
         }
         Command::Login(login) => {
-            if login.remove {
-                if let Some(session_file) = login::session_file_path() {
+            if login.list {
+                for profile in login::list_profiles().unwrap_or_default() {
+                    println!("{}", profile)
+                }
+                return;
+            } else if login.remove {
+                if let Some(session_file) = login::session_file_path(&cli.profile) {
                     let _ = fs::remove_file(session_file);
                 }
+                let _ = login::remove_keyring_session(&cli.profile);
                 return;
             } else {
+                login.profile = cli.profile.clone();
                 pre_check_executor(login).await
             }
         }
+        Command::Browse(browse) => pre_check_executor(browse).await,
         Command::Search(search) => pre_check_executor(search).await,
     };
 
-    let ctx = match create_ctx(&mut cli).await {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            error!("{}", e);
-            std::process::exit(1)
-        }
-    };
-    debug!("Created context");
-
     ctrlc::set_handler(move || {
         debug!("Ctrl-c detected");
-        if let Ok(dir) = fs::read_dir(&env::temp_dir()) {
-            for file in dir.flatten() {
-                if file
-                    .path()
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_str()
-                    .unwrap_or_default()
-                    .starts_with(".crunchy-cli_")
-                {
-                    let result = fs::remove_file(file.path());
-                    debug!(
-                        "Ctrl-c removed temporary file {} {}",
-                        file.path().to_string_lossy(),
-                        if result.is_ok() {
-                            "successfully"
-                        } else {
-                            "not successfully"
-                        }
-                    )
-                }
-            }
-        }
+        reap_own_temp();
         // when pressing ctrl-c while interactively choosing seasons the cursor stays hidden, this
         // line shows it again
         let _ = Term::stdout().show_cursor();
@@ -193,11 +338,13 @@ pub async fn cli_entrypoint() {
     .unwrap();
     debug!("Created ctrl-c handler");
 
-    match cli.command {
-        Command::Archive(archive) => execute_executor(archive, ctx).await,
-        Command::Download(download) => execute_executor(download, ctx).await,
-        Command::Login(login) => execute_executor(login, ctx).await,
-        Command::Search(search) => execute_executor(search, ctx).await,
+    let command = cli.command.clone();
+    match command {
+        Command::Archive(archive) => execute_executor(archive, &mut cli).await,
+        Command::Browse(browse) => execute_executor(browse, &mut cli).await,
+        Command::Download(download) => execute_executor(download, &mut cli).await,
+        Command::Login(login) => execute_executor(login, &mut cli).await,
+        Command::Search(search) => execute_executor(search, &mut cli).await,
     };
 }
 
@@ -208,27 +355,77 @@ async fn pre_check_executor(executor: &mut impl Execute) {
     }
 }
 
-async fn execute_executor(executor: impl Execute, ctx: Context) {
-    if let Err(mut err) = executor.execute(ctx).await {
-        if let Some(crunchy_error) = err.downcast_mut::<Error>() {
-            if let Error::Block { message, .. } = crunchy_error {
-                *message = "Triggered Cloudflare bot protection. Try again later or use a VPN or proxy to spoof your location".to_string()
-            } else if let Error::Request { message, .. } = crunchy_error {
-                *message = "You've probably hit a rate limit. Try again later, generally after 10-20 minutes the rate limit is over and you can continue to use the cli".to_string()
+/// Whether the given error is worth retrying (Cloudflare block or rate limit), and if so,
+/// rewrites it to the same user-facing message `execute_executor` has always shown.
+fn retryable_crunchy_error(err: &mut anyhow::Error) -> bool {
+    let Some(crunchy_error) = err.downcast_mut::<Error>() else {
+        return false;
+    };
+
+    if let Error::Block { message, .. } = crunchy_error {
+        *message = "Triggered Cloudflare bot protection. Try again later or use a VPN or proxy to spoof your location".to_string();
+        true
+    } else if let Error::Request { message, .. } = crunchy_error {
+        *message = "You've probably hit a rate limit. Try again later, generally after 10-20 minutes the rate limit is over and you can continue to use the cli".to_string();
+        true
+    } else {
+        false
+    }
+}
+
+/// Full jitter exponential backoff: `min(cap, base * 2^attempt)`, scaled by a random factor in
+/// `[0.5, 1.0]` so retrying clients don't all wake up at the same time.
+fn backoff_delay(attempt: u32) -> Duration {
+    let exp = RETRY_BACKOFF_BASE
+        .saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+        .min(RETRY_BACKOFF_CAP);
+    exp.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+async fn execute_executor(executor: impl Execute + Clone, cli: &mut Cli) {
+    let mut attempt = 0;
+    loop {
+        let ctx = match create_ctx(cli).await {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1)
             }
+        };
+        debug!("Created context (attempt {})", attempt + 1);
+
+        match executor.clone().execute(ctx).await {
+            Ok(()) => return,
+            Err(mut err) => {
+                if retryable_crunchy_error(&mut err) && attempt < cli.max_retries {
+                    attempt += 1;
+                    cli.proxy_pool_index = cli.proxy_pool_index.wrapping_add(1);
+                    let delay = backoff_delay(attempt);
+                    debug!(
+                        "Retrying in {:?} (attempt {}/{}), rotated to proxy {:?}",
+                        delay,
+                        attempt,
+                        cli.max_retries,
+                        cli.current_proxy()
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
 
-            error!("An error occurred: {}", crunchy_error)
-        } else {
-            error!("An error occurred: {}", err)
+                error!("An error occurred: {}", err);
+                std::process::exit(1)
+            }
         }
-
-        std::process::exit(1)
     }
 }
 
 async fn create_ctx(cli: &mut Cli) -> Result<Context> {
     let crunchy = crunchyroll_session(cli).await?;
-    Ok(Context { crunchy })
+    Ok(Context {
+        crunchy,
+        color: console::colors_enabled(),
+        experimental_fixes: cli.experimental_fixes,
+    })
 }
 
 async fn crunchyroll_session(cli: &mut Cli) -> Result<Crunchyroll> {
@@ -269,15 +466,23 @@ async fn crunchyroll_session(cli: &mut Cli) -> Result<Crunchyroll> {
         .locale(locale)
         .client({
             let mut builder = CrunchyrollBuilder::predefined_client_builder();
-            if let Some(p) = &cli.proxy {
-                builder = builder.proxy(p.clone())
+            if let Some(p) = cli.current_proxy()? {
+                builder = builder.proxy(p)
             }
             if let Some(ua) = &cli.user_agent {
                 builder = builder.user_agent(ua)
             }
 
+            // default to whichever backend the compile-time '*-tls' features selected, but let
+            // '--tls-backend' override it at runtime if this binary was built with more than one
+            let use_openssl = match &cli.tls_backend {
+                Some(TlsBackend::Openssl) => true,
+                Some(_) => false,
+                None => cfg!(any(feature = "openssl-tls", feature = "openssl-tls-static")),
+            };
+
             #[cfg(any(feature = "openssl-tls", feature = "openssl-tls-static"))]
-            let client = {
+            let client = if use_openssl {
                 let mut builder = builder.use_native_tls().tls_built_in_root_certs(false);
 
                 for certificate in rustls_native_certs::load_native_certs().unwrap() {
@@ -287,31 +492,63 @@ async fn crunchyroll_session(cli: &mut Cli) -> Result<Crunchyroll> {
                 }
 
                 builder.build().unwrap()
+            } else {
+                match cli.tls_backend {
+                    Some(TlsBackend::Native) => builder.use_native_tls(),
+                    Some(TlsBackend::Rustls) | None => builder,
+                    Some(TlsBackend::Openssl) => unreachable!(),
+                }
+                .build()
+                .unwrap()
             };
             #[cfg(not(any(feature = "openssl-tls", feature = "openssl-tls-static")))]
-            let client = builder.build().unwrap();
+            let client = {
+                if use_openssl {
+                    bail!(
+                        "This binary wasn't built with the 'openssl-tls' feature, so '--tls-backend openssl' isn't available"
+                    )
+                }
+                match cli.tls_backend {
+                    Some(TlsBackend::Native) => builder.use_native_tls(),
+                    Some(TlsBackend::Rustls) | None => builder,
+                    Some(TlsBackend::Openssl) => unreachable!(),
+                }
+                .build()
+                .unwrap()
+            };
 
             client
         })
         .stabilization_locales(cli.experimental_fixes)
         .stabilization_season_number(cli.experimental_fixes);
     if let Command::Download(download) = &cli.command {
-        builder = builder.preferred_audio_locale(download.audio.clone())
+        builder = builder.preferred_audio_locale(download.audio.first().unwrap().clone())
     }
 
-    let root_login_methods_count = cli.login_method.credentials.is_some() as u8
+    let root_credentials = login::resolve_credentials(
+        cli.login_method.credentials.as_deref(),
+        cli.login_method.save_credentials,
+        &cli.profile,
+    )?;
+    let root_login_methods_count = root_credentials.is_some() as u8
         + cli.login_method.etp_rt.is_some() as u8
         + cli.login_method.anonymous as u8;
     let mut login_login_methods_count = 0;
+    let mut login_credentials = None;
     if let Command::Login(login) = &cli.command {
-        login_login_methods_count += login.login_method.credentials.is_some() as u8
+        login_credentials = login::resolve_credentials(
+            login.login_method.credentials.as_deref(),
+            login.login_method.save_credentials,
+            &cli.profile,
+        )?;
+        login_login_methods_count += login_credentials.is_some() as u8
             + login.login_method.etp_rt.is_some() as u8
             + login.login_method.anonymous as u8
     }
 
     let progress_handler = progress!("Logging in");
     if root_login_methods_count + login_login_methods_count == 0 {
-        if let Some(login_file_path) = login::session_file_path() {
+        if let Some(login_file_path) = login::session_file_path(&cli.profile) {
             if login_file_path.exists() {
                 let session = fs::read_to_string(login_file_path)?;
                 if let Some((token_type, token)) = session.split_once(':') {
@@ -320,6 +557,14 @@ async fn crunchyroll_session(cli: &mut Cli) -> Result<Crunchyroll> {
                             return Ok(builder.login_with_refresh_token(token).await?)
                         }
                         "etp_rt" => return Ok(builder.login_with_etp_rt(token).await?),
+                        "encrypted_refresh_token" => {
+                            let refresh_token = login::decrypt_stored_session(token)?;
+                            return Ok(builder.login_with_refresh_token(&refresh_token).await?);
+                        }
+                        "keyring" => {
+                            let refresh_token = login::load_keyring_session(&cli.profile)?;
+                            return Ok(builder.login_with_refresh_token(&refresh_token).await?);
+                        }
                         _ => (),
                     }
                 }
@@ -331,17 +576,17 @@ async fn crunchyroll_session(cli: &mut Cli) -> Result<Crunchyroll> {
         bail!("Please use only one login method ('--credentials', '--etp-rt' or '--anonymous')")
     }
 
-    let login_method = if login_login_methods_count > 0 {
+    let (login_method, resolved_credentials) = if login_login_methods_count > 0 {
         if let Command::Login(login) = &cli.command {
-            login.login_method.clone()
+            (login.login_method.clone(), login_credentials)
         } else {
             unreachable!()
         }
     } else {
-        cli.login_method.clone()
+        (cli.login_method.clone(), root_credentials)
     };
 
-    let crunchy = if let Some(credentials) = &login_method.credentials {
+    let crunchy = if let Some(credentials) = &resolved_credentials {
         if let Some((user, password)) = credentials.split_once(':') {
             builder.login_with_credentials(user, password).await?
         } else {
@@ -2,34 +2,89 @@ use crate::utils::context::Context;
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305};
 use clap::Parser;
 use crunchyroll_rs::crunchyroll::SessionToken;
+use dialoguer::Password;
 use log::info;
-use std::fs;
+use std::io::BufRead;
 use std::path::PathBuf;
+use std::{env, fs, io};
 
-#[derive(Debug, clap::Parser)]
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Environment variable [`resolve_credentials`] falls back to when `--credentials` isn't given on
+/// the command line at all, so credentials never have to show up in argv (and thus `ps` or shell
+/// history).
+const CREDENTIALS_ENV_VAR: &str = "CRUNCHY_CREDENTIALS";
+
+/// Version byte prefixed to every encrypted session payload, so a future change to the salt/nonce
+/// sizes or AEAD can be told apart from this one instead of silently misreading old files.
+const ENCRYPTED_SESSION_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Save your login credentials persistent on disk")]
 pub struct Login {
     #[arg(help = "Remove your stored credentials (instead of saving them)")]
     #[arg(long)]
     pub remove: bool,
+
+    #[arg(help = "List all stored account profiles")]
+    #[arg(long)]
+    pub list: bool,
+
+    #[arg(help = "Encrypt the stored session with a passphrase instead of saving it as plaintext")]
+    #[arg(long_help = "Encrypt the stored session with a passphrase instead of saving it as plaintext. \
+    You'll be prompted for the passphrase now and again every time the session is loaded. \
+    Existing plaintext sessions (from before this option existed, or saved without it) keep working as before")]
+    #[arg(long, default_value_t = false)]
+    pub encrypt: bool,
+
+    #[arg(help = "Store the session in the OS keyring instead of the session file")]
+    #[arg(long_help = "Store the refresh token in the platform secret store (Secret Service / macOS Keychain / \
+    Windows Credential Manager) instead of writing it to the session file, even encrypted. The session file \
+    still gets a 'keyring' marker so '--list' and a plain login keep finding this profile. \
+    Conflicts with '--encrypt'")]
+    #[arg(long, default_value_t = false, conflicts_with = "encrypt")]
+    pub keyring: bool,
+
+    // populated from the global `--profile` flag before execution
+    #[arg(skip)]
+    pub profile: String,
 }
 
 impl Execute for Login {
     async fn execute(self, ctx: Context) -> Result<()> {
-        if let Some(login_file_path) = session_file_path() {
+        if let Some(login_file_path) = session_file_path(&self.profile) {
             fs::create_dir_all(login_file_path.parent().unwrap())?;
 
-            match ctx.crunchy.session_token().await {
-                SessionToken::RefreshToken(refresh_token) => {
-                    fs::write(login_file_path, format!("refresh_token:{}", refresh_token))?
-                }
+            let refresh_token = match ctx.crunchy.session_token().await {
+                SessionToken::RefreshToken(refresh_token) => refresh_token,
                 SessionToken::EtpRt(_) => bail!("Login with etp_rt isn't supported anymore. Please use your credentials to login"),
                 SessionToken::Anonymous => bail!("Anonymous login cannot be saved"),
+            };
+
+            if self.encrypt {
+                let passphrase = Password::new()
+                    .with_prompt("Passphrase to encrypt the session with")
+                    .with_confirmation("Confirm passphrase", "Passphrases don't match")
+                    .interact()?;
+                let sealed = encrypt_session(&refresh_token, &passphrase)?;
+                fs::write(login_file_path, format!("encrypted_refresh_token:{}", sealed))?
+            } else if self.keyring {
+                session_keyring_entry(&self.profile)?
+                    .set_password(&refresh_token)
+                    .map_err(|e| anyhow::anyhow!("Failed to save session to the OS keyring: {}", e))?;
+                fs::write(login_file_path, "keyring:")?
+            } else {
+                fs::write(login_file_path, format!("refresh_token:{}", refresh_token))?
             }
 
-            info!("Saved login");
+            info!("Saved login ('{}' profile)", self.profile);
 
             Ok(())
         } else {
@@ -38,18 +93,192 @@ impl Execute for Login {
     }
 }
 
+/// Seals `refresh_token` for at-rest storage: a random salt derives a 32-byte key from
+/// `passphrase` via Argon2id, which then encrypts the token under XChaCha20-Poly1305 with a random
+/// nonce. The returned string is `version byte + salt + nonce + ciphertext`, base64-encoded, so it
+/// can be written directly after the `encrypted_refresh_token:` tag in the session file.
+fn encrypt_session(refresh_token: &str, passphrase: &str) -> Result<String> {
+    let salt: [u8; SALT_LEN] = rand::random();
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive encryption key: {}", e))?;
+
+    let cipher = XChaCha20Poly1305::new((&key).into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, refresh_token.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt session"))?;
+
+    let mut sealed = vec![ENCRYPTED_SESSION_VERSION];
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(base64::encode(sealed))
+}
+
+/// The inverse of [`encrypt_session`]. Fails with a distinct, user-facing error if the passphrase
+/// is wrong or the file was tampered with (the AEAD auth tag won't verify), and with a different
+/// one if the payload doesn't even look like a version-1 encrypted session.
+fn decrypt_session(sealed: &str, passphrase: &str) -> Result<String> {
+    let sealed = base64::decode(sealed)
+        .map_err(|_| anyhow::anyhow!("Stored session is not a valid encrypted session"))?;
+
+    let header_len = 1 + SALT_LEN + NONCE_LEN;
+    if sealed.len() <= header_len || sealed[0] != ENCRYPTED_SESSION_VERSION {
+        bail!("Stored session uses an unsupported encrypted session format")
+    }
+
+    let salt = &sealed[1..1 + SALT_LEN];
+    let nonce = &sealed[1 + SALT_LEN..header_len];
+    let ciphertext = &sealed[header_len..];
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive encryption key: {}", e))?;
+
+    let cipher = XChaCha20Poly1305::new((&key).into());
+    let refresh_token = cipher
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Incorrect passphrase or corrupted session file"))?;
+
+    Ok(String::from_utf8(refresh_token)?)
+}
+
+/// Prompts for the passphrase and decrypts `sealed` (the base64 payload following the
+/// `encrypted_refresh_token:` tag in a session file written with `login --encrypt`).
+pub fn decrypt_stored_session(sealed: &str) -> Result<String> {
+    let passphrase = Password::new()
+        .with_prompt("Passphrase to unlock the stored session")
+        .interact()?;
+    decrypt_session(sealed, &passphrase)
+}
+
+/// Resolves the effective `--credentials` value without ever requiring it on argv, where it would
+/// leak into shell history and be visible to other processes via `ps`. Tries, in order: `-` read as
+/// a single `email:password` line from stdin, the raw value if one other than `-` was given, the
+/// `CRUNCHY_CREDENTIALS` env var, and finally a previously saved OS keyring entry for `profile`. If
+/// `save` is set and something was resolved, it's written back to the keyring for next time.
+pub fn resolve_credentials(raw: Option<&str>, save: bool, profile: &str) -> Result<Option<String>> {
+    let credentials = match raw {
+        Some("-") => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            Some(line.trim_end().to_string())
+        }
+        Some(raw) => Some(raw.to_string()),
+        None => match env::var(CREDENTIALS_ENV_VAR) {
+            Ok(env_credentials) => Some(env_credentials),
+            Err(_) => match keyring_entry(profile)?.get_password() {
+                Ok(credentials) => Some(credentials),
+                Err(keyring::Error::NoEntry) => None,
+                Err(e) => bail!("Failed to read credentials from the OS keyring: {}", e),
+            },
+        },
+    };
+
+    if save {
+        if let Some(credentials) = &credentials {
+            keyring_entry(profile)?
+                .set_password(credentials)
+                .map_err(|e| anyhow::anyhow!("Failed to save credentials to the OS keyring: {}", e))?;
+        }
+    }
+
+    Ok(credentials)
+}
+
+fn keyring_entry(profile: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new("crunchy-cli", profile)
+        .map_err(|e| anyhow::anyhow!("Failed to access the OS keyring: {}", e))
+}
+
+/// Separate keyring service from [`keyring_entry`] so a profile's stored credentials and its
+/// stored session (`login --keyring`) never collide in the same entry.
+fn session_keyring_entry(profile: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new("crunchy-cli-session", profile)
+        .map_err(|e| anyhow::anyhow!("Failed to access the OS keyring: {}", e))
+}
+
+/// Fetches a profile's refresh token back out of the OS keyring. Counterpart to the
+/// `keyring:` marker [`Login::execute`] writes to the session file when run with `--keyring`.
+pub fn load_keyring_session(profile: &str) -> Result<String> {
+    session_keyring_entry(profile)?
+        .get_password()
+        .map_err(|e| anyhow::anyhow!("Failed to read session from the OS keyring: {}", e))
+}
+
+/// Deletes a profile's session from the OS keyring, if it has one there. Called alongside deleting
+/// the session file itself on `login --remove`, so a profile saved with `--keyring` is actually
+/// forgotten instead of leaving its refresh token behind in the platform secret store. Missing
+/// entries (profiles that were never stored in the keyring) are not an error.
+pub fn remove_keyring_session(profile: &str) -> Result<()> {
+    match session_keyring_entry(profile)?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => bail!("Failed to remove session from the OS keyring: {}", e),
+    }
+}
+
 #[derive(Clone, Debug, Parser)]
 pub struct LoginMethod {
     #[arg(
-        help = "Login with credentials (email and password). Must be provided as email:password"
+        help = "Login with credentials (email and password). Must be provided as email:password, \
+        '-' to read it from stdin, or left out entirely to fall back to the CRUNCHY_CREDENTIALS \
+        env var / a previously saved OS keyring entry"
     )]
     #[arg(global = true, long)]
     pub credentials: Option<String>,
+    #[arg(help = "Save the resolved --credentials in the OS keyring for future runs")]
+    #[arg(global = true, long, default_value_t = false)]
+    pub save_credentials: bool,
+    #[arg(
+        help = "Login with a raw 'etp_rt' session cookie, e.g. one copied out of a browser that's \
+        already logged in"
+    )]
+    #[arg(global = true, long)]
+    pub etp_rt: Option<String>,
     #[arg(help = "Login anonymously / without an account")]
     #[arg(global = true, long, default_value_t = false)]
     pub anonymous: bool,
 }
 
-pub fn session_file_path() -> Option<PathBuf> {
-    dirs::config_dir().map(|config_dir| config_dir.join("crunchy-cli").join("session"))
+/// Returns the path the session for the given profile is stored at. The `default` profile keeps
+/// using the original, suffix-less `session` file so existing logins aren't invalidated by
+/// upgrading.
+pub fn session_file_path(profile: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|config_dir| {
+        let file_name = if profile == DEFAULT_PROFILE {
+            "session".to_string()
+        } else {
+            format!("session-{}", profile)
+        };
+        config_dir.join("crunchy-cli").join(file_name)
+    })
+}
+
+/// Lists the names of all profiles which have a stored session, `default` included.
+pub fn list_profiles() -> Result<Vec<String>> {
+    let Some(config_dir) = dirs::config_dir().map(|d| d.join("crunchy-cli")) else {
+        return Ok(vec![]);
+    };
+    if !config_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut profiles = vec![];
+    for entry in fs::read_dir(config_dir)?.flatten() {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name == "session" {
+            profiles.push(DEFAULT_PROFILE.to_string())
+        } else if let Some(profile) = file_name.strip_prefix("session-") {
+            profiles.push(profile.to_string())
+        }
+    }
+    profiles.sort();
+
+    Ok(profiles)
 }
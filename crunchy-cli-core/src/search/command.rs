@@ -1,5 +1,5 @@
 use crate::search::filter::FilterOptions;
-use crate::search::format::Format;
+use crate::search::format::{xml_escape, Format};
 use crate::utils::context::Context;
 use crate::utils::parse::{parse_url, UrlFilter};
 use crate::Execute;
@@ -7,8 +7,9 @@ use anyhow::{bail, Result};
 use crunchyroll_rs::common::StreamExt;
 use crunchyroll_rs::search::QueryResults;
 use crunchyroll_rs::{Episode, Locale, MediaCollection, MovieListing, MusicVideo, Series};
+use std::fmt::{Display, Formatter};
 
-#[derive(Debug, clap::Parser)]
+#[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Search in videos")]
 #[command(arg_required_else_help(true))]
 pub struct Search {
@@ -41,17 +42,33 @@ pub struct Search {
     /// The required pattern for this begins with `{{`, then the keyword, and closes with `}}` (e.g. `{{episode.title}}`).
     /// For example, if you want to get the title of an episode, you can use `Title {{episode.title}}` and `{{episode.title}}` will be replaced with the episode title
     ///
+    /// A keyword can carry an optional `:spec` suffix to control how its value is rendered.
+    /// `:padN` left-pads the value with zeros to a width of `N` (e.g. `{{episode.number:pad3}}`
+    /// → `003`), useful for sortable filenames. Any other spec is treated as a chrono strftime
+    /// pattern applied to the keyword's value as a unix timestamp (e.g.
+    /// `{{episode.air_date:%Y-%m-%d}}` → `2024-01-31`).
+    ///
+    /// A keyword can also carry a `| "default"` fallback, used whenever the resolved value is
+    /// empty (e.g. `{{series.title | "Unknown"}}`), and `{{#if scope.field}}...{{/if}}` wraps a
+    /// span of output text that is only kept if the keyword's value is non-empty - handy for
+    /// dropping surrounding punctuation around a field that many items simply don't have, e.g.
+    /// `{{#if episode.description}} - {{episode.description}}{{/if}}`. Blocks cannot be nested.
+    ///
     /// See the following list for all keywords and their meaning:
     ///     series.id                 → Series id
     ///     series.title              → Series title
     ///     series.description        → Series description
     ///     series.release_year       → Series release year
+    ///     series.score              → Series search match score
+    ///     series.rank               → Series search result rank, if the API provided one
+    ///     series.popularity_score   → Series popularity score
     ///
     ///     season.id                 → Season id
     ///     season.title              → Season title
     ///     season.description        → Season description
     ///     season.number             → Season number
     ///     season.episodes           → Number of episodes the season has
+    ///     season.audio_locales      → Audio languages available for the season, comma-separated
     ///
     ///     episode.id                → Episode id
     ///     episode.title             → Episode title
@@ -62,10 +79,16 @@ pub struct Search {
     ///     episode.duration          → Episode duration in milliseconds
     ///     episode.air_date          → Episode air date as unix timestamp
     ///     episode.premium_only      → If the episode is only available with Crunchyroll premium
+    ///     episode.score             → Episode search match score
+    ///     episode.rank              → Episode search result rank, if the API provided one
+    ///     episode.popularity_score  → Episode popularity score
     ///
     ///     movie_listing.id          → Movie listing id
     ///     movie_listing.title       → Movie listing title
     ///     movie_listing.description → Movie listing description
+    ///     movie_listing.score       → Movie listing search match score
+    ///     movie_listing.rank        → Movie listing search result rank, if the API provided one
+    ///     movie_listing.popularity_score → Movie listing popularity score
     ///
     ///     movie.id                  → Movie id
     ///     movie.title               → Movie title
@@ -78,6 +101,9 @@ pub struct Search {
     ///     music_video.description   → Music video description
     ///     music_video.duration      → Music video duration in milliseconds
     ///     music_video.premium_only  → If the music video is only available with Crunchyroll premium
+    ///     music_video.score         → Music video search match score
+    ///     music_video.rank          → Music video search result rank, if the API provided one
+    ///     music_video.popularity_score → Music video popularity score
     ///
     ///     concert.id                → Concert id
     ///     concert.title             → Concert title
@@ -88,6 +114,8 @@ pub struct Search {
     ///     stream.locale             → Stream locale/language
     ///     stream.dash_url           → Stream url in DASH format
     ///     stream.hls_url            → Stream url in HLS format
+    ///     stream.resolution         → Stream resolution (e.g. '1920x1080')
+    ///     stream.bandwidth          → Stream bitrate in bits/second
     ///
     ///     subtitle.locale           → Subtitle locale/language
     ///     subtitle.url              → Url to the subtitle
@@ -95,64 +123,226 @@ pub struct Search {
     #[arg(default_value = "S{{season.number}}E{{episode.number}} - {{episode.title}}")]
     output: String,
 
-    input: String,
+    /// Format in which the search results are printed.
+    ///
+    /// `text` renders `--output` as described above. `json`/`yaml` ignore `--output` entirely and
+    /// instead print the full resolved series/season/episode/movie/music object tree - plus the
+    /// available stream and subtitle locales - as an array of records, which is easier to
+    /// consume from scripts than parsing the templated text output. `rss` prints an RSS 2.0 feed
+    /// of a resolved series'/season's episodes, for following its release schedule in a feed
+    /// reader; it's only valid for series, season and episode results. `m3u8` prints a `#EXTM3U`
+    /// playlist of the same episodes, for handing a whole series/season to a player in one go;
+    /// it's also only valid for series, season and episode results. `ndjson` prints one JSON
+    /// object per series/season/episode/stream/subtitle leaf (`{"series": {...}, "season":
+    /// {...}, ...}`), for piping into `jq`; unlike `json`/`yaml` it always includes every field
+    /// the keywords above expose, regardless of what `--output` references, and it's also only
+    /// valid for series, season and episode results. `opml` prints an OPML
+    /// outline listing every resolved series/movie listing, for bundling several feeds together.
+    #[arg(long, verbatim_doc_comment)]
+    #[arg(value_parser = OutputFormat::parse, default_value = "text")]
+    output_format: OutputFormat,
+
+    /// Order in which the collected series/movie listing/episode/music video results are printed.
+    ///
+    /// `relevance` sorts by the search match score, `popularity` by the title's popularity score
+    /// and `alphabetical` by title. This does not affect `--search-top-results-limit` results,
+    /// which are kept in the order the Crunchyroll API returned them in.
+    #[arg(long, verbatim_doc_comment)]
+    #[arg(value_parser = SortBy::parse, default_value = "relevance")]
+    sort: SortBy,
+    #[arg(help = "Drop results with a search match score lower than this")]
+    #[arg(long)]
+    min_score: Option<f64>,
+
+    #[arg(help = "For every resolved series/movie listing, also print up to this many similar titles")]
+    #[arg(long, default_value_t = 0)]
+    similar: u32,
+
+    #[arg(help = "A query or url to search for. Can be given multiple times")]
+    inputs: Vec<String>,
+
+    #[arg(help = "Read additional queries/urls from a file, one per line ('-' reads from stdin)")]
+    #[arg(long)]
+    input_file: Option<String>,
 }
 
 #[async_trait::async_trait(?Send)]
 impl Execute for Search {
+    fn pre_check(&mut self) -> Result<()> {
+        if self.inputs.is_empty() && self.input_file.is_none() {
+            bail!("at least one input or '--input-file' is required")
+        }
+        Ok(())
+    }
+
     async fn execute(self, ctx: Context) -> Result<()> {
-        let input = if crunchyroll_rs::parse::parse_url(&self.input).is_some() {
-            match parse_url(&ctx.crunchy, self.input.clone(), true).await {
-                Ok(ok) => vec![ok],
-                Err(e) => bail!("url {} could not be parsed: {}", self.input, e),
-            }
-        } else {
-            let mut output = vec![];
-
-            let query = resolve_query(&self, ctx.crunchy.query(&self.input)).await?;
-            output.extend(query.0.into_iter().map(|m| (m, UrlFilter::default())));
-            output.extend(
-                query
-                    .1
-                    .into_iter()
-                    .map(|s| (s.into(), UrlFilter::default())),
-            );
-            output.extend(
-                query
-                    .2
-                    .into_iter()
-                    .map(|m| (m.into(), UrlFilter::default())),
-            );
-            output.extend(
-                query
-                    .3
-                    .into_iter()
-                    .map(|e| (e.into(), UrlFilter::default())),
+        let mut raw_inputs = self.inputs.clone();
+        if let Some(input_file) = &self.input_file {
+            let content = if input_file == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(input_file)?
+            };
+            raw_inputs.extend(
+                content
+                    .lines()
+                    .map(|l| l.trim().to_string())
+                    .filter(|l| !l.is_empty()),
             );
-            output.extend(
-                query
-                    .4
-                    .into_iter()
-                    .map(|m| (m.into(), UrlFilter::default())),
+        }
+
+        let mut input = vec![];
+        for raw_input in &raw_inputs {
+            input.extend(resolve_input(&self, &ctx, raw_input).await?);
+        }
+
+        if matches!(self.output_format, OutputFormat::Text) {
+            for (media_collection, url_filter) in input {
+                let filter_options = FilterOptions {
+                    audio: self.audio.clone(),
+                    url_filter,
+                };
+
+                let format = Format::new(self.output.clone(), filter_options)?;
+
+                let similar = if self.similar > 0 {
+                    resolve_similar(&media_collection, self.similar).await?
+                } else {
+                    vec![]
+                };
+
+                println!("{}", format.parse(media_collection).await?);
+                for similar_media_collection in similar {
+                    for line in format.parse(similar_media_collection).await?.lines() {
+                        println!("  ↳ {}", line);
+                    }
+                }
+            }
+        } else if matches!(self.output_format, OutputFormat::Rss) {
+            for (media_collection, url_filter) in input {
+                let filter_options = FilterOptions {
+                    audio: self.audio.clone(),
+                    url_filter,
+                };
+
+                let format = Format::new(self.output.clone(), filter_options)?;
+                println!("{}", format.rss(media_collection).await?);
+            }
+        } else if matches!(self.output_format, OutputFormat::M3u8) {
+            for (media_collection, url_filter) in input {
+                let filter_options = FilterOptions {
+                    audio: self.audio.clone(),
+                    url_filter,
+                };
+
+                let format = Format::new(self.output.clone(), filter_options)?;
+                println!("{}", format.m3u8(media_collection).await?);
+            }
+        } else if matches!(self.output_format, OutputFormat::Ndjson) {
+            for (media_collection, url_filter) in input {
+                let filter_options = FilterOptions {
+                    audio: self.audio.clone(),
+                    url_filter,
+                };
+
+                let format = Format::new(self.output.clone(), filter_options)?;
+                println!("{}", format.ndjson(media_collection).await?);
+            }
+        } else if matches!(self.output_format, OutputFormat::Opml) {
+            let mut outlines = String::new();
+            for (media_collection, _) in &input {
+                if let Some(title) = opml_title(media_collection) {
+                    outlines.push_str(&format!(
+                        "    <outline text=\"{0}\" title=\"{0}\" type=\"rss\"/>\n",
+                        xml_escape(&title)
+                    ));
+                }
+            }
+            println!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <opml version=\"2.0\"><head><title>crunchy-cli search feed</title></head><body>\n\
+                 {}</body></opml>",
+                outlines
             );
+        } else {
+            let mut records = vec![];
+            for (media_collection, url_filter) in input {
+                let filter_options = FilterOptions {
+                    audio: self.audio.clone(),
+                    url_filter,
+                };
 
-            output
-        };
+                let format = Format::new(self.output.clone(), filter_options)?;
+                records.push(format.record(media_collection).await?);
+            }
 
-        for (media_collection, url_filter) in input {
-            let filter_options = FilterOptions {
-                audio: self.audio.clone(),
-                url_filter,
+            let serialized = match self.output_format {
+                OutputFormat::Text
+                | OutputFormat::Rss
+                | OutputFormat::M3u8
+                | OutputFormat::Ndjson
+                | OutputFormat::Opml => unreachable!(),
+                OutputFormat::Json => serde_json::to_string_pretty(&records)?,
+                OutputFormat::Yaml => serde_yaml::to_string(&records)?,
             };
-
-            let format = Format::new(self.output.clone(), filter_options)?;
-            println!("{}", format.parse(media_collection).await?);
+            println!("{}", serialized);
         }
 
         Ok(())
     }
 }
 
+/// The title to list a search result under in an `--output-format opml` outline, or `None` for
+/// kinds an OPML feed list doesn't make sense for (e.g. a single episode or music video).
+fn opml_title(media_collection: &MediaCollection) -> Option<String> {
+    match media_collection {
+        MediaCollection::Series(series) => Some(series.title.clone()),
+        MediaCollection::MovieListing(movie_listing) => Some(movie_listing.title.clone()),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+    Yaml,
+    Rss,
+    M3u8,
+    Ndjson,
+    Opml,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Rss => "rss",
+            OutputFormat::M3u8 => "m3u8",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Opml => "opml",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl OutputFormat {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "yaml" => Ok(Self::Yaml),
+            "rss" => Ok(Self::Rss),
+            "m3u8" => Ok(Self::M3u8),
+            "ndjson" => Ok(Self::Ndjson),
+            "opml" => Ok(Self::Opml),
+            _ => Err(format!("invalid output format '{}'", s)),
+        }
+    }
+}
+
 macro_rules! resolve_query {
     ($limit:expr, $vec:expr, $item:expr) => {
         if $limit > 0 {
@@ -167,6 +357,100 @@ macro_rules! resolve_query {
     };
 }
 
+async fn resolve_input(
+    search: &Search,
+    ctx: &Context,
+    raw_input: &str,
+) -> Result<Vec<(MediaCollection, UrlFilter)>> {
+    if crunchyroll_rs::parse::parse_url(raw_input).is_some() {
+        match parse_url(&ctx.crunchy, raw_input.to_string(), true).await {
+            Ok(ok) => Ok(vec![ok]),
+            Err(e) => bail!("url {} could not be parsed: {}", raw_input, e),
+        }
+    } else {
+        let mut output = vec![];
+
+        let query = resolve_query(search, ctx.crunchy.query(raw_input)).await?;
+        output.extend(query.0.into_iter().map(|m| (m, UrlFilter::default())));
+        output.extend(
+            query
+                .1
+                .into_iter()
+                .map(|s| (s.into(), UrlFilter::default())),
+        );
+        output.extend(
+            query
+                .2
+                .into_iter()
+                .map(|m| (m.into(), UrlFilter::default())),
+        );
+        output.extend(
+            query
+                .3
+                .into_iter()
+                .map(|e| (e.into(), UrlFilter::default())),
+        );
+        output.extend(
+            query
+                .4
+                .into_iter()
+                .map(|m| (m.into(), UrlFilter::default())),
+        );
+
+        Ok(output)
+    }
+}
+
+async fn resolve_similar(
+    media_collection: &MediaCollection,
+    limit: u32,
+) -> Result<Vec<MediaCollection>> {
+    let mut similar = vec![];
+
+    macro_rules! collect_similar {
+        ($item:expr) => {
+            let mut similar_results = $item;
+            while let Some(item) = similar_results.next().await {
+                similar.push(item?.into());
+                if similar.len() >= limit as usize {
+                    break;
+                }
+            }
+        };
+    }
+
+    match media_collection {
+        MediaCollection::Series(series) => collect_similar!(series.similar()),
+        MediaCollection::MovieListing(movie_listing) => collect_similar!(movie_listing.similar()),
+        _ => (),
+    }
+
+    Ok(similar)
+}
+
+macro_rules! sort_and_filter {
+    ($vec:expr, $sort:expr, $min_score:expr) => {
+        if let Some(min_score) = $min_score {
+            $vec.retain(|item| item.score >= min_score);
+        }
+        match $sort {
+            SortBy::Relevance => $vec.sort_by(|a, b| {
+                b.score
+                    .partial_cmp(&a.score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            SortBy::Popularity => $vec.sort_by(|a, b| {
+                b.popularity_score
+                    .partial_cmp(&a.popularity_score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            SortBy::Alphabetical => {
+                $vec.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+            }
+        }
+    };
+}
+
 async fn resolve_query(
     search: &Search,
     query_results: QueryResults,
@@ -197,6 +481,11 @@ async fn resolve_query(
     resolve_query!(search.search_episode_limit, episode, query_results.episode);
     resolve_query!(search.search_music_limit, music_video, query_results.music);
 
+    sort_and_filter!(series, search.sort, search.min_score);
+    sort_and_filter!(movie_listing, search.sort, search.min_score);
+    sort_and_filter!(episode, search.sort, search.min_score);
+    sort_and_filter!(music_video, search.sort, search.min_score);
+
     Ok((
         media_collection,
         series,
@@ -205,3 +494,21 @@ async fn resolve_query(
         music_video,
     ))
 }
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum SortBy {
+    Relevance,
+    Popularity,
+    Alphabetical,
+}
+
+impl SortBy {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "relevance" => Ok(Self::Relevance),
+            "popularity" => Ok(Self::Popularity),
+            "alphabetical" => Ok(Self::Alphabetical),
+            _ => Err(format!("invalid sort method '{}'", s)),
+        }
+    }
+}
@@ -1,19 +1,23 @@
 use crate::search::filter::FilterOptions;
+use crate::utils::fmt::format_time_delta;
 use anyhow::{bail, Result};
+use chrono::{TimeZone, Utc};
 use crunchyroll_rs::media::{Stream, Subtitle};
 use crunchyroll_rs::{
     Concert, Episode, Locale, MediaCollection, Movie, MovieListing, MusicVideo, Season, Series,
 };
-use regex::Regex;
+use regex::{Captures, Regex};
 use serde::Serialize;
 use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::ops::Range;
 
 #[derive(Default, Serialize)]
 struct FormatSeries {
     pub title: String,
     pub description: String,
+    pub score: f64,
+    pub rank: Option<u32>,
+    pub popularity_score: f64,
 }
 
 impl From<&Series> for FormatSeries {
@@ -21,6 +25,9 @@ impl From<&Series> for FormatSeries {
         Self {
             title: value.title.clone(),
             description: value.description.clone(),
+            score: value.score,
+            rank: value.rank,
+            popularity_score: value.popularity_score,
         }
     }
 }
@@ -30,6 +37,9 @@ struct FormatSeason {
     pub title: String,
     pub description: String,
     pub number: u32,
+    /// Comma-separated, since individual keyword values are rendered with `serde_plain`, which
+    /// only supports scalars - see [`Format::replace`].
+    pub audio_locales: String,
 }
 
 impl From<&Season> for FormatSeason {
@@ -38,6 +48,12 @@ impl From<&Season> for FormatSeason {
             title: value.title.clone(),
             description: value.description.clone(),
             number: value.season_number,
+            audio_locales: value
+                .audio_locales
+                .iter()
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
         }
     }
 }
@@ -49,6 +65,15 @@ struct FormatEpisode {
     pub locale: Locale,
     pub number: u32,
     pub sequence_number: f32,
+    pub score: f64,
+    pub rank: Option<u32>,
+    pub popularity_score: f64,
+    /// Episode duration in milliseconds, matching the `episode.duration` keyword documented on
+    /// `--output`.
+    pub duration: i64,
+    /// Episode air date as a unix timestamp, matching the `episode.air_date` keyword documented
+    /// on `--output`.
+    pub air_date: i64,
 }
 
 impl From<&Episode> for FormatEpisode {
@@ -59,6 +84,11 @@ impl From<&Episode> for FormatEpisode {
             locale: value.audio_locale.clone(),
             number: value.episode_number,
             sequence_number: value.sequence_number,
+            score: value.score,
+            rank: value.rank,
+            popularity_score: value.popularity_score,
+            duration: value.duration.num_milliseconds(),
+            air_date: value.air_date.timestamp(),
         }
     }
 }
@@ -67,6 +97,9 @@ impl From<&Episode> for FormatEpisode {
 struct FormatMovieListing {
     pub title: String,
     pub description: String,
+    pub score: f64,
+    pub rank: Option<u32>,
+    pub popularity_score: f64,
 }
 
 impl From<&MovieListing> for FormatMovieListing {
@@ -74,6 +107,9 @@ impl From<&MovieListing> for FormatMovieListing {
         Self {
             title: value.title.clone(),
             description: value.description.clone(),
+            score: value.score,
+            rank: value.rank,
+            popularity_score: value.popularity_score,
         }
     }
 }
@@ -97,6 +133,9 @@ impl From<&Movie> for FormatMovie {
 struct FormatMusicVideo {
     pub title: String,
     pub description: String,
+    pub score: f64,
+    pub rank: Option<u32>,
+    pub popularity_score: f64,
 }
 
 impl From<&MusicVideo> for FormatMusicVideo {
@@ -104,6 +143,9 @@ impl From<&MusicVideo> for FormatMusicVideo {
         Self {
             title: value.title.clone(),
             description: value.description.clone(),
+            score: value.score,
+            rank: value.rank,
+            popularity_score: value.popularity_score,
         }
     }
 }
@@ -128,24 +170,33 @@ struct FormatStream {
     pub locale: Locale,
     pub dash_url: String,
     pub hls_url: String,
+    pub resolution: String,
+    pub bandwidth: u64,
 }
 
 impl From<&Stream> for FormatStream {
     fn from(value: &Stream) -> Self {
-        let (dash_url, hls_url) = value.variants.get(&Locale::Custom("".to_string())).map_or(
-            ("".to_string(), "".to_string()),
-            |v| {
-                (
-                    v.adaptive_dash.clone().unwrap_or_default().url,
-                    v.adaptive_hls.clone().unwrap_or_default().url,
-                )
-            },
-        );
+        let variant = value.variants.get(&Locale::Custom("".to_string()));
+        let (dash_url, hls_url) = variant.map_or(("".to_string(), "".to_string()), |v| {
+            (
+                v.adaptive_dash.clone().unwrap_or_default().url,
+                v.adaptive_hls.clone().unwrap_or_default().url,
+            )
+        });
+        // resolution/bandwidth are the same across dash/hls for a given variant, so either one
+        // that's actually present works
+        let best_variant =
+            variant.and_then(|v| v.adaptive_hls.clone().or(v.adaptive_dash.clone()));
 
         Self {
             locale: value.audio_locale.clone(),
             dash_url,
             hls_url,
+            resolution: best_variant
+                .as_ref()
+                .map(|v| v.resolution.to_string())
+                .unwrap_or_default(),
+            bandwidth: best_variant.map(|v| v.bandwidth).unwrap_or_default(),
         }
     }
 }
@@ -165,6 +216,71 @@ impl From<&Subtitle> for FormatSubtitle {
     }
 }
 
+#[derive(Default, Serialize)]
+pub struct SearchEpisodeRecord {
+    #[serde(flatten)]
+    pub episode: FormatEpisode,
+    pub stream_locales: Vec<Locale>,
+    pub subtitle_locales: Vec<Locale>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchSeasonRecord {
+    #[serde(flatten)]
+    pub season: FormatSeason,
+    pub episodes: Vec<SearchEpisodeRecord>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchSeriesRecord {
+    #[serde(flatten)]
+    pub series: FormatSeries,
+    pub seasons: Vec<SearchSeasonRecord>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchMovieRecord {
+    #[serde(flatten)]
+    pub movie: FormatMovie,
+    pub stream_locales: Vec<Locale>,
+    pub subtitle_locales: Vec<Locale>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchMovieListingRecord {
+    #[serde(flatten)]
+    pub movie_listing: FormatMovieListing,
+    pub movies: Vec<SearchMovieRecord>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchMusicVideoRecord {
+    #[serde(flatten)]
+    pub music_video: FormatMusicVideo,
+    pub stream_locales: Vec<Locale>,
+    pub subtitle_locales: Vec<Locale>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchConcertRecord {
+    #[serde(flatten)]
+    pub concert: FormatConcert,
+    pub stream_locales: Vec<Locale>,
+    pub subtitle_locales: Vec<Locale>,
+}
+
+/// The `--output-format json`/`yaml` counterpart to [`Format::parse`]. Unlike the templated text
+/// output, a record always contains the full resolved object tree regardless of which keywords
+/// (if any) are referenced in `--output`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchRecord {
+    Series(SearchSeriesRecord),
+    MovieListing(SearchMovieListingRecord),
+    MusicVideo(SearchMusicVideoRecord),
+    Concert(SearchConcertRecord),
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 enum Scope {
     Series,
@@ -178,6 +294,37 @@ enum Scope {
     Subtitle,
 }
 
+impl Scope {
+    fn from_str(s: &str) -> Option<Self> {
+        Some(match s {
+            "series" => Scope::Series,
+            "season" => Scope::Season,
+            "episode" => Scope::Episode,
+            "movie_listing" => Scope::MovieListing,
+            "movie" => Scope::Movie,
+            "music_video" => Scope::MusicVideo,
+            "concert" => Scope::Concert,
+            "stream" => Scope::Stream,
+            "subtitle" => Scope::Subtitle,
+            _ => return None,
+        })
+    }
+}
+
+/// Whether a resolved field value counts as "present" for `{{#if scope.field}}...{{/if}}`
+/// blocks and `{{scope.field | "default"}}` fallbacks - `null`, empty strings/arrays/objects and
+/// `0` are falsy, everything else is truthy.
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|f| f != 0.0).unwrap_or(true),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
 macro_rules! must_match_if_true {
     ($condition:expr => $media_collection:ident | $field:pat => $expr:expr) => {
         if $condition {
@@ -199,8 +346,37 @@ macro_rules! self_and_versions {
     }};
 }
 
+/// An optional `:spec` suffix on a `{{scope.field}}` token, e.g. `{{episode.number:pad3}}` or
+/// `{{episode.air_date:%Y-%m-%d}}`. Parsed once in [`Format::new`] so [`Format::replace`] never
+/// has to fail on a malformed spec mid-render.
+#[derive(Clone)]
+enum FieldSpec {
+    /// `padN` - left-pad the rendered value with zeros to the given width.
+    Pad(usize),
+    /// Anything else is treated as a chrono strftime pattern, applied to the field interpreted
+    /// as a unix timestamp.
+    Date(String),
+}
+
+fn parse_field_spec(spec: &str) -> Result<FieldSpec> {
+    Ok(match spec.strip_prefix("pad") {
+        Some(width) => FieldSpec::Pad(
+            width
+                .parse()
+                .map_err(|_| anyhow::anyhow!("'{}' is not a valid keyword specifier", spec))?,
+        ),
+        None => FieldSpec::Date(spec.to_string()),
+    })
+}
+
 pub struct Format {
-    pattern: Vec<(Range<usize>, Scope, String)>,
+    /// Matches a flat `{{scope.field[:spec]}}` or `{{scope.field | "default"}}` token.
+    field_regex: Regex,
+    /// Matches a `{{#if scope.field}}...{{/if}}` block. Resolved before `field_regex` since
+    /// keeping/dropping a block's body shifts every byte offset after it - blocks can't be
+    /// precomputed as static ranges the way flat tokens can, as their content depends on
+    /// per-leaf runtime data. Nesting is not supported.
+    if_regex: Regex,
     pattern_count: HashMap<Scope, u32>,
     input: String,
     filter_options: FilterOptions,
@@ -208,8 +384,14 @@ pub struct Format {
 
 impl Format {
     pub fn new(input: String, filter_options: FilterOptions) -> Result<Self> {
-        let scope_regex = Regex::new(r"(?m)\{\{\s*(?P<scope>\w+)\.(?P<field>\w+)\s*}}").unwrap();
-        let mut pattern = vec![];
+        let field_regex = Regex::new(
+            r#"(?m)\{\{\s*(?P<scope>\w+)\.(?P<field>\w+)(?::(?P<spec>[^}\s|]+))?(?:\s*\|\s*"(?P<default>[^"]*)")?\s*}}"#,
+        )
+        .unwrap();
+        let if_regex = Regex::new(
+            r"(?s)\{\{\s*#if\s+(?P<scope>\w+)\.(?P<field>\w+)\s*}}(?P<body>.*?)\{\{\s*/if\s*}}",
+        )
+        .unwrap();
         let mut pattern_count = HashMap::new();
 
         macro_rules! generate_field_check {
@@ -236,24 +418,9 @@ impl Format {
             Scope::Subtitle => FormatSubtitle
         );
 
-        for capture in scope_regex.captures_iter(&input) {
-            let full = capture.get(0).unwrap();
-            let scope = capture.name("scope").unwrap().as_str();
-            let field = capture.name("field").unwrap().as_str();
-
-            let format_pattern_scope = match scope {
-                "series" => Scope::Series,
-                "season" => Scope::Season,
-                "episode" => Scope::Episode,
-                "movie_listing" => Scope::MovieListing,
-                "movie" => Scope::Movie,
-                "music_video" => Scope::MusicVideo,
-                "concert" => Scope::Concert,
-                "stream" => Scope::Stream,
-                "subtitle" => Scope::Subtitle,
-                _ => bail!("'{}.{}' is not a valid keyword", scope, field),
-            };
-
+        let check_field = |scope: &str, field: &str| -> Result<Scope> {
+            let format_pattern_scope = Scope::from_str(scope)
+                .ok_or_else(|| anyhow::anyhow!("'{}.{}' is not a valid keyword", scope, field))?;
             if field_check
                 .get(&format_pattern_scope)
                 .unwrap()
@@ -262,17 +429,31 @@ impl Format {
             {
                 bail!("'{}.{}' is not a valid keyword", scope, field)
             }
+            Ok(format_pattern_scope)
+        };
+
+        for capture in if_regex.captures_iter(&input) {
+            let scope = capture.name("scope").unwrap().as_str();
+            let field = capture.name("field").unwrap().as_str();
+            let format_pattern_scope = check_field(scope, field)?;
+            *pattern_count.entry(format_pattern_scope).or_default() += 1
+        }
+
+        for capture in field_regex.captures_iter(&input) {
+            let scope = capture.name("scope").unwrap().as_str();
+            let field = capture.name("field").unwrap().as_str();
+            let format_pattern_scope = check_field(scope, field)?;
+
+            if let Some(spec) = capture.name("spec") {
+                parse_field_spec(spec.as_str())?;
+            }
 
-            pattern.push((
-                full.start()..full.end(),
-                format_pattern_scope.clone(),
-                field.to_string(),
-            ));
             *pattern_count.entry(format_pattern_scope).or_default() += 1
         }
 
         Ok(Self {
-            pattern,
+            field_regex,
+            if_regex,
             pattern_count,
             input,
             filter_options,
@@ -317,6 +498,351 @@ impl Format {
         }
     }
 
+    /// The `--output-format json`/`yaml` counterpart to [`Format::parse`]. Always resolves the
+    /// full object tree, since there's no `--output` template to tell which parts are needed.
+    pub async fn record(&self, media_collection: MediaCollection) -> Result<SearchRecord> {
+        match &media_collection {
+            MediaCollection::Series(_)
+            | MediaCollection::Season(_)
+            | MediaCollection::Episode(_) => Ok(SearchRecord::Series(
+                self.record_series(media_collection).await?,
+            )),
+            MediaCollection::MovieListing(_) | MediaCollection::Movie(_) => {
+                Ok(SearchRecord::MovieListing(
+                    self.record_movie_listing(media_collection).await?,
+                ))
+            }
+            MediaCollection::MusicVideo(music_video) => Ok(SearchRecord::MusicVideo(
+                self.record_music_video(music_video).await?,
+            )),
+            MediaCollection::Concert(concert) => {
+                Ok(SearchRecord::Concert(self.record_concert(concert).await?))
+            }
+        }
+    }
+
+    /// Builds an `--output-format rss` feed of the resolved series'/season's episodes. Each
+    /// episode becomes an `<item>` with its HLS stream as the enclosure, for following a show's
+    /// release schedule in a podcast-style feed reader.
+    pub async fn rss(&self, media_collection: MediaCollection) -> Result<String> {
+        let series = match &media_collection {
+            MediaCollection::Series(series) => series.clone(),
+            MediaCollection::Season(season) => season.series().await?,
+            MediaCollection::Episode(episode) => episode.series().await?,
+            _ => bail!("'--output-format rss' is only supported for series, seasons and episodes"),
+        };
+        if !self.filter_options.check_series(&series) {
+            return Ok(String::new());
+        }
+
+        let mut episodes = vec![];
+        if let MediaCollection::Episode(episode) = &media_collection {
+            episodes.push(episode.clone());
+        } else {
+            let seasons = match &media_collection {
+                MediaCollection::Series(series) => series.seasons().await?,
+                MediaCollection::Season(season) => vec![season.clone()],
+                _ => unreachable!(),
+            };
+            for season in self.filter_options.filter_seasons(seasons) {
+                episodes.extend(self.filter_options.filter_episodes(season.episodes().await?));
+            }
+        }
+
+        let mut items = String::new();
+        for episode in &episodes {
+            let stream = episode.streams().await?;
+            let enclosure_url = stream
+                .variants
+                .get(&Locale::Custom("".to_string()))
+                .and_then(|v| v.adaptive_hls.clone().or(v.adaptive_dash.clone()))
+                .map(|v| v.url)
+                .unwrap_or_default();
+
+            items.push_str(&format!(
+                "    <item><title>{}</title><description>{}</description>\
+                 <guid isPermaLink=\"false\">{}</guid>\
+                 <pubDate>{}</pubDate><enclosure url=\"{}\" type=\"application/x-mpegURL\"/>\
+                 <itunes:duration>{}</itunes:duration></item>\n",
+                xml_escape(&episode.title),
+                xml_escape(&episode.description),
+                xml_escape(&episode.id),
+                episode.air_date.to_rfc2822(),
+                xml_escape(&enclosure_url),
+                format_time_delta(&episode.duration)
+            ));
+        }
+
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <rss version=\"2.0\"><channel><title>{}</title><description>{}</description>\n{}</channel></rss>",
+            xml_escape(&series.title),
+            xml_escape(&series.description),
+            items
+        ))
+    }
+
+    /// Builds a `#EXTM3U` playlist of all resolved episodes in order, for handing a whole
+    /// series/season to a player like mpv/VLC. See [`Format::rss`] for how the episode list
+    /// itself is resolved.
+    pub async fn m3u8(&self, media_collection: MediaCollection) -> Result<String> {
+        let series = match &media_collection {
+            MediaCollection::Series(series) => series.clone(),
+            MediaCollection::Season(season) => season.series().await?,
+            MediaCollection::Episode(episode) => episode.series().await?,
+            _ => {
+                bail!("'--output-format m3u8' is only supported for series, seasons and episodes")
+            }
+        };
+        if !self.filter_options.check_series(&series) {
+            return Ok(String::new());
+        }
+
+        let mut episodes = vec![];
+        if let MediaCollection::Episode(episode) = &media_collection {
+            episodes.push(episode.clone());
+        } else {
+            let seasons = match &media_collection {
+                MediaCollection::Series(series) => series.seasons().await?,
+                MediaCollection::Season(season) => vec![season.clone()],
+                _ => unreachable!(),
+            };
+            for season in self.filter_options.filter_seasons(seasons) {
+                episodes.extend(self.filter_options.filter_episodes(season.episodes().await?));
+            }
+        }
+
+        let mut entries = String::new();
+        for episode in &episodes {
+            let format_episode = FormatEpisode::from(episode);
+            let stream = episode.streams().await?;
+            let url = stream
+                .variants
+                .get(&Locale::Custom("".to_string()))
+                .and_then(|v| v.adaptive_hls.clone().or(v.adaptive_dash.clone()))
+                .map(|v| v.url)
+                .unwrap_or_default();
+
+            // some players/tools reject integer-only EXTINF values, so always keep a decimal
+            // fraction
+            entries.push_str(&format!(
+                "#EXTINF:{:.1},{}\n{}\n",
+                format_episode.duration as f64 / 1000.0,
+                episode.title,
+                url
+            ));
+        }
+
+        Ok(format!("#EXTM3U\n{}", entries))
+    }
+
+    /// Serializes the fully-resolved series/season/episode/stream/subtitle tree as NDJSON - one
+    /// JSON object per leaf, shaped `{"series": {...}, "season": {...}, "episode": {...},
+    /// "stream": {...}, "subtitle": {...}}` - for piping into `jq` without parsing an `--output`
+    /// template. Unlike `--output`, this always includes every field the `Format*` structs
+    /// define, regardless of what keywords are actually referenced.
+    pub async fn ndjson(&self, media_collection: MediaCollection) -> Result<String> {
+        let series = match &media_collection {
+            MediaCollection::Series(series) => series.clone(),
+            MediaCollection::Season(season) => season.series().await?,
+            MediaCollection::Episode(episode) => episode.series().await?,
+            _ => bail!(
+                "'--output-format ndjson' is only supported for series, seasons and episodes"
+            ),
+        };
+        if !self.filter_options.check_series(&series) {
+            return Ok(String::new());
+        }
+
+        let mut seasons_episodes = vec![];
+        if let MediaCollection::Episode(episode) = &media_collection {
+            seasons_episodes.push((Season::default(), vec![episode.clone()]));
+        } else {
+            let seasons = match &media_collection {
+                MediaCollection::Series(series) => series.seasons().await?,
+                MediaCollection::Season(season) => vec![season.clone()],
+                _ => unreachable!(),
+            };
+            for season in self.filter_options.filter_seasons(seasons) {
+                let episodes = self.filter_options.filter_episodes(season.episodes().await?);
+                seasons_episodes.push((season, episodes));
+            }
+        }
+
+        let series_map = self.serializable_to_json_map(FormatSeries::from(&series));
+        let mut lines = vec![];
+        for (season, episodes) in &seasons_episodes {
+            let season_map = self.serializable_to_json_map(FormatSeason::from(season));
+            for episode in episodes {
+                let episode_map = self.serializable_to_json_map(FormatEpisode::from(episode));
+                let stream = episode.streams().await?;
+                let stream_map = self.serializable_to_json_map(FormatStream::from(&stream));
+                let subtitles = self
+                    .filter_options
+                    .filter_subtitles(stream.subtitles.clone().into_values().collect());
+
+                let subtitle_maps = if subtitles.is_empty() {
+                    vec![Map::new()]
+                } else {
+                    subtitles
+                        .iter()
+                        .map(|s| self.serializable_to_json_map(FormatSubtitle::from(s)))
+                        .collect()
+                };
+                for subtitle_map in subtitle_maps {
+                    lines.push(serde_json::to_string(&serde_json::json!({
+                        "series": series_map,
+                        "season": season_map,
+                        "episode": episode_map,
+                        "stream": stream_map,
+                        "subtitle": subtitle_map,
+                    }))?);
+                }
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    async fn record_series(&self, media_collection: MediaCollection) -> Result<SearchSeriesRecord> {
+        let series = match &media_collection {
+            MediaCollection::Series(series) => series.clone(),
+            MediaCollection::Season(season) => season.series().await?,
+            MediaCollection::Episode(episode) => episode.series().await?,
+            _ => panic!(),
+        };
+        if !self.filter_options.check_series(&series) {
+            return Ok(SearchSeriesRecord::default());
+        }
+
+        let tmp_seasons = match &media_collection {
+            MediaCollection::Series(series) => series.seasons().await?,
+            MediaCollection::Season(season) => vec![season.clone()],
+            MediaCollection::Episode(_) => vec![],
+            _ => panic!(),
+        };
+        let mut seasons = vec![];
+        for season in tmp_seasons {
+            seasons.extend(self_and_versions!(season => self.filter_options.audio.clone()))
+        }
+        let seasons = self.filter_options.filter_seasons(seasons);
+
+        let mut season_records = vec![];
+        if let MediaCollection::Episode(episode) = &media_collection {
+            let episodes = self_and_versions!(episode => self.filter_options.audio.clone());
+            let mut episode_records = vec![];
+            for episode in episodes
+                .into_iter()
+                .filter(|e| self.filter_options.audio.contains(&e.audio_locale))
+            {
+                episode_records.push(self.record_episode(&episode).await?);
+            }
+            season_records.push(SearchSeasonRecord {
+                season: FormatSeason::default(),
+                episodes: episode_records,
+            });
+        } else {
+            for season in seasons {
+                let episodes = self.filter_options.filter_episodes(season.episodes().await?);
+                let mut episode_records = vec![];
+                for episode in episodes {
+                    episode_records.push(self.record_episode(&episode).await?);
+                }
+                season_records.push(SearchSeasonRecord {
+                    season: FormatSeason::from(&season),
+                    episodes: episode_records,
+                });
+            }
+        }
+
+        Ok(SearchSeriesRecord {
+            series: FormatSeries::from(&series),
+            seasons: season_records,
+        })
+    }
+
+    async fn record_episode(&self, episode: &Episode) -> Result<SearchEpisodeRecord> {
+        let stream = episode.streams().await?;
+        Ok(SearchEpisodeRecord {
+            episode: FormatEpisode::from(episode),
+            stream_locales: stream.variants.keys().cloned().collect(),
+            subtitle_locales: self
+                .filter_options
+                .filter_subtitles(stream.subtitles.into_values().collect())
+                .into_iter()
+                .map(|s| s.locale)
+                .collect(),
+        })
+    }
+
+    async fn record_movie_listing(
+        &self,
+        media_collection: MediaCollection,
+    ) -> Result<SearchMovieListingRecord> {
+        let movie_listing = match &media_collection {
+            MediaCollection::MovieListing(movie_listing) => movie_listing.clone(),
+            MediaCollection::Movie(movie) => movie.movie_listing().await?,
+            _ => panic!(),
+        };
+        if !self.filter_options.check_movie_listing(&movie_listing) {
+            return Ok(SearchMovieListingRecord::default());
+        }
+
+        let movies = match &media_collection {
+            MediaCollection::MovieListing(movie_listing) => movie_listing.movies().await?,
+            MediaCollection::Movie(movie) => vec![movie.clone()],
+            _ => panic!(),
+        };
+
+        let mut movie_records = vec![];
+        for movie in movies {
+            let stream = movie.streams().await?;
+            movie_records.push(SearchMovieRecord {
+                movie: FormatMovie::from(&movie),
+                stream_locales: stream.variants.keys().cloned().collect(),
+                subtitle_locales: self
+                    .filter_options
+                    .filter_subtitles(stream.subtitles.into_values().collect())
+                    .into_iter()
+                    .map(|s| s.locale)
+                    .collect(),
+            });
+        }
+
+        Ok(SearchMovieListingRecord {
+            movie_listing: FormatMovieListing::from(&movie_listing),
+            movies: movie_records,
+        })
+    }
+
+    async fn record_music_video(&self, music_video: &MusicVideo) -> Result<SearchMusicVideoRecord> {
+        let stream = music_video.streams().await?;
+        Ok(SearchMusicVideoRecord {
+            music_video: FormatMusicVideo::from(music_video),
+            stream_locales: stream.variants.keys().cloned().collect(),
+            subtitle_locales: self
+                .filter_options
+                .filter_subtitles(stream.subtitles.into_values().collect())
+                .into_iter()
+                .map(|s| s.locale)
+                .collect(),
+        })
+    }
+
+    async fn record_concert(&self, concert: &Concert) -> Result<SearchConcertRecord> {
+        let stream = concert.streams().await?;
+        Ok(SearchConcertRecord {
+            concert: FormatConcert::from(concert),
+            stream_locales: stream.variants.keys().cloned().collect(),
+            subtitle_locales: self
+                .filter_options
+                .filter_subtitles(stream.subtitles.into_values().collect())
+                .into_iter()
+                .map(|s| s.locale)
+                .collect(),
+        })
+    }
+
     async fn parse_series(&self, media_collection: MediaCollection) -> Result<String> {
         let series_empty = self.check_pattern_count_empty(Scope::Series);
         let season_empty = self.check_pattern_count_empty(Scope::Season);
@@ -544,12 +1070,11 @@ impl Format {
     }
 
     fn check_scopes(&self, available_scopes: Vec<Scope>) -> Result<()> {
-        for (_, scope, field) in self.pattern.iter() {
+        for scope in self.pattern_count.keys() {
             if !available_scopes.contains(scope) {
                 bail!(
-                    "'{}.{}' is not a valid keyword",
-                    format!("{:?}", scope).to_lowercase(),
-                    field
+                    "'{}' keywords are not valid for this result type",
+                    format!("{:?}", scope).to_lowercase()
                 )
             }
         }
@@ -585,18 +1110,69 @@ impl Format {
     }
 
     fn replace(&self, values: HashMap<Scope, &Map<String, Value>>) -> String {
-        let mut output = self.input.clone();
-        let mut offset = 0;
-        for (range, scope, field) in &self.pattern {
-            let item =
-                serde_plain::to_string(values.get(scope).unwrap().get(field.as_str()).unwrap())
-                    .unwrap();
-            let start = (range.start as i32 + offset) as usize;
-            let end = (range.end as i32 + offset) as usize;
-            output.replace_range(start..end, &item);
-            offset += item.len() as i32 - range.len() as i32;
-        }
-
-        output
+        let resolve = |scope: &str, field: &str| -> Value {
+            Scope::from_str(scope)
+                .and_then(|scope| values.get(&scope))
+                .and_then(|map| map.get(field))
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+
+        // Blocks are resolved first since keeping/dropping a block's body changes the length of
+        // everything after it; the flat-token pass below then only ever sees tokens that survive.
+        let with_blocks_resolved =
+            self.if_regex
+                .replace_all(&self.input, |capture: &Captures| {
+                    let scope = capture.name("scope").unwrap().as_str();
+                    let field = capture.name("field").unwrap().as_str();
+                    let body = capture.name("body").unwrap().as_str();
+                    if is_truthy(&resolve(scope, field)) {
+                        body.to_string()
+                    } else {
+                        String::new()
+                    }
+                });
+
+        self.field_regex
+            .replace_all(&with_blocks_resolved, |capture: &Captures| {
+                let scope = capture.name("scope").unwrap().as_str();
+                let field = capture.name("field").unwrap().as_str();
+                let value = resolve(scope, field);
+
+                let rendered = match capture.name("spec").map(|m| m.as_str()) {
+                    Some(spec) => match parse_field_spec(spec).unwrap() {
+                        FieldSpec::Pad(width) => format!(
+                            "{:0>width$}",
+                            serde_plain::to_string(&value).unwrap(),
+                            width = width
+                        ),
+                        FieldSpec::Date(strftime) => Utc
+                            .timestamp_opt(value.as_i64().unwrap_or_default(), 0)
+                            .single()
+                            .unwrap_or_default()
+                            .format(&strftime)
+                            .to_string(),
+                    },
+                    None => serde_plain::to_string(&value).unwrap(),
+                };
+
+                if rendered.is_empty() || !is_truthy(&value) {
+                    if let Some(default) = capture.name("default") {
+                        return default.as_str().to_string();
+                    }
+                }
+                rendered
+            })
+            .to_string()
     }
 }
+
+/// Escapes the characters XML requires escaped in element/attribute text, used by
+/// [`Format::rss`] and the `--output-format opml` outline builder.
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,116 @@
+use crate::utils::os::{ffmpeg_command, tempfile};
+use anyhow::{bail, Result};
+use std::path::Path;
+use tempfile::TempPath;
+
+/// Per-skip-event-type speed factor to accelerate that event's range to (e.g. recap at 4x, credits
+/// at 2x) instead of only marking it with a passive chapter, set via `--accelerate-*`. `None`
+/// leaves that event type as a plain chapter, the existing behavior.
+#[derive(Clone, Default)]
+pub struct AccelerateFactors {
+    pub recap: Option<f64>,
+    pub intro: Option<f64>,
+    pub credits: Option<f64>,
+    pub preview: Option<f64>,
+}
+
+impl AccelerateFactors {
+    /// The factor for the skip event named `name` (matching the names `write_ffmpeg_chapters`
+    /// titles chapters with: "Recap"/"Intro"/"Credits"/"Preview"), if one was set.
+    pub fn factor_for(&self, name: &str) -> Option<f64> {
+        match name {
+            "Recap" => self.recap,
+            "Intro" => self.intro,
+            "Credits" => self.credits,
+            "Preview" => self.preview,
+            _ => None,
+        }
+    }
+}
+
+/// A `[start, end)` window (seconds into `src`'s own timeline) to speed up by `factor`, instead of
+/// only marking it with a passive chapter.
+pub struct AccelerateRange {
+    pub start: f64,
+    pub end: f64,
+    pub factor: f64,
+}
+
+/// Speeds up each of `ranges` in `src` and concatenates the result back into a single, shortened
+/// file, via one `filter_complex` pass: `src`'s timeline is cut at every range boundary (the gaps
+/// between ranges becoming their own untouched, factor-1.0 segments), each segment is `setpts`
+/// rescaled by its factor on video and given a matching, chained (a single `atempo` instance only
+/// accepts 0.5-2.0) `atempo` on audio, then every segment is `concat`ed back together in order.
+/// `video_len` is `src`'s own total length, used to add a trailing unmodified segment after the
+/// last range. `ranges` must be sorted by `start` and non-overlapping.
+///
+/// This always re-encodes with `encoder`/`"aac"` rather than preserving the source codec, since
+/// `setpts`/`atempo` can't be applied to a stream-copied track.
+pub fn accelerate_ranges(
+    src: &Path,
+    ranges: &[AccelerateRange],
+    video_len: f64,
+    encoder: &str,
+) -> Result<TempPath> {
+    let mut segments = vec![];
+    let mut cursor = 0.0;
+    for range in ranges {
+        if range.start > cursor {
+            segments.push((cursor, range.start, 1.0));
+        }
+        segments.push((range.start, range.end, range.factor));
+        cursor = range.end;
+    }
+    if cursor < video_len {
+        segments.push((cursor, video_len, 1.0));
+    }
+
+    let mut filter_complex = String::new();
+    let mut concat_labels = String::new();
+    for (i, (start, end, factor)) in segments.iter().enumerate() {
+        filter_complex.push_str(&format!(
+            "[0:v]trim=start={start:.3}:end={end:.3},setpts=(PTS-STARTPTS)/{factor:.6}[v{i}];\
+             [0:a]atrim=start={start:.3}:end={end:.3},asetpts=PTS-STARTPTS,{}[a{i}];",
+            atempo_chain(*factor)
+        ));
+        concat_labels.push_str(&format!("[v{i}][a{i}]"));
+    }
+    filter_complex.push_str(&format!(
+        "{concat_labels}concat=n={}:v=1:a=1[outv][outa]",
+        segments.len()
+    ));
+
+    let (_file, path) = tempfile(".mp4")?.into_parts();
+
+    let output = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-i", src.to_string_lossy().to_string().as_str()])
+        .args(["-filter_complex", filter_complex.as_str()])
+        .args(["-map", "[outv]", "-map", "[outa]"])
+        .args(["-c:v", encoder])
+        .args(["-c:a", "aac"])
+        .arg(path.to_string_lossy().to_string())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(path)
+}
+
+/// Decomposes `factor` into a chain of ffmpeg `atempo` filters, since a single `atempo` instance
+/// only accepts a 0.5-2.0 range.
+fn atempo_chain(factor: f64) -> String {
+    let mut remaining = factor;
+    let mut filters = vec![];
+    while remaining > 2.0 {
+        filters.push("atempo=2.0".to_string());
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        filters.push("atempo=0.5".to_string());
+        remaining /= 0.5;
+    }
+    filters.push(format!("atempo={:.6}", remaining));
+    filters.join(",")
+}
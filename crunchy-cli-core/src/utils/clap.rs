@@ -1,4 +1,6 @@
-use crate::utils::parse::parse_resolution;
+use crate::utils::parse::{parse_resolution, parse_time_delta};
+use crate::utils::video::ResolutionPreference;
+use chrono::TimeDelta;
 use crunchyroll_rs::media::Resolution;
 use regex::Regex;
 use reqwest::Proxy;
@@ -7,6 +9,18 @@ pub fn clap_parse_resolution(s: &str) -> Result<Resolution, String> {
     parse_resolution(s.to_string()).map_err(|e| e.to_string())
 }
 
+pub fn clap_parse_resolution_preferences(s: &str) -> Result<Vec<ResolutionPreference>, String> {
+    ResolutionPreference::parse_chain(s)
+}
+
+pub fn clap_parse_time_delta(s: &str) -> Result<TimeDelta, String> {
+    parse_time_delta(s).map_err(|e| e.to_string())
+}
+
+pub fn clap_parse_proxy(s: &str) -> Result<Proxy, String> {
+    Proxy::all(s).map_err(|e| e.to_string())
+}
+
 pub fn clap_parse_proxies(s: &str) -> Result<(Option<Proxy>, Option<Proxy>), String> {
     let double_proxy_regex =
         Regex::new(r"^(?P<first>(https?|socks5h?)://.+):(?P<second>(https?|socks5h?)://.+)$")
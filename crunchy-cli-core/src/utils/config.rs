@@ -1,5 +1,6 @@
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -12,12 +13,41 @@ pub enum Auth {
     Anonymous,
 }
 
+/// Default flag values for a single subcommand. Every field is stored as its raw toml value since
+/// the config is merged with the actual command line arguments before clap parses them, which
+/// don't care about the concrete type until parsing happens.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Defaults {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub archive: HashMap<String, toml::Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub download: HashMap<String, toml::Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub search: HashMap<String, toml::Value>,
+}
+
 #[derive(Default, Deserialize, Serialize)]
 pub struct Config {
     pub auth: Option<Auth>,
+
+    /// Default flag values which are applied if no profile is selected (via `--profile`).
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Named sets of default flag values, selectable via `--profile <name>`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Defaults>,
 }
 
 impl Config {
+    /// Returns the [`Defaults`] which should be applied for the given profile name. Falls back to
+    /// the top level `defaults` table if no profile name is given or the requested profile doesn't
+    /// exist.
+    pub fn defaults_for(&self, profile: Option<&str>) -> &Defaults {
+        profile
+            .and_then(|p| self.profiles.get(p))
+            .unwrap_or(&self.defaults)
+    }
+
     pub fn load() -> Result<Option<Self>> {
         let path = Config::assert_config_file_path(true)?;
 
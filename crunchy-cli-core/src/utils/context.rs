@@ -6,4 +6,10 @@ pub struct Context {
     pub crunchy: Crunchyroll,
     pub client: Client,
     pub rate_limiter: Option<RateLimiterService>,
+    /// Whether colored output is enabled, as resolved from `--color`/`NO_COLOR` by
+    /// [`crate::utils::log::apply_color_choice`].
+    pub color: bool,
+    /// Mirrors `Cli`'s top-level `--experimental-fixes` flag, for commands that need it outside of
+    /// the crunchyroll-rs client builder (e.g. season-duplicate disambiguation).
+    pub experimental_fixes: bool,
 }
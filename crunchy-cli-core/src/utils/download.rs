@@ -1,25 +1,37 @@
-use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::accelerate::{accelerate_ranges, AccelerateFactors, AccelerateRange};
+use crate::utils::ffmpeg::{FFmpegAudioChannel, FFmpegPreset};
 use crate::utils::filter::real_dedup_vec;
 use crate::utils::log::progress;
 use crate::utils::os::{
-    cache_dir, is_special_file, temp_directory, temp_named_pipe, tempdir, tempfile,
+    cache_dir, ffmpeg_command, ffprobe_command, is_special_file, temp_directory, temp_named_pipe,
+    tempdir, tempfile,
 };
 use crate::utils::rate_limit::RateLimiterService;
+use crate::utils::subtitle_export::{
+    apply_subtitle_style, convert_subtitle, encode_subtitle_charset, shift_subtitle_events,
+    SubtitleFormat, SubtitleOutput, SubtitleStyleOverrides,
+};
+use crate::utils::subtitle_sync::sync_subtitle_to_audio;
+use crate::utils::sync::{sync_audios, SyncAudio};
+use crate::utils::iso_bmff::{fast_start_reorder, rewrite_audio_edit_lists, EditListResult, FastStartResult};
+use crate::utils::transcode::reencode_by_scene;
 use anyhow::{bail, Result};
 use chrono::{NaiveTime, TimeDelta};
 use crunchyroll_rs::media::{SkipEvents, SkipEventsEvent, StreamData, StreamSegment, Subtitle};
 use crunchyroll_rs::Locale;
+use futures_util::stream::{self, StreamExt};
 use image_hasher::{Hasher, HasherConfig, ImageHash};
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
 use log::{debug, warn, LevelFilter};
 use regex::Regex;
 use reqwest::Client;
+use serde::Deserialize;
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -33,11 +45,43 @@ use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 use tower_service::Service;
 
+/// What kind of subtitle a `(Subtitle, SubtitleKind)` pair (as collected by the `get_format`
+/// implementations) is, so the downloader can set the right Matroska track flags instead of
+/// conflating closed captions with forced subtitles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubtitleKind {
+    /// A regular, full translation of the dialogue.
+    Regular,
+    /// Only covers foreign dialogue/signs which the audio itself doesn't already carry (e.g. a
+    /// subtitle accompanying a dub, which only translates the few lines the dub kept untranslated).
+    /// Gets the Matroska "forced" disposition so players show it automatically over the dub.
+    Forced,
+    /// A closed caption / SDH track (`stream.captions`), which also transcribes non-dialogue audio
+    /// for the hard-of-hearing.
+    ClosedCaption,
+}
+
+impl SubtitleKind {
+    /// Lower sorts first when ordering same-locale subtitles: a regular track before the forced
+    /// track before the closed caption/SDH track, since that's the more commonly wanted default.
+    fn sort_priority(&self) -> u8 {
+        match self {
+            SubtitleKind::Regular => 0,
+            SubtitleKind::Forced => 1,
+            SubtitleKind::ClosedCaption => 2,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum MergeBehavior {
     Video,
     Audio,
     Auto,
+    /// Like `Auto`, but when videos differ in length, the offset between their audio tracks is
+    /// found via [`crate::utils::sync::sync_audios`] and removed from the beginning of the
+    /// shorter ones instead of keeping every video as a separate track.
+    Sync,
 }
 
 impl MergeBehavior {
@@ -46,16 +90,42 @@ impl MergeBehavior {
             "video" => MergeBehavior::Video,
             "audio" => MergeBehavior::Audio,
             "auto" => MergeBehavior::Auto,
+            "sync" => MergeBehavior::Sync,
             _ => return Err(format!("'{}' is not a valid merge behavior", s)),
         })
     }
 }
 
+/// Voice-activity-based subtitle-to-audio alignment mode, see `--subtitle-sync`.
+#[derive(Clone, Debug)]
+pub enum SubtitleSyncMode {
+    /// A single offset for the whole track.
+    Global,
+    /// Lets different parts of the episode (e.g. around an ad break) pick up their own offset, via
+    /// [`crate::utils::subtitle_sync::sync_subtitle_to_audio`]'s split DP.
+    Split,
+}
+
+impl SubtitleSyncMode {
+    pub fn parse(s: &str) -> Result<SubtitleSyncMode, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "global" => SubtitleSyncMode::Global,
+            "split" => SubtitleSyncMode::Split,
+            _ => return Err(format!("'{}' is not a valid subtitle sync mode", s)),
+        })
+    }
+
+    fn is_split(&self) -> bool {
+        matches!(self, SubtitleSyncMode::Split)
+    }
+}
+
 #[derive(Clone, derive_setters::Setters)]
 pub struct DownloadBuilder {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
     ffmpeg_preset: FFmpegPreset,
+    audio_channel: Option<FFmpegAudioChannel>,
     default_subtitle: Option<Locale>,
     output_format: Option<String>,
     audio_sort: Option<Vec<Locale>>,
@@ -63,9 +133,33 @@ pub struct DownloadBuilder {
     force_hardsub: bool,
     download_fonts: bool,
     no_closed_caption: bool,
+    prefer_sdh: bool,
     sync_start_value: Option<f64>,
+    merge_sync_tolerance: Option<u32>,
+    merge_sync_precision: Option<u32>,
+    merge_sync_max_offset_variance: Option<u32>,
+    clip_start: Option<TimeDelta>,
+    clip_duration: Option<TimeDelta>,
+    metadata_tags: Vec<(String, String)>,
+    info_json: Option<String>,
+    subtitle_output: SubtitleOutput,
+    subtitle_format: SubtitleFormat,
+    subtitle_charset: String,
+    subtitle_style: SubtitleStyleOverrides,
+    subtitle_sync: Option<SubtitleSyncMode>,
+    encode_preset: Option<String>,
+    force_color_transfer: Option<String>,
+    fragment_duration: Option<f64>,
+    disable_faststart: bool,
+    video_track_timescale: Option<u32>,
+    hls_output: bool,
+    accelerate_skip_events: AccelerateFactors,
     threads: usize,
+    retries: usize,
     ffmpeg_threads: Option<usize>,
+    work_dir: Option<PathBuf>,
+    keep_work_dir: bool,
+    verify_integrity: bool,
     audio_locale_output_map: HashMap<Locale, String>,
     subtitle_locale_output_map: HashMap<Locale, String>,
 }
@@ -76,6 +170,7 @@ impl DownloadBuilder {
             client,
             rate_limiter,
             ffmpeg_preset: FFmpegPreset::default(),
+            audio_channel: None,
             default_subtitle: None,
             output_format: None,
             audio_sort: None,
@@ -83,9 +178,33 @@ impl DownloadBuilder {
             force_hardsub: false,
             download_fonts: false,
             no_closed_caption: false,
+            prefer_sdh: false,
             sync_start_value: None,
+            merge_sync_tolerance: None,
+            merge_sync_precision: None,
+            merge_sync_max_offset_variance: None,
+            clip_start: None,
+            clip_duration: None,
+            metadata_tags: vec![],
+            info_json: None,
+            subtitle_output: SubtitleOutput::Embed,
+            subtitle_format: SubtitleFormat::Srt,
+            subtitle_charset: "utf-8".to_string(),
+            subtitle_style: SubtitleStyleOverrides::default(),
+            subtitle_sync: None,
+            encode_preset: None,
+            force_color_transfer: None,
+            fragment_duration: None,
+            disable_faststart: false,
+            video_track_timescale: None,
+            hls_output: false,
+            accelerate_skip_events: AccelerateFactors::default(),
             threads: num_cpus::get(),
+            retries: 5,
             ffmpeg_threads: None,
+            work_dir: None,
+            keep_work_dir: false,
+            verify_integrity: false,
             audio_locale_output_map: HashMap::new(),
             subtitle_locale_output_map: HashMap::new(),
         }
@@ -96,6 +215,7 @@ impl DownloadBuilder {
             client: self.client,
             rate_limiter: self.rate_limiter,
             ffmpeg_preset: self.ffmpeg_preset,
+            audio_channel: self.audio_channel,
             default_subtitle: self.default_subtitle,
             output_format: self.output_format,
             audio_sort: self.audio_sort,
@@ -104,11 +224,36 @@ impl DownloadBuilder {
             force_hardsub: self.force_hardsub,
             download_fonts: self.download_fonts,
             no_closed_caption: self.no_closed_caption,
+            prefer_sdh: self.prefer_sdh,
 
             sync_start_value: self.sync_start_value,
+            merge_sync_tolerance: self.merge_sync_tolerance,
+            merge_sync_precision: self.merge_sync_precision,
+            merge_sync_max_offset_variance: self.merge_sync_max_offset_variance,
+            clip_start: self.clip_start,
+            clip_duration: self.clip_duration,
+
+            metadata_tags: self.metadata_tags,
+            info_json: self.info_json,
+            subtitle_output: self.subtitle_output,
+            subtitle_format: self.subtitle_format,
+            subtitle_charset: self.subtitle_charset,
+            subtitle_style: self.subtitle_style,
+            subtitle_sync: self.subtitle_sync,
+            encode_preset: self.encode_preset,
+            force_color_transfer: self.force_color_transfer,
+            fragment_duration: self.fragment_duration,
+            disable_faststart: self.disable_faststart,
+            video_track_timescale: self.video_track_timescale,
+            hls_output: self.hls_output,
+            accelerate_skip_events: self.accelerate_skip_events,
 
             download_threads: self.threads,
+            retries: self.retries,
             ffmpeg_threads: self.ffmpeg_threads,
+            work_dir: self.work_dir,
+            keep_work_dir: self.keep_work_dir,
+            verify_integrity: self.verify_integrity,
 
             formats: vec![],
 
@@ -122,6 +267,40 @@ struct FFmpegVideoMeta {
     path: TempPath,
     length: TimeDelta,
     start_time: Option<TimeDelta>,
+    color: ColorInfo,
+}
+
+/// `color_primaries`/`color_transfer`/`color_space` read off a downloaded video segment via
+/// ffprobe. `None` covers both "ffprobe reported nothing" and ffprobe's own `unknown` value, so SDR
+/// content never gets mislabeled with a copied-over HDR tag.
+#[derive(Default)]
+struct ColorInfo {
+    codec: String,
+    primaries: Option<String>,
+    transfer: Option<String>,
+    space: Option<String>,
+    mastering_display: Option<MasteringDisplay>,
+    content_light_level: Option<ContentLightLevel>,
+}
+
+/// HDR10 mastering display primaries/white point (CIE 1931 xy) and luminance range, as reported by
+/// ffprobe's `side_data_list`. Re-injected via the `hevc_metadata` bitstream filter's
+/// `master_display` option, which expects the same values scaled to its own fixed-point units.
+#[derive(Clone)]
+struct MasteringDisplay {
+    red: (f64, f64),
+    green: (f64, f64),
+    blue: (f64, f64),
+    white_point: (f64, f64),
+    min_luminance: f64,
+    max_luminance: f64,
+}
+
+/// HDR10 content/frame-average light level in cd/m², as reported by ffprobe's `side_data_list`.
+#[derive(Clone)]
+struct ContentLightLevel {
+    max_content: u32,
+    max_average: u32,
 }
 
 struct FFmpegAudioMeta {
@@ -133,14 +312,14 @@ struct FFmpegAudioMeta {
 struct FFmpegSubtitleMeta {
     path: TempPath,
     locale: Locale,
-    cc: bool,
+    kind: SubtitleKind,
     start_time: Option<TimeDelta>,
 }
 
 pub struct DownloadFormat {
     pub video: (StreamData, Locale),
     pub audios: Vec<(StreamData, Locale)>,
-    pub subtitles: Vec<(Subtitle, bool)>,
+    pub subtitles: Vec<(Subtitle, SubtitleKind)>,
     pub metadata: DownloadFormatMetadata,
 }
 
@@ -148,11 +327,18 @@ pub struct DownloadFormatMetadata {
     pub skip_events: Option<SkipEvents>,
 }
 
+/// Initial delay before the first retry of a failed segment request; doubled on each subsequent
+/// retry (capped at [`RETRY_MAX_DELAY`]) to back off from transient network/server failures instead
+/// of hammering a struggling endpoint.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(16);
+
 pub struct Downloader {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
 
     ffmpeg_preset: FFmpegPreset,
+    audio_channel: Option<FFmpegAudioChannel>,
     default_subtitle: Option<Locale>,
     output_format: Option<String>,
     audio_sort: Option<Vec<Locale>>,
@@ -161,11 +347,50 @@ pub struct Downloader {
     force_hardsub: bool,
     download_fonts: bool,
     no_closed_caption: bool,
+    prefer_sdh: bool,
 
     sync_start_value: Option<f64>,
+    merge_sync_tolerance: Option<u32>,
+    merge_sync_precision: Option<u32>,
+    /// Hard-fail threshold, in milliseconds, for how much a format's computed sync offset is
+    /// allowed to vary across [`crate::utils::sync::sync_audios`]' refinement runs before it's
+    /// rejected as unreliable instead of used, see [`Downloader::sync_via_audio`].
+    merge_sync_max_offset_variance: Option<u32>,
+    clip_start: Option<TimeDelta>,
+    clip_duration: Option<TimeDelta>,
+
+    metadata_tags: Vec<(String, String)>,
+    info_json: Option<String>,
+    subtitle_output: SubtitleOutput,
+    subtitle_format: SubtitleFormat,
+    subtitle_charset: String,
+    subtitle_style: SubtitleStyleOverrides,
+    subtitle_sync: Option<SubtitleSyncMode>,
+    encode_preset: Option<String>,
+    force_color_transfer: Option<String>,
+    fragment_duration: Option<f64>,
+    disable_faststart: bool,
+    /// Overrides the mov muxer's automatic video track timescale (samples/second the track's
+    /// timestamps are expressed in), e.g. so a CMAF/fragmented output's video and audio tracks
+    /// both express exact durations instead of one rounding against the other's framerate-derived
+    /// default. The audio track's timescale already comes from its own sample rate, so there's no
+    /// equivalent audio knob to expose here.
+    video_track_timescale: Option<u32>,
+    /// Writes a self-hosting-friendly single-rendition HLS VOD (proper init + media fMP4 segments
+    /// plus `.m3u8` playlists, no subtitles, no DASH) into `dst` as a directory instead of muxing
+    /// everything into one file at `dst`. See [`Downloader::download_hls`].
+    hls_output: bool,
+    /// Per-skip-event-type speed factor to accelerate that event's range to in the rendered output
+    /// instead of only marking it with a passive chapter, applied after muxing via
+    /// [`accelerate_ranges`].
+    accelerate_skip_events: AccelerateFactors,
 
     download_threads: usize,
+    retries: usize,
     ffmpeg_threads: Option<usize>,
+    work_dir: Option<PathBuf>,
+    keep_work_dir: bool,
+    verify_integrity: bool,
 
     formats: Vec<DownloadFormat>,
 
@@ -179,6 +404,14 @@ impl Downloader {
     }
 
     pub async fn download(mut self, dst: &Path) -> Result<()> {
+        if self.hls_output {
+            return self.download_hls(dst).await;
+        }
+
+        if self.try_native_remux(dst).await? {
+            return Ok(());
+        }
+
         // `.unwrap_or_default()` here unless https://doc.rust-lang.org/stable/std/path/fn.absolute.html
         // gets stabilized as the function might throw error on weird file paths
         let required = self.check_free_space(dst).await.unwrap_or_default();
@@ -225,13 +458,13 @@ impl Downloader {
             if let Some(subtitle_sort) = &self.subtitle_sort {
                 format
                     .subtitles
-                    .sort_by(|(a_subtitle, a_not_cc), (b_subtitle, b_not_cc)| {
+                    .sort_by(|(a_subtitle, a_kind), (b_subtitle, b_kind)| {
                         let ordering = subtitle_sort
                             .iter()
                             .position(|l| l == &a_subtitle.locale)
                             .cmp(&subtitle_sort.iter().position(|l| l == &b_subtitle.locale));
                         if matches!(ordering, Ordering::Equal) {
-                            a_not_cc.cmp(b_not_cc).reverse()
+                            a_kind.sort_priority().cmp(&b_kind.sort_priority())
                         } else {
                             ordering
                         }
@@ -247,6 +480,7 @@ impl Downloader {
         let mut subtitles = vec![];
         let mut fonts = vec![];
         let mut chapters = None;
+        let mut accelerate_events: Vec<(&str, &SkipEventsEvent, f64)> = vec![];
         let mut max_len = TimeDelta::min_value();
         let mut max_frames = 0;
         let fmt_space = self
@@ -260,41 +494,80 @@ impl Downloader {
             .max()
             .unwrap();
 
-        if self.formats.len() > 1 && self.sync_start_value.is_some() {
-            let all_segments_count: Vec<usize> = self
-                .formats
-                .iter()
-                .map(|f| f.video.0.segments().len())
-                .collect();
-            let sync_segments = 11.max(
-                all_segments_count.iter().max().unwrap() - all_segments_count.iter().min().unwrap(),
-            );
-            let mut sync_vids = vec![];
-            for (i, format) in self.formats.iter().enumerate() {
-                let path = self
-                    .download_video(
-                        &format.video.0,
-                        format!("Downloading video #{} sync segments", i + 1),
-                        Some(sync_segments),
+        if self.formats.len() > 1
+            && (self.sync_start_value.is_some() || self.merge_sync_tolerance.is_some())
+        {
+            let mut offsets = if let Some(sync_start_value) = self.sync_start_value {
+                let all_segments_count: Vec<usize> = self
+                    .formats
+                    .iter()
+                    .map(|f| f.video.0.segments().len())
+                    .collect();
+                let sync_segments = 11.max(
+                    all_segments_count.iter().max().unwrap()
+                        - all_segments_count.iter().min().unwrap(),
+                );
+                let mut sync_vids = vec![];
+                for (i, format) in self.formats.iter().enumerate() {
+                    let path = self
+                        .download_video(
+                            &format.video.0,
+                            format!("Downloading video #{} sync segments", i + 1),
+                            Some(sync_segments),
+                            None,
+                        )
+                        .await?;
+                    sync_vids.push(SyncVideo {
+                        path,
+                        length: len_from_segments(&format.video.0.segments()),
+                        available_frames: (len_from_segments(
+                            &format.video.0.segments()[0..sync_segments],
+                        )
+                        .num_milliseconds() as f64
+                            * format.video.0.fps().unwrap()
+                            / 1000.0) as u64,
+                        idx: i,
+                    })
+                }
+
+                let _progress_handler =
+                    progress!("Syncing video start times (this might take some time)");
+                let offsets = sync_videos(sync_vids, sync_start_value)?;
+                drop(_progress_handler);
+
+                if offsets.is_some() {
+                    offsets
+                } else {
+                    // frame-hashing couldn't confidently align every format (e.g. regional releases
+                    // with the same audio bed but different intro cards/logos/censorship, which
+                    // looks nothing alike but sounds identical) - fall back to the same chromaprint
+                    // audio cross-correlation `--merge sync` uses below before giving up entirely
+                    debug!("Frame-hash sync found no confident match, falling back to audio cross-correlation");
+                    self.sync_via_audio(
+                        self.merge_sync_tolerance.unwrap_or(6),
+                        self.merge_sync_precision.unwrap_or(4),
+                        self.merge_sync_max_offset_variance.unwrap_or(250),
                     )
-                    .await?;
-                sync_vids.push(SyncVideo {
-                    path,
-                    length: len_from_segments(&format.video.0.segments()),
-                    available_frames: (len_from_segments(
-                        &format.video.0.segments()[0..sync_segments],
+                    .await?
+                }
+            } else {
+                // `MergeBehavior::Sync`: instead of frame-hashing the video, the offset between
+                // each format's leading audio track is found via chromaprint cross-correlation,
+                // which is more reliable than comparing frames when the source is grainy or has
+                // letterboxing. Each format's leading audio track is downloaded in full for this,
+                // not just a short sample
+                let _progress_handler =
+                    progress!("Syncing audio start times (this might take some time)");
+                let offsets = self
+                    .sync_via_audio(
+                        self.merge_sync_tolerance.unwrap(),
+                        self.merge_sync_precision.unwrap_or(4),
+                        self.merge_sync_max_offset_variance.unwrap_or(250),
                     )
-                    .num_milliseconds() as f64
-                        * format.video.0.fps().unwrap()
-                        / 1000.0) as u64,
-                    idx: i,
-                })
-            }
-
-            let _progress_handler =
-                progress!("Syncing video start times (this might take some time)");
-            let mut offsets = sync_videos(sync_vids, self.sync_start_value.unwrap())?;
-            drop(_progress_handler);
+                    .await?;
+                drop(_progress_handler);
+                offsets
+            };
 
             let mut offset_pre_checked = false;
             if let Some(tmp_offsets) = &offsets {
@@ -403,17 +676,50 @@ impl Downloader {
             }
         }
 
-        // downloads all videos
-        for (i, format) in self.formats.iter().enumerate() {
-            let path = self
-                .download_video(
-                    &format.video.0,
-                    format!("{:<1$}", format!("Downloading video #{}", i + 1), fmt_space),
-                    None,
-                )
-                .await?;
-
-            let (len, fps) = get_video_stats(&path)?;
+        // a plain reference so the concurrent downloads below can each borrow it independently
+        // instead of every future trying to move the whole (owned) `self` into itself
+        let self_ref = &self;
+
+        // downloads all videos, up to `download_threads` at once instead of one after another -
+        // every format's video is an independent request, so there's nothing to gain from serializing
+        // them the way the segments within a single video still are
+        let mut video_results: Vec<(usize, Result<(TempPath, TimeDelta, f64, ColorInfo)>)> =
+            stream::iter(self.formats.iter().enumerate().map(|(i, format)| async move {
+                let result: Result<_> = async {
+                    let mut path = self_ref
+                        .download_video(
+                            &format.video.0,
+                            format!("{:<1$}", format!("Downloading video #{}", i + 1), fmt_space),
+                            None,
+                            Some(resume_cache_dir(self_ref.work_dir.as_deref(), dst, &format!("video-{}", i))),
+                        )
+                        .await?;
+                    if let Some(encoder) = &self_ref.encode_preset {
+                        path = reencode_by_scene(
+                            &path,
+                            encoder,
+                            self_ref.ffmpeg_threads,
+                            &format!("{:<1$}", format!("Re-encoding video #{}", i + 1), fmt_space),
+                        )
+                        .await?;
+                    }
+                    let (len, fps) = get_video_stats(&path)?;
+                    let mut color = probe_color_info(&path)?;
+                    if let Some(transfer) = &self_ref.force_color_transfer {
+                        color.transfer = Some(transfer.clone());
+                    }
+                    Ok((path, len, fps, color))
+                }
+                .await;
+                (i, result)
+            }))
+            .buffer_unordered(self.download_threads.max(1))
+            .collect()
+            .await;
+        video_results.sort_by_key(|(i, _)| *i);
+
+        for (_, result) in video_results {
+            let (path, len, fps, color) = result?;
             if max_len < len {
                 max_len = len
             }
@@ -428,25 +734,52 @@ impl Downloader {
             videos.push(FFmpegVideoMeta {
                 path,
                 length: len,
-                start_time: video_offset,
+                start_time: combine_clip_start(video_offset, self.clip_start),
+                color,
             })
         }
 
-        // downloads all audios
-        for format in &self.formats {
-            for (j, (stream_data, locale)) in format.audios.iter().enumerate() {
-                let path = self
-                    .download_audio(
-                        stream_data,
-                        format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
-                    )
-                    .await?;
-                audios.push(FFmpegAudioMeta {
-                    path,
-                    locale: locale.clone(),
-                    start_time: audio_offsets.get(&j).cloned(),
-                })
-            }
+        // downloads all audios with the same bounded concurrency as the video pass above
+        let audio_jobs: Vec<(usize, usize, &StreamData, &Locale)> = self
+            .formats
+            .iter()
+            .enumerate()
+            .flat_map(|(i, format)| {
+                format
+                    .audios
+                    .iter()
+                    .enumerate()
+                    .map(move |(j, (stream_data, locale))| (i, j, stream_data, locale))
+            })
+            .collect();
+        let mut audio_results: Vec<(usize, usize, Result<TempPath>)> = stream::iter(
+            audio_jobs
+                .iter()
+                .map(|&(i, j, stream_data, locale)| async move {
+                    let result = self_ref
+                        .download_audio(
+                            stream_data,
+                            format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
+                            Some(resume_cache_dir(self_ref.work_dir.as_deref(), dst, &format!("audio-{}-{}", i, j))),
+                        )
+                        .await;
+                    (i, j, result)
+                }),
+        )
+        .buffer_unordered(self.download_threads.max(1))
+        .collect()
+        .await;
+        // sorting by (format index, audio index) restores the exact nested order `audio_jobs` was
+        // built in above, so it can be zipped back with it to recover each result's locale
+        audio_results.sort_by_key(|(i, j, _)| (*i, *j));
+
+        for ((_, j, result), &(_, _, _, locale)) in audio_results.into_iter().zip(&audio_jobs) {
+            let path = result?;
+            audios.push(FFmpegAudioMeta {
+                path,
+                locale: locale.clone(),
+                start_time: combine_clip_start(audio_offsets.get(&j).cloned(), self.clip_start),
+            })
         }
 
         for (i, format) in self.formats.iter().enumerate() {
@@ -474,8 +807,8 @@ impl Downloader {
                 None
             };
 
-            for (j, (subtitle, not_cc)) in format.subtitles.iter().enumerate() {
-                if !not_cc && self.no_closed_caption {
+            for (j, (subtitle, kind)) in format.subtitles.iter().enumerate() {
+                if *kind == SubtitleKind::ClosedCaption && self.no_closed_caption {
                     continue;
                 }
 
@@ -485,8 +818,10 @@ impl Downloader {
                         progress_message += ", "
                     }
                     progress_message += &subtitle.locale.to_string();
-                    if !not_cc {
-                        progress_message += " (CC)";
+                    match kind {
+                        SubtitleKind::ClosedCaption => progress_message += " (CC)",
+                        SubtitleKind::Forced => progress_message += " (Forced)",
+                        SubtitleKind::Regular => {}
                     }
                     if i.min(videos.len() - 1) != 0 {
                         progress_message += &format!(" [Video: #{}]", i + 1);
@@ -498,34 +833,80 @@ impl Downloader {
                     .download_subtitle(subtitle.clone(), videos[i.min(videos.len() - 1)].length)
                     .await?;
                 debug!(
-                    "Downloaded {} subtitles{}",
-                    subtitle.locale,
-                    (!not_cc).then_some(" (cc)").unwrap_or_default(),
+                    "Downloaded {} subtitles ({:?})",
+                    subtitle.locale, kind
                 );
-                subtitles.push(FFmpegSubtitleMeta {
-                    path,
-                    locale: subtitle.locale.clone(),
-                    cc: !not_cc,
-                    start_time: subtitle_offsets.get(&j).cloned(),
-                })
+
+                // a `--merge sync` offset is baked directly into the ass timeline (instead of
+                // seeking past it like the video/audio inputs are) since not every ffmpeg subtitle
+                // demuxer honors '-ss' on a subtitle input the same way it does for audio/video
+                if let Some(offset) = subtitle_offsets.get(&j).filter(|o| !o.is_zero()) {
+                    shift_subtitle_file(&path, *offset)?;
+                }
+
+                // aligns against the matching audio track's voice activity instead of the video's
+                // own sync offset, since region-mismatched subtitles can drift independently of it
+                if let Some(sync_mode) = &self.subtitle_sync {
+                    if let Some(audio) = audios.iter().find(|a| a.locale == subtitle.locale) {
+                        sync_subtitle_file(&path, &audio.path, sync_mode.is_split())?;
+                    }
+                }
+
+                // reuses the ass payload that was just downloaded/fixed up for muxing instead of
+                // fetching the subtitle a second time, since both end up wanting the same content
+                if self.subtitle_output.writes_external() {
+                    let locale_tag = self
+                        .subtitle_locale_output_map
+                        .get(&subtitle.locale)
+                        .cloned()
+                        .unwrap_or_else(|| subtitle.locale.to_string());
+                    write_subtitle_sidecar(
+                        &path,
+                        dst,
+                        &locale_tag,
+                        self.subtitle_format,
+                        &self.subtitle_charset,
+                        format.video.0.fps().unwrap(),
+                    )?;
+                }
+
+                if self.subtitle_output.embeds() {
+                    subtitles.push(FFmpegSubtitleMeta {
+                        path,
+                        locale: subtitle.locale.clone(),
+                        kind: *kind,
+                        start_time: self.clip_start,
+                    })
+                }
             }
         }
 
         for format in self.formats.iter() {
             if let Some(skip_events) = &format.metadata.skip_events {
-                let (file, path) = tempfile(".chapter")?.into_parts();
-                chapters = Some((
-                    (file, path),
-                    [
-                        skip_events.recap.as_ref().map(|e| ("Recap", e)),
-                        skip_events.intro.as_ref().map(|e| ("Intro", e)),
-                        skip_events.credits.as_ref().map(|e| ("Credits", e)),
-                        skip_events.preview.as_ref().map(|e| ("Preview", e)),
-                    ]
+                let all_events: Vec<(&str, &SkipEventsEvent)> = [
+                    skip_events.recap.as_ref().map(|e| ("Recap", e)),
+                    skip_events.intro.as_ref().map(|e| ("Intro", e)),
+                    skip_events.credits.as_ref().map(|e| ("Credits", e)),
+                    skip_events.preview.as_ref().map(|e| ("Preview", e)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+
+                // an event type with an `--accelerate-*` factor set is sped up in the rendered
+                // output instead of only getting a passive chapter marker - a viewer who's already
+                // watching it play back fast doesn't also need a chapter to seek past it
+                let (accelerated, chaptered): (Vec<_>, Vec<_>) = all_events
                     .into_iter()
-                    .flatten()
-                    .collect::<Vec<(&str, &SkipEventsEvent)>>(),
-                ));
+                    .partition(|(name, _)| self.accelerate_skip_events.factor_for(name).is_some());
+                accelerate_events.extend(accelerated.into_iter().map(|(name, event)| {
+                    (name, event, self.accelerate_skip_events.factor_for(name).unwrap())
+                }));
+
+                if !chaptered.is_empty() {
+                    let (file, path) = tempfile(".chapter")?.into_parts();
+                    chapters = Some(((file, path), chaptered));
+                }
             }
         }
 
@@ -580,10 +961,22 @@ impl Downloader {
                     }
 
                     fonts.push(font)
+                } else {
+                    warn!(
+                        "Font '{}' is used by a subtitle but isn't in the embeddable font table; \
+                        it won't be attached and players without it installed locally may render \
+                        those lines with a fallback font",
+                        font_name
+                    )
                 }
             }
         }
 
+        // mp4/mov can express a track's start offset as an edit list (`-use_editlist`, set below)
+        // instead of trimming samples at the container level; mkv and everything else still falls
+        // back to a plain `-ss` seek
+        let use_edit_list = ["mov", "mp4"].contains(&dst.extension().unwrap_or_default().to_str().unwrap());
+
         let mut input = vec![];
         let mut maps = vec![];
         let mut attachments = vec![];
@@ -591,7 +984,10 @@ impl Downloader {
 
         for (i, meta) in videos.iter().enumerate() {
             if let Some(start_time) = meta.start_time {
-                input.extend(["-ss".to_string(), format_time_delta(start_time)])
+                input.extend(seek_args(start_time, use_edit_list))
+            }
+            if let Some(clip_duration) = self.clip_duration {
+                input.extend(["-t".to_string(), format_time_delta(clip_duration)])
             }
             input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
             maps.extend(["-map".to_string(), i.to_string()]);
@@ -608,11 +1004,57 @@ impl Downloader {
             ]);
             // the empty language metadata is created to avoid that metadata from the original track
             // is copied
-            metadata.extend([format!("-metadata:s:v:{}", i), "language=".to_string()])
+            metadata.extend([format!("-metadata:s:v:{}", i), "language=".to_string()]);
+
+            // re-assert the color signaling detected on the actual downloaded segment, since a
+            // plain stream-copy mux can otherwise silently drop it and leave players to guess
+            if let Some(primaries) = &meta.color.primaries {
+                metadata.extend([format!("-color_primaries:v:{}", i), primaries.clone()])
+            }
+            if let Some(transfer) = &meta.color.transfer {
+                metadata.extend([format!("-color_trc:v:{}", i), transfer.clone()])
+            }
+            if let Some(space) = &meta.color.space {
+                metadata.extend([format!("-colorspace:v:{}", i), space.clone()])
+            }
+
+            // mastering-display/CLL side data only round-trips through a stream copy via a codec-
+            // specific bitstream filter; HDR10 sources in this pipeline are HEVC, so that's the only
+            // codec this re-injects it for
+            if meta.color.codec == "hevc" {
+                let mut bsf_opts = vec![];
+                if let Some(md) = &meta.color.mastering_display {
+                    bsf_opts.push(format!(
+                        "master_display=G({},{})B({},{})R({},{})WP({},{})L({},{})",
+                        (md.green.0 * 50000.0).round() as u64,
+                        (md.green.1 * 50000.0).round() as u64,
+                        (md.blue.0 * 50000.0).round() as u64,
+                        (md.blue.1 * 50000.0).round() as u64,
+                        (md.red.0 * 50000.0).round() as u64,
+                        (md.red.1 * 50000.0).round() as u64,
+                        (md.white_point.0 * 50000.0).round() as u64,
+                        (md.white_point.1 * 50000.0).round() as u64,
+                        (md.max_luminance * 10000.0).round() as u64,
+                        (md.min_luminance * 10000.0).round() as u64,
+                    ))
+                }
+                if let Some(cll) = &meta.color.content_light_level {
+                    bsf_opts.push(format!("max_cll={},{}", cll.max_content, cll.max_average))
+                }
+                if !bsf_opts.is_empty() {
+                    metadata.extend([
+                        format!("-bsf:v:{}", i),
+                        format!("hevc_metadata={}", bsf_opts.join(":")),
+                    ])
+                }
+            }
         }
         for (i, meta) in audios.iter().enumerate() {
             if let Some(start_time) = meta.start_time {
-                input.extend(["-ss".to_string(), format_time_delta(start_time)])
+                input.extend(seek_args(start_time, use_edit_list))
+            }
+            if let Some(clip_duration) = self.clip_duration {
+                input.extend(["-t".to_string(), format_time_delta(clip_duration)])
             }
             input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
             maps.extend(["-map".to_string(), (i + videos.len()).to_string()]);
@@ -646,16 +1088,41 @@ impl Downloader {
             ])
         }
 
+        // global Matroska tags (title, series, episode number, release date, ...) set via
+        // `--metadata`/`DownloadBuilder::metadata_tags`, so the output file is self-describing to
+        // media servers without a separate NFO sidecar
+        for (key, value) in &self.metadata_tags {
+            metadata.extend(["-metadata".to_string(), format!("{}={}", key, value)])
+        }
+
+        // attaches the full format metadata as a JSON file inside the container, set via
+        // `--embed-info-json`/`DownloadBuilder::info_json`. The temp file path has to be kept
+        // alive until ffmpeg runs, hence it living in this outer scope instead of a block
+        let mut _info_json_path = None;
+        if let Some(info_json) = &self.info_json {
+            let (mut file, path) = tempfile(".json")?.into_parts();
+            file.write_all(info_json.as_bytes())?;
+            attachments.extend(["-attach".to_string(), path.to_string_lossy().to_string()]);
+            metadata.extend([
+                format!("-metadata:s:t:{}", fonts.len()),
+                "mimetype=application/json".to_string(),
+            ]);
+            _info_json_path = Some(path);
+        }
+
         // this formats are supporting embedding subtitles into the video container instead of
         // burning it into the video stream directly
         let container_supports_softsubs = !self.force_hardsub
-            && ["mkv", "mov", "mp4"]
+            && ["mkv", "mov", "mp4", "webm"]
                 .contains(&dst.extension().unwrap_or_default().to_str().unwrap());
 
         if container_supports_softsubs {
             for (i, meta) in subtitles.iter().enumerate() {
                 if let Some(start_time) = meta.start_time {
-                    input.extend(["-ss".to_string(), format_time_delta(start_time)])
+                    input.extend(seek_args(start_time, use_edit_list))
+                }
+                if let Some(clip_duration) = self.clip_duration {
+                    input.extend(["-t".to_string(), format_time_delta(clip_duration)])
                 }
                 input.extend(["-i".to_string(), meta.path.to_string_lossy().to_string()]);
                 maps.extend([
@@ -675,8 +1142,10 @@ impl Downloader {
                     format!("-metadata:s:s:{}", i),
                     format!("title={}", {
                         let mut title = meta.locale.to_string();
-                        if meta.cc {
-                            title += " (CC)"
+                        match meta.kind {
+                            SubtitleKind::ClosedCaption => title += " (CC)",
+                            SubtitleKind::Forced => title += " (Forced)",
+                            SubtitleKind::Regular => {}
                         }
                         if videos.len() > 1 {
                             title += &format!(" [Video: #{}]", i + 1)
@@ -687,22 +1156,43 @@ impl Downloader {
             }
         }
 
+        // the actual muxed program length: `max_len` clipped to whatever `--start`/`--duration`
+        // carved out of it, reused below both for chapter timestamps and `--verify-integrity`
+        let clipped_len = {
+            let clip_start = self.clip_start.unwrap_or_default();
+            let clipped = (max_len - clip_start).max(TimeDelta::zero());
+            self.clip_duration.map_or(clipped, |d| d.min(clipped))
+        };
+
         if let Some(((file, path), chapters)) = chapters.as_mut() {
-            write_ffmpeg_chapters(file, max_len, chapters)?;
+            let clip_start = self.clip_start.unwrap_or_default();
+            write_ffmpeg_chapters(file, clipped_len, chapters, clip_start)?;
             input.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
+            let chapter_input_index = (videos.len()
+                + audios.len()
+                + container_supports_softsubs
+                    .then_some(subtitles.len())
+                    .unwrap_or_default())
+            .to_string();
+            // spelled out explicitly instead of relying on ffmpeg's default chapter source
+            // selection, so the chapter file above is unambiguously where the muxed chapters come
+            // from even if a future input (e.g. a font attachment) happens to carry its own
             maps.extend([
                 "-map_metadata".to_string(),
-                (videos.len()
-                    + audios.len()
-                    + container_supports_softsubs
-                        .then_some(subtitles.len())
-                        .unwrap_or_default())
-                .to_string(),
+                chapter_input_index.clone(),
+                "-map_chapters".to_string(),
+                chapter_input_index,
             ])
         }
 
+        if let Some(video) = videos.first() {
+            self.ffmpeg_preset = self.ffmpeg_preset.resolve_target_quality(&video.path)?;
+        }
+
         let preset_custom = matches!(self.ffmpeg_preset, FFmpegPreset::Custom(_));
-        let (input_presets, mut output_presets) = self.ffmpeg_preset.into_input_output_args();
+        let (input_presets, mut output_presets) = self
+            .ffmpeg_preset
+            .into_input_output_args(self.audio_channel.clone());
         let fifo = temp_named_pipe()?;
 
         let mut command_args = vec![
@@ -722,20 +1212,76 @@ impl Downloader {
             }
         }
 
+        // finds the subtitle to show/default to for a locale. If `--prefer-sdh` is set and the
+        // locale has both a regular and a closed caption/SDH track, the SDH one is preferred; the
+        // other track (e.g. a forced track) stays in the file, it's just not the one marked default
+        let default_subtitle_position = |locale: &Locale| -> Option<usize> {
+            if self.prefer_sdh {
+                if let Some(position) = subtitles
+                    .iter()
+                    .position(|m| &m.locale == locale && m.kind == SubtitleKind::ClosedCaption)
+                {
+                    return Some(position);
+                }
+            }
+            subtitles.iter().position(|m| &m.locale == locale)
+        };
+
+        // lets the mov muxer actually turn the `-itsoffset` delays `seek_args` emitted above into
+        // `elst` edit-list entries instead of silently ignoring them
+        if use_edit_list {
+            output_presets.extend(["-use_editlist".to_string(), "1".to_string()]);
+        }
+
+        let is_progressive_mp4_like =
+            ["mov", "mp4", "m4a"].contains(&dst.extension().unwrap_or_default().to_str().unwrap());
+
+        // fragmented mp4 (moov with no samples, followed by moof+mdat fragments) is playable/
+        // seekable before the full file has downloaded and needs no separate faststart pass, unlike
+        // the flat mp4 `faststart` below produces
+        if use_edit_list {
+            if let Some(fragment_duration) = self.fragment_duration {
+                output_presets.extend([
+                    "-movflags".to_string(),
+                    "frag_keyframe+empty_moov+default_base_moof".to_string(),
+                    "-frag_duration".to_string(),
+                    ((fragment_duration * 1_000_000.0) as u64).to_string(),
+                ]);
+            }
+
+            if let Some(timescale) = self.video_track_timescale {
+                output_presets.extend([
+                    "-video_track_timescale".to_string(),
+                    timescale.to_string(),
+                ]);
+            }
+        }
+
+        // moves `moov` before `mdat` so playback (or an HTTP range request) can start before the
+        // file has fully downloaded, unconditionally unless the caller opts out or the fragmented
+        // path above is already streamable without it
+        if is_progressive_mp4_like
+            && !self.disable_faststart
+            && !(use_edit_list && self.fragment_duration.is_some())
+        {
+            output_presets.extend(["-movflags".to_string(), "faststart".to_string()]);
+        }
+
+        // none of these containers can hold the ASS subtitles are downloaded as, so every soft
+        // subtitle track (not just the default one) has to be transcoded into the format they do
+        // support: mov/mp4 only carry `mov_text`/`tx3g`, webm only carries WebVTT
+        if container_supports_softsubs {
+            match dst.extension().unwrap_or_default().to_str().unwrap() {
+                "mov" | "mp4" => output_presets.extend(["-c:s".to_string(), "mov_text".to_string()]),
+                "webm" => output_presets.extend(["-c:s".to_string(), "webvtt".to_string()]),
+                _ => {}
+            }
+        }
+
         // set default subtitle
         if let Some(default_subtitle) = self.default_subtitle {
-            if let Some(position) = subtitles.iter().position(|m| m.locale == default_subtitle) {
-                if container_supports_softsubs {
-                    match dst.extension().unwrap_or_default().to_str().unwrap() {
-                        "mov" | "mp4" => output_presets.extend([
-                            "-movflags".to_string(),
-                            "faststart".to_string(),
-                            "-c:s".to_string(),
-                            "mov_text".to_string(),
-                        ]),
-                        _ => (),
-                    }
-                } else {
+            if let Some(position) = default_subtitle_position(&default_subtitle) {
+                if !container_supports_softsubs {
                     // remove '-c:v copy' and '-c:a copy' from output presets as its causes issues with
                     // burning subs into the video
                     let mut last = String::new();
@@ -781,10 +1327,7 @@ impl Downloader {
             }
 
             if container_supports_softsubs {
-                if let Some(position) = subtitles
-                    .iter()
-                    .position(|meta| meta.locale == default_subtitle)
-                {
+                if let Some(position) = default_subtitle_position(&default_subtitle) {
                     command_args.extend([
                         format!("-disposition:s:s:{}", position),
                         "default".to_string(),
@@ -793,15 +1336,27 @@ impl Downloader {
             }
         }
 
-        // set the 'forced' flag to CC subtitles
+        // set the 'forced' flag on subtitles which only cover the foreign dialogue/signs a dub
+        // doesn't already translate, so players show them automatically even with subs off
         for (i, subtitle) in subtitles.iter().enumerate() {
-            if !subtitle.cc {
+            if subtitle.kind != SubtitleKind::Forced {
                 continue;
             }
 
             command_args.extend([format!("-disposition:s:s:{}", i), "forced".to_string()])
         }
 
+        // flag the primary locale (always the first audio track, see `audios` construction above)
+        // as the default track and explicitly clear the flag on every other one, since the
+        // downloaded segments can otherwise carry over a source 'default' disposition onto more
+        // than one muxed audio track
+        for i in 0..audios.len() {
+            command_args.extend([
+                format!("-disposition:a:{}", i),
+                if i == 0 { "default" } else { "0" }.to_string(),
+            ])
+        }
+
         command_args.extend(output_presets);
         if let Some(output_format) = self.output_format {
             command_args.extend(["-f".to_string(), output_format]);
@@ -828,7 +1383,7 @@ impl Downloader {
             }
         }
 
-        let ffmpeg = Command::new("ffmpeg")
+        let ffmpeg = ffmpeg_command()
             // pass ffmpeg stdout to real stdout only if output file is stdout
             .stdout(if dst.to_str().unwrap() == "-" {
                 Stdio::inherit()
@@ -856,7 +1411,80 @@ impl Downloader {
             bail!("{}", String::from_utf8_lossy(result.stderr.as_slice()))
         }
         ffmpeg_progress_cancel.cancel();
-        ffmpeg_progress.await?
+        ffmpeg_progress.await??;
+
+        // ffmpeg's own `-itsoffset`/`-use_editlist` (see `seek_args`) already wrote valid `elst`
+        // boxes for every offset start time, but it's still just one sample-accurate `-ss` seek
+        // away from the same PTS rounding `--audio-sync` is trying to eliminate in the first
+        // place - replace them with exact edit lists computed straight from `audio_offsets`
+        // wherever that's safe, and just keep ffmpeg's own result otherwise
+        if use_edit_list && !audio_offsets.is_empty() && !is_special_file(dst) && dst.to_string_lossy() != "-" {
+            let ms_offsets: HashMap<usize, i64> = audio_offsets
+                .iter()
+                .map(|(i, offset)| (*i, offset.num_milliseconds()))
+                .collect();
+            let data = fs::read(dst)?;
+            match rewrite_audio_edit_lists(&data, &ms_offsets)? {
+                EditListResult::Rewritten(out) => fs::write(dst, out)?,
+                EditListResult::Unsupported => debug!(
+                    "Could not natively rewrite audio edit lists, keeping ffmpeg's own '-use_editlist' output"
+                ),
+            }
+        }
+
+        if !accelerate_events.is_empty() && !is_special_file(dst) && dst.to_string_lossy() != "-" {
+            // burned-in hardsubs move with the accelerated frames for free, but a soft subtitle
+            // track's own timestamps wouldn't be retimed along with the shortened video/audio, so
+            // it would silently drift out of sync - skip acceleration rather than risk that
+            if container_supports_softsubs && !subtitles.is_empty() {
+                warn!(
+                    "--accelerate-* factors are set but the output has embedded soft subtitles, \
+                    which would drift out of sync with the shortened timeline; skipping acceleration"
+                );
+            } else if audios.len() > 1 {
+                warn!(
+                    "--accelerate-* factors only support a single muxed audio track right now; \
+                    skipping acceleration"
+                );
+            } else {
+                let ranges = build_accelerate_ranges(
+                    &accelerate_events,
+                    clipped_len,
+                    self.clip_start.unwrap_or_default(),
+                );
+                if !ranges.is_empty() {
+                    let encoder = self
+                        .encode_preset
+                        .clone()
+                        .unwrap_or_else(|| "libx264".to_string());
+                    let accelerated = accelerate_ranges(
+                        dst,
+                        &ranges,
+                        clipped_len.num_milliseconds() as f64 / 1000.0,
+                        &encoder,
+                    )?;
+                    fs::write(dst, fs::read(&accelerated)?)?;
+                }
+            }
+        }
+
+        if self.verify_integrity && !is_special_file(dst) && dst.to_string_lossy() != "-" {
+            verify_output_integrity(
+                dst,
+                videos.len(),
+                audios.len(),
+                container_supports_softsubs
+                    .then_some(subtitles.len())
+                    .unwrap_or_default(),
+                clipped_len,
+            )?;
+        }
+
+        if !self.keep_work_dir {
+            let _ = fs::remove_dir_all(episode_work_dir(self.work_dir.as_deref(), dst));
+        }
+
+        Ok(())
     }
 
     async fn check_free_space(
@@ -924,27 +1552,117 @@ impl Downloader {
         stream_data: &StreamData,
         message: String,
         max_segments: Option<usize>,
+        resume_dir: Option<PathBuf>,
     ) -> Result<TempPath> {
         let tempfile = tempfile(".mp4")?;
         let (mut file, path) = tempfile.into_parts();
 
-        self.download_segments(&mut file, message, stream_data, max_segments)
+        self.download_segments(
+            &mut file,
+            message,
+            stream_data,
+            max_segments,
+            resume_dir.as_deref(),
+        )
+        .await?;
+
+        Ok(path)
+    }
+
+    /// Exposed crate-wide (instead of private) so `--update` can fetch a single missing audio track
+    /// without going through the full `download()` pipeline, which always bundles a video stream.
+    pub(crate) async fn download_audio(
+        &self,
+        stream_data: &StreamData,
+        message: String,
+        resume_dir: Option<PathBuf>,
+    ) -> Result<TempPath> {
+        let tempfile = tempfile(".m4a")?;
+        let (mut file, path) = tempfile.into_parts();
+
+        self.download_segments(&mut file, message, stream_data, None, resume_dir.as_deref())
             .await?;
 
         Ok(path)
     }
 
-    async fn download_audio(&self, stream_data: &StreamData, message: String) -> Result<TempPath> {
+    /// Same as [`Self::download_audio`], but only fetches the leading `max_segments` segments
+    /// instead of the whole track. Used by `--verify-duplicates` to sample just enough audio to
+    /// fingerprint a candidate without paying for its full download.
+    pub(crate) async fn download_audio_sample(
+        &self,
+        stream_data: &StreamData,
+        message: String,
+        max_segments: usize,
+    ) -> Result<TempPath> {
         let tempfile = tempfile(".m4a")?;
         let (mut file, path) = tempfile.into_parts();
 
-        self.download_segments(&mut file, message, stream_data, None)
+        self.download_segments(&mut file, message, stream_data, Some(max_segments), None)
             .await?;
 
         Ok(path)
     }
 
-    async fn download_subtitle(
+    /// Downloads each format's leading audio track in full (not a shorter sample) and
+    /// cross-correlates them with [`sync_audios`], converting its millisecond offsets into the frame counts the rest of the
+    /// sync logic (inherited from the frame-hash path) works in. Shared by `--merge sync` and the
+    /// frame-hash sync path's audio fallback. Bails with [`sync_audios`]' error if a format's
+    /// offset varies by more than `max_offset_variance` across its refinement runs.
+    async fn sync_via_audio(
+        &self,
+        sync_tolerance: u32,
+        sync_precision: u32,
+        max_offset_variance: u32,
+    ) -> Result<Option<HashMap<usize, u64>>> {
+        let mut sync_audio_list = vec![];
+        for (i, format) in self.formats.iter().enumerate() {
+            let (stream_data, locale) = format.audios.first().unwrap();
+            let path = self
+                .download_audio(
+                    stream_data,
+                    format!("Downloading audio #{} sync sample", i + 1),
+                    None,
+                )
+                .await?;
+            sync_audio_list.push(SyncAudio {
+                format_id: i,
+                path,
+                locale: locale.clone(),
+                video_idx: i,
+            })
+        }
+
+        let offsets = sync_audios(
+            &sync_audio_list,
+            sync_tolerance,
+            sync_precision,
+            max_offset_variance,
+        )?;
+
+        // the rest of this function works in frames (inherited from the video-hash sync path), so
+        // the millisecond offsets `sync_audios` returns are converted to the equivalent frame count
+        // of their format
+        Ok(offsets.map(|offsets| {
+            offsets
+                .into_iter()
+                .map(|(i, sync_offset)| {
+                    let fps = self.formats[i].video.0.fps().unwrap();
+                    debug!(
+                        "Format {} audio sync offset confidence: {} runs, {:.0}ms stddev, {:.2} bit error rate",
+                        i, sync_offset.runs, sync_offset.stddev_ms, sync_offset.bit_error_rate
+                    );
+                    (
+                        i,
+                        (sync_offset.offset.num_milliseconds().max(0) as f64 / 1000.0 * fps) as u64,
+                    )
+                })
+                .collect()
+        }))
+    }
+
+    /// Exposed crate-wide for the same reason as [`Self::download_audio`].
+    pub(crate) async fn download_subtitle(
         &self,
         subtitle: Subtitle,
         max_length: TimeDelta,
@@ -955,12 +1673,25 @@ impl Downloader {
         let mut buf = vec![];
         subtitle.write_to(&mut buf).await?;
         fix_subtitles(&mut buf, max_length);
+        if !self.subtitle_style.is_empty() {
+            buf = apply_subtitle_style(&String::from_utf8_lossy(&buf), &self.subtitle_style).into_bytes();
+        }
 
         file.write_all(buf.as_slice())?;
 
         Ok(path)
     }
 
+    /// Fetches (or reuses the cached copy of) the `.woff2` file [`FONTS`] maps `name` to, the same
+    /// asset Crunchyroll's own web player embeds for offline-looking subtitle rendering. Returns
+    /// `None` (instead of erroring) when `name` isn't in the table, so a single unrecognized font
+    /// only costs that one font's embedding rather than the whole mux.
+    ///
+    /// Attached as-is rather than transcoded to `ttf`/`otf`: doing that correctly needs a full
+    /// WOFF2 decompressor (WOFF2's payload is a custom Brotli stream, plus glyph table
+    /// reconstruction for transformed `glyf`/`loca` tables), which is too large a dependency-free
+    /// undertaking to hand-roll reliably here. Players with FreeType built with WOFF2 support
+    /// (mpv/libass on any reasonably current distro) render the attachment fine regardless.
     async fn download_font(&self, name: &str) -> Result<Option<(PathBuf, bool)>> {
         let Some((_, font_file)) = FONTS.iter().find(|(f, _)| f == &name) else {
             return Ok(None);
@@ -988,12 +1719,133 @@ impl Downloader {
         Ok(Some((file, false)))
     }
 
+    /// Attempts to produce `dst` by natively reordering the already-downloaded video into
+    /// fast-start order (see [`fast_start_reorder`]) instead of shelling out to ffmpeg, for the
+    /// narrow case where ffmpeg wouldn't actually be doing any real muxing work anyway: a single
+    /// format with no audio/subtitle tracks, chapters, metadata, re-encoding or color overrides,
+    /// and no clipping. Returns `true` if `dst` was written and the caller should stop, `false` if
+    /// any of those conditions don't hold (or the reorder itself turns out to be unsupported for
+    /// this particular file) and the normal ffmpeg mux path below should run instead.
+    ///
+    /// Everything beyond this - muxing separate video/audio/subtitle tracks into one `moov`,
+    /// hardsub burning, chapters, attachments - still needs a real box-tree-aware muxer (building
+    /// `trak`/`stbl` per track) that this doesn't attempt; ffmpeg remains the path for all of that.
+    async fn try_native_remux(&self, dst: &Path) -> Result<bool> {
+        let ext = dst.extension().unwrap_or_default().to_str().unwrap();
+        if !["mp4", "mov"].contains(&ext) {
+            return Ok(false);
+        }
+
+        let [format] = self.formats.as_slice() else {
+            return Ok(false);
+        };
+        if !format.audios.is_empty()
+            || !format.subtitles.is_empty()
+            || format.metadata.skip_events.is_some()
+            || !self.metadata_tags.is_empty()
+            || self.info_json.is_some()
+            || self.encode_preset.is_some()
+            || self.force_color_transfer.is_some()
+            || self.clip_start.is_some()
+            || self.clip_duration.is_some()
+            || self.fragment_duration.is_some()
+            || self.disable_faststart
+            || self.video_track_timescale.is_some()
+        {
+            return Ok(false);
+        }
+
+        let video = self
+            .download_video(&format.video.0, "Downloading video".to_string(), None, None)
+            .await?;
+        let data = fs::read(&video)?;
+
+        let out = match fast_start_reorder(&data)? {
+            FastStartResult::Unsupported => return Ok(false),
+            FastStartResult::AlreadyFastStart => data,
+            FastStartResult::Reordered(reordered) => reordered,
+        };
+
+        if let Some(parent) = dst.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(dst, out)?;
+        Ok(true)
+    }
+
+    /// Writes the first format's video and (first) audio track out as a single-rendition HLS VOD
+    /// instead of muxing them into one file, so the result can be served as-is by any static file
+    /// host/CDN. Scoped to one rendition - full multi-bitrate adaptive streaming would need every
+    /// format's video re-encoded to a shared set of resolutions/bitrates, which is a much bigger
+    /// feature than "write out what was already fetched as HLS". Subtitles aren't carried over
+    /// either (mov_text/WebVTT HLS renditions are their own feature), and DASH (`.mpd`) output
+    /// isn't implemented - this writes HLS only.
+    ///
+    /// `dst` is treated as a directory (created if missing) rather than a single output file, since
+    /// an HLS VOD is inherently a playlist plus several segment files.
+    ///
+    /// Video and audio are first fetched the same way any other download is (through
+    /// [`Self::download_video`]/[`Self::download_audio`], so the usual segment-level resume cache
+    /// still applies), then each handed to [`write_hls_fmp4_rendition`] to be remuxed into a real
+    /// init-segment-plus-media-segments HLS rendition instead of hand-writing a playlist over the
+    /// raw per-segment cache files.
+    async fn download_hls(&self, dst: &Path) -> Result<()> {
+        let format = self
+            .formats
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No format was added to download as HLS"))?;
+        let (audio, audio_locale) = format
+            .audios
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Format has no audio track to download as HLS"))?;
+
+        fs::create_dir_all(dst)?;
+        let video_dir = dst.join("video");
+        let audio_dir = dst.join("audio");
+
+        let video = self
+            .download_video(
+                &format.video.0,
+                "Downloading video".to_string(),
+                None,
+                Some(video_dir.join(".segments")),
+            )
+            .await?;
+        let audio_file = self
+            .download_audio(
+                audio,
+                "Downloading audio".to_string(),
+                Some(audio_dir.join(".segments")),
+            )
+            .await?;
+
+        write_hls_fmp4_rendition(&video, &video_dir)?;
+        write_hls_fmp4_rendition(&audio_file, &audio_dir)?;
+
+        fs::write(
+            dst.join("master.m3u8"),
+            format!(
+                "#EXTM3U\n\
+                 #EXT-X-VERSION:3\n\
+                 #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"{}\",DEFAULT=YES,AUTOSELECT=YES,URI=\"audio/playlist.m3u8\"\n\
+                 #EXT-X-STREAM-INF:BANDWIDTH={},AUDIO=\"audio\"\n\
+                 video/playlist.m3u8\n",
+                audio_locale, format.video.0.bandwidth
+            ),
+        )?;
+
+        Ok(())
+    }
+
     async fn download_segments(
         &self,
         writer: &mut impl Write,
         message: String,
         stream_data: &StreamData,
         max_segments: Option<usize>,
+        resume_dir: Option<&Path>,
     ) -> Result<()> {
         let mut segments = stream_data.segments();
         if let Some(max_segments) = max_segments {
@@ -1003,7 +1855,29 @@ impl Downloader {
         }
         let total_segments = segments.len();
 
-        let count = Arc::new(Mutex::new(0));
+        // segments a previous, interrupted run of this exact stream already fetched are read from
+        // the resume cache instead of being fetched again. everything still missing is handed to
+        // the worker threads below as usual, keeping its original segment index instead of being
+        // renumbered, since that index is both the cache file name and the final write position
+        let mut write_buf: BTreeMap<i32, Vec<u8>> = BTreeMap::new();
+        let mut pending: Vec<(usize, StreamSegment)> = vec![];
+        for (i, segment) in segments.iter().enumerate() {
+            match resume_dir.and_then(|dir| fs::read(dir.join(i.to_string())).ok()) {
+                Some(bytes) => {
+                    write_buf.insert(i as i32, bytes);
+                }
+                None => pending.push((i, segment.clone())),
+            }
+        }
+        if !write_buf.is_empty() {
+            debug!(
+                "Resuming download, {} of {} segments already cached",
+                write_buf.len(),
+                total_segments
+            );
+        }
+
+        let count = Arc::new(Mutex::new(write_buf.len()));
 
         let progress = if log::max_level() == LevelFilter::Info {
             let estimated_file_size = estimate_stream_data_file_size(stream_data, &segments);
@@ -1018,29 +1892,33 @@ impl Downloader {
                 )
                 .with_message(message)
                 .with_finish(ProgressFinish::Abandon);
+            let already_downloaded: u64 = write_buf.values().map(|b| b.len() as u64).sum();
+            progress.inc(already_downloaded);
             Some(progress)
         } else {
             None
         };
 
-        let cpus = self.download_threads.min(segments.len());
-        let mut segs: Vec<Vec<StreamSegment>> = Vec::with_capacity(cpus);
+        let cpus = self.download_threads.min(pending.len().max(1));
+        let mut segs: Vec<Vec<(usize, StreamSegment)>> = Vec::with_capacity(cpus);
         for _ in 0..cpus {
             segs.push(vec![])
         }
-        for (i, segment) in segments.clone().into_iter().enumerate() {
-            segs[i - ((i / cpus) * cpus)].push(segment);
+        for (n, entry) in pending.into_iter().enumerate() {
+            segs[n % cpus].push(entry);
         }
 
         let (sender, mut receiver) = unbounded_channel();
 
         let mut join_set: JoinSet<Result<()>> = JoinSet::new();
-        for num in 0..cpus {
+        for _ in 0..cpus {
             let thread_sender = sender.clone();
             let thread_segments = segs.remove(0);
             let thread_client = self.client.clone();
             let mut thread_rate_limiter = self.rate_limiter.clone();
             let thread_count = count.clone();
+            let thread_resume_dir = resume_dir.map(|dir| dir.to_path_buf());
+            let thread_retries = self.retries;
             join_set.spawn(async move {
                 let after_download_sender = thread_sender.clone();
 
@@ -1048,7 +1926,7 @@ impl Downloader {
                 // catch errors which get returned with `...?` and `bail!(...)` and that the thread
                 // itself can report that an error has occurred
                 let download = || async move {
-                    for (i, segment) in thread_segments.into_iter().enumerate() {
+                    for (index, segment) in thread_segments.into_iter() {
                         let mut retry_count = 0;
                         let buf = loop {
                             let request = thread_client
@@ -1060,7 +1938,7 @@ impl Downloader {
                                 request.send().await.map_err(anyhow::Error::new)
                             };
 
-                            let err = match response {
+                            let err = match response.and_then(|r| r.error_for_status().map_err(anyhow::Error::new)) {
                                 Ok(r) => match r.bytes().await {
                                     Ok(b) => break b.to_vec(),
                                     Err(e) => anyhow::Error::new(e)
@@ -1068,24 +1946,48 @@ impl Downloader {
                                 Err(e) => e,
                             };
 
-                            if retry_count == 5 {
-                                bail!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, num + (i * cpus), err)
+                            // a 4xx means the url itself is bad and retrying won't fix it, so fail
+                            // immediately instead of burning through the retry budget
+                            if let Some(status) = err
+                                .downcast_ref::<reqwest::Error>()
+                                .and_then(reqwest::Error::status)
+                            {
+                                if status.is_client_error() {
+                                    bail!("Segment {} request failed permanently with {}: {}", index, status, err)
+                                }
                             }
-                            debug!("Failed to download segment {} ({}). Retrying, {} out of 5 retries left", num + (i * cpus), err, 5 - retry_count);
+
+                            if retry_count == thread_retries {
+                                bail!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, index, err)
+                            }
+
+                            let delay = RETRY_BASE_DELAY
+                                .mul_f64(2f64.powi(retry_count as i32))
+                                .min(RETRY_MAX_DELAY);
+                            warn!("Failed to download segment {} ({}). Retrying in {:?}, {} out of {} retries left", index, err, delay, thread_retries - retry_count, thread_retries);
+                            tokio::time::sleep(delay).await;
 
                             retry_count += 1;
                         };
 
+                        // persist the segment to the resume cache before handing it off to the
+                        // writer, so a kill between here and the final mux still leaves it on disk
+                        // for the next run to pick up
+                        if let Some(dir) = &thread_resume_dir {
+                            fs::create_dir_all(dir)?;
+                            fs::write(dir.join(index.to_string()), &buf)?;
+                        }
+
                         let mut c = thread_count.lock().await;
                         debug!(
                             "Downloaded segment [{}/{} {:.2}%] {}",
-                            num + (i * cpus) + 1,
+                            index + 1,
                             total_segments,
                             ((*c + 1) as f64 / total_segments as f64) * 100f64,
                             segment.url
                         );
 
-                        thread_sender.send((num as i32 + (i * cpus) as i32, buf))?;
+                        thread_sender.send((index as i32, buf))?;
 
                         *c += 1;
                     }
@@ -1109,7 +2011,6 @@ impl Downloader {
         // happens synchronized. the download consist of multiple segments. the map keys are representing
         // the segment number and the values the corresponding bytes
         let mut data_pos = 0;
-        let mut buf: BTreeMap<i32, Vec<u8>> = BTreeMap::new();
         while let Some((pos, bytes)) = receiver.recv().await {
             // if the position is lower than 0, an error occurred in the sending download thread
             if pos < 0 {
@@ -1133,10 +2034,10 @@ impl Downloader {
                 writer.write_all(bytes.borrow())?;
                 data_pos += 1;
             } else {
-                buf.insert(pos, bytes);
+                write_buf.insert(pos, bytes);
             }
             // check if the buffer contains the next segment(s)
-            while let Some(b) = buf.remove(&data_pos) {
+            while let Some(b) = write_buf.remove(&data_pos) {
                 writer.write_all(b.borrow())?;
                 data_pos += 1;
             }
@@ -1148,25 +2049,93 @@ impl Downloader {
         }
 
         // write the remaining buffer, if existent
-        while let Some(b) = buf.remove(&data_pos) {
+        while let Some(b) = write_buf.remove(&data_pos) {
             writer.write_all(b.borrow())?;
             data_pos += 1;
         }
 
-        if !buf.is_empty() {
+        if !write_buf.is_empty() {
             bail!(
                 "Download buffer is not empty. Remaining segments: {}",
-                buf.into_keys()
+                write_buf
+                    .into_keys()
                     .map(|k| k.to_string())
                     .collect::<Vec<String>>()
                     .join(", ")
             )
         }
 
+        // the per-track cache isn't removed here even though this stream is now fully written out -
+        // if a sibling track or the final mux fails afterwards, the whole `download()` call gets
+        // retried and this track would otherwise have to be fetched again from scratch. it's instead
+        // removed as a whole alongside the rest of the episode's work dir once muxing succeeds
+
         Ok(())
     }
 }
 
+/// Remuxes the already fully-downloaded, directly-playable `src` (the same flat `.mp4`/`.m4a`
+/// [`Downloader::download_video`]/[`Downloader::download_audio`] hand everywhere else) into a
+/// real CMAF/fMP4 HLS rendition inside `dir`: a shared `init.mp4` (the empty-`moov` initialization
+/// segment every media segment afterwards depends on), numbered `.m4s` media segments, and a
+/// `playlist.m3u8` that references the init segment via `#EXT-X-MAP` the way any real HLS player
+/// requires.
+///
+/// Letting ffmpeg's own `hls` muxer do this (instead of hand-writing segment files and a playlist
+/// around the raw per-segment cache, like this used to) means the init segment is never an
+/// afterthought: it's intrinsic to how the muxer's fMP4 output works, so there's no separate
+/// "also fetch/synthesize an init segment" step to get wrong.
+fn write_hls_fmp4_rendition(src: &Path, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let output = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-i", src.to_string_lossy().to_string().as_str()])
+        .args(["-c", "copy"])
+        .args(["-f", "hls"])
+        .args(["-hls_playlist_type", "vod"])
+        .args(["-hls_segment_type", "fmp4"])
+        .args(["-hls_fmp4_init_filename", "init.mp4"])
+        .args([
+            "-hls_segment_filename",
+            dir.join("%d.m4s").to_string_lossy().to_string().as_str(),
+        ])
+        .args(["-hls_flags", "independent_segments"])
+        .arg(dir.join("playlist.m3u8").to_string_lossy().to_string())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    Ok(())
+}
+
+/// Directory individual HLS segments of one stream are cached under while it's downloading, so a
+/// run interrupted partway through only has to re-fetch what's still missing instead of the whole
+/// stream. Scoped to the final output path plus `key` (which stream within it, e.g. `video-0`,
+/// `audio-1-0`) so unrelated downloads never collide. The whole episode's work dir (see
+/// [`episode_work_dir`]) is removed once the episode's mkv is generated, unless `--keep-work-dir`
+/// is set.
+fn resume_cache_dir(work_dir: Option<&Path>, dst: &Path, key: &str) -> PathBuf {
+    episode_work_dir(work_dir, dst).join(key)
+}
+
+/// The directory all of one episode's per-track caches (see [`resume_cache_dir`]) live under.
+/// Defaults to a hidden directory next to `dst`, named after it so unrelated episodes downloading
+/// into the same directory never collide; `--work-dir` overrides the parent directory this lives
+/// in, e.g. to keep it off the (possibly network-mounted) destination volume.
+fn episode_work_dir(work_dir: Option<&Path>, dst: &Path) -> PathBuf {
+    let dir_name = format!(
+        ".{}.crpartial",
+        dst.file_name().unwrap_or_default().to_string_lossy()
+    );
+    match work_dir {
+        Some(work_dir) => work_dir.join(dir_name),
+        None => dst.with_file_name(dir_name),
+    }
+}
+
 fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
     (stream_data.bandwidth / 8) * segments.iter().map(|s| s.length.as_secs()).sum::<u64>()
 }
@@ -1176,7 +2145,7 @@ fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
     let video_length = Regex::new(r"Duration:\s(?P<time>\d+:\d+:\d+\.\d+),")?;
     let video_fps = Regex::new(r"(?P<fps>[\d/.]+)\sfps")?;
 
-    let ffmpeg = Command::new("ffmpeg")
+    let ffmpeg = ffmpeg_command()
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
         .arg("-y")
@@ -1205,6 +2174,216 @@ fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
     ))
 }
 
+#[derive(Deserialize)]
+struct FfprobeColorOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeColorStream>,
+}
+
+#[derive(Default, Deserialize)]
+struct FfprobeColorStream {
+    codec_name: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    color_space: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<FfprobeSideData>,
+}
+
+#[derive(Default, Deserialize)]
+struct FfprobeSideData {
+    side_data_type: Option<String>,
+    red_x: Option<String>,
+    red_y: Option<String>,
+    green_x: Option<String>,
+    green_y: Option<String>,
+    blue_x: Option<String>,
+    blue_y: Option<String>,
+    white_point_x: Option<String>,
+    white_point_y: Option<String>,
+    min_luminance: Option<String>,
+    max_luminance: Option<String>,
+    max_content: Option<u32>,
+    max_average: Option<u32>,
+}
+
+/// Parses ffprobe's `"num/den"` rational side-data fields into a plain float.
+fn parse_rational(s: &str) -> Option<f64> {
+    let (num, den) = s.split_once('/')?;
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (den != 0.0).then_some(num / den)
+}
+
+/// Reads the `color_primaries`/`color_transfer`/`color_space` ffprobe reports for a downloaded
+/// video segment, preferring what's actually on the file over any assumption the playlist would
+/// give. A field ffprobe reports as absent or `unknown` is treated as not present at all. Also
+/// picks up HDR10 mastering-display and content-light-level side data when present.
+fn probe_color_info(path: &Path) -> Result<ColorInfo> {
+    let clean = |value: Option<String>| value.filter(|v| v != "unknown" && !v.is_empty());
+
+    let ffprobe = ffprobe_command()
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .args(["-v", "quiet", "-print_format", "json", "-show_streams"])
+        .args(["-select_streams", "v:0"])
+        .arg(&path.to_string_lossy().to_string())
+        .output()?;
+    let probed: FfprobeColorOutput = serde_json::from_slice(&ffprobe.stdout)?;
+    let stream = probed.streams.into_iter().next().unwrap_or_default();
+
+    let mastering_display = stream
+        .side_data_list
+        .iter()
+        .find(|d| d.side_data_type.as_deref() == Some("Mastering display metadata"))
+        .and_then(|d| {
+            Some(MasteringDisplay {
+                red: (parse_rational(d.red_x.as_ref()?)?, parse_rational(d.red_y.as_ref()?)?),
+                green: (
+                    parse_rational(d.green_x.as_ref()?)?,
+                    parse_rational(d.green_y.as_ref()?)?,
+                ),
+                blue: (
+                    parse_rational(d.blue_x.as_ref()?)?,
+                    parse_rational(d.blue_y.as_ref()?)?,
+                ),
+                white_point: (
+                    parse_rational(d.white_point_x.as_ref()?)?,
+                    parse_rational(d.white_point_y.as_ref()?)?,
+                ),
+                min_luminance: parse_rational(d.min_luminance.as_ref()?)?,
+                max_luminance: parse_rational(d.max_luminance.as_ref()?)?,
+            })
+        });
+    let content_light_level = stream
+        .side_data_list
+        .iter()
+        .find(|d| d.side_data_type.as_deref() == Some("Content light level metadata"))
+        .and_then(|d| {
+            Some(ContentLightLevel {
+                max_content: d.max_content?,
+                max_average: d.max_average?,
+            })
+        });
+
+    Ok(ColorInfo {
+        codec: stream.codec_name.unwrap_or_default(),
+        primaries: clean(stream.color_primaries),
+        transfer: clean(stream.color_transfer),
+        space: clean(stream.color_space),
+        mastering_display,
+        content_light_level,
+    })
+}
+
+/// A muxed output's duration is allowed to drift this many seconds from the program length the
+/// download pipeline expected before `--verify-integrity` considers it a mismatch, since ffmpeg's
+/// container-level duration rarely lines up with the source segments to the millisecond.
+const INTEGRITY_DURATION_TOLERANCE_SECS: f64 = 5.0;
+
+#[derive(Deserialize)]
+struct FfprobeIntegrityOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeIntegrityStream>,
+    format: FfprobeIntegrityFormat,
+}
+
+#[derive(Deserialize)]
+struct FfprobeIntegrityStream {
+    codec_type: String,
+}
+
+#[derive(Deserialize)]
+struct FfprobeIntegrityFormat {
+    duration: Option<String>,
+}
+
+/// `--verify-integrity`'s check: confirms `target` actually contains the video/audio/subtitle
+/// streams the download pipeline asked ffmpeg to mux and that its duration is within
+/// [`INTEGRITY_DURATION_TOLERANCE_SECS`] of `expected_duration`, instead of trusting ffmpeg's exit
+/// code alone. `target` is deleted and a descriptive error returned on any mismatch, so a
+/// truncated/corrupt file is never left behind looking like a finished download.
+fn verify_output_integrity(
+    target: &Path,
+    expected_videos: usize,
+    expected_audios: usize,
+    expected_subtitles: usize,
+    expected_duration: TimeDelta,
+) -> Result<()> {
+    let check = || -> Result<()> {
+        let ffprobe = ffprobe_command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "stream=index,codec_type",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "json",
+            ])
+            .arg(&target.to_string_lossy().to_string())
+            .output()?;
+        let probed: FfprobeIntegrityOutput = serde_json::from_slice(&ffprobe.stdout)?;
+
+        let videos = probed
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == "video")
+            .count();
+        let audios = probed
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == "audio")
+            .count();
+        let subtitles = probed
+            .streams
+            .iter()
+            .filter(|s| s.codec_type == "subtitle")
+            .count();
+        if (videos, audios, subtitles) != (expected_videos, expected_audios, expected_subtitles) {
+            bail!(
+                "expected {} video, {} audio and {} subtitle stream(s), found {}, {} and {}",
+                expected_videos,
+                expected_audios,
+                expected_subtitles,
+                videos,
+                audios,
+                subtitles
+            )
+        }
+
+        let duration: f64 = probed
+            .format
+            .duration
+            .as_deref()
+            .and_then(|d| d.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("ffprobe reported no container duration"))?;
+        let expected_secs = expected_duration.num_milliseconds() as f64 / 1000.0;
+        if (duration - expected_secs).abs() > INTEGRITY_DURATION_TOLERANCE_SECS {
+            bail!(
+                "expected a duration of ~{:.1}s, found {:.1}s",
+                expected_secs,
+                duration
+            )
+        }
+
+        Ok(())
+    };
+
+    if let Err(e) = check() {
+        let _ = fs::remove_file(target);
+        bail!(
+            "Integrity verification failed for '{}', deleted the partial file: {}",
+            target.to_string_lossy(),
+            e
+        )
+    }
+
+    Ok(())
+}
+
 // all subtitle fonts (extracted from javascript)
 const FONTS: [(&str, &str); 68] = [
     ("Adobe Arabic", "AdobeArabic-Bold.woff2"),
@@ -1401,18 +2580,101 @@ fn fix_subtitles(raw: &mut Vec<u8>, max_length: TimeDelta) {
     *raw = as_lines.join("\n").into_bytes()
 }
 
+/// Writes `ass_path`'s content, converted to `format`, as a sidecar file next to `dst`, named
+/// after `dst`'s stem plus `locale_tag` so it lines up with the muxed track naming.
+/// Applies a `--merge sync` alignment offset to a just-downloaded subtitle's ass timeline in place.
+fn shift_subtitle_file(path: &Path, shift: TimeDelta) -> Result<()> {
+    let ass = fs::read_to_string(path)?;
+    fs::write(path, shift_subtitle_events(&ass, shift))?;
+    Ok(())
+}
+
+/// Aligns a just-downloaded subtitle's ass timeline against `audio_path`'s voice activity, for
+/// `--subtitle-sync`. See [`sync_subtitle_to_audio`] for the alignment itself.
+fn sync_subtitle_file(path: &Path, audio_path: &Path, split: bool) -> Result<()> {
+    let ass = fs::read_to_string(path)?;
+    fs::write(path, sync_subtitle_to_audio(&ass, audio_path, split)?)?;
+    Ok(())
+}
+
+fn write_subtitle_sidecar(
+    ass_path: &Path,
+    dst: &Path,
+    locale_tag: &str,
+    format: SubtitleFormat,
+    charset: &str,
+    fps: f64,
+) -> Result<()> {
+    let ass = fs::read_to_string(ass_path)?;
+    let converted = convert_subtitle(&ass, format, fps);
+    let encoded = encode_subtitle_charset(&converted, charset)
+        .map_err(|e| anyhow::anyhow!("Failed to write subtitle sidecar: {}", e))?;
+
+    let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+    let sidecar = dst.with_file_name(format!("{}.{}.{}", stem, locale_tag, format.extension()));
+    fs::write(sidecar, encoded)?;
+
+    Ok(())
+}
+
+/// Rebases `events` onto the clipped output's own timeline the same way `write_ffmpeg_chapters`
+/// does (dropping any that fall fully outside of it), and sorts them by start time. Events that
+/// still overlap after clamping are trimmed to not overlap the previous one, since
+/// `accelerate_ranges` requires non-overlapping ranges.
+fn build_accelerate_ranges(
+    events: &[(&str, &SkipEventsEvent, f64)],
+    video_len: TimeDelta,
+    clip_start: TimeDelta,
+) -> Vec<AccelerateRange> {
+    let video_len = video_len.num_milliseconds() as f32 / 1000.0;
+    let clip_start = clip_start.num_milliseconds() as f32 / 1000.0;
+
+    let mut events: Vec<_> = events.to_vec();
+    events.sort_by(|(_, a, _), (_, b, _)| a.start.total_cmp(&b.start));
+
+    let mut ranges = vec![];
+    let mut last_end = 0.0f32;
+    for (_, event, factor) in events {
+        let start = (event.start - clip_start).max(last_end);
+        let end = (event.end - clip_start).min(video_len);
+        if end <= start {
+            continue;
+        }
+
+        ranges.push(AccelerateRange {
+            start: start as f64,
+            end: end as f64,
+            factor,
+        });
+        last_end = end;
+    }
+    ranges
+}
+
 fn write_ffmpeg_chapters(
     file: &mut fs::File,
     video_len: TimeDelta,
     events: &mut Vec<(&str, &SkipEventsEvent)>,
+    clip_start: TimeDelta,
 ) -> Result<()> {
     let video_len = video_len.num_milliseconds() as f32 / 1000.0;
+    let clip_start = clip_start.num_milliseconds() as f32 / 1000.0;
     events.sort_by(|(_, event_a), (_, event_b)| event_a.start.total_cmp(&event_b.start));
 
     writeln!(file, ";FFMETADATA1")?;
 
     let mut last_end_time = 0.0;
     for (name, event) in events {
+        // rebase the event to the clipped window's zero point, dropping it entirely if it falls
+        // fully outside of the window
+        let start = event.start - clip_start;
+        let end = event.end - clip_start;
+        if end <= 0.0 || start >= video_len {
+            continue;
+        }
+        let start = start.max(0.0);
+        let end = end.min(video_len);
+
         /*
             - Convert from seconds to milliseconds for the correct timescale
             - Include an extra 'Episode' chapter if the start of the current chapter is more than 10
@@ -1420,21 +2682,21 @@ fn write_ffmpeg_chapters(
               This is done before writing the actual chapter of this loop to keep the chapter
               chronologically in order
         */
-        if event.start - last_end_time > 10.0 {
+        if start - last_end_time > 10.0 {
             writeln!(file, "[CHAPTER]")?;
             writeln!(file, "TIMEBASE=1/1000")?;
             writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
-            writeln!(file, "END={}", (event.start * 1000.0) as u32)?;
+            writeln!(file, "END={}", (start * 1000.0) as u32)?;
             writeln!(file, "title=Episode")?;
         }
 
         writeln!(file, "[CHAPTER]")?;
         writeln!(file, "TIMEBASE=1/1000")?;
-        writeln!(file, "START={}", (event.start * 1000.0) as u32)?;
-        writeln!(file, "END={}", (event.end * 1000.0) as u32)?;
+        writeln!(file, "START={}", (start * 1000.0) as u32)?;
+        writeln!(file, "END={}", (end * 1000.0) as u32)?;
         writeln!(file, "title={}", name)?;
 
-        last_end_time = event.end;
+        last_end_time = end;
     }
 
     // only add a trailing chapter if the gap between the end of the last chapter and the total video
@@ -1457,7 +2719,11 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
     cancellation_token: CancellationToken,
 ) -> Result<()> {
     let current_frame = Regex::new(r"frame=\s+(?P<frame>\d+)")?;
+    // the vstats line also carries the output bitrate ffmpeg is currently muxing at, which is the
+    // same kind of "is this actually moving" feedback `-progress`'s `speed=`/`bitrate=` fields give
+    let current_bitrate = Regex::new(r"br=\s*(?P<br>\S+)")?;
 
+    let base_message = message.clone();
     let progress = if log::max_level() == LevelFilter::Info {
         let progress = ProgressBar::new(total_frames)
             .with_style(
@@ -1498,7 +2764,13 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
                 frame = frame_str.as_str().parse()?;
 
                 if let Some(p) = &progress {
-                    p.set_position(frame)
+                    p.set_position(frame);
+                    if let Some(br) = current_bitrate
+                        .captures(line.as_str())
+                        .and_then(|c| c.name("br"))
+                    {
+                        p.set_message(format!("{} ({})", base_message, br.as_str()));
+                    }
                 }
 
                 debug!(
@@ -1607,7 +2879,7 @@ fn extract_frame_hashes(
                     .to_string()
             )
     ))?;
-    let extract_output = Command::new("ffmpeg")
+    let extract_output = ffmpeg_command()
         .arg("-hide_banner")
         .arg("-y")
         .args(["-i", input_file.to_string_lossy().to_string().as_str()])
@@ -1652,6 +2924,30 @@ fn check_frame_windows(base_hashes: &[ImageHash], check_hashes: &[ImageHash]) ->
     results
 }
 
+/// Add `clip_start` (set via `--start`) on top of a track's own seek offset (e.g. a `--merge sync`
+/// alignment offset), so the clip start is applied after the per-track sync offset is computed and
+/// every track stays aligned inside the clipped window.
+fn combine_clip_start(
+    offset: Option<TimeDelta>,
+    clip_start: Option<TimeDelta>,
+) -> Option<TimeDelta> {
+    match (offset, clip_start) {
+        (None, None) => None,
+        (Some(offset), None) => Some(offset),
+        (None, Some(clip_start)) => Some(clip_start),
+        (Some(offset), Some(clip_start)) => Some(offset + clip_start),
+    }
+}
+
+/// Encodes a track's start offset as either a container-level seek (`-ss`, which trims samples
+/// before it to the nearest packet) or, when `use_edit_list` is set, an `-itsoffset` delay that
+/// keeps every sample and lets the mov muxer's `-use_editlist` record the offset as a bit-exact
+/// `elst` edit instead.
+fn seek_args(start_time: TimeDelta, use_edit_list: bool) -> Vec<String> {
+    let flag = if use_edit_list { "-itsoffset" } else { "-ss" };
+    vec![flag.to_string(), format_time_delta(start_time)]
+}
+
 fn format_time_delta(time_delta: TimeDelta) -> String {
     let hours = time_delta.num_hours();
     let minutes = time_delta.num_minutes() - time_delta.num_hours() * 60;
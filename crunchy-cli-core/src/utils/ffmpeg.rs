@@ -1,12 +1,199 @@
+use crate::utils::os::{
+    cache_dir, ffmpeg_binary, ffmpeg_command, has_ffmpeg, set_ffprobe_binary, tempfile,
+};
+use anyhow::{anyhow, bail, Result};
 use lazy_static::lazy_static;
+use log::{debug, info, warn};
 use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::str::FromStr;
+use std::sync::Mutex;
 
 pub const SOFTSUB_CONTAINERS: [&str; 3] = ["mkv", "mov", "mp4"];
 
+/// The platform identifier used by the prebuilt ffmpeg binaries this downloader fetches,
+/// following the naming scheme the `ffmpeg-static` project uses for its release assets.
+fn ffmpeg_static_platform() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("linux-x64"),
+        ("linux", "aarch64") => Some("linux-arm64"),
+        ("macos", "x86_64") => Some("darwin-x64"),
+        ("macos", "aarch64") => Some("darwin-arm64"),
+        ("windows", "x86_64") => Some("win32-x64.exe"),
+        _ => None,
+    }
+}
+
+const FFMPEG_STATIC_RELEASE_BASE: &str =
+    "https://github.com/eugeneware/ffmpeg-static/releases/latest/download";
+/// GitHub's own release API, queried for the published per-asset sha256 `digest` before
+/// downloading anything - the actual known-good checksum the download below is verified against,
+/// fetched over a separate request/endpoint than the asset itself rather than derived from it.
+const FFMPEG_STATIC_RELEASE_API: &str =
+    "https://api.github.com/repos/eugeneware/ffmpeg-static/releases/latest";
+
+#[derive(Deserialize)]
+struct GithubReleaseAsset {
+    name: String,
+    digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    assets: Vec<GithubReleaseAsset>,
+}
+
+/// Downloads a static ffmpeg build for the host platform into the cache directory and returns the
+/// path to the executable. If a binary from a previous download is already cached and its
+/// checksum still matches the current release's published digest, it's reused instead of being
+/// re-fetched.
+async fn download_ffmpeg(client: &reqwest::Client) -> Result<PathBuf> {
+    let Some(platform) = ffmpeg_static_platform() else {
+        bail!(
+            "No prebuilt ffmpeg is available for this platform ({} {}). Install ffmpeg manually and point `--ffmpeg-path` to it",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )
+    };
+
+    let cache_dir = cache_dir("ffmpeg")?;
+    let binary_path = cache_dir.join(if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" });
+    let checksum_path = cache_dir.join("ffmpeg.sha256");
+
+    let release: GithubRelease = client
+        .get(FFMPEG_STATIC_RELEASE_API)
+        .header("User-Agent", "crunchy-cli")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let asset = release.assets.iter().find(|a| a.name == platform).ok_or_else(|| {
+        anyhow!(
+            "No '{}' asset found in the latest ffmpeg-static release",
+            platform
+        )
+    })?;
+    let expected_checksum = asset
+        .digest
+        .as_deref()
+        .and_then(|d| d.strip_prefix("sha256:"))
+        .ok_or_else(|| {
+            anyhow!(
+                "GitHub didn't publish a sha256 digest for the '{}' ffmpeg-static asset, refusing to download an unverifiable binary",
+                platform
+            )
+        })?
+        .to_string();
+
+    if binary_path.is_file() {
+        if let Ok(cached_checksum) = fs::read_to_string(&checksum_path) {
+            if cached_checksum.trim() == expected_checksum {
+                debug!(
+                    "Using cached ffmpeg binary ({})",
+                    binary_path.to_string_lossy()
+                );
+                return Ok(binary_path);
+            }
+        }
+    }
+
+    let url = format!("{}/{}", FFMPEG_STATIC_RELEASE_BASE, platform);
+    info!("Downloading ffmpeg from {}", url);
+    let bytes = client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let checksum = format!("{:x}", Sha256::digest(&bytes));
+    if checksum != expected_checksum {
+        bail!(
+            "Downloaded ffmpeg binary's checksum ({}) doesn't match the one GitHub published for this release ({}), refusing to use it",
+            checksum,
+            expected_checksum
+        )
+    }
+
+    let mut file = fs::File::create(&binary_path)?;
+    file.write_all(&bytes)?;
+    fs::write(&checksum_path, &checksum)?;
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(&binary_path)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        fs::set_permissions(&binary_path, permissions)?;
+    }
+
+    info!("Downloaded ffmpeg (sha256: {})", checksum);
+
+    Ok(binary_path)
+}
+
+/// Resolves the ffmpeg binary to use for the rest of the run, in order of preference: an explicit
+/// `--ffmpeg-path` override, the `CRUNCHY_CLI_FFMPEG` env var, `ffmpeg` already on `PATH`, or (if
+/// `--download-ffmpeg` is set) a freshly downloaded static build.
+pub async fn resolve_ffmpeg(
+    client: &reqwest::Client,
+    explicit_path: Option<&Path>,
+    allow_download: bool,
+) -> Result<PathBuf> {
+    let ffmpeg_path = if let Some(path) = explicit_path {
+        if !path.is_file() {
+            bail!("ffmpeg path '{}' does not exist", path.to_string_lossy())
+        }
+        path.to_path_buf()
+    } else if has_ffmpeg() {
+        ffmpeg_binary()
+    } else if allow_download {
+        download_ffmpeg(client).await?
+    } else {
+        bail!(
+            "FFmpeg is needed to run this command. Install it and make it available on `PATH`, pass its location via `--ffmpeg-path`, or use `--download-ffmpeg` to fetch a static build automatically"
+        )
+    };
+
+    use_sibling_ffprobe(&ffmpeg_path);
+
+    Ok(ffmpeg_path)
+}
+
+/// If the resolved ffmpeg binary lives in an explicit directory (an `--ffmpeg-path` override or a
+/// downloaded build), prefer an `ffprobe`/`ffprobe.exe` sitting right next to it over whatever's on
+/// `PATH`, the same way video tools that ship both binaries together expect them to be found.
+fn use_sibling_ffprobe(ffmpeg_path: &Path) {
+    let Some(dir) = ffmpeg_path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return;
+    };
+    let sibling = dir.join(if cfg!(windows) { "ffprobe.exe" } else { "ffprobe" });
+    if sibling.is_file() {
+        set_ffprobe_binary(sibling);
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum FFmpegPreset {
-    Predefined(FFmpegCodec, Option<FFmpegHwAccel>, FFmpegQuality),
+    Predefined(
+        FFmpegCodec,
+        Option<FFmpegHwAccel>,
+        FFmpegQuality,
+        FFmpegAudioCodec,
+        /// Explicit `-pix_fmt`, e.g. from a `pix<fmt>` token (`pixyuv420p10le`). `None` leaves
+        /// whatever pixel format the chosen encoder defaults to untouched.
+        Option<String>,
+        /// Explicit `-vf scale=w:h`, from a `scale<W>x<H>` token (`scale1280x720`). `None` leaves
+        /// the source resolution untouched.
+        Option<(u32, u32)>,
+    ),
     Custom(Option<String>),
 }
 
@@ -16,7 +203,7 @@ lazy_static! {
 
 macro_rules! ffmpeg_enum {
     (enum $name:ident { $($field:ident),* }) => {
-        #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+        #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
         pub enum $name {
             $(
                 $field
@@ -67,17 +254,133 @@ ffmpeg_enum! {
 }
 
 ffmpeg_enum! {
+    // `Auto` isn't a real accelerator; `available_hwaccel` resolves it into whichever of the
+    // others the host's ffmpeg build actually has an encoder for, falling back to software if
+    // none do
     enum FFmpegHwAccel {
         Nvidia,
-        Apple
+        Apple,
+        Vaapi,
+        Qsv,
+        Auto
     }
 }
 
 ffmpeg_enum! {
-    enum FFmpegQuality {
-        Lossless,
-        Normal,
-        Low
+    enum FFmpegAudioCodec {
+        Copy,
+        Aac,
+        Opus,
+        Flac
+    }
+}
+
+/// Which channel of a multi-channel audio track to keep via an ffmpeg `pan` filter, or `Mono` to
+/// downmix all of them into one. Unlike [`FFmpegAudioCodec`] this isn't a `--ffmpeg-preset` token
+/// (it's an orthogonal filter, not an encoder choice) and is instead selected with its own
+/// `--audio-channel` flag.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FFmpegAudioChannel {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    Lfe,
+    SideLeft,
+    SideRight,
+    Mono,
+}
+
+impl FFmpegAudioChannel {
+    pub(crate) fn parse(s: &str) -> Result<FFmpegAudioChannel, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "fl" => FFmpegAudioChannel::FrontLeft,
+            "fr" => FFmpegAudioChannel::FrontRight,
+            "fc" => FFmpegAudioChannel::FrontCenter,
+            "lfe" => FFmpegAudioChannel::Lfe,
+            "sl" => FFmpegAudioChannel::SideLeft,
+            "sr" => FFmpegAudioChannel::SideRight,
+            "mono" => FFmpegAudioChannel::Mono,
+            _ => return Err(format!("'{}' is not a valid audio channel", s)),
+        })
+    }
+
+    /// The `-af` filtergraph which keeps only this channel. `pan=mono|c0=cN` keeps input channel
+    /// index `N` (standard layout order: FL=0, FR=1, FC=2, LFE=3, SL=4, SR=5) as the sole output
+    /// channel; `Mono` instead averages the front left/right channels down to one.
+    fn filter(&self) -> String {
+        match self {
+            FFmpegAudioChannel::FrontLeft => "pan=mono|c0=c0".to_string(),
+            FFmpegAudioChannel::FrontRight => "pan=mono|c0=c1".to_string(),
+            FFmpegAudioChannel::FrontCenter => "pan=mono|c0=c2".to_string(),
+            FFmpegAudioChannel::Lfe => "pan=mono|c0=c3".to_string(),
+            FFmpegAudioChannel::SideLeft => "pan=mono|c0=c4".to_string(),
+            FFmpegAudioChannel::SideRight => "pan=mono|c0=c5".to_string(),
+            FFmpegAudioChannel::Mono => "pan=mono|c0=0.5*FL+0.5*FR".to_string(),
+        }
+    }
+}
+
+/// Unlike [`FFmpegCodec`]/[`FFmpegHwAccel`] this isn't generated by the [`ffmpeg_enum`] macro
+/// because `Target` and `Custom` carry a value, which the macro's token-equality based
+/// (de)serialization can't express.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum FFmpegQuality {
+    Lossless,
+    Normal,
+    Low,
+    /// A CRF (or, on VideoToolbox, `-q:v`) value resolved ahead of time rather than picked from
+    /// one of the fixed presets above, parsed from a `crfN`/`qN` token (e.g. `crf23`). This is also
+    /// what `Target` below is resolved into once the VMAF probe has found a matching CRF.
+    Custom(u32),
+    /// Requested VMAF score (0-100) to hit via per-file CRF probing, parsed from a `vmafN` token,
+    /// e.g. `vmaf95`. Resolved into `Custom` by [`FFmpegPreset::resolve_target_quality`] before
+    /// [`FFmpegPreset::into_input_output_args`] is called.
+    Target(u8),
+}
+
+impl FFmpegQuality {
+    fn all() -> Vec<FFmpegQuality> {
+        vec![
+            FFmpegQuality::Lossless,
+            FFmpegQuality::Normal,
+            FFmpegQuality::Low,
+        ]
+    }
+}
+
+impl ToString for FFmpegQuality {
+    fn to_string(&self) -> String {
+        match self {
+            FFmpegQuality::Lossless => "lossless".to_string(),
+            FFmpegQuality::Normal => "normal".to_string(),
+            FFmpegQuality::Low => "low".to_string(),
+            FFmpegQuality::Custom(crf) => format!("custom{}", crf),
+            FFmpegQuality::Target(score) => format!("vmaf{}", score),
+        }
+    }
+}
+
+impl FromStr for FFmpegQuality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "Lossless" => Ok(FFmpegQuality::Lossless),
+            "Normal" => Ok(FFmpegQuality::Normal),
+            "Low" => Ok(FFmpegQuality::Low),
+            _ => s
+                .strip_prefix("vmaf")
+                .and_then(|score| score.parse::<u8>().ok())
+                .filter(|score| (1..=100).contains(score))
+                .map(FFmpegQuality::Target)
+                .or_else(|| {
+                    s.strip_prefix("crf")
+                        .or_else(|| s.strip_prefix('q'))
+                        .and_then(|crf| crf.parse::<u32>().ok())
+                        .map(FFmpegQuality::Custom)
+                })
+                .ok_or_else(|| anyhow::anyhow!("{} is not a valid ffmpegquality", s)),
+        }
     }
 }
 
@@ -165,8 +468,22 @@ impl FFmpegPreset {
                 )
             };
 
+            // `auto` always degrades silently (that's the point of it), so it never gets this note
+            let unavailable_note = hwaccel
+                .as_ref()
+                .filter(|h| **h != FFmpegHwAccel::Auto)
+                .and_then(|h| {
+                    let encoder = hwaccel_encoder(&codec, h);
+                    (!available_encoders().contains(encoder)).then(|| {
+                        format!(
+                            " (falls back to software encoding: `{}` isn't in this ffmpeg build)",
+                            encoder
+                        )
+                    })
+                });
+
             return_values.push(format!(
-                "{} ({})",
+                "{} ({}{})",
                 vec![
                     Some(codec.to_string()),
                     hwaccel.map(|h| h.to_string()),
@@ -176,7 +493,8 @@ impl FFmpegPreset {
                 .flatten()
                 .collect::<Vec<String>>()
                 .join("-"),
-                description
+                description,
+                unavailable_note.unwrap_or_default()
             ))
         }
         return_values
@@ -190,6 +508,9 @@ impl FFmpegPreset {
         let mut codec: Option<FFmpegCodec> = None;
         let mut hwaccel: Option<FFmpegHwAccel> = None;
         let mut quality: Option<FFmpegQuality> = None;
+        let mut audio_codec: Option<FFmpegAudioCodec> = None;
+        let mut pixel_format: Option<String> = None;
+        let mut scale: Option<(u32, u32)> = None;
         for token in s.split('-') {
             if let Some(c) = FFmpegCodec::all()
                 .into_iter()
@@ -227,6 +548,63 @@ impl FFmpegPreset {
                     ));
                 }
                 quality = Some(q)
+            } else if let Some(score) = token
+                .strip_prefix("vmaf")
+                .and_then(|score| score.parse::<u8>().ok())
+                .filter(|score| (1..=100).contains(score))
+            {
+                if let Some(qq) = quality {
+                    return Err(format!(
+                        "cannot use multiple ffmpeg preset qualities (found {} and vmaf{})",
+                        qq.to_string(),
+                        score
+                    ));
+                }
+                quality = Some(FFmpegQuality::Target(score))
+            } else if let Some(crf) = token
+                .strip_prefix("crf")
+                .or_else(|| token.strip_prefix('q'))
+                .and_then(|value| value.parse::<u32>().ok())
+            {
+                if let Some(qq) = quality {
+                    return Err(format!(
+                        "cannot use multiple ffmpeg preset qualities (found {} and {})",
+                        qq.to_string(),
+                        token
+                    ));
+                }
+                quality = Some(FFmpegQuality::Custom(crf))
+            } else if let Some(a) = FFmpegAudioCodec::all()
+                .into_iter()
+                .find(|p| p.to_string() == token.to_lowercase())
+            {
+                if let Some(aa) = audio_codec {
+                    return Err(format!(
+                        "cannot use multiple audio codecs (found {} and {})",
+                        aa.to_string(),
+                        a.to_string()
+                    ));
+                }
+                audio_codec = Some(a)
+            } else if let Some(fmt) = token.strip_prefix("pix") {
+                if let Some(pp) = pixel_format {
+                    return Err(format!(
+                        "cannot use multiple pixel formats (found {} and {})",
+                        pp, fmt
+                    ));
+                }
+                pixel_format = Some(fmt.to_string())
+            } else if let Some((w, h)) = token.strip_prefix("scale").and_then(|wh| {
+                let (w, h) = wh.split_once('x')?;
+                Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?))
+            }) {
+                if let Some((ww, hh)) = scale {
+                    return Err(format!(
+                        "cannot use multiple scales (found {}x{} and {}x{})",
+                        ww, hh, w, h
+                    ));
+                }
+                scale = Some((w, h))
             } else {
                 return Err(format!(
                     "'{}' is not a valid ffmpeg preset (unknown token '{}')",
@@ -236,10 +614,18 @@ impl FFmpegPreset {
         }
 
         if let Some(c) = codec {
+            // `vmafN`/`crfN`/`qN` don't pick one of the fixed presets below, so they're checked
+            // as compatible with whatever codec/hwaccel combination 'normal' is
+            let checked_quality = match &quality {
+                Some(FFmpegQuality::Target(_)) | Some(FFmpegQuality::Custom(_)) => {
+                    Some(FFmpegQuality::Normal)
+                }
+                other => other.clone(),
+            };
             if !FFmpegPreset::available_matches().contains(&(
                 c.clone(),
                 hwaccel.clone(),
-                quality.clone(),
+                checked_quality,
             )) {
                 return Err("ffmpeg preset is not supported".to_string());
             }
@@ -247,136 +633,641 @@ impl FFmpegPreset {
                 c,
                 hwaccel,
                 quality.unwrap_or(FFmpegQuality::Normal),
+                audio_codec.unwrap_or(FFmpegAudioCodec::Copy),
+                pixel_format,
+                scale,
             ))
         } else {
             Err("cannot use ffmpeg preset with without a codec".to_string())
         }
     }
 
-    pub(crate) fn into_input_output_args(self) -> (Vec<String>, Vec<String>) {
+    /// `Flac` audio only reliably works in the container formats `SOFTSUB_CONTAINERS` lists (and
+    /// even then needs a recent ffmpeg for the `mp4`/`mov` muxer), so `Archive`/`Download`'s
+    /// `pre_check` calls this to reject an incompatible combination up front instead of failing
+    /// deep inside ffmpeg.
+    pub(crate) fn validate_audio_codec_container(&self, container: &str) -> Result<(), String> {
+        if matches!(
+            self,
+            FFmpegPreset::Predefined(_, _, _, FFmpegAudioCodec::Flac, _, _)
+        ) && !SOFTSUB_CONTAINERS.contains(&container)
+        {
+            return Err(format!(
+                "flac audio is only supported in {} containers, not '{}'",
+                SOFTSUB_CONTAINERS.join("/"),
+                container
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that ffmpeg actually has the encoder(s) this preset would need, failing early with a
+    /// clear message instead of only discovering a `-c:v` ffmpeg doesn't recognize partway into a
+    /// (possibly long) mux. An explicitly requested (non-`auto`) hwaccel is checked too: `auto`
+    /// itself is exempt, since it already silently degrades to whatever the build supports, down
+    /// to software if nothing else is available (see `available_hwaccel`).
+    pub(crate) fn validate_encoder_availability(&self) -> Result<(), String> {
+        let FFmpegPreset::Predefined(codec, hwaccel, ..) = self else {
+            return Ok(());
+        };
+
+        let capabilities = FfmpegCapabilities::probe();
+
+        if let Some(hwaccel) = hwaccel {
+            if *hwaccel != FFmpegHwAccel::Auto && !capabilities.supports(codec, hwaccel) {
+                return Err(format!(
+                    "`{}` ({} hardware acceleration) is not available in this ffmpeg build. Drop `-{}` from the preset (or use `-auto` instead) to fall back automatically, or use a different ffmpeg build",
+                    hwaccel_encoder(codec, hwaccel),
+                    hwaccel.to_string(),
+                    hwaccel.to_string()
+                ));
+            }
+        }
+
+        let software_encoder = software_encoder(codec);
+        if !capabilities.encoders.contains(software_encoder) {
+            return Err(format!(
+                "`{}` is not compiled into this ffmpeg build, so {} can't be encoded. Use a different `--ffmpeg-preset` codec or a different ffmpeg build",
+                software_encoder,
+                codec.to_string()
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn into_input_output_args(
+        self,
+        audio_channel: Option<FFmpegAudioChannel>,
+    ) -> (Vec<String>, Vec<String>) {
         match self {
             FFmpegPreset::Custom(output) => (
                 vec![],
                 output.map_or(vec![], |o| shlex::split(&o).unwrap_or_default()),
             ),
-            FFmpegPreset::Predefined(codec, hwaccel_opt, quality) => {
-                let mut input = vec![];
-                let mut output = vec![];
+            FFmpegPreset::Predefined(codec, hwaccel_opt, quality, audio_codec, pixel_format, scale) => {
+                let mut input: Vec<String> = vec![];
+                let mut output: Vec<String> = vec![];
+
+                macro_rules! push {
+                    ($vec:expr, $($s:expr),+ $(,)?) => {
+                        $vec.extend([$($s.to_string()),+])
+                    };
+                }
 
                 match codec {
                     FFmpegCodec::H264 => {
-                        let mut crf_quality = || match quality {
-                            FFmpegQuality::Lossless => output.extend(["-crf", "18"]),
+                        // `flag` is `-crf` for software/NVENC, `-qp` for VAAPI and
+                        // `-global_quality` for QSV, all of which share the same numeric scale.
+                        let mut crf_quality = |flag: &str| match quality {
+                            FFmpegQuality::Lossless => push!(output, flag, "18"),
                             FFmpegQuality::Normal => (),
-                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                            FFmpegQuality::Low => push!(output, flag, "35"),
+                            FFmpegQuality::Custom(crf) => push!(output, flag, crf),
+                            // should already have been resolved into `Custom` by
+                            // `resolve_target_quality`; fall back to `Normal` if not
+                            FFmpegQuality::Target(_) => (),
                         };
 
-                        if let Some(hwaccel) = hwaccel_opt {
+                        let hw = available_hwaccel(&FFmpegCodec::H264, hwaccel_opt);
+                        if let Some(hwaccel) = hw {
                             match hwaccel {
                                 FFmpegHwAccel::Nvidia => {
-                                    input.extend([
+                                    push!(
+                                        input,
                                         "-hwaccel",
                                         "cuda",
                                         "-hwaccel_output_format",
                                         "cuda",
                                         "-c:v",
                                         "h264_cuvid",
-                                    ]);
-                                    crf_quality();
-                                    output.extend(["-c:v", "h264_nvenc", "-c:a", "copy"])
+                                    );
+                                    crf_quality("-crf");
+                                    push!(output, "-c:v", "h264_nvenc")
+                                }
+                                FFmpegHwAccel::Vaapi => {
+                                    push!(
+                                        input,
+                                        "-hwaccel",
+                                        "vaapi",
+                                        "-hwaccel_output_format",
+                                        "vaapi",
+                                        "-vaapi_device",
+                                        "/dev/dri/renderD128",
+                                    );
+                                    crf_quality("-qp");
+                                    push!(output, "-c:v", "h264_vaapi")
+                                }
+                                FFmpegHwAccel::Qsv => {
+                                    crf_quality("-global_quality");
+                                    push!(output, "-c:v", "h264_qsv")
                                 }
                                 FFmpegHwAccel::Apple => {
                                     // Apple's Video Toolbox encoders ignore `-crf`, use `-q:v`
-                                    // instead. It's on a scale of 1-100, 100 being lossless. Just
-                                    // did some math ((-a/51+1)*99+1 where `a` is the old crf value)
-                                    // so these settings very likely need some more tweaking
+                                    // instead, on a scale of 1-100 (100 being lossless). `Custom`
+                                    // is already on that scale end to end for Apple - both
+                                    // `resolve_target_quality`'s probe (via `crf_search_range`)
+                                    // and a user-supplied `crfN`/`qN` preset token - so it's
+                                    // forwarded as-is instead of being reinterpreted as a 0-51 CRF.
                                     match quality {
-                                        FFmpegQuality::Lossless => output.extend(["-q:v", "65"]),
+                                        FFmpegQuality::Lossless => push!(output, "-q:v", "65"),
                                         FFmpegQuality::Normal => (),
-                                        FFmpegQuality::Low => output.extend(["-q:v", "32"]),
+                                        FFmpegQuality::Low => push!(output, "-q:v", "32"),
+                                        FFmpegQuality::Custom(crf) => push!(output, "-q:v", crf),
+                                        FFmpegQuality::Target(_) => (),
                                     }
 
-                                    output.extend(["-c:v", "h264_videotoolbox", "-c:a", "copy"])
+                                    push!(output, "-c:v", "h264_videotoolbox")
+                                }
+                                FFmpegHwAccel::Auto => {
+                                    unreachable!("resolved by available_hwaccel before this match")
                                 }
                             }
                         } else {
-                            crf_quality();
-                            output.extend(["-c:v", "libx264", "-c:a", "copy"])
+                            crf_quality("-crf");
+                            push!(output, "-c:v", "libx264")
                         }
                     }
                     FFmpegCodec::H265 => {
-                        let mut crf_quality = || match quality {
-                            FFmpegQuality::Lossless => output.extend(["-crf", "20"]),
+                        let mut crf_quality = |flag: &str| match quality {
+                            FFmpegQuality::Lossless => push!(output, flag, "20"),
                             FFmpegQuality::Normal => (),
-                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                            FFmpegQuality::Low => push!(output, flag, "35"),
+                            FFmpegQuality::Custom(crf) => push!(output, flag, crf),
+                            FFmpegQuality::Target(_) => (),
                         };
 
-                        if let Some(hwaccel) = hwaccel_opt {
+                        let hw = available_hwaccel(&FFmpegCodec::H265, hwaccel_opt);
+                        if let Some(hwaccel) = hw {
                             match hwaccel {
                                 FFmpegHwAccel::Nvidia => {
-                                    input.extend([
+                                    push!(
+                                        input,
                                         "-hwaccel",
                                         "cuda",
                                         "-hwaccel_output_format",
                                         "cuda",
                                         "-c:v",
                                         "h264_cuvid",
-                                    ]);
-                                    crf_quality();
-                                    output.extend([
-                                        "-c:v",
-                                        "hevc_nvenc",
-                                        "-c:a",
-                                        "copy",
-                                        "-tag:v",
-                                        "hvc1",
-                                    ])
+                                    );
+                                    crf_quality("-crf");
+                                    push!(output, "-c:v", "hevc_nvenc", "-tag:v", "hvc1")
+                                }
+                                FFmpegHwAccel::Vaapi => {
+                                    push!(
+                                        input,
+                                        "-hwaccel",
+                                        "vaapi",
+                                        "-hwaccel_output_format",
+                                        "vaapi",
+                                        "-vaapi_device",
+                                        "/dev/dri/renderD128",
+                                    );
+                                    crf_quality("-qp");
+                                    push!(output, "-c:v", "hevc_vaapi", "-tag:v", "hvc1")
+                                }
+                                FFmpegHwAccel::Qsv => {
+                                    crf_quality("-global_quality");
+                                    push!(output, "-c:v", "hevc_qsv", "-tag:v", "hvc1")
                                 }
                                 FFmpegHwAccel::Apple => {
-                                    // See the comment that starts on line 287.
+                                    // `-q:v` is already the scale `Custom` carries for Apple, see
+                                    // the H264 branch above.
                                     match quality {
-                                        FFmpegQuality::Lossless => output.extend(["-q:v", "61"]),
+                                        FFmpegQuality::Lossless => push!(output, "-q:v", "61"),
                                         FFmpegQuality::Normal => (),
-                                        FFmpegQuality::Low => output.extend(["-q:v", "32"]),
+                                        FFmpegQuality::Low => push!(output, "-q:v", "32"),
+                                        FFmpegQuality::Custom(crf) => push!(output, "-q:v", crf),
+                                        FFmpegQuality::Target(_) => (),
                                     }
 
-                                    output.extend([
-                                        "-c:v",
-                                        "hevc_videotoolbox",
-                                        "-c:a",
-                                        "copy",
-                                        "-tag:v",
-                                        "hvc1",
-                                    ])
+                                    push!(output, "-c:v", "hevc_videotoolbox", "-tag:v", "hvc1")
+                                }
+                                FFmpegHwAccel::Auto => {
+                                    unreachable!("resolved by available_hwaccel before this match")
                                 }
                             }
                         } else {
-                            crf_quality();
-                            output.extend(["-c:v", "libx265", "-c:a", "copy", "-tag:v", "hvc1"])
+                            crf_quality("-crf");
+                            push!(output, "-c:v", "libx265", "-tag:v", "hvc1")
                         }
                     }
                     FFmpegCodec::Av1 => {
-                        output.extend(["-c:v", "libsvtav1", "-c:a", "copy"]);
+                        push!(output, "-c:v", "libsvtav1");
 
                         match quality {
-                            FFmpegQuality::Lossless => output.extend(["-crf", "22"]),
+                            FFmpegQuality::Lossless => push!(output, "-crf", "22"),
                             FFmpegQuality::Normal => (),
-                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                            FFmpegQuality::Low => push!(output, "-crf", "35"),
+                            FFmpegQuality::Custom(crf) => push!(output, "-crf", crf),
+                            FFmpegQuality::Target(_) => (),
                         }
                     }
                 }
 
-                (
-                    input
-                        .into_iter()
-                        .map(|s| s.to_string())
-                        .collect::<Vec<String>>(),
-                    output
-                        .into_iter()
-                        .map(|s| s.to_string())
-                        .collect::<Vec<String>>(),
-                )
+                // extracting/downmixing a channel needs a real audio filter, which `copy` can't
+                // run through, so fall back to `Aac` if the user didn't already pick a codec
+                let audio_codec =
+                    if audio_channel.is_some() && audio_codec == FFmpegAudioCodec::Copy {
+                        FFmpegAudioCodec::Aac
+                    } else {
+                        audio_codec
+                    };
+                output.extend(audio_args(&audio_codec));
+                if let Some(channel) = audio_channel {
+                    push!(output, "-af", channel.filter());
+                }
+
+                if let Some(fmt) = pixel_format {
+                    push!(output, "-pix_fmt", fmt);
+                }
+                if let Some((w, h)) = scale {
+                    push!(output, "-vf", format!("scale={}:{}", w, h));
+                }
+
+                (input, output)
             }
         }
     }
+
+    /// If this preset's quality is [`FFmpegQuality::Target`], probes `source` to find the CRF (or,
+    /// on VideoToolbox, `-q:v`) value that gets closest to the requested VMAF score without going
+    /// under it, and returns an equivalent preset with that value resolved into
+    /// [`FFmpegQuality::Custom`]. Presets with any other quality are returned unchanged.
+    pub(crate) fn resolve_target_quality(self, source: &Path) -> Result<FFmpegPreset> {
+        let FFmpegPreset::Predefined(
+            codec,
+            hwaccel,
+            FFmpegQuality::Target(target_score),
+            audio_codec,
+            pixel_format,
+            scale,
+        ) = &self
+        else {
+            return Ok(self);
+        };
+        let (codec, hwaccel, target_score, audio_codec, pixel_format, scale) = (
+            codec.clone(),
+            hwaccel.clone(),
+            *target_score,
+            audio_codec.clone(),
+            pixel_format.clone(),
+            *scale,
+        );
+
+        if !has_libvmaf() {
+            bail!(
+                "vmaf{} quality target requires an ffmpeg build with the `libvmaf` filter",
+                target_score
+            )
+        }
+
+        let cache_key = (codec.clone(), hwaccel.clone(), target_score);
+        if let Some(crf) = VMAF_CRF_CACHE.lock().unwrap().get(&cache_key) {
+            debug!("Reusing crf {} probed earlier for vmaf{}", crf, target_score);
+            return Ok(FFmpegPreset::Predefined(
+                codec,
+                hwaccel,
+                FFmpegQuality::Custom(*crf),
+                audio_codec,
+                pixel_format,
+                scale,
+            ));
+        }
+
+        info!(
+            "Probing crf for vmaf{} target on {}",
+            target_score,
+            source.to_string_lossy()
+        );
+
+        let (low, high) = crf_search_range(&codec, &hwaccel);
+        let resolved = if hwaccel == Some(FFmpegHwAccel::Apple) {
+            // VideoToolbox's `-q:v` scale runs from worst (low) to best (high) quality, so we
+            // binary-search for the smallest `-q:v` that still meets the target.
+            let (mut low, mut high) = (low, high);
+            let mut resolved = high;
+            while low <= high {
+                let mid = low + (high - low) / 2;
+                let score = encode_and_score(source, &codec, &hwaccel, mid)?;
+                debug!("q:v {} scored vmaf {:.2}", mid, score);
+                if score >= target_score as f64 {
+                    resolved = mid;
+                    if mid == low {
+                        break;
+                    }
+                    high = mid - 1;
+                } else {
+                    if mid == high {
+                        break;
+                    }
+                    low = mid + 1;
+                }
+            }
+            resolved
+        } else {
+            // A CRF scale runs the other way (lower is better quality), so we binary-search for
+            // the largest crf that still meets the target, saving as much size as possible.
+            let (mut low, mut high) = (low, high);
+            let mut resolved = low;
+            while low <= high {
+                let mid = low + (high - low) / 2;
+                let score = encode_and_score(source, &codec, &hwaccel, mid)?;
+                debug!("crf {} scored vmaf {:.2}", mid, score);
+                if score >= target_score as f64 {
+                    resolved = mid;
+                    if mid == high {
+                        break;
+                    }
+                    low = mid + 1;
+                } else {
+                    if mid == low {
+                        break;
+                    }
+                    high = mid - 1;
+                }
+            }
+            resolved
+        };
+
+        info!("Resolved vmaf{} target to crf {}", target_score, resolved);
+        VMAF_CRF_CACHE.lock().unwrap().insert(cache_key, resolved);
+
+        Ok(FFmpegPreset::Predefined(
+            codec,
+            hwaccel,
+            FFmpegQuality::Custom(resolved),
+            audio_codec,
+            pixel_format,
+            scale,
+        ))
+    }
+}
+
+/// Per-run cache of probed CRF values, keyed by codec/hwaccel/target score. Probing is expensive
+/// (it encodes and scores multiple short clips), and an archive/download run processing many
+/// episodes of the same show rarely needs a meaningfully different CRF between them, so later
+/// files reuse the first probe's result instead of repeating it.
+lazy_static! {
+    static ref VMAF_CRF_CACHE: Mutex<HashMap<(FFmpegCodec, Option<FFmpegHwAccel>, u8), u32>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The `-c:a` flags `into_input_output_args` appends for `audio_codec`, with sensible bitrate
+/// defaults for the lossy codecs.
+fn audio_args(audio_codec: &FFmpegAudioCodec) -> Vec<String> {
+    match audio_codec {
+        FFmpegAudioCodec::Copy => vec!["-c:a".to_string(), "copy".to_string()],
+        FFmpegAudioCodec::Aac => vec![
+            "-c:a".to_string(),
+            "aac".to_string(),
+            "-b:a".to_string(),
+            "192k".to_string(),
+        ],
+        FFmpegAudioCodec::Opus => vec![
+            "-c:a".to_string(),
+            "libopus".to_string(),
+            "-b:a".to_string(),
+            "128k".to_string(),
+        ],
+        FFmpegAudioCodec::Flac => vec!["-c:a".to_string(), "flac".to_string()],
+    }
+}
+
+/// The hardware encoder `into_input_output_args` would pick for `(codec, hwaccel)`, regardless of
+/// whether the local ffmpeg build actually has it. Used both to probe [`available_encoders`] and
+/// to decide whether a preset needs to fall back to software encoding.
+fn hwaccel_encoder(codec: &FFmpegCodec, hwaccel: &FFmpegHwAccel) -> &'static str {
+    match (codec, hwaccel) {
+        (FFmpegCodec::H264, FFmpegHwAccel::Nvidia) => "h264_nvenc",
+        (FFmpegCodec::H264, FFmpegHwAccel::Apple) => "h264_videotoolbox",
+        (FFmpegCodec::H264, FFmpegHwAccel::Vaapi) => "h264_vaapi",
+        (FFmpegCodec::H264, FFmpegHwAccel::Qsv) => "h264_qsv",
+        (FFmpegCodec::H265, FFmpegHwAccel::Nvidia) => "hevc_nvenc",
+        (FFmpegCodec::H265, FFmpegHwAccel::Apple) => "hevc_videotoolbox",
+        (FFmpegCodec::H265, FFmpegHwAccel::Vaapi) => "hevc_vaapi",
+        (FFmpegCodec::H265, FFmpegHwAccel::Qsv) => "hevc_qsv",
+        (_, FFmpegHwAccel::Auto) => {
+            unreachable!("resolved into a concrete accelerator (or None) by available_hwaccel")
+        }
+        (FFmpegCodec::Av1, _) => unreachable!("av1 has no hwaccel variants, see available_matches"),
+    }
+}
+
+/// The accelerators `auto` tries, in the order it tries them. Nvidia/Quicksync/VAAPI encoders are
+/// all just a different ffmpeg build flag away regardless of host, so the most broadly-supported
+/// (and typically fastest) ones are tried first; Apple's VideoToolbox is last since it only exists
+/// on macOS builds in the first place.
+const AUTO_HWACCEL_PRIORITY: [FFmpegHwAccel; 4] = [
+    FFmpegHwAccel::Nvidia,
+    FFmpegHwAccel::Qsv,
+    FFmpegHwAccel::Vaapi,
+    FFmpegHwAccel::Apple,
+];
+
+/// The `ffmpeg -hwaccels` name for a [`FFmpegHwAccel`] variant, distinct from [`hwaccel_encoder`]'s
+/// per-codec encoder name: this is the underlying acceleration *method*, which ffmpeg can report
+/// as present even for a codec whose specific hw encoder isn't compiled in, and vice versa.
+fn hwaccel_method(hwaccel: &FFmpegHwAccel) -> &'static str {
+    match hwaccel {
+        FFmpegHwAccel::Nvidia => "cuda",
+        FFmpegHwAccel::Apple => "videotoolbox",
+        FFmpegHwAccel::Vaapi => "vaapi",
+        FFmpegHwAccel::Qsv => "qsv",
+        FFmpegHwAccel::Auto => {
+            unreachable!("resolved into a concrete accelerator (or None) by available_hwaccel")
+        }
+    }
+}
+
+/// The software (non-hardware-accelerated) encoder [`FFmpegPreset::into_input_output_args`] falls
+/// back to for `codec` when no hwaccel is requested, available, or resolved.
+fn software_encoder(codec: &FFmpegCodec) -> &'static str {
+    match codec {
+        FFmpegCodec::H264 => "libx264",
+        FFmpegCodec::H265 => "libx265",
+        FFmpegCodec::Av1 => "libsvtav1",
+    }
+}
+
+/// Snapshot of what the resolved ffmpeg binary reports it can do: which hardware acceleration
+/// methods (`ffmpeg -hwaccels`) and encoders (`ffmpeg -encoders`) it has compiled in. [`Self::probe`]
+/// runs both checks once per run and caches the result, since every encoder/hwaccel lookup used to
+/// re-spawn ffmpeg via the now-removed, encoders-only `ENCODER_CAPABILITIES` cache this replaces.
+#[derive(Clone)]
+pub struct FfmpegCapabilities {
+    pub hwaccel_methods: HashSet<String>,
+    pub encoders: HashSet<String>,
+}
+
+lazy_static! {
+    static ref FFMPEG_CAPABILITIES: Mutex<Option<FfmpegCapabilities>> = Mutex::new(None);
+}
+
+impl FfmpegCapabilities {
+    pub fn probe() -> Self {
+        let mut cache = FFMPEG_CAPABILITIES.lock().unwrap();
+        if let Some(capabilities) = &*cache {
+            return capabilities.clone();
+        }
+
+        let capabilities = FfmpegCapabilities {
+            hwaccel_methods: ffmpeg_command()
+                .args(["-hide_banner", "-hwaccels"])
+                .output()
+                .map(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        // first line is the "Hardware acceleration methods:" header
+                        .skip(1)
+                        .map(|line| line.trim().to_string())
+                        .filter(|line| !line.is_empty())
+                        .collect::<HashSet<String>>()
+                })
+                .unwrap_or_default(),
+            encoders: ffmpeg_command()
+                .args(["-hide_banner", "-encoders"])
+                .output()
+                .map(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .filter_map(|line| {
+                            let mut tokens = line.split_whitespace();
+                            let flags = tokens.next()?;
+                            if flags.len() != 6 || !flags.chars().all(|c| "VASFXBDIL.".contains(c))
+                            {
+                                return None;
+                            }
+                            tokens.next().map(|name| name.to_string())
+                        })
+                        .collect::<HashSet<String>>()
+                })
+                .unwrap_or_default(),
+        };
+
+        *cache = Some(capabilities.clone());
+        capabilities
+    }
+
+    /// Whether `hwaccel`'s encoder for `codec` is actually usable: both the encoder itself
+    /// (`-encoders`) and the underlying acceleration method (`-hwaccels`) are present.
+    fn supports(&self, codec: &FFmpegCodec, hwaccel: &FFmpegHwAccel) -> bool {
+        self.encoders.contains(hwaccel_encoder(codec, hwaccel))
+            && self.hwaccel_methods.contains(hwaccel_method(hwaccel))
+    }
+}
+
+/// Returns `hwaccel` back if the resolved ffmpeg binary actually supports it for `codec` (see
+/// [`FfmpegCapabilities::supports`]), or `None` (with a warning logged) if it was requested but
+/// isn't available, so callers can fall back to software encoding instead of handing ffmpeg an
+/// encoder name it doesn't know. `FFmpegHwAccel::Auto` is resolved here too: the first accelerator
+/// in [`AUTO_HWACCEL_PRIORITY`] the build supports, or `None` (silently, this being the whole
+/// point of `auto`) if it supports none of them.
+fn available_hwaccel(
+    codec: &FFmpegCodec,
+    hwaccel: Option<FFmpegHwAccel>,
+) -> Option<FFmpegHwAccel> {
+    let hwaccel = hwaccel?;
+    let capabilities = FfmpegCapabilities::probe();
+    if hwaccel == FFmpegHwAccel::Auto {
+        return AUTO_HWACCEL_PRIORITY
+            .into_iter()
+            .find(|h| capabilities.supports(codec, h));
+    }
+    if capabilities.supports(codec, &hwaccel) {
+        Some(hwaccel)
+    } else {
+        warn!(
+            "{} hardware acceleration was requested but `{}` is not available in this ffmpeg build, falling back to software encoding",
+            hwaccel.to_string(),
+            hwaccel_encoder(codec, &hwaccel)
+        );
+        None
+    }
+}
+
+/// The encoder names the resolved ffmpeg binary reports as available.
+fn available_encoders() -> HashSet<String> {
+    FfmpegCapabilities::probe().encoders
+}
+
+/// Checks whether the resolved ffmpeg binary has the `libvmaf` filter compiled in, which
+/// [`FFmpegPreset::resolve_target_quality`] needs to score candidate encodes.
+fn has_libvmaf() -> bool {
+    let Ok(output) = ffmpeg_command().arg("-filters").output() else {
+        return false;
+    };
+    String::from_utf8_lossy(&output.stdout).contains("libvmaf")
+}
+
+/// Binary-search bounds for the quality probe, per codec. VideoToolbox uses the `-q:v` scale
+/// (1-100) instead of CRF, so its bounds are independent of the codec.
+fn crf_search_range(codec: &FFmpegCodec, hwaccel: &Option<FFmpegHwAccel>) -> (u32, u32) {
+    if hwaccel == &Some(FFmpegHwAccel::Apple) {
+        return (1, 100);
+    }
+    match codec {
+        FFmpegCodec::H264 => (16, 35),
+        FFmpegCodec::H265 => (18, 35),
+        FFmpegCodec::Av1 => (20, 40),
+    }
+}
+
+/// Encodes a short probe clip starting 30s into `source` at the given crf/`-q:v` and returns the
+/// VMAF score ffmpeg's `libvmaf` filter computes for it against the untouched source.
+fn encode_and_score(
+    source: &Path,
+    codec: &FFmpegCodec,
+    hwaccel: &Option<FFmpegHwAccel>,
+    crf: u32,
+) -> Result<f64> {
+    // the probe only scores the video stream, so audio codec choice doesn't matter here
+    let probe = FFmpegPreset::Predefined(
+        codec.clone(),
+        hwaccel.clone(),
+        FFmpegQuality::Custom(crf),
+        FFmpegAudioCodec::Copy,
+        None,
+        None,
+    );
+    let (input_args, output_args) = probe.into_input_output_args(None);
+
+    let encoded = tempfile(".mkv")?.into_temp_path();
+    let status = ffmpeg_command()
+        .args(["-y", "-ss", "30", "-t", "4"])
+        .args(&input_args)
+        .arg("-i")
+        .arg(source)
+        .args(&output_args)
+        .arg(&encoded)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        bail!("probe encode at crf {} failed", crf)
+    }
+
+    let vmaf_log = tempfile(".json")?.into_temp_path();
+    let status = ffmpeg_command()
+        .args(["-ss", "30", "-t", "4", "-i"])
+        .arg(source)
+        .args(["-i"])
+        .arg(&encoded)
+        .arg("-lavfi")
+        .arg(format!(
+            "[0:v]setpts=PTS-STARTPTS[ref];[1:v]setpts=PTS-STARTPTS[dist];[dist][ref]libvmaf=log_fmt=json:log_path={}",
+            vmaf_log.to_string_lossy()
+        ))
+        .args(["-f", "null", "-"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        bail!("vmaf scoring at crf {} failed", crf)
+    }
+
+    let log: serde_json::Value = serde_json::from_str(&fs::read_to_string(&vmaf_log)?)?;
+    log["pooled_metrics"]["vmaf"]["mean"]
+        .as_f64()
+        .ok_or_else(|| anyhow::anyhow!("could not read vmaf score from libvmaf output"))
 }
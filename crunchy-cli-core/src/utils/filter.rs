@@ -1,7 +1,12 @@
 use crate::utils::format::{SingleFormat, SingleFormatCollection};
-use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
+use crate::utils::interactive_select::{
+    check_for_duplicated_seasons, get_duplicated_seasons, resolve_duplicated_seasons,
+};
+use crate::utils::locale::locale_from_season_slug;
+use crate::utils::media_cache::MediaCache;
 use crate::utils::parse::{fract, UrlFilter};
 use anyhow::Result;
+use chrono::{Datelike, TimeDelta};
 use crunchyroll_rs::{
     Concert, Episode, Locale, MediaCollection, Movie, MovieListing, MusicVideo, Season, Series,
 };
@@ -29,6 +34,20 @@ pub(crate) struct Filter {
     audio_locales: Vec<Locale>,
     subtitle_locales: Vec<Locale>,
 
+    /// Guess a season's audio locale from its slug title's trailing dub suffix (see
+    /// [`locale_from_season_slug`]) when `audio_locales` itself is empty, instead of the fragile
+    /// `unwrap_or(Locale::ja_JP)` fallback silently keeping/dropping the wrong episodes.
+    infer_locale_from_slug: bool,
+
+    /// Order episodes within a season by release month (`release_date`'s year and month) first and
+    /// `sequence_number` second, instead of the other way round, see [`Self::finish`].
+    sort_by_air_date: bool,
+
+    /// On-disk cache for the `seasons()`/`episodes()` lookups [`Filter::visit_series`] and
+    /// [`Filter::visit_season`] would otherwise re-fetch on every run. See `--cache-ttl`/
+    /// `--no-cache`/`--refresh-cache`.
+    media_cache: MediaCache,
+
     audios_missing: fn(FilterMediaScope, Vec<&Locale>) -> Result<bool>,
     subtitles_missing: fn(FilterMediaScope, Vec<&Locale>) -> Result<bool>,
     no_premium: fn(u32) -> Result<()>,
@@ -54,6 +73,10 @@ impl Filter {
         interactive_input: bool,
         skip_specials: bool,
         is_premium: bool,
+        infer_locale_from_slug: bool,
+        sort_by_air_date: bool,
+        cache_ttl: TimeDelta,
+        refresh_cache: bool,
     ) -> Self {
         Self {
             url_filter,
@@ -61,6 +84,9 @@ impl Filter {
             subtitle_locales,
             relative_episode_number,
             interactive_input,
+            infer_locale_from_slug,
+            sort_by_air_date,
+            media_cache: MediaCache::new(cache_ttl, refresh_cache, false),
             audios_missing,
             subtitles_missing,
             no_premium,
@@ -73,6 +99,26 @@ impl Filter {
         }
     }
 
+    /// `series.seasons()` through [`Self::media_cache`], keyed by `series.id`.
+    async fn cached_seasons(&self, series: &Series) -> Result<Vec<Season>> {
+        if let Some(seasons) = self.media_cache.get("seasons", &series.id) {
+            return Ok(seasons);
+        }
+        let seasons = series.seasons().await?;
+        self.media_cache.set("seasons", &series.id, &seasons);
+        Ok(seasons)
+    }
+
+    /// `season.episodes()` through [`Self::media_cache`], keyed by `season.id`.
+    async fn cached_episodes(&self, season: &Season) -> Result<Vec<Episode>> {
+        if let Some(episodes) = self.media_cache.get("episodes", &season.id) {
+            return Ok(episodes);
+        }
+        let episodes = season.episodes().await?;
+        self.media_cache.set("episodes", &season.id, &episodes);
+        Ok(episodes)
+    }
+
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
         // the audio locales field isn't always populated
         if !series.audio_locales.is_empty() {
@@ -92,7 +138,7 @@ impl Filter {
         }
 
         let mut seasons = vec![];
-        for season in series.seasons().await? {
+        for season in self.cached_seasons(&series).await? {
             if !self.url_filter.is_season_valid(season.season_number) {
                 continue;
             }
@@ -118,13 +164,14 @@ impl Filter {
                 check_for_duplicated_seasons(&mut seasons)
             } else {
                 info!(
-                    "Found duplicated seasons: {}",
+                    "Found duplicated seasons: {}, keeping the release matching the requested audio",
                     duplicated_seasons
                         .iter()
                         .map(|d| d.to_string())
                         .collect::<Vec<String>>()
                         .join(", ")
-                )
+                );
+                resolve_duplicated_seasons(&mut seasons, &self.audio_locales, None, false, None)
             }
         }
 
@@ -151,14 +198,21 @@ impl Filter {
                 continue;
             }
             if self.audio_locales.contains(&version.audio_locale) {
-                seasons.push(version.season().await?)
+                if let Some(cached) = self.media_cache.get("season-version", &version.id) {
+                    seasons.push(cached)
+                } else {
+                    let version_season = version.season().await?;
+                    self.media_cache
+                        .set("season-version", &version.id, &version_season);
+                    seasons.push(version_season)
+                }
             }
         }
 
         let mut episodes = vec![];
         for season in seasons {
             self.season_sorting.push(season.id.clone());
-            let mut eps = season.episodes().await?;
+            let mut eps = self.cached_episodes(&season).await?;
 
             // removes any episode that does not have the audio locale of the season. yes, this is
             // the case sometimes
@@ -167,6 +221,13 @@ impl Filter {
                     .audio_locales
                     .first()
                     .cloned()
+                    // `audio_locales` is sometimes empty entirely; guess the locale from the
+                    // season's slug title (e.g. `...-german`) instead of blindly assuming Japanese
+                    .or_else(|| {
+                        self.infer_locale_from_slug
+                            .then(|| locale_from_season_slug(&season.slug_title))
+                            .flatten()
+                    })
                     .unwrap_or(Locale::ja_JP);
                 eps.retain(|e| e.audio_locale == season_locale)
             }
@@ -289,10 +350,10 @@ impl Filter {
             let season_eps = match self.season_episodes.get(&episode.season_id) {
                 Some(eps) => eps,
                 None => {
-                    self.season_episodes.insert(
-                        episode.season_id.clone(),
-                        episode.season().await?.episodes().await?,
-                    );
+                    let season = episode.season().await?;
+                    let eps = self.cached_episodes(&season).await?;
+                    self.season_episodes
+                        .insert(episode.season_id.clone(), eps);
                     self.season_episodes.get(&episode.season_id).unwrap()
                 }
             };
@@ -335,7 +396,20 @@ impl Filter {
     }
 
     async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
-        Ok(movie_listing.movies().await?)
+        // movie listings have no season/episode numbers of their own, so a season/episode filter
+        // treats the whole listing as season 1 and each movie's position within it as the episode
+        // number, letting e.g. `[E2]` pick a single movie out of a listing
+        if !self.url_filter.is_season_valid(1) {
+            return Ok(vec![]);
+        }
+        Ok(movie_listing
+            .movies()
+            .await?
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.url_filter.is_episode_valid((i + 1) as f32, 1))
+            .map(|(_, movie)| movie)
+            .collect())
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Vec<SingleFormat>> {
@@ -365,17 +439,31 @@ impl Filter {
 
         let mut sorted: Vec<(String, Vec<SingleFormat>)> = pre_sorted.into_iter().collect();
         sorted.sort_by(|(_, a), (_, b)| {
-            self.season_sorting
-                .iter()
-                .position(|p| p == &a.first().unwrap().season_id)
-                .unwrap()
-                .cmp(
-                    &self
-                        .season_sorting
-                        .iter()
-                        .position(|p| p == &b.first().unwrap().season_id)
-                        .unwrap(),
-                )
+            let a = a.first().unwrap();
+            let b = b.first().unwrap();
+
+            // falls back to the end instead of panicking if a season_id was somehow never visited
+            let season_position = |id: &str| {
+                self.season_sorting
+                    .iter()
+                    .position(|p| p == id)
+                    .unwrap_or(usize::MAX)
+            };
+            let air_month = |f: &SingleFormat| f.release_date.map(|d| (d.year(), d.month()));
+
+            season_position(&a.season_id)
+                .cmp(&season_position(&b.season_id))
+                .then_with(|| {
+                    if self.sort_by_air_date {
+                        air_month(a)
+                            .cmp(&air_month(b))
+                            .then_with(|| a.sequence_number.total_cmp(&b.sequence_number))
+                    } else {
+                        a.sequence_number
+                            .total_cmp(&b.sequence_number)
+                            .then_with(|| air_month(a).cmp(&air_month(b)))
+                    }
+                })
         });
 
         for (_, mut data) in sorted {
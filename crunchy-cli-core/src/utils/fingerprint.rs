@@ -0,0 +1,143 @@
+use crate::utils::download::Downloader;
+use crate::utils::os::{ffmpeg_command, tempfile};
+use anyhow::{anyhow, bail, Result};
+use crunchyroll_rs::Episode;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How much of a candidate's audio is sampled for fingerprinting, see `--verify-duplicates`.
+const SAMPLE_SECONDS: u32 = 45;
+/// Leading HLS/DASH segments fetched per candidate before fingerprinting, generous enough to cover
+/// `SAMPLE_SECONDS` even with short (~2s) segments.
+const SAMPLE_SEGMENTS: usize = 30;
+/// Fingerprint frames are taken 1/8s apart, the same granularity Chromaprint itself targets.
+const FRAMES_PER_SECOND: u32 = 8;
+const SAMPLE_RATE: u32 = 11025;
+/// Two fingerprints must overlap by at least this many frames (half of `SAMPLE_SECONDS`) before a
+/// match score is considered meaningful, mirroring Chromaprint's own `match_fingerprints` minimum.
+const MIN_ALIGNED_FRAMES: usize = (SAMPLE_SECONDS * FRAMES_PER_SECOND / 2) as usize;
+/// Best-alignment score above which two candidates are treated as the same content, see
+/// `--verify-duplicates`.
+pub const DUPLICATE_MATCH_THRESHOLD: f64 = 0.65;
+
+/// A coarse, per-frame energy-banded audio fingerprint: a self-contained stand-in for Chromaprint
+/// (not vendored anywhere in this tree) that's compared the same way czkawka's matcher does, via a
+/// sliding-window best-alignment scan rather than an exact hash compare, so the two samples don't
+/// need to start in perfect sync. Cheap enough to cache per episode id, see `MediaCache`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AudioFingerprint(Vec<u32>);
+
+impl AudioFingerprint {
+    /// Decodes the first `SAMPLE_SECONDS` of `path`'s audio (expected to already be the candidate's
+    /// lowest-bitrate variant) and reduces each 1/`FRAMES_PER_SECOND`s slice to an 8-band energy
+    /// signature packed into a `u32`.
+    pub fn compute(path: &Path) -> Result<Self> {
+        let (_file, raw_path) = tempfile(".pcm")?.into_parts();
+        let output = ffmpeg_command()
+            .arg("-hide_banner")
+            .arg("-y")
+            .args(["-i", path.to_string_lossy().as_ref()])
+            .args(["-t", &SAMPLE_SECONDS.to_string()])
+            .args(["-vn", "-ac", "1", "-ar", &SAMPLE_RATE.to_string()])
+            .args(["-f", "s16le"])
+            .arg(raw_path.to_string_lossy().to_string())
+            .output()?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr));
+        }
+
+        let raw = std::fs::read(&raw_path)?;
+        let samples_per_frame = (SAMPLE_RATE / FRAMES_PER_SECOND) as usize;
+        let frames = raw
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect::<Vec<i16>>()
+            .chunks(samples_per_frame)
+            .map(pack_frame)
+            .collect();
+
+        Ok(Self(frames))
+    }
+
+    /// Downloads a short leading sample of `episode`'s lowest-bitrate audio track through
+    /// `downloader` and fingerprints it, for `--verify-duplicates`. Errors (so the caller falls
+    /// back to the metadata heuristic instead of treating the candidate as verified either way) if
+    /// the episode exposes no stream or audio data at all.
+    pub async fn compute_for_episode(episode: &Episode, downloader: &Downloader) -> Result<Self> {
+        let stream = episode.stream().await?;
+        let (_, mut audios) = stream
+            .stream_data(None)
+            .await?
+            .ok_or_else(|| anyhow!("episode {} exposes no stream data", episode.id))?;
+        audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth));
+        let lowest_bitrate = audios
+            .first()
+            .ok_or_else(|| anyhow!("episode {} has no audio track", episode.id))?;
+
+        let path = downloader
+            .download_audio_sample(
+                lowest_bitrate,
+                format!(
+                    "Sampling audio of episode {} for duplicate verification",
+                    episode.id
+                ),
+                SAMPLE_SEGMENTS,
+            )
+            .await?;
+
+        Self::compute(&path)
+    }
+}
+
+/// Splits one frame's samples into 8 contiguous bands and packs each band's mean amplitude
+/// (relative to the frame's own peak) into 4 bits, à la Chromaprint's per-band quantization.
+fn pack_frame(frame: &[i16]) -> u32 {
+    let peak = frame.iter().map(|s| s.unsigned_abs()).max().unwrap_or(1).max(1) as u32;
+    let band_len = (frame.len() / 8).max(1);
+    let mut packed = 0u32;
+    for (band_idx, band) in frame.chunks(band_len).take(8).enumerate() {
+        let mean = band.iter().map(|s| s.unsigned_abs() as u32).sum::<u32>() / band.len() as u32;
+        let level = (mean * 15) / peak;
+        packed |= level.min(15) << (band_idx * 4);
+    }
+    packed
+}
+
+/// Slides `b` over `a` looking for the offset with the most per-frame agreement, the same shape as
+/// Chromaprint's own `match_fingerprints`. Returns a score in `0.0..=1.0` (the best-alignment
+/// fraction of matching frames), or `0.0` if the two never overlap by at least `MIN_ALIGNED_FRAMES`.
+pub fn match_fingerprints(a: &AudioFingerprint, b: &AudioFingerprint) -> f64 {
+    let (a, b) = (a.0.as_slice(), b.0.as_slice());
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let mut best = 0.0f64;
+    for offset in -(b.len() as isize - 1)..(a.len() as isize) {
+        let mut aligned = 0usize;
+        let mut matching = 0usize;
+        for (i, &va) in a.iter().enumerate() {
+            let j = i as isize - offset;
+            if j < 0 || j as usize >= b.len() {
+                continue;
+            }
+            aligned += 1;
+            // Frames "match" if they differ in at most 2 of the 8 packed 4-bit bands, the same
+            // tolerant per-frame compare Chromaprint applies before counting an error bit.
+            let diff_bands = (0..8)
+                .filter(|band| {
+                    let shift = band * 4;
+                    ((va >> shift) & 0xF) != ((b[j as usize] >> shift) & 0xF)
+                })
+                .count();
+            if diff_bands <= 2 {
+                matching += 1;
+            }
+        }
+        if aligned < MIN_ALIGNED_FRAMES {
+            continue;
+        }
+        best = best.max(matching as f64 / aligned as f64);
+    }
+    best
+}
@@ -1,23 +1,94 @@
+use crate::search::format::xml_escape;
 use crate::utils::filter::real_dedup_vec;
+use crate::utils::download::SubtitleKind;
+use crate::utils::fmt::format_time_delta;
 use crate::utils::log::tab_info;
 use crate::utils::os::{is_special_file, sanitize};
+use crate::utils::resume::parse_existing_files;
 use anyhow::Result;
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use crunchyroll_rs::media::{Resolution, Stream, Subtitle, VariantData};
 use crunchyroll_rs::{Concert, Episode, Locale, MediaCollection, Movie, MusicVideo};
 use log::{debug, info};
+use regex::{Captures, Regex};
+use serde::Serialize;
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
 use std::path::{Path, PathBuf};
 
+lazy_static::lazy_static! {
+    /// Matches a `{name}` template token, optionally in its `{name:width}` (fixed-width numeric
+    /// padding) or `{?name:literal}` (omitted entirely if `name`'s value is absent) forms.
+    static ref TEMPLATE_TOKEN_RE: Regex = Regex::new(r"\{(\??)([a-z_]+)(?::([^}]*))?}").unwrap();
+}
+
+/// Lowercases `title` and strips a leading "a ", "an " or "the " article (case-insensitively), so
+/// e.g. "The Movie" sorts next to other titles starting with "m" instead of under "t".
+pub fn sort_title(title: &str) -> String {
+    let lower = title.to_lowercase();
+    for article in ["the ", "an ", "a "] {
+        if let Some(stripped) = lower.strip_prefix(article) {
+            return stripped.to_string();
+        }
+    }
+    lower
+}
+
+/// Compares two strings chunk-wise, treating contiguous runs of digits as numbers, so e.g.
+/// "Season 2" sorts before "Season 10" instead of after it (plain string comparison would put the
+/// "1" of "10" before the "2" of "2").
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> Vec<(bool, String)> {
+        let mut chunks: Vec<(bool, String)> = vec![];
+        for c in s.chars() {
+            let is_digit = c.is_ascii_digit();
+            match chunks.last_mut() {
+                Some((last_is_digit, chunk)) if *last_is_digit == is_digit => chunk.push(c),
+                _ => chunks.push((is_digit, c.to_string())),
+            }
+        }
+        chunks
+    }
+
+    let a_chunks = chunks(a);
+    let b_chunks = chunks(b);
+
+    for (a_chunk, b_chunk) in a_chunks.iter().zip(b_chunks.iter()) {
+        let ordering = if a_chunk.0 && b_chunk.0 {
+            a_chunk
+                .1
+                .parse::<u64>()
+                .unwrap_or(0)
+                .cmp(&b_chunk.1.parse::<u64>().unwrap_or(0))
+        } else {
+            a_chunk.1.cmp(&b_chunk.1)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a_chunks.len().cmp(&b_chunks.len())
+}
+
 #[derive(Clone)]
 pub struct SingleFormat {
     pub identifier: String,
 
     pub title: String,
     pub description: String,
+    /// Lowercased `title` with a leading article stripped, used as a deterministic, human-expected
+    /// tiebreaker wherever titles are sorted (see [`sort_title`]).
+    pub sort_title: String,
 
     pub audio: Locale,
+    /// Whether `audio` is the production-original language for this title (e.g. Japanese for
+    /// most anime, but Korean/Chinese/English for others), as marked by the stream's version
+    /// metadata. Used to resolve the `original` audio/subtitle keyword and to pick the MKV
+    /// default audio track without hardcoding a single locale.
+    pub is_original: bool,
     pub subtitles: Vec<Locale>,
 
     pub series_id: String,
@@ -34,6 +105,9 @@ pub struct SingleFormat {
     pub relative_sequence_number: Option<f32>,
 
     pub duration: Duration,
+    /// When the episode first aired, if known. `None` for movies, music videos and concerts,
+    /// which don't expose an air date.
+    pub release_date: Option<DateTime<Utc>>,
 
     source: MediaCollection,
 }
@@ -44,6 +118,7 @@ impl SingleFormat {
         subtitles: Vec<Locale>,
         relative_episode_number: Option<u32>,
         relative_sequence_number: Option<f32>,
+        is_original: bool,
     ) -> Self {
         Self {
             identifier: if episode.identifier.is_empty() {
@@ -59,8 +134,10 @@ impl SingleFormat {
                 episode.identifier.clone()
             },
             title: episode.title.clone(),
+            sort_title: sort_title(&episode.title),
             description: episode.description.clone(),
             audio: episode.audio_locale.clone(),
+            is_original,
             subtitles,
             series_id: episode.series_id.clone(),
             series_name: episode.series_title.clone(),
@@ -77,6 +154,7 @@ impl SingleFormat {
             relative_episode_number,
             relative_sequence_number,
             duration: episode.duration,
+            release_date: Some(episode.air_date),
             source: episode.into(),
         }
     }
@@ -85,8 +163,10 @@ impl SingleFormat {
         Self {
             identifier: movie.id.clone(),
             title: movie.title.clone(),
+            sort_title: sort_title(&movie.title),
             description: movie.description.clone(),
             audio: Locale::ja_JP,
+            is_original: true,
             subtitles,
             series_id: movie.movie_listing_id.clone(),
             series_name: movie.movie_listing_title.clone(),
@@ -99,6 +179,7 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: movie.duration,
+            release_date: None,
             source: movie.into(),
         }
     }
@@ -107,8 +188,10 @@ impl SingleFormat {
         Self {
             identifier: music_video.id.clone(),
             title: music_video.title.clone(),
+            sort_title: sort_title(&music_video.title),
             description: music_video.description.clone(),
             audio: Locale::ja_JP,
+            is_original: true,
             subtitles: vec![],
             series_id: music_video.id.clone(),
             series_name: music_video.title.clone(),
@@ -121,6 +204,7 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: music_video.duration,
+            release_date: None,
             source: music_video.into(),
         }
     }
@@ -129,8 +213,10 @@ impl SingleFormat {
         Self {
             identifier: concert.id.clone(),
             title: concert.title.clone(),
+            sort_title: sort_title(&concert.title),
             description: concert.description.clone(),
             audio: Locale::ja_JP,
+            is_original: true,
             subtitles: vec![],
             series_id: concert.id.clone(),
             series_name: concert.title.clone(),
@@ -143,6 +229,7 @@ impl SingleFormat {
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
             duration: concert.duration,
+            release_date: None,
             source: concert.into(),
         }
     }
@@ -172,6 +259,196 @@ impl SingleFormat {
     pub fn is_episode(&self) -> bool {
         matches!(self.source, MediaCollection::Episode(_))
     }
+
+    /// Builds the `--dump-json` representation of this episode/movie/music video/concert,
+    /// including the resolutions its stream is available in.
+    pub async fn dump_json(&self) -> DumpJsonEpisode {
+        let resolutions = match self.stream().await.map(|s| s.stream_data(None)) {
+            Ok(fut) => match fut.await {
+                Ok(Some((videos, _))) => {
+                    let mut resolutions: Vec<String> = videos
+                        .iter()
+                        .filter_map(|v| v.resolution())
+                        .map(|r| r.to_string())
+                        .collect();
+                    real_dedup_vec(&mut resolutions);
+                    resolutions
+                }
+                _ => vec![],
+            },
+            Err(_) => vec![],
+        };
+
+        DumpJsonEpisode {
+            id: self.episode_id.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            audio: self.audio.to_string(),
+            subtitles: self.subtitles.iter().map(|l| l.to_string()).collect(),
+            episode_number: self.episode_number.clone(),
+            sequence_number: self.sequence_number,
+            duration: format_time_delta(&self.duration),
+            resolutions,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DumpJsonEpisode {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub audio: String,
+    pub subtitles: Vec<String>,
+    pub episode_number: String,
+    pub sequence_number: f32,
+    pub duration: String,
+    pub resolutions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DumpJsonSeason {
+    pub id: String,
+    pub title: String,
+    pub number: u32,
+    /// Whether this season shares its number with another season of the same series (e.g. a dub
+    /// uploaded as its own season). See `get_duplicated_seasons`.
+    pub duplicated: bool,
+    pub episodes: Vec<DumpJsonEpisode>,
+}
+
+#[derive(Serialize)]
+pub struct DumpJsonSeries {
+    pub series_id: String,
+    pub series_name: String,
+    pub seasons: Vec<DumpJsonSeason>,
+}
+
+#[derive(Serialize)]
+pub struct FormatsEpisode {
+    pub id: String,
+    pub title: String,
+    pub episode_number: String,
+    pub relative_episode_number: Option<u32>,
+    pub sequence_number: f32,
+    pub relative_sequence_number: Option<f32>,
+    /// `(audio, [subtitles])` tuples, one per resolved audio track, mirroring [`Format::locales`].
+    pub locales: Vec<(String, Vec<String>)>,
+    pub resolution: String,
+    pub fps: f64,
+    pub duration: String,
+}
+
+#[derive(Serialize)]
+pub struct FormatsSeason {
+    pub id: String,
+    pub title: String,
+    pub number: u32,
+    pub episodes: Vec<FormatsEpisode>,
+}
+
+#[derive(Serialize)]
+pub struct FormatsSeries {
+    pub series_id: String,
+    pub series_name: String,
+    pub seasons: Vec<FormatsSeason>,
+}
+
+/// Serialized by [`Format::info_json`] into the `--embed-info-json` attachment.
+#[derive(Serialize)]
+pub struct FormatInfoJson {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub series_id: String,
+    pub series_name: String,
+    pub season_id: String,
+    pub season_title: String,
+    pub season_number: u32,
+    pub episode_number: String,
+    pub relative_episode_number: Option<u32>,
+    pub sequence_number: f32,
+    pub relative_sequence_number: Option<f32>,
+    pub duration: String,
+    pub release_date: Option<String>,
+    pub locales: Vec<(String, Vec<String>)>,
+    pub resolution: String,
+    pub fps: f64,
+}
+
+/// Groups already-resolved [`Format`]s (as produced per episode by `get_format` during a real
+/// download/archive run) into the per-season tree `--print-formats` serializes. Unlike
+/// [`SingleFormatCollection::dump_json`], which runs before any stream is resolved and can only
+/// list the resolutions a stream offers, this reflects the audio/subtitle tracks and
+/// resolution/fps actually selected for output.
+pub fn group_formats_by_season(formats: &[Format]) -> FormatsSeries {
+    let mut series_id = String::new();
+    let mut series_name = String::new();
+    let mut seasons: Vec<FormatsSeason> = vec![];
+    for format in formats {
+        series_id = format.series_id.clone();
+        series_name = format.series_name.clone();
+
+        let episode = format.formats_episode();
+        match seasons
+            .iter_mut()
+            .find(|s| s.number == format.season_number && s.id == format.season_id)
+        {
+            Some(season) => season.episodes.push(episode),
+            None => seasons.push(FormatsSeason {
+                id: format.season_id.clone(),
+                title: format.season_title.clone(),
+                number: format.season_number,
+                episodes: vec![episode],
+            }),
+        }
+    }
+
+    FormatsSeries {
+        series_id,
+        series_name,
+        seasons,
+    }
+}
+
+/// Output format for `--print-formats`. `yaml` is gated behind the `yaml` feature the same way
+/// rustypipe gates its `report-yaml` output, since pulling in a YAML serializer isn't worth it for
+/// users who only ever want JSON.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PrintFormatsOutput {
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl Display for PrintFormatsOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            PrintFormatsOutput::Json => "json",
+            #[cfg(feature = "yaml")]
+            PrintFormatsOutput::Yaml => "yaml",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+impl PrintFormatsOutput {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            #[cfg(feature = "yaml")]
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(format!("invalid print-formats output '{}'", s)),
+        }
+    }
+
+    pub fn serialize(&self, series: &FormatsSeries) -> Result<String> {
+        Ok(match self {
+            PrintFormatsOutput::Json => serde_json::to_string_pretty(series)?,
+            #[cfg(feature = "yaml")]
+            PrintFormatsOutput::Yaml => serde_yaml::to_string(series)?,
+        })
+    }
 }
 
 struct SingleFormatCollectionEpisodeKey(f32);
@@ -193,34 +470,32 @@ impl PartialEq for SingleFormatCollectionEpisodeKey {
 }
 impl Eq for SingleFormatCollectionEpisodeKey {}
 
-struct SingleFormatCollectionSeasonKey((u32, String));
+struct SingleFormatCollectionSeasonKey {
+    season_number: u32,
+    season_id: String,
+    /// [`sort_title`] of the season title (for movies, their movie listing's title; for music
+    /// videos and concerts, which don't have one, the item's own title), used to deterministically order multiple "seasons" which share a season number
+    /// (e.g. a series with several same-numbered dub seasons, or a collection of movies which all
+    /// report season 1) instead of the previous arbitrary "first come first serve" ordering.
+    sort_title: String,
+}
 
-#[allow(clippy::incorrect_partial_ord_impl_on_ord_type)]
 impl PartialOrd for SingleFormatCollectionSeasonKey {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let mut cmp = self.0 .0.partial_cmp(&other.0 .0);
-        if let Some(ordering) = cmp {
-            if matches!(ordering, Ordering::Equal) && self.0 .1 != other.0 .1 {
-                // first come first serve
-                cmp = Some(Ordering::Greater)
-            }
-        }
-        cmp
+        Some(self.cmp(other))
     }
 }
 impl Ord for SingleFormatCollectionSeasonKey {
     fn cmp(&self, other: &Self) -> Ordering {
-        let mut cmp = self.0 .0.cmp(&other.0 .0);
-        if matches!(cmp, Ordering::Equal) && self.0 .1 != other.0 .1 {
-            // first come first serve
-            cmp = Ordering::Greater
-        }
-        cmp
+        self.season_number
+            .cmp(&other.season_number)
+            .then_with(|| natural_cmp(&self.sort_title, &other.sort_title))
+            .then_with(|| self.season_id.cmp(&other.season_id))
     }
 }
 impl PartialEq for SingleFormatCollectionSeasonKey {
     fn eq(&self, other: &Self) -> bool {
-        self.0.eq(&other.0)
+        self.season_number == other.season_number && self.season_id == other.season_id
     }
 }
 impl Eq for SingleFormatCollectionSeasonKey {}
@@ -244,10 +519,11 @@ impl SingleFormatCollection {
     pub fn add_single_formats(&mut self, single_formats: Vec<SingleFormat>) {
         let format = single_formats.first().unwrap();
         self.0
-            .entry(SingleFormatCollectionSeasonKey((
-                format.season_number,
-                format.season_id.clone(),
-            )))
+            .entry(SingleFormatCollectionSeasonKey {
+                season_number: format.season_number,
+                season_id: format.season_id.clone(),
+                sort_title: sort_title(&format.season_title),
+            })
             .or_default()
             .insert(
                 SingleFormatCollectionEpisodeKey(format.sequence_number),
@@ -255,6 +531,119 @@ impl SingleFormatCollection {
             );
     }
 
+    /// Removes episodes which are already present in `dir` from this collection, so a re-run of a
+    /// season download only fetches what's missing. Existing file names are recovered via
+    /// [`parse_existing_files`] instead of relying on an exact `format_path` match, since the
+    /// template, resolution or release-group-style tags around the name may have changed since the
+    /// file was downloaded. Only `(season_number, episode_number)` is compared (optionally with
+    /// `resolution` if `match_resolution` is set); special episodes with a fractional
+    /// `sequence_number` are never matched since their episode number doesn't round-trip through
+    /// a filename.
+    ///
+    /// Returns the number of episodes removed.
+    pub fn remove_existing(&mut self, dir: &Path, match_resolution: Option<Resolution>) -> usize {
+        let existing = parse_existing_files(dir);
+
+        let mut removed = 0;
+        self.0.retain(|_, episodes| {
+            episodes.retain(|episode_key, formats| {
+                let format = &formats[0];
+                if format.sequence_number.fract() != 0.0 {
+                    return true;
+                }
+                let episode_number = episode_key.0 as u32;
+
+                let is_existing = existing.iter().any(|parsed| {
+                    parsed.season_number == Some(format.season_number)
+                        && parsed.episode_number == Some(episode_number)
+                        && match_resolution
+                            .as_ref()
+                            .map_or(true, |res| parsed.resolution.as_ref() == Some(res))
+                });
+
+                if is_existing {
+                    removed += 1;
+                }
+                !is_existing
+            });
+            !episodes.is_empty()
+        });
+
+        removed
+    }
+
+    /// Removes episodes whose `episode_id` is present in `archived` from this collection, used by
+    /// `--archive` to skip episodes a previous run already completed, without requiring any of the
+    /// filename-guessing `remove_existing` needs.
+    ///
+    /// Returns the number of episodes removed.
+    pub fn remove_archived(&mut self, archived: &HashSet<String>) -> usize {
+        let mut removed = 0;
+        self.0.retain(|_, episodes| {
+            episodes.retain(|_, formats| {
+                let is_archived = formats.iter().any(|f| archived.contains(&f.episode_id));
+                if is_archived {
+                    removed += 1;
+                }
+                !is_archived
+            });
+            !episodes.is_empty()
+        });
+
+        removed
+    }
+
+    /// Builds the `--dump-json` representation of the whole collection, marking seasons which
+    /// share a season number with another season as duplicated (the same condition
+    /// `get_duplicated_seasons` checks for interactive selection).
+    pub async fn dump_json(&self) -> DumpJsonSeries {
+        let mut season_number_counts: HashMap<u32, usize> = HashMap::new();
+        for season_key in self.0.keys() {
+            *season_number_counts.entry(season_key.season_number).or_default() += 1;
+        }
+
+        let mut series_id = String::new();
+        let mut series_name = String::new();
+        let mut seasons = vec![];
+        for (season_key, episodes) in &self.0 {
+            let mut season_id = String::new();
+            let mut season_title = String::new();
+            let mut dump_episodes = vec![];
+            for formats in episodes.values() {
+                let format = &formats[0];
+                series_id = format.series_id.clone();
+                series_name = format.series_name.clone();
+                season_id = format.season_id.clone();
+                season_title = format.season_title.clone();
+                dump_episodes.push(format.dump_json().await);
+            }
+            seasons.push(DumpJsonSeason {
+                id: season_id,
+                title: season_title,
+                number: season_key.season_number,
+                duplicated: season_number_counts[&season_key.season_number] > 1,
+                episodes: dump_episodes,
+            });
+        }
+
+        DumpJsonSeries {
+            series_id,
+            series_name,
+            seasons,
+        }
+    }
+
+    /// Iterates over one representative [`SingleFormat`] per episode in the collection, without
+    /// consuming it (unlike [`SingleFormatCollection::into_iter`]). Used by the `gc` subsystem to
+    /// compute the expected output paths without having to drain the collection first.
+    pub fn single_formats(&self) -> impl Iterator<Item = &SingleFormat> {
+        self.0.values().flat_map(|episodes| {
+            episodes
+                .values()
+                .map(|formats| formats.first().unwrap())
+        })
+    }
+
     pub fn full_visual_output(&self) {
         debug!("Series has {} seasons", self.0.len());
         for (season_key, episodes) in &self.0 {
@@ -262,7 +651,7 @@ impl SingleFormatCollection {
             info!(
                 "{} Season {} ({})",
                 first_episode.series_name.clone(),
-                season_key.0 .0,
+                season_key.season_number,
                 first_episode.season_title.clone(),
             );
             for (i, (_, formats)) in episodes.iter().enumerate() {
@@ -316,9 +705,17 @@ impl Iterator for SingleFormatCollectionIterator {
 #[derive(Clone)]
 pub struct Format {
     pub title: String,
+    /// See [`SingleFormat::sort_title`].
+    pub sort_title: String,
     pub description: String,
 
     pub locales: Vec<(Locale, Vec<Locale>)>,
+    /// The locale among `locales` that's the production-original language, if any of the muxed
+    /// audios is. See [`SingleFormat::is_original`].
+    pub original_audio: Option<Locale>,
+    /// Server-side hardsub variant the video was requested with, if any. Set by the caller after
+    /// construction, see `download`'s `--hardsub`.
+    pub hardsub: Option<Locale>,
 
     pub resolution: Resolution,
     pub fps: f64,
@@ -335,12 +732,16 @@ pub struct Format {
     pub relative_episode_number: Option<u32>,
     pub sequence_number: f32,
     pub relative_sequence_number: Option<f32>,
+
+    pub duration: Duration,
+    /// See [`SingleFormat::release_date`].
+    pub release_date: Option<DateTime<Utc>>,
 }
 
 impl Format {
     #[allow(clippy::type_complexity)]
     pub fn from_single_formats(
-        mut single_formats: Vec<(SingleFormat, VariantData, Vec<(Subtitle, bool)>)>,
+        mut single_formats: Vec<(SingleFormat, VariantData, Vec<(Subtitle, SubtitleKind)>)>,
     ) -> Self {
         let locales: Vec<(Locale, Vec<Locale>)> = single_formats
             .iter()
@@ -354,12 +755,19 @@ impl Format {
                 )
             })
             .collect();
+        let original_audio = single_formats
+            .iter()
+            .find(|(single_format, _, _)| single_format.is_original)
+            .map(|(single_format, _, _)| single_format.audio.clone());
         let (first_format, first_stream, _) = single_formats.remove(0);
 
         Self {
             title: first_format.title,
+            sort_title: first_format.sort_title,
             description: first_format.description,
             locales,
+            original_audio,
+            hardsub: None,
             resolution: first_stream.resolution,
             fps: first_stream.fps,
             series_id: first_format.series_id,
@@ -372,67 +780,160 @@ impl Format {
             relative_episode_number: first_format.relative_episode_number,
             sequence_number: first_format.sequence_number,
             relative_sequence_number: first_format.relative_sequence_number,
+            duration: first_format.duration,
+            release_date: first_format.release_date,
+        }
+    }
+
+    /// Builds the `--print-formats` representation of this already-resolved format. See
+    /// [`group_formats_by_season`].
+    pub fn formats_episode(&self) -> FormatsEpisode {
+        FormatsEpisode {
+            id: self.episode_id.clone(),
+            title: self.title.clone(),
+            episode_number: self.episode_number.clone(),
+            relative_episode_number: self.relative_episode_number,
+            sequence_number: self.sequence_number,
+            relative_sequence_number: self.relative_sequence_number,
+            locales: self
+                .locales
+                .iter()
+                .map(|(audio, subtitles)| {
+                    (
+                        audio.to_string(),
+                        subtitles.iter().map(|s| s.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            resolution: self.resolution.to_string(),
+            fps: self.fps,
+            duration: format_time_delta(&self.duration),
+        }
+    }
+
+    /// Returns the literal directory prefix of `template` (the directory components which don't
+    /// contain a `{...}` placeholder), i.e. the root directory [`Format::format_path`] can ever
+    /// write into for this template. Used by `--skip-existing` and the `gc` subsystem to know
+    /// where on disk they're allowed to look.
+    ///
+    /// Returns `None` when `template` has no literal directory component at all (e.g. the default
+    /// `"{title}.mp4"`) - the caller isn't allowed to fall back to the current directory in that
+    /// case, since that would silently turn "no directory was given" into "recursively scan
+    /// wherever this command happens to be run from".
+    pub fn template_root_dir(template: &str) -> Option<PathBuf> {
+        let mut root = PathBuf::new();
+        if let Some(parent) = Path::new(template).parent() {
+            for component in parent.components() {
+                if component.as_os_str().to_string_lossy().contains('{') {
+                    break;
+                }
+                root.push(component);
+            }
         }
+        (!root.as_os_str().is_empty()).then_some(root)
+    }
+
+    /// Raw (unpadded, unsanitized) value of a plain template token, or `None` if `name` isn't a
+    /// known token - in which case the original `{name}` text is left untouched by
+    /// [`Format::format_path`].
+    fn token_value(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "title" => self.title.clone(),
+            "audio" => self
+                .locales
+                .iter()
+                .map(|(a, _)| a.to_string())
+                .collect::<Vec<String>>()
+                .join("|"),
+            "hardsub" => self
+                .hardsub
+                .as_ref()
+                .map(|l| l.to_string())
+                .unwrap_or_default(),
+            "resolution" => self.resolution.to_string(),
+            "series_id" => self.series_id.clone(),
+            "series_name" => self.series_name.clone(),
+            "season_id" => self.season_id.clone(),
+            "season_name" => self.season_title.clone(),
+            "season_number" => self.season_number.to_string(),
+            "episode_id" => self.episode_id.clone(),
+            "episode_number" => self.episode_number.clone(),
+            "relative_episode_number" => {
+                self.relative_episode_number.unwrap_or_default().to_string()
+            }
+            "sequence_number" => self.sequence_number.to_string(),
+            "relative_sequence_number" => self
+                .relative_sequence_number
+                .unwrap_or_default()
+                .to_string(),
+            _ => return None,
+        })
+    }
+
+    /// The zero-padded width an un-suffixed (`{name}`, no `:width`) numeric token historically
+    /// padded to. Non-numeric tokens (`title`, `series_name`, ...) are never padded.
+    fn token_default_width(name: &str) -> usize {
+        match name {
+            "season_number" | "episode_number" | "relative_episode_number" | "sequence_number"
+            | "relative_sequence_number" => 2,
+            _ => 0,
+        }
+    }
+
+    /// Value of an `{?name:...}` optional-group token, or `None` if `name` isn't a token which
+    /// supports the optional-group form. The inner `Option` mirrors the field's own presence.
+    fn optional_token_value(&self, name: &str) -> Option<Option<String>> {
+        Some(match name {
+            "relative_episode_number" => self.relative_episode_number.map(|n| n.to_string()),
+            "relative_sequence_number" => self.relative_sequence_number.map(|n| n.to_string()),
+            "hardsub" => self.hardsub.as_ref().map(|l| l.to_string()),
+            _ => return None,
+        })
     }
 
     /// Formats the given string if it has specific pattern in it. It also sanitizes the filename.
+    ///
+    /// Besides the plain `{token}` tokens (which keep padding numeric values to 2 digits, as
+    /// before), two extensions are supported:
+    ///   - `{token:width}` formats a numeric token with a fixed `width` instead of the default of
+    ///     2 (e.g. `{episode_number:03}` for three-digit episode numbers, `{season_number:1}` for
+    ///     an un-padded season number).
+    ///   - `{?token:literal}` expands to nothing - dropping `literal` along with it - when
+    ///     `token`'s underlying value is absent, instead of silently substituting a default `0`;
+    ///     any `%` inside `literal` is replaced with the token's value (e.g.
+    ///     `{?relative_episode_number:E%}` expands to `E5`, or to `""` if there's no relative
+    ///     episode number).
     pub fn format_path(&self, path: PathBuf) -> PathBuf {
-        let mut path = sanitize(path.to_string_lossy(), false);
-        path = path
-            .replace("{title}", &sanitize(&self.title, true))
-            .replace(
-                "{audio}",
-                &sanitize(
-                    self.locales
-                        .iter()
-                        .map(|(a, _)| a.to_string())
-                        .collect::<Vec<String>>()
-                        .join("|"),
-                    true,
-                ),
-            )
-            .replace("{resolution}", &sanitize(self.resolution.to_string(), true))
-            .replace("{series_id}", &sanitize(&self.series_id, true))
-            .replace("{series_name}", &sanitize(&self.series_name, true))
-            .replace("{season_id}", &sanitize(&self.season_id, true))
-            .replace("{season_name}", &sanitize(&self.season_title, true))
-            .replace(
-                "{season_number}",
-                &format!("{:0>2}", sanitize(self.season_number.to_string(), true)),
-            )
-            .replace("{episode_id}", &sanitize(&self.episode_id, true))
-            .replace(
-                "{episode_number}",
-                &format!("{:0>2}", sanitize(&self.episode_number, true)),
-            )
-            .replace(
-                "{relative_episode_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_episode_number.unwrap_or_default().to_string(),
-                        true,
-                    )
-                ),
-            )
-            .replace(
-                "{sequence_number}",
-                &format!("{:0>2}", sanitize(self.sequence_number.to_string(), true)),
-            )
-            .replace(
-                "{relative_sequence_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_sequence_number
-                            .unwrap_or_default()
-                            .to_string(),
-                        true,
-                    )
-                ),
-            );
+        let path = sanitize(path.to_string_lossy(), false);
 
-        PathBuf::from(path)
+        let replaced = TEMPLATE_TOKEN_RE.replace_all(&path, |caps: &Captures| {
+            let name = &caps[2];
+
+            if &caps[1] == "?" {
+                return match self.optional_token_value(name) {
+                    Some(Some(value)) => {
+                        let value = sanitize(value, true);
+                        match caps.get(3) {
+                            Some(literal) => literal.as_str().replace('%', &value),
+                            None => value,
+                        }
+                    }
+                    _ => String::new(),
+                };
+            }
+
+            let Some(value) = self.token_value(name) else {
+                return caps[0].to_string();
+            };
+            let width = caps
+                .get(3)
+                .and_then(|spec| spec.as_str().parse::<usize>().ok())
+                .unwrap_or_else(|| Self::token_default_width(name));
+
+            format!("{:0>width$}", sanitize(value, true), width = width)
+        });
+
+        PathBuf::from(replaced.into_owned())
     }
 
     pub fn visual_output(&self, dst: &Path) {
@@ -476,8 +977,105 @@ impl Format {
         self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
     }
 
+    /// Whether `s` references `{relative_episode_number}`/`{relative_sequence_number}` in any of
+    /// their plain, width-suffixed (`{relative_episode_number:03}`) or optional-group
+    /// (`{?relative_episode_number:E%}`) forms, so relative-numbering resolution still gets
+    /// triggered for the new template syntax.
     pub fn has_relative_fmt<S: AsRef<str>>(s: S) -> bool {
-        return s.as_ref().contains("{relative_episode_number}")
-            || s.as_ref().contains("{relative_sequence_number}");
+        TEMPLATE_TOKEN_RE.captures_iter(s.as_ref()).any(|caps| {
+            matches!(
+                &caps[2],
+                "relative_episode_number" | "relative_sequence_number"
+            )
+        })
+    }
+
+    /// Builds the `(key, value)` pairs written as global Matroska tags during the mux (see
+    /// `DownloadBuilder::metadata_tags`), so the output file is self-describing to media servers
+    /// without a separate NFO sidecar.
+    pub fn mkv_tags(&self) -> Vec<(String, String)> {
+        let mut tags = vec![
+            ("title".to_string(), self.title.clone()),
+            ("artist".to_string(), self.series_name.clone()),
+            ("part_number".to_string(), self.episode_number.clone()),
+        ];
+        if !self.description.is_empty() {
+            tags.push(("synopsis".to_string(), self.description.clone()))
+        }
+        if let Some(release_date) = &self.release_date {
+            tags.push((
+                "date_released".to_string(),
+                release_date.format("%Y-%m-%d").to_string(),
+            ))
+        }
+        tags
+    }
+
+    /// Builds the full metadata dump attached inside the output file as a JSON file when
+    /// `--embed-info-json` is set, mirroring how other downloaders (e.g. yt-dlp) attach an
+    /// info-json alongside their output.
+    pub fn info_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&FormatInfoJson {
+            id: self.episode_id.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            series_id: self.series_id.clone(),
+            series_name: self.series_name.clone(),
+            season_id: self.season_id.clone(),
+            season_title: self.season_title.clone(),
+            season_number: self.season_number,
+            episode_number: self.episode_number.clone(),
+            relative_episode_number: self.relative_episode_number,
+            sequence_number: self.sequence_number,
+            relative_sequence_number: self.relative_sequence_number,
+            duration: format_time_delta(&self.duration),
+            release_date: self
+                .release_date
+                .map(|release_date| release_date.format("%Y-%m-%d").to_string()),
+            locales: self
+                .locales
+                .iter()
+                .map(|(audio, subtitles)| {
+                    (
+                        audio.to_string(),
+                        subtitles.iter().map(|s| s.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            resolution: self.resolution.to_string(),
+            fps: self.fps,
+        })?)
+    }
+
+    /// Writes a Kodi/Jellyfin/Plex-compatible `<episodedetails>` NFO sidecar next to
+    /// `media_path` (same file stem, `.nfo` extension), populated from the metadata already
+    /// resolved onto this `Format`. This lets users drop crunchy-cli output straight into a
+    /// media server library that auto-scans for sidecar metadata.
+    pub fn write_nfo(&self, media_path: &Path) -> Result<()> {
+        let nfo_path = media_path.with_extension("nfo");
+
+        let nfo = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+             <episodedetails>\n\
+             \t<title>{}</title>\n\
+             \t<showtitle>{}</showtitle>\n\
+             \t<season>{}</season>\n\
+             \t<episode>{}</episode>\n\
+             \t<plot>{}</plot>\n\
+             \t<uniqueid type=\"crunchyroll-episode\" default=\"true\">{}</uniqueid>\n\
+             \t<uniqueid type=\"crunchyroll-series\">{}</uniqueid>\n\
+             </episodedetails>\n",
+            xml_escape(&self.title),
+            xml_escape(&self.series_name),
+            self.season_number,
+            self.episode_number,
+            xml_escape(&self.description),
+            xml_escape(&self.episode_id),
+            xml_escape(&self.series_id),
+        );
+
+        fs::write(nfo_path, nfo)?;
+
+        Ok(())
     }
 }
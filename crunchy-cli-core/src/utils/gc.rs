@@ -0,0 +1,99 @@
+use crate::utils::format::{Format, SingleFormatCollection};
+use crate::utils::os::sanitize;
+use anyhow::{bail, Result};
+use regex::{escape, Regex};
+use std::path::{Path, PathBuf};
+
+/// Builds a regex which matches any path [`Format::format_path`] could produce for `template`
+/// filled in with `single_format`'s metadata. Placeholders whose value isn't known without
+/// resolving a stream first (`{audio}`, `{resolution}`) are matched with a wildcard instead of an
+/// exact value, since computing the expected set for `gc` must not require a network request.
+fn expected_path_regex(template: &str, season_number: u32, episode_number: &str) -> Regex {
+    let mut pattern = escape(template);
+
+    pattern = pattern
+        .replace(&escape("{season_number}"), &format!("{:0>2}", season_number))
+        .replace(
+            &escape("{episode_number}"),
+            &escape(&sanitize(format!("{:0>2}", episode_number), true)),
+        );
+
+    for volatile in [
+        "{title}",
+        "{series_name}",
+        "{season_name}",
+        "{audio}",
+        "{width}",
+        "{height}",
+        "{resolution}",
+        "{relative_episode_number}",
+        "{sequence_number}",
+        "{relative_sequence_number}",
+        "{release_year}",
+        "{release_month}",
+        "{release_day}",
+        "{series_id}",
+        "{season_id}",
+        "{episode_id}",
+    ] {
+        pattern = pattern.replace(&escape(volatile), ".+?");
+    }
+
+    Regex::new(&format!("^{pattern}$")).unwrap()
+}
+
+/// Computes the full set of expected final paths `collection` would produce under `template`,
+/// walks the directory roots the template can write into and returns every file found there which
+/// isn't among the expected paths. Never descends outside [`Format::template_root_dir`], so files
+/// unrelated to this template are left untouched.
+pub fn find_orphaned_files(
+    template: &str,
+    collection: &SingleFormatCollection,
+) -> Result<Vec<PathBuf>> {
+    let Some(root) = Format::template_root_dir(template) else {
+        bail!(
+            "`--output`/`{}` has no literal directory component, so `--gc` doesn't know which \
+             directory it's allowed to scan (and, with `--gc-remove`, delete files under) - add a \
+             directory to `--output`, e.g. `some/dir/{}`",
+            template,
+            template
+        )
+    };
+    if !root.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let expected_patterns: Vec<Regex> = collection
+        .single_formats()
+        .map(|single_format| {
+            expected_path_regex(
+                template,
+                single_format.season_number,
+                &single_format.episode_number,
+            )
+        })
+        .collect();
+
+    let mut orphaned = vec![];
+    walk(&root, &expected_patterns, &mut orphaned)?;
+    Ok(orphaned)
+}
+
+fn walk(dir: &Path, expected_patterns: &[Regex], orphaned: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, expected_patterns, orphaned)?;
+            continue;
+        }
+
+        let path_str = path.to_string_lossy();
+        let is_expected = expected_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&path_str));
+        if !is_expected {
+            orphaned.push(path);
+        }
+    }
+    Ok(())
+}
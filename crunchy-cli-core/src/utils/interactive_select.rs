@@ -1,5 +1,7 @@
+use crate::utils::fingerprint::{match_fingerprints, AudioFingerprint, DUPLICATE_MATCH_THRESHOLD};
+use crate::utils::locale::locale_from_season_slug;
 use crate::utils::log::progress_pause;
-use crunchyroll_rs::Season;
+use crunchyroll_rs::{Locale, Season};
 use dialoguer::console::Term;
 use dialoguer::MultiSelect;
 use std::collections::BTreeMap;
@@ -53,6 +55,113 @@ pub fn check_for_duplicated_seasons(seasons: &mut Vec<Season>) {
     seasons.retain(|s| !remove_ids.contains(&s.id));
 }
 
+/// Non-interactive counterpart to [`check_for_duplicated_seasons`]: for every season number with
+/// more than one re-release (typically one per dub, but sometimes alternate cuts like an uncut vs.
+/// broadcast version), keeps only the release `version` points at, if given (see
+/// [`VersionSelector`]); otherwise the release whose `audio_locales` contains the first of
+/// `preferred_locales` any release has, falling back to whichever release was listed first if none
+/// of them do. With `experimental_fixes` set, a release whose slug title ([`locale_from_season_slug`])
+/// matches a preferred locale is also accepted, for titles where `audio_locales` itself is missing
+/// or wrong.
+///
+/// With `--verify-duplicates`, `fingerprints` carries one [`AudioFingerprint`] per season id
+/// (computed upstream from each candidate's lowest-bitrate audio, since fetching that media is
+/// outside this function's reach) and every other candidate in the group is checked against the
+/// one `keep_index` picked above via [`match_fingerprints`]: below [`DUPLICATE_MATCH_THRESHOLD`],
+/// a candidate is treated as distinct content that happens to share a season number, and is kept
+/// alongside it rather than dropped. A candidate missing a fingerprint (or every other candidate,
+/// if the picked one has none) is left unverified and dropped, as without `--verify-duplicates`.
+pub fn resolve_duplicated_seasons(
+    seasons: &mut Vec<Season>,
+    preferred_locales: &[Locale],
+    version: Option<&VersionSelector>,
+    experimental_fixes: bool,
+    fingerprints: Option<&BTreeMap<String, AudioFingerprint>>,
+) {
+    let mut as_map: BTreeMap<u32, Vec<Season>> = BTreeMap::new();
+    for season in seasons.drain(..) {
+        as_map.entry(season.season_number).or_default().push(season)
+    }
+
+    let mut resolved = vec![];
+    for (_, mut group) in as_map {
+        if group.len() <= 1 {
+            resolved.append(&mut group);
+            continue;
+        }
+
+        let keep_index = version
+            .and_then(|v| v.resolve(group.iter().map(|s| s.title.as_str())))
+            .or_else(|| {
+                preferred_locales.iter().find_map(|locale| {
+                    group.iter().position(|s| {
+                        s.audio_locales.contains(locale)
+                            || (experimental_fixes
+                                && locale_from_season_slug(&s.slug_title).as_ref() == Some(locale))
+                    })
+                })
+            })
+            .unwrap_or(0);
+
+        let keep_fingerprint = fingerprints.and_then(|fp| fp.get(&group[keep_index].id)).cloned();
+        let mut distinct = vec![];
+        let mut kept = None;
+        for (i, season) in group.into_iter().enumerate() {
+            if i != keep_index {
+                if let (Some(keep_fp), Some(fp)) =
+                    (&keep_fingerprint, fingerprints.and_then(|fp| fp.get(&season.id)))
+                {
+                    if match_fingerprints(keep_fp, fp) < DUPLICATE_MATCH_THRESHOLD {
+                        distinct.push(season);
+                        continue;
+                    }
+                }
+            } else {
+                kept = Some(season);
+            }
+        }
+
+        if let Some(kept) = kept {
+            resolved.push(kept);
+        }
+        resolved.extend(distinct);
+    }
+
+    *seasons = resolved;
+}
+
+/// A `--version` selector for choosing among re-releases which would otherwise only be
+/// disambiguated by `audio_locales` (e.g. 'uncut'/'simulcast'/'tv', or a 1-based position in
+/// listing order). Crunchyroll exposes no dedicated "cut" field, so a keyword is matched against
+/// each candidate's title; if nothing matches, the caller falls back to its own default.
+#[derive(Clone, Debug)]
+pub enum VersionSelector {
+    Index(usize),
+    Keyword(String),
+}
+
+impl VersionSelector {
+    pub fn parse(s: &str) -> Result<VersionSelector, String> {
+        if let Ok(index) = s.parse::<usize>() {
+            if index == 0 {
+                return Err("Version index must start at 1".to_string());
+            }
+            return Ok(VersionSelector::Index(index - 1));
+        }
+        Ok(VersionSelector::Keyword(s.to_lowercase()))
+    }
+
+    /// Returns the index of the matching candidate, if any.
+    fn resolve<'a>(&self, mut titles: impl Iterator<Item = &'a str>) -> Option<usize> {
+        match self {
+            VersionSelector::Index(index) => titles.nth(*index).map(|_| *index),
+            VersionSelector::Keyword(keyword) => {
+                titles.position(|title| title.to_lowercase().contains(keyword.as_str()))
+            }
+        }
+    }
+}
+
 pub fn select(prompt: &str, input: Vec<String>) -> Vec<usize> {
     if input.is_empty() {
         return vec![];
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+/// One top-level ISO/IEC 14496-12 box: `[start, end)` spans the full box including its 8 (or 16,
+/// for a 64 bit `largesize`) byte header, so `data[start..end]` is the box verbatim.
+struct BoxSpan {
+    kind: [u8; 4],
+    start: usize,
+    end: usize,
+}
+
+/// Walks `data` as a flat sequence of top-level ISO-BMFF boxes (`size` + 4 byte type, optionally
+/// followed by a 64 bit `largesize` when `size == 1`, or running to the end of `data` when
+/// `size == 0`). Used instead of a full parser since only the top-level box order/bounds matter
+/// here, not the tree underneath any of them.
+fn parse_top_level_boxes(data: &[u8]) -> Result<Vec<BoxSpan>> {
+    let mut boxes = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        if data.len() - offset < 8 {
+            bail!("Truncated box header at offset {}", offset);
+        }
+        let size32 = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let kind: [u8; 4] = data[offset + 4..offset + 8].try_into().unwrap();
+
+        let (header_len, box_len) = if size32 == 1 {
+            if data.len() - offset < 16 {
+                bail!("Truncated largesize box header at offset {}", offset);
+            }
+            let size64 = u64::from_be_bytes(data[offset + 8..offset + 16].try_into().unwrap());
+            (16usize, size64 as usize)
+        } else if size32 == 0 {
+            (8usize, data.len() - offset)
+        } else {
+            (8usize, size32 as usize)
+        };
+
+        if box_len < header_len || offset + box_len > data.len() {
+            bail!("Box '{}' at offset {} has an invalid size", String::from_utf8_lossy(&kind), offset);
+        }
+
+        boxes.push(BoxSpan {
+            kind,
+            start: offset,
+            end: offset + box_len,
+        });
+        offset += box_len;
+    }
+    Ok(boxes)
+}
+
+/// Outcome of attempting a native fast-start reorder (see [`fast_start_reorder`]).
+pub enum FastStartResult {
+    /// `moov` already precedes the first `mdat`/`moof`; the input can be used as-is.
+    AlreadyFastStart,
+    /// `moov` was relocated to right after `ftyp`; this is the resulting byte stream.
+    Reordered(Vec<u8>),
+    /// Either `data` isn't a box-parsable ISO-BMFF stream, or its `moov` can't be safely
+    /// relocated without also patching sample offsets (see [`moov_has_absolute_offsets`]). The
+    /// caller should fall back to ffmpeg in this case.
+    Unsupported,
+}
+
+/// Reorders `data` (an already-valid ISO-BMFF byte stream, e.g. concatenated fragmented-MP4
+/// segments) so its `moov` box comes right after `ftyp`, per the fast-start ordering in ISO/IEC
+/// 14496-12 §6.2.3, without touching any other box's bytes or relative order.
+///
+/// This only moves bytes around - it never rewrites sample tables - so it's only correct when
+/// `moov`'s sample tables (if any) don't carry absolute file offsets that the move would
+/// invalidate. That holds for genuinely fragmented MP4 (where `moov` has no `stbl`/`stco` and
+/// each `moof`'s `trun` gives sample offsets relative to itself), which is what this CLI's
+/// segmented downloads are already expected to be. For anything else, [`FastStartResult::Unsupported`]
+/// is returned so the caller can fall back to ffmpeg instead of emitting a subtly broken file.
+pub fn fast_start_reorder(data: &[u8]) -> Result<FastStartResult> {
+    let Ok(boxes) = parse_top_level_boxes(data) else {
+        return Ok(FastStartResult::Unsupported);
+    };
+
+    let Some(moov_idx) = boxes.iter().position(|b| &b.kind == b"moov") else {
+        return Ok(FastStartResult::Unsupported);
+    };
+    let first_media_idx = boxes
+        .iter()
+        .position(|b| &b.kind == b"mdat" || &b.kind == b"moof");
+    if first_media_idx.map_or(true, |i| moov_idx < i) {
+        return Ok(FastStartResult::AlreadyFastStart);
+    }
+
+    let moov = &data[boxes[moov_idx].start..boxes[moov_idx].end];
+    if moov_has_absolute_offsets(moov) {
+        return Ok(FastStartResult::Unsupported);
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    for (i, b) in boxes.iter().enumerate() {
+        if i == moov_idx {
+            continue;
+        }
+        out.extend_from_slice(&data[b.start..b.end]);
+        if &b.kind == b"ftyp" {
+            out.extend_from_slice(moov);
+        }
+    }
+    Ok(FastStartResult::Reordered(out))
+}
+
+/// Conservatively checks whether `moov` contains a classic (non-fragmented) sample table with
+/// absolute file offsets (`stco`/`co64`), which [`fast_start_reorder`] would invalidate by moving
+/// `moov` earlier in the file. Rather than walking the `trak`/`mdia`/`minf`/`stbl` box tree to
+/// find these precisely, this just scans `moov`'s raw bytes for the fourcc - a real `stco`/`co64`
+/// box is vanishingly unlikely to also appear as a false positive inside sample description data,
+/// and a false positive only costs a safe fallback to ffmpeg, never a corrupted file.
+fn moov_has_absolute_offsets(moov: &[u8]) -> bool {
+    moov.windows(4).any(|w| w == b"stco" || w == b"co64")
+}
+
+/// Outcome of attempting to natively encode per-track sync offsets as MP4 edit lists (see
+/// [`rewrite_audio_edit_lists`]).
+pub enum EditListResult {
+    /// The edit lists were written in place; this is the resulting byte stream.
+    Rewritten(Vec<u8>),
+    /// Either `data` isn't a box-parsable ISO-BMFF stream, `moov` doesn't precede the first
+    /// `mdat`/`moof` (see [`fast_start_reorder`]'s equivalent caveat), or none of `offsets_ms`'
+    /// keys matched an actual audio track. The caller should fall back to ffmpeg's own
+    /// `-itsoffset`/`-use_editlist` instead.
+    Unsupported,
+}
+
+/// A box's header is 8 bytes, or 16 when its 32 bit size field is `1` (`largesize` extension) -
+/// the same rule [`parse_top_level_boxes`] applies per-box, exposed here so callers can skip past
+/// a container's own header to reach its children.
+fn box_header_len(data: &[u8]) -> usize {
+    if data.len() >= 4 && u32::from_be_bytes(data[0..4].try_into().unwrap()) == 1 {
+        16
+    } else {
+        8
+    }
+}
+
+/// Parses `container`'s immediate children (e.g. `moov`'s `mvhd`/`trak`*, or a `trak`'s
+/// `tkhd`/`edts`?/`mdia`). `container` must be the full box span including its own header.
+fn parse_children(container: &[u8]) -> Result<Vec<BoxSpan>> {
+    parse_top_level_boxes(&container[box_header_len(container)..])
+}
+
+/// Reads the `(timescale, duration)` field pair `mvhd` and `mdhd` both lay out identically (ISO/IEC
+/// 14496-12 §8.2.2/§8.4.2 share the same full-box header plus creation/modification/timescale/
+/// duration prefix), honoring the box's version for 32 vs 64 bit time fields.
+fn read_timescale_duration(data: &[u8]) -> Result<(u32, u64)> {
+    let version = *data.get(8).ok_or_else(|| anyhow!("truncated full box"))?;
+    if version == 1 {
+        let timescale = data
+            .get(28..32)
+            .ok_or_else(|| anyhow!("truncated mvhd/mdhd (version 1)"))?;
+        let duration = data
+            .get(32..40)
+            .ok_or_else(|| anyhow!("truncated mvhd/mdhd (version 1)"))?;
+        Ok((
+            u32::from_be_bytes(timescale.try_into().unwrap()),
+            u64::from_be_bytes(duration.try_into().unwrap()),
+        ))
+    } else {
+        let timescale = data
+            .get(20..24)
+            .ok_or_else(|| anyhow!("truncated mvhd/mdhd (version 0)"))?;
+        let duration = data
+            .get(24..28)
+            .ok_or_else(|| anyhow!("truncated mvhd/mdhd (version 0)"))?;
+        Ok((
+            u32::from_be_bytes(timescale.try_into().unwrap()),
+            u32::from_be_bytes(duration.try_into().unwrap()) as u64,
+        ))
+    }
+}
+
+/// Whether `trak`'s `mdia.hdlr` declares it a `soun` (audio) track. `hdlr`'s body is
+/// version/flags(4) + pre_defined(4) + handler_type(4) + ..., so the fourcc sits right after the
+/// box's own 8 byte header plus those two leading fields.
+fn trak_is_audio(trak: &[u8]) -> Result<bool> {
+    let children = parse_children(trak)?;
+    let header_len = box_header_len(trak);
+    let Some(mdia_span) = children.iter().find(|b| &b.kind == b"mdia") else {
+        return Ok(false);
+    };
+    let mdia = &trak[header_len + mdia_span.start..header_len + mdia_span.end];
+
+    let mdia_children = parse_children(mdia)?;
+    let mdia_header_len = box_header_len(mdia);
+    let Some(hdlr_span) = mdia_children.iter().find(|b| &b.kind == b"hdlr") else {
+        return Ok(false);
+    };
+    let hdlr = &mdia[mdia_header_len + hdlr_span.start..mdia_header_len + hdlr_span.end];
+
+    Ok(hdlr.get(16..20) == Some(b"soun".as_slice()))
+}
+
+/// Builds the `(segment_duration, media_time)` entries for `offset_ms`'s `elst`, per the scheme
+/// laid out in [`rewrite_audio_edit_lists`]'s doc comment. `segment_duration` is always expressed
+/// in the movie timescale, `media_time` in the track's own media timescale.
+fn edit_list_entries(
+    offset_ms: i64,
+    movie_timescale: u32,
+    media_timescale: u32,
+    media_duration: u64,
+) -> Vec<(u32, i32)> {
+    let media_duration_movie_ts =
+        (media_duration as u128 * movie_timescale as u128 / media_timescale.max(1) as u128) as u64;
+
+    if offset_ms >= 0 {
+        let delay = (offset_ms as u128 * movie_timescale as u128 / 1000) as u64;
+        vec![
+            (delay as u32, -1),
+            (media_duration_movie_ts.saturating_sub(delay) as u32, 0),
+        ]
+    } else {
+        let skip_media = (-offset_ms as u128 * media_timescale as u128 / 1000) as u64;
+        let skip_movie = (-offset_ms as u128 * movie_timescale as u128 / 1000) as u64;
+        vec![(
+            media_duration_movie_ts.saturating_sub(skip_movie) as u32,
+            skip_media as i32,
+        )]
+    }
+}
+
+/// Serializes `entries` as a version 0 `elst` box (ISO/IEC 14496-12 §8.6.6), each entry's
+/// `media_rate` fixed at `1.0` (`0x00010000`), wrapped in its parent `edts` box.
+fn build_edit_list_box(entries: &[(u32, i32)]) -> Vec<u8> {
+    let mut elst_body = Vec::with_capacity(8 + entries.len() * 12);
+    elst_body.extend_from_slice(&0u32.to_be_bytes()); // version/flags
+    elst_body.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for (segment_duration, media_time) in entries {
+        elst_body.extend_from_slice(&segment_duration.to_be_bytes());
+        elst_body.extend_from_slice(&media_time.to_be_bytes());
+        elst_body.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    }
+
+    let mut elst = Vec::with_capacity(8 + elst_body.len());
+    elst.extend_from_slice(&((8 + elst_body.len()) as u32).to_be_bytes());
+    elst.extend_from_slice(b"elst");
+    elst.extend_from_slice(&elst_body);
+
+    let mut edts = Vec::with_capacity(8 + elst.len());
+    edts.extend_from_slice(&((8 + elst.len()) as u32).to_be_bytes());
+    edts.extend_from_slice(b"edts");
+    edts.extend_from_slice(&elst);
+    edts
+}
+
+/// Rebuilds `trak` with a fresh `edts`/`elst` inserted right after `tkhd` (the canonical
+/// `tkhd`, `edts`, `mdia` order), dropping any edit list it already carried.
+fn rewrite_trak_with_edit_list(trak: &[u8], movie_timescale: u32, offset_ms: i64) -> Result<Vec<u8>> {
+    let children = parse_children(trak)?;
+    let header_len = box_header_len(trak);
+
+    let mdia_span = children
+        .iter()
+        .find(|b| &b.kind == b"mdia")
+        .ok_or_else(|| anyhow!("trak has no mdia box"))?;
+    let mdia = &trak[header_len + mdia_span.start..header_len + mdia_span.end];
+    let mdia_children = parse_children(mdia)?;
+    let mdia_header_len = box_header_len(mdia);
+    let mdhd_span = mdia_children
+        .iter()
+        .find(|b| &b.kind == b"mdhd")
+        .ok_or_else(|| anyhow!("mdia has no mdhd box"))?;
+    let mdhd = &mdia[mdia_header_len + mdhd_span.start..mdia_header_len + mdhd_span.end];
+    let (media_timescale, media_duration) = read_timescale_duration(mdhd)?;
+
+    let entries = edit_list_entries(offset_ms, movie_timescale, media_timescale, media_duration);
+    let new_edts = build_edit_list_box(&entries);
+
+    let mut new_trak_body = Vec::with_capacity(trak.len() + new_edts.len());
+    for child in &children {
+        if &child.kind == b"edts" {
+            continue;
+        }
+        new_trak_body.extend_from_slice(&trak[header_len + child.start..header_len + child.end]);
+        if &child.kind == b"tkhd" {
+            new_trak_body.extend_from_slice(&new_edts);
+        }
+    }
+
+    let mut new_trak = Vec::with_capacity(8 + new_trak_body.len());
+    new_trak.extend_from_slice(&((8 + new_trak_body.len()) as u32).to_be_bytes());
+    new_trak.extend_from_slice(b"trak");
+    new_trak.extend_from_slice(&new_trak_body);
+    Ok(new_trak)
+}
+
+/// Locates every `stco`/`co64` box actually reachable via `moov/trak*/mdia/minf/stbl` (the only
+/// place ISO/IEC 14496-12 allows them), returning each as `(is_stco, start, end)` offsets into
+/// `moov` (the full box span, header included). Unlike [`moov_has_absolute_offsets`] - a read-only
+/// probe where a false fourcc match only costs a safe fallback to ffmpeg - [`shift_chunk_offsets`]
+/// mutates in place, so it walks the real box tree instead of scanning for the fourcc: a metadata
+/// string or codec config blob that happens to contain `stco`/`co64` bytes must never be mistaken
+/// for a real one here.
+fn find_chunk_offset_boxes(moov: &[u8]) -> Result<Vec<(bool, usize, usize)>> {
+    let mut found = vec![];
+    let moov_header_len = box_header_len(moov);
+
+    for trak in parse_children(moov)?
+        .into_iter()
+        .filter(|b| &b.kind == b"trak")
+    {
+        let trak_abs = moov_header_len + trak.start;
+        let trak_bytes = &moov[trak_abs..moov_header_len + trak.end];
+        let trak_header_len = box_header_len(trak_bytes);
+
+        let Some(mdia) = parse_children(trak_bytes)?
+            .into_iter()
+            .find(|b| &b.kind == b"mdia")
+        else {
+            continue;
+        };
+        let mdia_abs = trak_abs + trak_header_len + mdia.start;
+        let mdia_bytes = &moov[mdia_abs..trak_abs + trak_header_len + mdia.end];
+        let mdia_header_len = box_header_len(mdia_bytes);
+
+        let Some(minf) = parse_children(mdia_bytes)?
+            .into_iter()
+            .find(|b| &b.kind == b"minf")
+        else {
+            continue;
+        };
+        let minf_abs = mdia_abs + mdia_header_len + minf.start;
+        let minf_bytes = &moov[minf_abs..mdia_abs + mdia_header_len + minf.end];
+        let minf_header_len = box_header_len(minf_bytes);
+
+        let Some(stbl) = parse_children(minf_bytes)?
+            .into_iter()
+            .find(|b| &b.kind == b"stbl")
+        else {
+            continue;
+        };
+        let stbl_abs = minf_abs + minf_header_len + stbl.start;
+        let stbl_bytes = &moov[stbl_abs..minf_abs + minf_header_len + stbl.end];
+        let stbl_header_len = box_header_len(stbl_bytes);
+
+        for child in parse_children(stbl_bytes)? {
+            if &child.kind == b"stco" || &child.kind == b"co64" {
+                found.push((
+                    &child.kind == b"stco",
+                    stbl_abs + stbl_header_len + child.start,
+                    stbl_abs + stbl_header_len + child.end,
+                ));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Adds `delta` (may be negative) to every sample offset recorded in `moov`'s `stco`/`co64` boxes,
+/// keeping them valid after `moov`'s own size changes shift `mdat` by `delta` bytes. Boxes are
+/// reached via [`find_chunk_offset_boxes`]'s real box-tree walk rather than a raw fourcc scan, and
+/// each entry table is bounds-checked against its own box span before anything is mutated.
+fn shift_chunk_offsets(moov: &mut [u8], delta: i64) -> Result<()> {
+    for (is_stco, box_start, box_end) in find_chunk_offset_boxes(moov)? {
+        let entry_count_bytes = moov
+            .get(box_start + 12..box_start + 16)
+            .ok_or_else(|| anyhow!("truncated stco/co64 box"))?;
+        let entry_count = u32::from_be_bytes(entry_count_bytes.try_into().unwrap()) as usize;
+        let entry_size = if is_stco { 4 } else { 8 };
+        // 8 byte box header, 4 byte version/flags, 4 byte entry_count
+        let entries_start = box_start + 16;
+        let entries_end = entries_start + entry_count * entry_size;
+        if entries_end > box_end {
+            bail!("stco/co64 entry table runs past its own box");
+        }
+
+        for e in 0..entry_count {
+            let off = entries_start + e * entry_size;
+            if is_stco {
+                let value = u32::from_be_bytes(moov[off..off + 4].try_into().unwrap());
+                let shifted = (value as i64 + delta).max(0) as u32;
+                moov[off..off + 4].copy_from_slice(&shifted.to_be_bytes());
+            } else {
+                let value = u64::from_be_bytes(moov[off..off + 8].try_into().unwrap());
+                let shifted = (value as i64 + delta).max(0) as u64;
+                moov[off..off + 8].copy_from_slice(&shifted.to_be_bytes());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes [`crate::utils::sync::sync_audios`]' per-track offsets (`offsets_ms`, keyed the same way
+/// as its `HashMap<usize, TimeDelta>` result, here in milliseconds) directly into `data`'s `moov`
+/// as MP4 edit lists, instead of re-muxing through ffmpeg's own `-itsoffset`/`-use_editlist`.
+///
+/// For a positive delay (the track should start later), this emits an empty edit first -
+/// `media_time = -1`, `segment_duration` covering the delay, `media_rate = 1.0` - followed by a
+/// normal edit with `media_time = 0` covering the remaining track duration. For a negative offset
+/// (skip leading audio), it emits a single edit with `media_time` set to the skipped amount in the
+/// track's own media timescale. Both `segment_duration`s are expressed in the movie timescale, per
+/// ISO/IEC 14496-12 §8.6.6.
+///
+/// Growing a track's `moov` entry shifts every byte after it, which would invalidate any
+/// `stco`/`co64` absolute sample offsets recorded before the shift - this only rewrites in place
+/// when `moov` already precedes the first `mdat`/`moof` (true for this CLI's own ffmpeg-muxed
+/// output) and corrects those offsets for the shift itself, the same way relocating `moov` in
+/// [`fast_start_reorder`] would need to, had the offsets there not already been ruled out.
+pub fn rewrite_audio_edit_lists(
+    data: &[u8],
+    offsets_ms: &HashMap<usize, i64>,
+) -> Result<EditListResult> {
+    if offsets_ms.values().all(|&o| o == 0) {
+        return Ok(EditListResult::Unsupported);
+    }
+
+    let Ok(boxes) = parse_top_level_boxes(data) else {
+        return Ok(EditListResult::Unsupported);
+    };
+    let Some(moov_idx) = boxes.iter().position(|b| &b.kind == b"moov") else {
+        return Ok(EditListResult::Unsupported);
+    };
+    let first_media_idx = boxes
+        .iter()
+        .position(|b| &b.kind == b"mdat" || &b.kind == b"moof");
+    if first_media_idx.is_some_and(|i| moov_idx > i) {
+        return Ok(EditListResult::Unsupported);
+    }
+
+    let moov = &data[boxes[moov_idx].start..boxes[moov_idx].end];
+    let moov_header_len = box_header_len(moov);
+    let moov_children = parse_children(moov)?;
+    let Some(mvhd_span) = moov_children.iter().find(|b| &b.kind == b"mvhd") else {
+        return Ok(EditListResult::Unsupported);
+    };
+    let mvhd = &moov[moov_header_len + mvhd_span.start..moov_header_len + mvhd_span.end];
+    let (movie_timescale, _) = read_timescale_duration(mvhd)?;
+
+    let mut audio_track_index = 0usize;
+    let mut touched_any = false;
+    let mut new_moov_body = Vec::with_capacity(moov.len());
+    for child in &moov_children {
+        let bytes = &moov[moov_header_len + child.start..moov_header_len + child.end];
+        if &child.kind != b"trak" || !trak_is_audio(bytes).unwrap_or(false) {
+            new_moov_body.extend_from_slice(bytes);
+            continue;
+        }
+
+        let offset_ms = offsets_ms.get(&audio_track_index).copied().unwrap_or(0);
+        audio_track_index += 1;
+        if offset_ms == 0 {
+            new_moov_body.extend_from_slice(bytes);
+            continue;
+        }
+
+        new_moov_body.extend_from_slice(&rewrite_trak_with_edit_list(
+            bytes,
+            movie_timescale,
+            offset_ms,
+        )?);
+        touched_any = true;
+    }
+
+    if !touched_any {
+        return Ok(EditListResult::Unsupported);
+    }
+
+    let mut new_moov = Vec::with_capacity(8 + new_moov_body.len());
+    new_moov.extend_from_slice(&((8 + new_moov_body.len()) as u32).to_be_bytes());
+    new_moov.extend_from_slice(b"moov");
+    new_moov.extend_from_slice(&new_moov_body);
+
+    let growth = new_moov.len() as i64 - moov.len() as i64;
+    shift_chunk_offsets(&mut new_moov, growth)?;
+
+    let mut out = Vec::with_capacity((data.len() as i64 + growth).max(0) as usize);
+    for (i, b) in boxes.iter().enumerate() {
+        if i == moov_idx {
+            out.extend_from_slice(&new_moov);
+        } else {
+            out.extend_from_slice(&data[b.start..b.end]);
+        }
+    }
+    Ok(EditListResult::Rewritten(out))
+}
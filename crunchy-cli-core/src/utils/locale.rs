@@ -6,6 +6,9 @@ use log::warn;
 pub enum LanguageTagging {
     Default,
     IETF,
+    /// Like `IETF`, but tags carry a region/script subtag per locale (`pt-BR`, `zh-Hant`, ...)
+    /// instead of collapsing every regional variant of a language to the same bare subtag.
+    BCP47,
 }
 
 impl LanguageTagging {
@@ -13,6 +16,7 @@ impl LanguageTagging {
         Ok(match s.to_lowercase().as_str() {
             "default" => Self::Default,
             "ietf" => Self::IETF,
+            "bcp47" => Self::BCP47,
             _ => return Err(format!("'{}' is not a valid language tagging", s)),
         })
     }
@@ -24,20 +28,22 @@ impl LanguageTagging {
         match &self {
             LanguageTagging::Default => {
                 for locale in locales {
-                    let Some((_, available)) =
-                        ietf_language_codes.iter().find(|(_, l)| l.contains(locale))
+                    let Some((_, available)) = ietf_language_codes
+                        .iter()
+                        .find(|(_, l)| l.iter().any(|(loc, _)| loc == locale))
                     else {
                         // if no matching IETF language code was found, just pass it as it is
                         converted.push(locale.to_string());
                         continue;
                     };
-                    converted.push(available.first().unwrap().to_string())
+                    converted.push(available.first().unwrap().0.to_string())
                 }
             }
             LanguageTagging::IETF => {
                 for locale in locales {
-                    let Some((tag, _)) =
-                        ietf_language_codes.iter().find(|(_, l)| l.contains(locale))
+                    let Some((tag, _)) = ietf_language_codes
+                        .iter()
+                        .find(|(_, l)| l.iter().any(|(loc, _)| loc == locale))
                     else {
                         // if no matching IETF language code was found, just pass it as it is
                         converted.push(locale.to_string());
@@ -46,6 +52,20 @@ impl LanguageTagging {
                     converted.push(tag.to_string())
                 }
             }
+            LanguageTagging::BCP47 => {
+                for locale in locales {
+                    let Some((_, bcp47_tag)) = ietf_language_codes
+                        .iter()
+                        .flat_map(|(_, l)| l)
+                        .find(|(loc, _)| loc == locale)
+                    else {
+                        // if no matching IETF language code was found, just pass it as it is
+                        converted.push(locale.to_string());
+                        continue;
+                    };
+                    converted.push(bcp47_tag.to_string())
+                }
+            }
         }
 
         converted
@@ -55,12 +75,17 @@ impl LanguageTagging {
         match &self {
             LanguageTagging::Default => ietf_language_codes()
                 .iter()
-                .find(|(_, l)| l.contains(locale))
-                .map_or(locale.to_string(), |(_, l)| l[0].to_string()),
+                .find(|(_, l)| l.iter().any(|(loc, _)| loc == locale))
+                .map_or(locale.to_string(), |(_, l)| l[0].0.to_string()),
             LanguageTagging::IETF => ietf_language_codes()
                 .iter()
-                .find(|(_, l)| l.contains(locale))
+                .find(|(_, l)| l.iter().any(|(loc, _)| loc == locale))
                 .map_or(locale.to_string(), |(tag, _)| tag.to_string()),
+            LanguageTagging::BCP47 => ietf_language_codes()
+                .iter()
+                .flat_map(|(_, l)| l)
+                .find(|(loc, _)| loc == locale)
+                .map_or(locale.to_string(), |(_, bcp47_tag)| bcp47_tag.to_string()),
         }
     }
 }
@@ -73,17 +98,26 @@ pub fn resolve_locales(locales: &[Locale]) -> Vec<Locale> {
     for locale in locales {
         if all_locales.contains(locale) {
             resolved.push(locale.clone())
+        } else if let Some((resolved_locale, _)) = ietf_language_codes
+            .iter()
+            .flat_map(|(_, l)| l)
+            .find(|(_, bcp47_tag)| bcp47_tag.eq_ignore_ascii_case(&locale.to_string()))
+        {
+            // an exact, region/script-qualified BCP-47 tag (e.g. 'pt-BR') resolves to the first
+            // locale carrying it, without the alternatives warning the bare language subtag below
+            // gets; 'es-419' and 'zh-Hant' are each shared by two locales, of which the first wins
+            resolved.push(resolved_locale.clone())
         } else if let Some((_, resolved_locales)) = ietf_language_codes
             .iter()
             .find(|(tag, _)| tag == &locale.to_string().as_str())
         {
             let (first, alternatives) = resolved_locales.split_first().unwrap();
 
-            resolved.push(first.clone());
+            resolved.push(first.0.clone());
             // ignoring `Locale::en_IN` because I think the majority of users which want english
             // audio / subs want the "actual" english version and not the hindi accent dub
-            if !alternatives.is_empty() && resolved_locales.first().unwrap() != &Locale::en_IN {
-                warn!("Resolving locale '{}' to '{}', but there are some alternatives: {}. If you an alternative instead, please write it completely out instead of '{}'", locale, first, alternatives.iter().map(|l| format!("'{l}'")).collect::<Vec<String>>().join(", "), locale)
+            if !alternatives.is_empty() && resolved_locales.first().unwrap().0 != Locale::en_IN {
+                warn!("Resolving locale '{}' to '{}', but there are some alternatives: {}. If you an alternative instead, please write it completely out instead of '{}'", locale, first.0, alternatives.iter().map(|(l, _)| format!("'{l}'")).collect::<Vec<String>>().join(", "), locale)
             }
         } else {
             resolved.push(locale.clone());
@@ -94,29 +128,51 @@ pub fn resolve_locales(locales: &[Locale]) -> Vec<Locale> {
     resolved
 }
 
-fn ietf_language_codes<'a>() -> Vec<(&'a str, Vec<Locale>)> {
+/// Each IETF primary language subtag mapped to the locales that fall under it, paired with the
+/// region/script-qualified BCP-47 tag for that specific locale (e.g. under `"pt"`,
+/// `Locale::pt_BR` carries the tag `"pt-BR"`). The bare subtag is used by
+/// [`LanguageTagging::IETF`]/[`LanguageTagging::Default`] and by [`resolve_locales`]'s
+/// fall-back (ambiguous) reverse lookup; the per-locale tag is used by
+/// [`LanguageTagging::BCP47`] and by [`resolve_locales`]'s exact-tag reverse lookup.
+fn ietf_language_codes<'a>() -> Vec<(&'a str, Vec<(Locale, &'a str)>)> {
     vec![
-        ("ar", vec![Locale::ar_ME, Locale::ar_SA]),
-        ("ca", vec![Locale::ca_ES]),
-        ("de", vec![Locale::de_DE]),
-        ("en", vec![Locale::en_US, Locale::hi_IN]),
-        ("es", vec![Locale::es_ES, Locale::es_419, Locale::es_LA]),
-        ("fr", vec![Locale::fr_FR]),
-        ("hi", vec![Locale::hi_IN]),
-        ("id", vec![Locale::id_ID]),
-        ("it", vec![Locale::it_IT]),
-        ("ja", vec![Locale::ja_JP]),
-        ("ko", vec![Locale::ko_KR]),
-        ("ms", vec![Locale::ms_MY]),
-        ("pl", vec![Locale::pl_PL]),
-        ("pt", vec![Locale::pt_PT, Locale::pt_BR]),
-        ("ru", vec![Locale::ru_RU]),
-        ("ta", vec![Locale::ta_IN]),
-        ("te", vec![Locale::te_IN]),
-        ("th", vec![Locale::th_TH]),
-        ("tr", vec![Locale::tr_TR]),
-        ("vi", vec![Locale::vi_VN]),
-        ("zh", vec![Locale::zh_CN, Locale::zh_HK, Locale::zh_TW]),
+        // `ar_ME` is a generic "Middle East" dub rather than a specific country, so it's tagged
+        // with the UN M.49 "world" region subtag instead of guessing a single country
+        ("ar", vec![(Locale::ar_ME, "ar-001"), (Locale::ar_SA, "ar-SA")]),
+        ("ca", vec![(Locale::ca_ES, "ca-ES")]),
+        ("de", vec![(Locale::de_DE, "de-DE")]),
+        ("en", vec![(Locale::en_US, "en-US"), (Locale::hi_IN, "hi-IN")]),
+        (
+            "es",
+            vec![
+                (Locale::es_ES, "es-ES"),
+                (Locale::es_419, "es-419"),
+                (Locale::es_LA, "es-419"),
+            ],
+        ),
+        ("fr", vec![(Locale::fr_FR, "fr-FR")]),
+        ("hi", vec![(Locale::hi_IN, "hi-IN")]),
+        ("id", vec![(Locale::id_ID, "id-ID")]),
+        ("it", vec![(Locale::it_IT, "it-IT")]),
+        ("ja", vec![(Locale::ja_JP, "ja-JP")]),
+        ("ko", vec![(Locale::ko_KR, "ko-KR")]),
+        ("ms", vec![(Locale::ms_MY, "ms-MY")]),
+        ("pl", vec![(Locale::pl_PL, "pl-PL")]),
+        ("pt", vec![(Locale::pt_PT, "pt-PT"), (Locale::pt_BR, "pt-BR")]),
+        ("ru", vec![(Locale::ru_RU, "ru-RU")]),
+        ("ta", vec![(Locale::ta_IN, "ta-IN")]),
+        ("te", vec![(Locale::te_IN, "te-IN")]),
+        ("th", vec![(Locale::th_TH, "th-TH")]),
+        ("tr", vec![(Locale::tr_TR, "tr-TR")]),
+        ("vi", vec![(Locale::vi_VN, "vi-VN")]),
+        (
+            "zh",
+            vec![
+                (Locale::zh_CN, "zh-Hans"),
+                (Locale::zh_HK, "zh-Hant"),
+                (Locale::zh_TW, "zh-Hant"),
+            ],
+        ),
     ]
 }
 
@@ -134,6 +190,77 @@ pub fn system_locale() -> Locale {
     }
 }
 
+/// Check if [`Locale::Custom("original")`] is in the provided locale list. Used to request the
+/// original-language audio version of a title instead of a fixed locale, which is resolved
+/// separately against the title's version metadata since it varies per title.
+pub fn has_original_locale(locales: &[Locale]) -> bool {
+    locales
+        .iter()
+        .any(|l| l.to_string().eq_ignore_ascii_case("original"))
+}
+
+/// Check if [`Locale::Custom("all")`] is in the provided locale list. Used to request every
+/// audio version an episode exposes instead of a fixed locale list, resolved separately against
+/// each episode's own version metadata since the available set varies per episode.
+pub fn has_all_locale(locales: &[Locale]) -> bool {
+    locales
+        .iter()
+        .any(|l| l.to_string().eq_ignore_ascii_case("all"))
+}
+
+/// Find the locale marked `original: true` in a title's version list (`(locale, original)`
+/// pairs), falling back to `own_locale` if none is marked, which happens for some older catalog
+/// entries that don't expose the flag.
+pub fn original_locale_of(versions: &[(Locale, bool)], own_locale: Option<&Locale>) -> Locale {
+    versions
+        .iter()
+        .find(|(_, original)| *original)
+        .map(|(locale, _)| locale.clone())
+        .or_else(|| own_locale.cloned())
+        .unwrap_or(Locale::ja_JP)
+}
+
+/// Find `locale`'s position in a `--audio`/`--subtitle`-style `requested` list, treating an
+/// `original` entry as a match for `locale` if `is_original` is set. Used to sort already-resolved
+/// audio/subtitle tracks back into the user's requested order even though `requested` may still
+/// contain the unresolved `original` keyword instead of `locale` itself.
+pub fn locale_position(requested: &[Locale], locale: &Locale, is_original: bool) -> usize {
+    requested
+        .iter()
+        .position(|l| {
+            l == locale || (is_original && l.to_string().eq_ignore_ascii_case("original"))
+        })
+        .unwrap_or(usize::MAX)
+}
+
+/// Trailing dub-language suffixes Crunchyroll appends to a season's slug title (after a possible
+/// generic `-dub` marker), longest first so e.g. `-english-in` is checked before `-english`.
+fn season_slug_locale_suffixes() -> Vec<(&'static str, Locale)> {
+    vec![
+        ("-english-in", Locale::en_IN),
+        ("-english", Locale::en_US),
+        ("-german", Locale::de_DE),
+        ("-french", Locale::fr_FR),
+        ("-italian", Locale::it_IT),
+        ("-hindi", Locale::hi_IN),
+        ("-castilian", Locale::es_ES),
+        ("-arabic", Locale::ar_SA),
+    ]
+}
+
+/// Derives a season's dub [`Locale`] from its slug title instead of its (sometimes unreliable)
+/// `season_number`/`audio_locales`, e.g. `attack-on-titan-german` -> `Some(Locale::de_DE)`. `None`
+/// if the slug doesn't end in a recognized suffix (e.g. it's the original-language season). Used as
+/// an `--experimental-fixes` fallback signal when disambiguating same-numbered seasons.
+pub fn locale_from_season_slug(slug_title: &str) -> Option<Locale> {
+    let slug = slug_title.trim_end_matches("-dub");
+    season_slug_locale_suffixes()
+        .into_iter()
+        .filter(|(suffix, _)| slug.ends_with(suffix))
+        .max_by_key(|(suffix, _)| suffix.len())
+        .map(|(_, locale)| locale)
+}
+
 /// Check if [`Locale::Custom("all")`] is in the provided locale list and return [`Locale::all`] if
 /// so. If not, just return the provided locale list.
 pub fn all_locale_in_locales(locales: Vec<Locale>) -> Vec<Locale> {
@@ -1,30 +1,322 @@
-use log::info;
+use dialoguer::console::{self, Term};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{
+    set_boxed_logger, set_max_level, Level, LevelFilter, Log, Metadata, Record, SetLoggerError,
+};
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
 
+/// One update published on the progress bus, modeled on shellcaster's `MainMessage` enum: every
+/// event names the `id` of the item it belongs to, so a single channel can drive any number of
+/// concurrent [`indicatif`] bars (one per episode/segment being worked on) instead of the one
+/// implicit global spinner the old single-shot `progress!`/`ProgressHandler` pair used to own.
+/// `total: None` renders as a spinner, `Some(n)` as a bar out of `n`.
+#[derive(Clone, Debug)]
+pub enum ProgressEvent {
+    Started {
+        id: u64,
+        total: Option<u64>,
+        message: String,
+    },
+    Advanced {
+        id: u64,
+        delta: u64,
+    },
+    Message {
+        id: u64,
+        text: String,
+    },
+    Finished {
+        id: u64,
+        message: Option<String>,
+    },
+}
+
+static NEXT_PROGRESS_ID: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn next_progress_id() -> u64 {
+    NEXT_PROGRESS_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The single [`MultiProgress`] every bar spawned through [`progress_bus`] is registered on, and
+/// that [`CliLogger`] prints ordinary log lines through instead of a bare `println!`/`eprintln!` -
+/// that's what keeps a log line from garbling whatever bars happen to be active at the time,
+/// regardless of how many of them there are.
+fn multi_progress() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(MultiProgress::new)
+}
+
+static PROGRESS_BUS: OnceLock<Sender<ProgressEvent>> = OnceLock::new();
+
+/// Returns the sender [`ProgressEvent`]s are published through, starting the renderer thread that
+/// owns every concurrent bar the first time it's called. Idempotent - there's only ever one bus and
+/// one renderer per process, and its sender is cheap to clone, so callers don't need to cache it.
+/// This is the seam an alternate frontend (plain log, a JSON progress stream, ...) would subscribe
+/// a different renderer to instead of this one.
+pub(crate) fn progress_bus() -> Sender<ProgressEvent> {
+    PROGRESS_BUS.get_or_init(spawn_progress_renderer).clone()
+}
+
+fn spawn_progress_renderer() -> Sender<ProgressEvent> {
+    let (tx, rx) = mpsc::channel::<ProgressEvent>();
+    thread::spawn(move || {
+        let mut bars: HashMap<u64, ProgressBar> = HashMap::new();
+        for event in rx {
+            match event {
+                ProgressEvent::Started { id, total, message } => {
+                    let bar = match total {
+                        Some(total) => ProgressBar::new(total).with_style(
+                            ProgressStyle::with_template(
+                                ":: {msg} [{wide_bar}] {pos}/{len}",
+                            )
+                            .unwrap()
+                            .progress_chars("##-"),
+                        ),
+                        None => {
+                            #[cfg(not(windows))]
+                            let finish_str = "✔";
+                            #[cfg(windows)]
+                            let finish_str = "√";
+
+                            let bar = ProgressBar::new_spinner().with_style(
+                                ProgressStyle::with_template(":: {spinner:.green} {msg}")
+                                    .unwrap()
+                                    .tick_strings(&["-", "\\", "|", "/", finish_str]),
+                            );
+                            bar.enable_steady_tick(Duration::from_millis(200));
+                            bar
+                        }
+                    };
+                    bar.set_message(message);
+                    bars.insert(id, multi_progress().add(bar));
+                }
+                ProgressEvent::Advanced { id, delta } => {
+                    if let Some(bar) = bars.get(&id) {
+                        bar.inc(delta)
+                    }
+                }
+                ProgressEvent::Message { id, text } => {
+                    if let Some(bar) = bars.get(&id) {
+                        bar.set_message(text)
+                    }
+                }
+                ProgressEvent::Finished { id, message } => {
+                    if let Some(bar) = bars.remove(&id) {
+                        match message {
+                            Some(msg) => bar.finish_with_message(msg),
+                            None => bar.finish(),
+                        }
+                        multi_progress().remove(&bar);
+                    }
+                }
+            }
+        }
+    });
+    tx
+}
+
+/// Handle to one in-flight item on the progress bus, returned by [`progress`]/[`progress_unless`].
+/// Dropping it without calling [`Self::stop`] finishes its bar with no final message, the same way
+/// letting the old `ProgressHandler` fall out of scope used to emit a bare `progress_end`.
 pub struct ProgressHandler {
+    pub(crate) id: u64,
     pub(crate) stopped: bool,
 }
 
 impl Drop for ProgressHandler {
     fn drop(&mut self) {
         if !self.stopped {
-            info!(target: "progress_end", "")
+            let _ = progress_bus().send(ProgressEvent::Finished {
+                id: self.id,
+                message: None,
+            });
         }
     }
 }
 
 impl ProgressHandler {
+    /// Reports incremental progress (bytes, segments, ...) for a bar started with a known total,
+    /// instead of leaving it a silent span until [`Self::stop`].
+    pub(crate) fn advance(&self, delta: u64) {
+        let _ = progress_bus().send(ProgressEvent::Advanced {
+            id: self.id,
+            delta,
+        });
+    }
+
+    /// Updates the bar's message without finishing it.
+    pub(crate) fn message<S: Into<String>>(&self, text: S) {
+        let _ = progress_bus().send(ProgressEvent::Message {
+            id: self.id,
+            text: text.into(),
+        });
+    }
+
     pub(crate) fn stop<S: AsRef<str>>(mut self, msg: S) {
         self.stopped = true;
-        info!(target: "progress_end", "{}", msg.as_ref())
+        let _ = progress_bus().send(ProgressEvent::Finished {
+            id: self.id,
+            message: Some(msg.as_ref().to_string()),
+        });
     }
 }
 
+/// Starts a spinner-style progress item and returns its handle. The message is what the old
+/// `progress!(...)` macro logged at the `"progress"` target; it's now published as a
+/// [`ProgressEvent::Started`] on the bus instead of a log record, so [`ProgressHandler::advance`] can report
+/// real progress for long operations instead of leaving them a silent span.
 macro_rules! progress {
     ($($arg:tt)+) => {
         {
-            log::info!(target: "progress", $($arg)+);
-            $crate::utils::log::ProgressHandler{stopped: false}
+            let id = $crate::utils::log::next_progress_id();
+            let _ = $crate::utils::log::progress_bus().send($crate::utils::log::ProgressEvent::Started {
+                id,
+                total: None,
+                message: format!($($arg)+),
+            });
+            $crate::utils::log::ProgressHandler{id, stopped: false}
         }
     }
 }
 pub(crate) use progress;
+
+/// Like [`progress`], but does not emit anything if `$silent` is true (e.g. in `--dump-json`
+/// mode, which suppresses all progress output regardless of verbosity).
+macro_rules! progress_unless {
+    ($silent:expr, $($arg:tt)+) => {
+        if $silent {
+            None
+        } else {
+            Some($crate::utils::log::progress!($($arg)+))
+        }
+    }
+}
+pub(crate) use progress_unless;
+
+/// Like [`log::info`], but indents the message unless debug/verbose output is active, in which
+/// case it's logged like any other record instead (the extended debug format already makes
+/// nested calls visually distinguishable).
+macro_rules! tab_info {
+    ($($arg:tt)+) => {
+        if log::max_level() == log::LevelFilter::Debug {
+            log::info!($($arg)+)
+        } else {
+            log::info!("\t{}", format!($($arg)+))
+        }
+    }
+}
+pub(crate) use tab_info;
+
+/// When ANSI colors should be used for progress bars and log output.
+#[derive(Clone, Debug)]
+pub enum ColorChoice {
+    Always,
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "always" => Self::Always,
+            "auto" => Self::Auto,
+            "never" => Self::Never,
+            _ => return Err(format!("'{}' is not a valid color option", s)),
+        })
+    }
+
+    /// Resolve this choice to whether colors should actually be enabled. `Auto` falls back to
+    /// stdout TTY detection. The conventional `NO_COLOR` environment variable
+    /// (<https://no-color.org>) is honored as an implicit `Never`, unless `Always` was explicitly
+    /// requested.
+    fn resolve(&self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => env::var_os("NO_COLOR").is_none() && Term::stdout().is_term(),
+        }
+    }
+}
+
+/// Resolve `choice` and apply it globally to every [`indicatif`]/[`dialoguer`] progress bar,
+/// prompt and the [`CliLogger`], since they all render through the `console` crate. Returns the
+/// resolved enabled/disabled state so it can be stored in [`crate::utils::context::Context`].
+pub fn apply_color_choice(choice: &ColorChoice) -> bool {
+    let enabled = choice.resolve();
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+    enabled
+}
+
+#[allow(clippy::type_complexity)]
+pub struct CliLogger {
+    all: bool,
+    level: LevelFilter,
+}
+
+impl Log for CliLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata())
+            || (!self.all && !record.target().starts_with("crunchy_cli"))
+        {
+            return;
+        }
+
+        if self.level >= LevelFilter::Debug {
+            self.extended(record);
+            return;
+        }
+
+        if record.level() > Level::Warn {
+            self.normal(record)
+        } else {
+            self.error(record)
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl CliLogger {
+    pub fn new(all: bool, level: LevelFilter) -> Self {
+        Self { all, level }
+    }
+
+    pub fn init(all: bool, level: LevelFilter) -> Result<(), SetLoggerError> {
+        set_max_level(level);
+        set_boxed_logger(Box::new(CliLogger::new(all, level)))
+    }
+
+    fn extended(&self, record: &Record) {
+        let _ = multi_progress().println(format!(
+            "[{}] {}  {} ({}) {}",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record
+                .target()
+                .replacen("crunchy_cli_core", "crunchy_cli", 1),
+            format!("{:?}", thread::current().id())
+                .replace("ThreadId(", "")
+                .replace(')', ""),
+            record.args()
+        ));
+    }
+
+    fn normal(&self, record: &Record) {
+        let _ = multi_progress().println(format!(":: {}", record.args()));
+    }
+
+    fn error(&self, record: &Record) {
+        let _ = multi_progress().println(format!(":: {}", console::style(record.args()).red()));
+    }
+}
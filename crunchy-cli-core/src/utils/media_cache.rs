@@ -0,0 +1,104 @@
+use crate::utils::os::persistent_cache_dir;
+use anyhow::{bail, Result};
+use chrono::{TimeDelta, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Deserialize)]
+struct MediaCacheEntry<T> {
+    fetched_at: i64,
+    data: T,
+}
+
+#[derive(Serialize)]
+struct MediaCacheEntryRef<'a, T> {
+    fetched_at: i64,
+    data: &'a T,
+}
+
+/// On-disk, TTL-bounded cache for the child lists [`crate::utils::filter::Filter::visit`] fetches
+/// while walking a [`crunchyroll_rs::MediaCollection`] (a series' seasons, a season's episodes, ...),
+/// keyed by the parent's own id, the same way rustypipe caches its Innertube responses. Lives under
+/// `dirs::config_dir()/crunchy-cli/cache`, next to the login session files, so it actually persists
+/// across runs instead of being swept by the OS temp cleanup `utils::os::cache_dir` is subject to.
+/// Speeds up repeatedly filtering/downloading the same title and reduces API rate-limit pressure, at
+/// the cost of serving stale data until an entry's TTL expires, and backs `--offline`.
+pub struct MediaCache {
+    dir: Option<PathBuf>,
+    ttl: TimeDelta,
+    refresh: bool,
+    offline: bool,
+}
+
+impl MediaCache {
+    /// `ttl <= TimeDelta::zero()` disables the cache entirely (`--no-cache`): every lookup misses
+    /// and nothing is written, unless `offline` is set, in which case entries are still read (with
+    /// their TTL ignored) since there's nowhere else to get the data from. `refresh`
+    /// (`--refresh-cache`) keeps writing fresh entries but always misses on read, forcing every id
+    /// to be revalidated once; it's ignored while `offline`, which always prefers a cache hit.
+    pub fn new(ttl: TimeDelta, refresh: bool, offline: bool) -> Self {
+        let dir = (ttl > TimeDelta::zero() || offline)
+            .then(|| persistent_cache_dir("media-tree").ok())
+            .flatten();
+        Self {
+            dir,
+            ttl,
+            refresh,
+            offline,
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, kind: &str, id: &str) -> Option<T> {
+        if self.refresh && !self.offline {
+            return None;
+        }
+        let entry = self.read_entry::<T>(kind, id)?;
+        if self.offline {
+            return Some(entry.data);
+        }
+        (Utc::now().timestamp() - entry.fetched_at < self.ttl.num_seconds()).then_some(entry.data)
+    }
+
+    pub fn set<T: Serialize>(&self, kind: &str, id: &str, data: &T) {
+        let Some(path) = self.path(kind, id) else {
+            return;
+        };
+        let entry = MediaCacheEntryRef {
+            fetched_at: Utc::now().timestamp(),
+            data,
+        };
+        if let Ok(raw) = serde_json::to_vec(&entry) {
+            let _ = fs::write(path, raw);
+        }
+    }
+
+    /// Like [`Self::get`], but for `--offline`: a miss is an error instead of a signal to fall
+    /// through to a live fetch, since `--offline` has no live fetch to fall through to.
+    pub fn get_or_offline_err<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        id: &str,
+        what: &str,
+    ) -> Result<Option<T>> {
+        if !self.offline {
+            return Ok(self.get(kind, id));
+        }
+        match self.get::<T>(kind, id) {
+            Some(data) => Ok(Some(data)),
+            None => bail!("--offline is set but no cached {} is available for this request", what),
+        }
+    }
+
+    fn read_entry<T: DeserializeOwned>(&self, kind: &str, id: &str) -> Option<MediaCacheEntry<T>> {
+        let raw = fs::read(self.path(kind, id)?).ok()?;
+        serde_json::from_slice(&raw).ok()
+    }
+
+    fn path(&self, kind: &str, id: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{kind}-{id}.json")))
+    }
+}
@@ -1,17 +1,60 @@
 use log::debug;
 use regex::{Regex, RegexBuilder};
 use std::borrow::Cow;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write as _};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 use std::{env, fs, io};
 use tempfile::{Builder, NamedTempFile, TempPath};
 use tokio::io::{AsyncRead, ReadBuf};
 
+lazy_static::lazy_static! {
+    // mirrors `CRUNCHY_CLI_TEMP_DIR`/`temp_directory()`: a `--ffmpeg-path`-equivalent override for
+    // users who want it set once in their environment instead of on every invocation. `--ffmpeg-path`
+    // itself still wins when given, since `set_ffmpeg_binary` overwrites this afterward.
+    static ref FFMPEG_BINARY: Mutex<PathBuf> = Mutex::new(
+        env::var("CRUNCHY_CLI_FFMPEG").map_or_else(|_| PathBuf::from("ffmpeg"), PathBuf::from)
+    );
+    static ref FFPROBE_BINARY: Mutex<PathBuf> = Mutex::new(PathBuf::from("ffprobe"));
+}
+
+/// Overrides the ffmpeg binary [`ffmpeg_command`] spawns. Called once after the binary has been
+/// located or downloaded (see `utils::ffmpeg::resolve_ffmpeg`).
+pub fn set_ffmpeg_binary(path: PathBuf) {
+    *FFMPEG_BINARY.lock().unwrap() = path;
+}
+
+/// Overrides the ffprobe binary [`ffprobe_command`] spawns. Called once `utils::ffmpeg::resolve_ffmpeg`
+/// has found a sibling `ffprobe` next to the resolved ffmpeg binary; otherwise `ffprobe` from `PATH`
+/// is used, same as the default for [`FFMPEG_BINARY`].
+pub fn set_ffprobe_binary(path: PathBuf) {
+    *FFPROBE_BINARY.lock().unwrap() = path;
+}
+
+/// Creates a [`Command`] for the resolved ffmpeg binary. Every call site which previously did
+/// `Command::new("ffmpeg")` should use this instead, so `--ffmpeg-path`/`--download-ffmpeg`
+/// actually take effect.
+pub fn ffmpeg_command() -> Command {
+    Command::new(&*FFMPEG_BINARY.lock().unwrap())
+}
+
+/// The currently configured ffmpeg binary path: whatever [`set_ffmpeg_binary`] last set it to, or
+/// the `CRUNCHY_CLI_FFMPEG`/`ffmpeg` default if it's never been called.
+pub fn ffmpeg_binary() -> PathBuf {
+    FFMPEG_BINARY.lock().unwrap().clone()
+}
+
+/// Creates a [`Command`] for the resolved ffprobe binary, analogous to [`ffmpeg_command`].
+pub fn ffprobe_command() -> Command {
+    Command::new(&*FFPROBE_BINARY.lock().unwrap())
+}
+
 pub fn has_ffmpeg() -> bool {
-    if let Err(e) = Command::new("ffmpeg").stderr(Stdio::null()).spawn() {
+    if let Err(e) = ffmpeg_command().stderr(Stdio::null()).spawn() {
         if ErrorKind::NotFound != e.kind() {
             debug!(
                 "unknown error occurred while checking if ffmpeg exists: {}",
@@ -33,10 +76,12 @@ pub fn temp_directory() -> PathBuf {
 /// Any tempfile should be created with this function. The prefix and directory of every file
 /// created with this method stays the same which is helpful to query all existing tempfiles and
 /// e.g. remove them in a case of ctrl-c. Having one function also good to prevent mistakes like
-/// setting the wrong prefix if done manually.
+/// setting the wrong prefix if done manually. The current process id is embedded right after the
+/// shared prefix (e.g. `.crunchy-cli_1234_foo`) so [`reap_own_temp`]/[`reap_stale_temp`] can tell
+/// which process a leftover file belonged to.
 pub fn tempfile<S: AsRef<str>>(suffix: S) -> io::Result<NamedTempFile> {
     let tempfile = Builder::default()
-        .prefix(".crunchy-cli_")
+        .prefix(&format!(".crunchy-cli_{}_", std::process::id()))
         .suffix(suffix.as_ref())
         .tempfile_in(temp_directory())?;
     debug!(
@@ -46,12 +91,102 @@ pub fn tempfile<S: AsRef<str>>(suffix: S) -> io::Result<NamedTempFile> {
     Ok(tempfile)
 }
 
+/// Unlike [`tempfile`], cache directories are meant to outlive the process that created them (see
+/// `utils::ffmpeg`'s binary cache), so their name carries no pid and
+/// [`reap_stale_temp`] only ever reaps them via the mtime cutoff, never by liveness.
 pub fn cache_dir<S: AsRef<str>>(name: S) -> io::Result<PathBuf> {
     let cache_dir = temp_directory().join(format!(".crunchy-cli_{}_cache", name.as_ref()));
     fs::create_dir_all(&cache_dir)?;
     Ok(cache_dir)
 }
 
+/// Unlike [`cache_dir`], this lives next to the login session files (`dirs::config_dir()/crunchy-cli`)
+/// instead of the OS temp directory, so it survives reboots/`--clean-temp`-style cleanup and is
+/// actually usable as a durable offline cache (see `utils::media_cache`).
+pub fn persistent_cache_dir<S: AsRef<str>>(name: S) -> io::Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no config directory available"))?;
+    let cache_dir = config_dir.join("crunchy-cli").join("cache").join(name.as_ref());
+    fs::create_dir_all(&cache_dir)?;
+    Ok(cache_dir)
+}
+
+/// Parses the pid [`tempfile`] embeds out of a `.crunchy-cli_<pid>_...` name, if present (older
+/// tempfiles from before this existed, and `cache_dir`'s `.crunchy-cli_<name>_cache` directories,
+/// have none).
+fn pid_from_temp_name(name: &str) -> Option<u32> {
+    name.strip_prefix(".crunchy-cli_")?.split('_').next()?.parse().ok()
+}
+
+/// Whether a process with the given pid currently exists.
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// No liveness check on non-unix targets: every pid is reported alive, so there
+/// [`reap_stale_temp`] never reaps an entry whose name carries a pid; only pid-less entries are
+/// reaped, via its mtime cutoff.
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Scans `temp_directory()` for `.crunchy-cli_` entries and removes the ones `should_reap` accepts,
+/// handling both loose tempfiles/pipes and `cache_dir` directories.
+fn reap_matching(should_reap: impl Fn(&Path, Option<u32>) -> bool) {
+    let Ok(entries) = fs::read_dir(temp_directory()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !name.starts_with(".crunchy-cli_") || !should_reap(&path, pid_from_temp_name(name)) {
+            continue;
+        }
+
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        debug!(
+            "Reaped temporary entry {} {}",
+            path.to_string_lossy(),
+            if result.is_ok() {
+                "successfully"
+            } else {
+                "not successfully"
+            }
+        );
+    }
+}
+
+/// Removes every tempfile/pipe this process itself created (matched by the pid [`tempfile`]/
+/// [`temp_named_pipe`] embed in their name), regardless of age. Called from the ctrl-c handler so
+/// a killed run's own scratch files are gone immediately instead of waiting on [`reap_stale_temp`].
+pub fn reap_own_temp() {
+    let own_pid = std::process::id();
+    reap_matching(|_, pid| pid == Some(own_pid));
+}
+
+/// Removes every `.crunchy-cli_` entry whose owning process is no longer running, or, for entries
+/// that don't encode a pid (like `cache_dir`'s long-lived `*_cache` directories), that haven't been
+/// touched in over `max_age`. Meant to run once at startup, to clean up after a previous run that
+/// was killed or crashed before its own cleanup (ctrl-c's handler, [`reap_own_temp`]) could run.
+pub fn reap_stale_temp(max_age: Duration) {
+    let now = SystemTime::now();
+    reap_matching(|path, pid| match pid {
+        Some(pid) => !process_alive(pid),
+        None => fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map(|modified| now.duration_since(modified).unwrap_or_default() > max_age)
+            .unwrap_or(false),
+    });
+}
+
 pub struct TempNamedPipe {
     path: TempPath,
 
@@ -138,7 +273,96 @@ pub fn temp_named_pipe() -> io::Result<TempNamedPipe> {
     }
 }
 
-/// Check if the given path exists and rename it until the new (renamed) file does not exist.
+/// Default in-memory threshold [`SpooledTemp::new_default`] rolls over at, in bytes.
+const SPOOLED_TEMP_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// A write buffer that starts out as a plain `Vec<u8>` and transparently rolls over to a real
+/// [`tempfile`] once its contents exceed a threshold. Downloading HLS/DASH produces thousands of
+/// small segment buffers; the common case comfortably fits in RAM, so only the rare oversized
+/// segment needs to actually thrash the temp directory.
+pub enum SpooledTemp {
+    Memory(Vec<u8>, usize),
+    Disk(NamedTempFile),
+}
+
+impl SpooledTemp {
+    /// Spool in memory until `threshold` bytes have been written, then roll over to disk.
+    pub fn new(threshold: usize) -> Self {
+        SpooledTemp::Memory(Vec::new(), threshold)
+    }
+
+    /// Like [`Self::new`], using [`SPOOLED_TEMP_THRESHOLD`] (8 MiB) as the rollover threshold.
+    pub fn new_default() -> Self {
+        Self::new(SPOOLED_TEMP_THRESHOLD)
+    }
+
+    /// Stream the buffered contents back, regardless of whether they ever touched disk.
+    pub fn into_reader(self) -> io::Result<SpooledTempReader> {
+        match self {
+            SpooledTemp::Memory(buf, _) => Ok(SpooledTempReader::Memory(io::Cursor::new(buf))),
+            SpooledTemp::Disk(file) => Ok(SpooledTempReader::Disk(tokio::fs::File::from_std(
+                file.reopen()?,
+            ))),
+        }
+    }
+}
+
+impl io::Write for SpooledTemp {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let SpooledTemp::Memory(mem, threshold) = self {
+            if mem.len() + buf.len() > *threshold {
+                let mut file = tempfile("")?;
+                file.write_all(mem)?;
+                *self = SpooledTemp::Disk(file);
+            }
+        }
+
+        match self {
+            SpooledTemp::Memory(mem, _) => {
+                mem.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            SpooledTemp::Disk(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SpooledTemp::Memory(_, _) => Ok(()),
+            SpooledTemp::Disk(file) => file.flush(),
+        }
+    }
+}
+
+/// Returned by [`SpooledTemp::into_reader`]; reads back whatever [`SpooledTemp`] buffered,
+/// in-memory or on disk, through a single `AsyncRead` implementation.
+pub enum SpooledTempReader {
+    Memory(io::Cursor<Vec<u8>>),
+    Disk(tokio::fs::File),
+}
+
+impl AsyncRead for SpooledTempReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SpooledTempReader::Memory(cursor) => {
+                let mut tmp = vec![0; buf.remaining()];
+                let n = io::Read::read(cursor, &mut tmp)?;
+                buf.put_slice(&tmp[..n]);
+                Poll::Ready(Ok(()))
+            }
+            SpooledTempReader::Disk(file) => Pin::new(file).poll_read(cx, buf),
+        }
+    }
+}
+
+/// Check if the given path exists and rename it until the new (renamed) file does not exist. The
+/// path it returns is only meant to be handed to [`AtomicOutput::new`] right afterwards: nothing
+/// actually writes to it before then, so the only remaining race is between this check and that
+/// `AtomicOutput` being created, not between this check and a, possibly hours-long, download.
 pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     // do not rename it if it exists but is a special file
     if is_special_file(&path) {
@@ -170,6 +394,108 @@ pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     (path, i != 0)
 }
 
+/// A destination path that's only made visible at `target` once its contents are fully written.
+/// [`Self::path`] is a temporary path next to `target` (same directory, almost always the same
+/// filesystem) to write into; [`Self::commit`] then publishes it with a single rename, so nothing
+/// ever observes a half-written file at `target`. If `commit` is never called (the write errors
+/// out or the process is killed) [`Drop`] removes the temporary path instead.
+pub struct AtomicOutput {
+    temp: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl AtomicOutput {
+    /// Prepare an atomic output for `target`. Fails if a leftover temporary path from a previous
+    /// crashed run targeting the same file can't be removed.
+    pub fn new(target: PathBuf) -> io::Result<Self> {
+        let file_name = target.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let temp = target
+            .with_file_name(format!(".crunchy-cli_{}_{}.part", std::process::id(), file_name));
+
+        if temp.is_dir() {
+            fs::remove_dir_all(&temp)?;
+        } else if temp.exists() {
+            fs::remove_file(&temp)?;
+        }
+
+        Ok(Self { temp, target, committed: false })
+    }
+
+    /// The temporary path to write the output to. Write here instead of the real target; call
+    /// [`Self::commit`] once writing has fully succeeded to publish it.
+    pub fn path(&self) -> &Path {
+        &self.temp
+    }
+
+    /// Publish the written output at `target`, replacing anything already there. Tries a plain
+    /// rename first; if `temp` and `target` end up on different filesystems (`EXDEV`) falls back
+    /// to copying the output over and removing `temp` afterwards.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.committed = true;
+
+        match fs::rename(&self.temp, &self.target) {
+            Ok(()) => Ok(()),
+            Err(err) if is_cross_device(&err) => {
+                if self.temp.is_dir() {
+                    copy_dir_all(&self.temp, &self.target)?;
+                    fs::remove_dir_all(&self.temp)?;
+                } else {
+                    fs::copy(&self.temp, &self.target)?;
+                    fs::remove_file(&self.temp)?;
+                }
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for AtomicOutput {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        if self.temp.is_dir() {
+            let _ = fs::remove_dir_all(&self.temp);
+        } else {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Whether `err` was raised by [`fs::rename`] because its source and destination are on different
+/// filesystems. `std::io::ErrorKind::CrossesDevices` is still unstable and this repo has no `libc`
+/// dependency to compare against `libc::EXDEV` directly, so the raw, platform-specific errno is
+/// checked instead (`18` on Unix, `17`/`ERROR_NOT_SAME_DEVICE` on Windows).
+fn is_cross_device(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    let exdev = 18;
+    #[cfg(windows)]
+    let exdev = 17;
+    #[cfg(not(any(unix, windows)))]
+    let exdev = i32::MIN;
+
+    err.raw_os_error() == Some(exdev)
+}
+
+/// Recursively copy `src` into `dst`, creating `dst` and any missing subdirectories along the way.
+/// Only used by [`AtomicOutput::commit`] as the cross-filesystem fallback for directory outputs
+/// (`--hls-output`).
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let to = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), &to)?;
+        }
+    }
+    Ok(())
+}
+
 /// Check if the given path is a special file. On Linux this is probably a pipe and on Windows
 /// ¯\_(ツ)_/¯
 pub fn is_special_file<P: AsRef<Path>>(path: P) -> bool {
@@ -190,18 +516,78 @@ lazy_static::lazy_static! {
     static ref RESERVED_RE: Regex = Regex::new(r"^\.+$").unwrap();
 }
 
+/// Maps common accented Latin letters and full-width (CJK IME) ASCII variants to their plain
+/// ASCII equivalent, e.g. for `--universal-output` names meant to also survive on FAT/exFAT
+/// targets. This is a small, hand-picked table rather than a full Unicode decomposition (this
+/// repo has no `unicode-normalization`-style dependency to lean on), so it only covers the
+/// characters anime titles actually tend to contain; anything else passes through unchanged.
+fn transliterate_to_ascii(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'À'..='Å' => 'A',
+            'à'..='å' => 'a',
+            'È'..='Ë' => 'E',
+            'è'..='ë' => 'e',
+            'Ì'..='Ï' => 'I',
+            'ì'..='ï' => 'i',
+            'Ò'..='Ö' => 'O',
+            'ò'..='ö' => 'o',
+            'Ù'..='Ü' => 'U',
+            'ù'..='ü' => 'u',
+            'Ý' => 'Y',
+            'ý' | 'ÿ' => 'y',
+            'Ñ' => 'N',
+            'ñ' => 'n',
+            'Ç' => 'C',
+            'ç' => 'c',
+            // full-width ASCII block (full-width punctuation, digits and letters titles sometimes
+            // mix in, e.g. "ＡＢＣ！１２３") maps 1:1 onto normal ASCII, shifted down by 0xfee0
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
 /// Sanitizes a filename with the option to include/exclude the path separator from sanitizing.
+/// `universal` additionally runs the name through [`transliterate_to_ascii`] before the rest of
+/// the Windows-rule sanitizing, so names also stay stable on cross-platform/FAT targets.
 pub fn sanitize<S: AsRef<str>>(path: S, include_path_separator: bool, universal: bool) -> String {
     let path = Cow::from(path.as_ref().trim());
 
     let path = RESERVED_RE.replace(&path, "");
+    let path: Cow<str> = if universal {
+        Cow::from(transliterate_to_ascii(&path))
+    } else {
+        path
+    };
 
+    // most filesystems cap a single path component at 255 *bytes*, not characters, so a naive
+    // `name[..255]` can both panic (the cut can fall inside a multi-byte char) and chop the
+    // extension off a long title. split the extension off first and only truncate the stem, down
+    // to the largest `char_indices()` boundary that still leaves the whole name within budget
     let collect = |name: String| {
-        if name.len() > 255 {
-            name[..255].to_string()
-        } else {
-            name
+        if name.len() <= 255 {
+            return name;
+        }
+
+        let (stem, ext) = match name.rfind('.') {
+            // a leading dot (e.g. a name starting with "...") is part of the stem, not an
+            // extension to preserve
+            Some(i) if i > 0 => (&name[..i], &name[i..]),
+            _ => (name.as_str(), ""),
+        };
+        let budget = 255usize.saturating_sub(ext.len());
+
+        let mut end = 0;
+        for (i, c) in stem.char_indices() {
+            let char_end = i + c.len_utf8();
+            if char_end > budget {
+                break;
+            }
+            end = char_end;
         }
+
+        format!("{}{}", &stem[..end], ext)
     };
 
     if universal || cfg!(windows) {
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression as GzCompression;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Archive format for [`package_season`]. A `.zip` keeps every file individually compressed (and
+/// seekable without decompressing the whole archive); a `.tar.gz` compresses the concatenated
+/// stream as a whole, usually resulting in a smaller file for many similarly-encoded episodes.
+#[derive(Clone, Debug)]
+pub enum Compression {
+    Zip,
+    Gzip,
+}
+
+impl Compression {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "zip" => Compression::Zip,
+            "gzip" => Compression::Gzip,
+            _ => return Err(format!("'{}' is not a valid compression format", s)),
+        })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Zip => "zip",
+            Compression::Gzip => "tar.gz",
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Compression::Zip => "zip",
+            Compression::Gzip => "gzip",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Bundles `files` into a single `.zip`/`.tar.gz` named `{archive_stem}.{ext}` in `files`' parent
+/// directory, then removes the original files. Used once a season's episodes have all finished
+/// downloading, so the archive boundary lines up with the season boundary instead of every episode
+/// being left as a loose file.
+pub fn package_season(files: &[PathBuf], archive_stem: &str, compression: &Compression) -> Result<PathBuf> {
+    let parent = files
+        .first()
+        .and_then(|p| p.parent())
+        .unwrap_or_else(|| Path::new("."));
+    let archive_path = parent.join(format!("{}.{}", archive_stem, compression.extension()));
+
+    match compression {
+        Compression::Zip => write_zip(files, &archive_path)?,
+        Compression::Gzip => write_tar_gz(files, &archive_path)?,
+    }
+
+    for file in files {
+        fs::remove_file(file)?;
+    }
+
+    Ok(archive_path)
+}
+
+fn write_zip(files: &[PathBuf], archive_path: &Path) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(File::create(archive_path)?);
+    let options =
+        zip::write::FileOptions::<()>::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for file in files {
+        let name = file
+            .file_name()
+            .map_or_else(|| file.to_string_lossy().to_string(), |n| n.to_string_lossy().to_string());
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(file)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_tar_gz(files: &[PathBuf], archive_path: &Path) -> Result<()> {
+    let encoder = GzEncoder::new(File::create(archive_path)?, GzCompression::default());
+    let mut tar = tar::Builder::new(encoder);
+
+    for file in files {
+        let name = file
+            .file_name()
+            .map_or_else(|| file.to_string_lossy().to_string(), |n| n.to_string_lossy().to_string());
+        tar.append_path_with_name(file, name)?;
+    }
+
+    tar.finish()?;
+    Ok(())
+}
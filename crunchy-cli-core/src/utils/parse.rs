@@ -1,4 +1,5 @@
 use anyhow::{anyhow, bail, Result};
+use chrono::TimeDelta;
 use crunchyroll_rs::media::Resolution;
 use crunchyroll_rs::{Crunchyroll, MediaCollection, UrlType};
 use log::debug;
@@ -12,45 +13,122 @@ use regex::Regex;
 pub struct InnerUrlFilter {
     from_episode: Option<f32>,
     to_episode: Option<f32>,
+    /// Set instead of `from_episode` by a `E-N` token: "N episodes counted back from the season's
+    /// last episode", resolved against the season's total episode count at match time.
+    from_episode_from_end: Option<u32>,
+    /// Same as `from_episode_from_end`, but for the end of the range.
+    to_episode_from_end: Option<u32>,
     from_season: Option<u32>,
     to_season: Option<u32>,
+    /// Whether this range was given as `!...`, which excludes it instead of including it.
+    negate: bool,
 }
 
-#[derive(Debug)]
-pub struct UrlFilter {
-    inner: Vec<InnerUrlFilter>,
-}
+impl InnerUrlFilter {
+    fn season_matches(&self, season: u32) -> bool {
+        let from_season = self.from_season.unwrap_or(u32::MIN);
+        let to_season = self.to_season.unwrap_or(u32::MAX);
+        season >= from_season && season <= to_season
+    }
 
-impl Default for UrlFilter {
-    fn default() -> Self {
-        Self {
-            inner: vec![InnerUrlFilter::default()],
+    /// Resolve an (absolute, from-end) episode bound pair to an absolute episode number, given the
+    /// season's total episode count if known. Returns `None` only when a from-end bound was given
+    /// but `episode_count` isn't known, in which case the caller should treat the bound as unmet
+    /// rather than silently falling back to an unconstrained one.
+    fn resolve_bound(
+        absolute: Option<f32>,
+        from_end: Option<u32>,
+        episode_count: Option<u32>,
+        default: f32,
+    ) -> Option<f32> {
+        match (absolute, from_end) {
+            (Some(episode), _) => Some(episode),
+            (None, Some(n)) => {
+                episode_count.map(|count| (count as i64 - n as i64 + 1).max(1) as f32)
+            }
+            (None, None) => Some(default),
         }
     }
+
+    fn episode_matches(&self, episode: f32, season: u32, episode_count: Option<u32>) -> bool {
+        if !self.season_matches(season) {
+            return false;
+        }
+
+        let (Some(from_episode), Some(to_episode)) = (
+            Self::resolve_bound(
+                self.from_episode,
+                self.from_episode_from_end,
+                episode_count,
+                f32::MIN,
+            ),
+            Self::resolve_bound(
+                self.to_episode,
+                self.to_episode_from_end,
+                episode_count,
+                f32::MAX,
+            ),
+        ) else {
+            return false;
+        };
+
+        episode >= from_episode && episode <= to_episode
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UrlFilter {
+    inner: Vec<InnerUrlFilter>,
+    /// Set by a `latest:N` token: only match the `N` episodes with the highest episode number in
+    /// each season the rest of the filter already matches. Resolved the same way as `E-N` bounds,
+    /// against the season's total episode count.
+    latest: Option<u32>,
 }
 
 impl UrlFilter {
+    /// An excluded (`!...`) range only narrows what the included ranges already matched, so with no
+    /// included range at all (e.g. a filter consisting solely of `!S2`) everything is considered
+    /// included by default, same as an absent filter.
     pub fn is_season_valid(&self, season: u32) -> bool {
-        self.inner.iter().any(|f| {
-            let from_season = f.from_season.unwrap_or(u32::MIN);
-            let to_season = f.to_season.unwrap_or(u32::MAX);
+        let (included, excluded): (Vec<_>, Vec<_>) =
+            self.inner.iter().partition(|f| !f.negate);
 
-            season >= from_season && season <= to_season
-        })
+        (included.is_empty() || included.iter().any(|f| f.season_matches(season)))
+            && !excluded.iter().any(|f| f.season_matches(season))
     }
 
+    /// Like [`Self::is_episode_valid_with_count`], but without knowledge of the season's total
+    /// episode count, so `E-N`/`latest:N` relative selectors never match (they degrade to "doesn't
+    /// match" instead of silently becoming unconstrained). Use [`Self::is_episode_valid_with_count`]
+    /// wherever the season's episode count is already on hand.
     pub fn is_episode_valid(&self, episode: f32, season: u32) -> bool {
-        self.inner.iter().any(|f| {
-            let from_episode = f.from_episode.unwrap_or(f32::MIN);
-            let to_episode = f.to_episode.unwrap_or(f32::MAX);
-            let from_season = f.from_season.unwrap_or(u32::MIN);
-            let to_season = f.to_season.unwrap_or(u32::MAX);
-
-            episode >= from_episode
-                && episode <= to_episode
-                && season >= from_season
-                && season <= to_season
-        })
+        self.is_episode_valid_with_count(episode, season, None)
+    }
+
+    pub fn is_episode_valid_with_count(
+        &self,
+        episode: f32,
+        season: u32,
+        episode_count: Option<u32>,
+    ) -> bool {
+        let (included, excluded): (Vec<_>, Vec<_>) =
+            self.inner.iter().partition(|f| !f.negate);
+
+        let range_matches = (included.is_empty()
+            || included
+                .iter()
+                .any(|f| f.episode_matches(episode, season, episode_count)))
+            && !excluded
+                .iter()
+                .any(|f| f.episode_matches(episode, season, episode_count));
+
+        let latest_matches = match (self.latest, episode_count) {
+            (Some(n), Some(count)) => episode > count as f32 - n as f32,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        range_matches && latest_matches
     }
 }
 
@@ -69,6 +147,12 @@ impl UrlFilter {
 /// - `...[S1E4-S3]` - Download all episodes from and including season 1, episode 4, until andincluding season 3.
 /// - `...[S3,S5]` - Download episode 3 and 5.
 /// - `...[S1-S3,S4E2-S4E6]` - Download season 1 to 3 and episode 2 to episode 6 of season 4.
+/// - `...[S1-S5,!S3]` - Download season 1 to 5, except season 3.
+/// - `...[S1E-3-]` - Download the last three episodes of season 1 (`E-3` counts back from the
+///   season's last episode; resolving it requires the season's total episode count to be known
+///   to the caller, see [`UrlFilter::is_episode_valid_with_count`]).
+/// - `...[latest:5]` - Download the 5 episodes with the highest episode number of every season the
+///   rest of the filter matches (same episode-count caveat as `E-N`).
 
 /// In practice, it would look like this: `https://crunchyroll.com/series/12345678/example[S1E5-S3E2]`.
 pub async fn parse_url(
@@ -90,20 +174,52 @@ pub async fn parse_url(
             "".to_string()
         };
 
-        let filter_regex = Regex::new(r"((S(?P<from_season>\d+))?(E(?P<from_episode>\d+))?)(((?P<dash>-)((S(?P<to_season>\d+))?(E(?P<to_episode>\d+))?))?)(,|$)").unwrap();
+        // `latest:N` is a standalone token (not tied to a season/episode range), so it's pulled out
+        // of the comma-separated list before the range regex below runs over the rest
+        let mut latest = None;
+        let filter = filter
+            .split(',')
+            .filter(|token| match token.strip_prefix("latest:") {
+                Some(n) => {
+                    latest = n.parse().ok();
+                    false
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let filter_regex = Regex::new(r"(?P<negate>!)?((S(?P<from_season>\d+))?(E(?P<from_episode_neg>-)?(?P<from_episode>\d+))?)(((?P<dash>-)((S(?P<to_season>\d+))?(E(?P<to_episode_neg>-)?(?P<to_episode>\d+))?))?)(,|$)").unwrap();
 
         let mut filters = vec![];
 
         for capture in filter_regex.captures_iter(&filter) {
+            let negate = capture.name("negate").is_some();
             let dash = capture.name("dash").is_some();
-            let from_episode = capture
+            let from_episode_is_relative = capture.name("from_episode_neg").is_some();
+            let from_episode_value = capture
                 .name("from_episode")
                 .map_or(anyhow::Ok(None), |fe| Ok(Some(fe.as_str().parse()?)))?;
-            let to_episode = capture
+            let (from_episode, from_episode_from_end) = if from_episode_is_relative {
+                (None, from_episode_value.map(|v: f32| v as u32))
+            } else {
+                (from_episode_value, None)
+            };
+            let to_episode_is_relative = capture.name("to_episode_neg").is_some();
+            let to_episode_value = capture
                 .name("to_episode")
                 .map_or(anyhow::Ok(if dash { None } else { from_episode }), |te| {
                     Ok(Some(te.as_str().parse()?))
                 })?;
+            let (to_episode, to_episode_from_end) = if to_episode_is_relative {
+                (None, to_episode_value.map(|v: f32| v as u32))
+            } else if !dash && from_episode.is_none() && from_episode_from_end.is_some() {
+                // a single relative point (e.g. `E-3` without a trailing `-`) also closes the range
+                // at the same relative position
+                (None, from_episode_from_end)
+            } else {
+                (to_episode_value, None)
+            };
             let from_season = capture
                 .name("from_season")
                 .map_or(anyhow::Ok(None), |fs| Ok(Some(fs.as_str().parse()?)))?;
@@ -116,12 +232,18 @@ pub async fn parse_url(
             filters.push(InnerUrlFilter {
                 from_episode,
                 to_episode,
+                from_episode_from_end,
+                to_episode_from_end,
                 from_season,
                 to_season,
+                negate,
             })
         }
 
-        let url_filter = UrlFilter { inner: filters };
+        let url_filter = UrlFilter {
+            inner: filters,
+            latest,
+        };
 
         debug!("Url find: {:?}", url_filter);
 
@@ -193,6 +315,32 @@ pub fn parse_resolution(mut resolution: String) -> Result<Resolution> {
     }
 }
 
+/// Parse a `--start`/`--duration`-style time, given as `HH:MM:SS(.ms)`, `MM:SS(.ms)` or a plain
+/// number of seconds.
+pub fn parse_time_delta(time: &str) -> Result<TimeDelta> {
+    if let Ok(seconds) = time.parse::<f64>() {
+        return Ok(TimeDelta::milliseconds((seconds * 1000.0) as i64));
+    }
+
+    let parts: Vec<&str> = time.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        bail!(
+            "'{}' is not a valid time (expected 'HH:MM:SS', 'MM:SS' or a plain number of seconds)",
+            time
+        )
+    }
+
+    let mut seconds = 0f64;
+    for part in parts {
+        seconds = seconds * 60.0
+            + part
+                .parse::<f64>()
+                .map_err(|_| anyhow!("'{}' is not a valid time", time))?
+    }
+
+    Ok(TimeDelta::milliseconds((seconds * 1000.0) as i64))
+}
+
 /// Dirty implementation of [`f32::fract`] with more accuracy.
 pub fn fract(input: f32) -> f32 {
     if input.fract() == 0.0 {
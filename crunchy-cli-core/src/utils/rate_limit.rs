@@ -1,12 +1,14 @@
 use async_speed_limit::Limiter;
 use crunchyroll_rs::error::Error;
 use futures_util::TryStreamExt;
-use reqwest::{Client, Request, Response, ResponseBuilderExt};
+use reqwest::{Body, Client, Request, Response, ResponseBuilderExt};
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
 use tower_service::Service;
 
 #[derive(Clone)]
@@ -38,10 +40,7 @@ impl Service<Request> for RateLimiterService {
         let rate_limiter = self.rate_limiter.clone();
 
         Box::pin(async move {
-            let mut body = vec![];
             let res = client.execute(req).await?;
-            let _url = res.url().clone().to_string();
-            let url = _url.as_str();
 
             let mut http_res = http::Response::builder()
                 .url(res.url().clone())
@@ -53,19 +52,17 @@ impl Service<Request> for RateLimiterService {
                 .unwrap()
                 .clone_from(&res.extensions());
 
-            let limiter = rate_limiter.limit(
-                res.bytes_stream()
-                    .map_err(io::Error::other)
-                    .into_async_read(),
-            );
-
-            futures_util::io::copy(limiter, &mut body)
-                .await
-                .map_err(|e| Error::Request {
-                    url: url.to_string(),
-                    status: None,
-                    message: e.to_string(),
-                })?;
+            // Throttle the response as it's consumed instead of buffering it fully first, so large
+            // video segments don't spike memory and the downstream muxer can start working on bytes as
+            // they arrive.
+            let limited = rate_limiter
+                .limit(
+                    res.bytes_stream()
+                        .map_err(io::Error::other)
+                        .into_async_read(),
+                )
+                .compat();
+            let body = Body::wrap_stream(ReaderStream::new(limited));
 
             Ok(Response::from(http_res.body(body).unwrap()))
         })
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Why an entry was skipped/degraded during an archive run. Mirrors the log sites in
+/// [`crate::archive::filter::ArchiveFilter`] that currently only surface through `warn!`/`info!`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    MissingAudio,
+    MissingSubtitle,
+    PremiumSkipped,
+    RelativeNumberFailed,
+    DuplicatedSeason,
+}
+
+/// One finding accumulated by `--report`, identified by whatever series/season/episode id the
+/// finding was raised against.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReportEntry {
+    pub identifier: String,
+    pub reason: ReportReason,
+    pub message: String,
+}
+
+/// Output format for `--report`. `yaml` is gated behind the `yaml` feature the same way
+/// [`crate::utils::format::PrintFormatsOutput`] gates it, since pulling in a YAML serializer isn't
+/// worth it for users who only ever want JSON.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ReportFormat {
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl ReportFormat {
+    /// Picked from the `--report` path's extension; `.yml`/`.yaml` select [`Self::Yaml`], anything
+    /// else (including no extension) defaults to [`Self::Json`].
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "yaml")]
+            Some("yml" | "yaml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    fn serialize(self, entries: &[ReportEntry]) -> Result<String> {
+        Ok(match self {
+            ReportFormat::Json => serde_json::to_string_pretty(entries)?,
+            #[cfg(feature = "yaml")]
+            ReportFormat::Yaml => serde_yaml::to_string(entries)?,
+        })
+    }
+}
+
+/// Write `entries` to `path`, merging them with whatever report a previous url of the same run
+/// (or a previous invocation) already left behind, so archiving multiple urls in one command
+/// still ends up with a single report covering all of them instead of each url overwriting the
+/// last one's findings.
+pub fn write_report(path: &Path, entries: &[ReportEntry]) -> Result<()> {
+    let format = ReportFormat::from_path(path);
+
+    let mut all_entries = match format {
+        ReportFormat::Json => fs::read(path)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Vec<ReportEntry>>(&raw).ok())
+            .unwrap_or_default(),
+        #[cfg(feature = "yaml")]
+        ReportFormat::Yaml => fs::read_to_string(path)
+            .ok()
+            .and_then(|raw| serde_yaml::from_str::<Vec<ReportEntry>>(&raw).ok())
+            .unwrap_or_default(),
+    };
+    all_entries.extend(entries.iter().cloned());
+
+    fs::write(path, format.serialize(&all_entries)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::utils::parse::parse_resolution;
+use crunchyroll_rs::media::Resolution;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    static ref SEASON_EPISODE_SXXEXX_RE: Regex = Regex::new(r"(?i)S(\d+)E(\d+)").unwrap();
+    static ref SEASON_EPISODE_NXN_RE: Regex = Regex::new(r"(?i)(\d+)x(\d+)").unwrap();
+    static ref RESOLUTION_RE: Regex = Regex::new(r"(?i)(\d{3,4})p").unwrap();
+    static ref YEAR_RE: Regex = Regex::new(r"(19|20)\d\d").unwrap();
+}
+
+/// Metadata recovered from an existing file name on disk, used by `--skip-existing` to figure out
+/// which episodes of a `SingleFormatCollection` are already present without relying on the exact
+/// `format_path` output (the template, resolution or release-group-style tags around it may have
+/// changed since the file was downloaded).
+///
+/// Parsing is intentionally best-effort: run an ordered list of case-insensitive regexes over the
+/// file stem and keep the first match for each field (in the style of torrent-name-parser). Only
+/// `season_number`/`episode_number` are used for matching; `resolution`/`year` are exposed so
+/// callers can additionally require them to match, and `title` is recovered for display purposes
+/// only, since sanitized titles don't always round-trip.
+#[derive(Debug, Default)]
+pub struct ParsedFilename {
+    pub title: Option<String>,
+    pub season_number: Option<u32>,
+    pub episode_number: Option<u32>,
+    pub resolution: Option<Resolution>,
+    pub year: Option<u32>,
+}
+
+impl ParsedFilename {
+    pub fn parse(stem: &str) -> Self {
+        let mut earliest_match_start = stem.len();
+        let mut season_number = None;
+        let mut episode_number = None;
+
+        if let Some(captures) = SEASON_EPISODE_SXXEXX_RE
+            .captures(stem)
+            .or_else(|| SEASON_EPISODE_NXN_RE.captures(stem))
+        {
+            let whole_match = captures.get(0).unwrap();
+            season_number = captures.get(1).and_then(|m| m.as_str().parse().ok());
+            episode_number = captures.get(2).and_then(|m| m.as_str().parse().ok());
+            earliest_match_start = earliest_match_start.min(whole_match.start());
+        }
+
+        let mut resolution = None;
+        if let Some(captures) = RESOLUTION_RE.captures(stem) {
+            let whole_match = captures.get(0).unwrap();
+            resolution = parse_resolution(format!("{}p", &captures[1])).ok();
+            earliest_match_start = earliest_match_start.min(whole_match.start());
+        }
+
+        let mut year = None;
+        if let Some(whole_match) = YEAR_RE.find(stem) {
+            year = whole_match.as_str().parse().ok();
+            earliest_match_start = earliest_match_start.min(whole_match.start());
+        }
+
+        let title_part = stem[..earliest_match_start].trim_matches(|c: char| {
+            c.is_whitespace() || c == '.' || c == '_' || c == '-' || c == '['
+        });
+        let title = (!title_part.is_empty()).then(|| title_part.replace(['.', '_'], " "));
+
+        Self {
+            title,
+            season_number,
+            episode_number,
+            resolution,
+            year,
+        }
+    }
+}
+
+/// Recursively walks `dir` and parses every file name in it. Missing directories are treated as
+/// empty instead of erroring, since a fresh output directory not existing yet is the common case.
+pub fn parse_existing_files(dir: &Path) -> Vec<ParsedFilename> {
+    let mut parsed = vec![];
+    walk(dir, &mut parsed);
+    parsed
+}
+
+fn walk(dir: &Path, parsed: &mut Vec<ParsedFilename>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, parsed);
+        } else if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+            parsed.push(ParsedFilename::parse(stem));
+        }
+    }
+}
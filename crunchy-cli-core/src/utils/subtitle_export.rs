@@ -0,0 +1,523 @@
+use chrono::TimeDelta;
+use encoding_rs::Encoding;
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+
+/// Whether a subtitle is embedded into the muxed output, written next to it as a sidecar file (see
+/// `--subtitle-format`/`--subtitle-charset`), or both. Mirrors the `--subtitle-output` flag.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubtitleOutput {
+    Embed,
+    External,
+    Both,
+}
+
+impl SubtitleOutput {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "embed" => SubtitleOutput::Embed,
+            "external" => SubtitleOutput::External,
+            "both" => SubtitleOutput::Both,
+            _ => return Err(format!("'{}' is not a valid subtitle output", s)),
+        })
+    }
+
+    pub fn embeds(&self) -> bool {
+        matches!(self, SubtitleOutput::Embed | SubtitleOutput::Both)
+    }
+
+    pub fn writes_external(&self) -> bool {
+        matches!(self, SubtitleOutput::External | SubtitleOutput::Both)
+    }
+}
+
+impl Display for SubtitleOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SubtitleOutput::Embed => "embed",
+            SubtitleOutput::External => "external",
+            SubtitleOutput::Both => "both",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Sidecar subtitle format a downloaded ASS track can be converted to via `--subtitle-format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubtitleFormat {
+    /// The format the subtitle is downloaded in, written out as-is.
+    Ass,
+    /// Plain timed text. Styling, positioning and overlapping events are dropped/merged since SRT
+    /// has no concept of either.
+    Srt,
+    /// WebVTT. Keeps basic positioning cues (derived from ASS `\an` alignment overrides) that SRT
+    /// can't represent.
+    Vtt,
+    /// Scenarist SCC, a pop-on CEA-608 (line-21) caption sidecar for set-top boxes/editing tools
+    /// that only read line-21 captions rather than ASS. Styling/positioning is dropped; only plain
+    /// dialogue text survives, like SRT.
+    Scc,
+}
+
+impl SubtitleFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "ass" => SubtitleFormat::Ass,
+            "srt" => SubtitleFormat::Srt,
+            "vtt" | "webvtt" => SubtitleFormat::Vtt,
+            "scc" => SubtitleFormat::Scc,
+            _ => return Err(format!("'{}' is not a valid subtitle format", s)),
+        })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Scc => "scc",
+        }
+    }
+}
+
+impl Display for SubtitleFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// Per-style overrides for a downloaded ASS track's `[V4+ Styles]` section, set via
+/// `--subtitle-font`/`--subtitle-font-size`/`--subtitle-outline`/`--subtitle-shadow`/
+/// `--subtitle-margin-v`. Crunchyroll's own styling renders poorly on some players; this lets
+/// users restyle during download instead of needing a separate post-processing pass.
+#[derive(Clone, Default)]
+pub struct SubtitleStyleOverrides {
+    pub font_name: Option<String>,
+    pub font_size: Option<u32>,
+    pub outline: Option<f32>,
+    pub shadow: Option<f32>,
+    pub margin_v: Option<u32>,
+}
+
+impl SubtitleStyleOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.font_name.is_none()
+            && self.font_size.is_none()
+            && self.outline.is_none()
+            && self.shadow.is_none()
+            && self.margin_v.is_none()
+    }
+}
+
+/// Rewrites every `Style:` line in `ass`, applying whichever fields `overrides` sets. A V4+ style
+/// line is `Style: Name,Fontname,Fontsize,PrimaryColour,SecondaryColour,OutlineColour,BackColour,
+/// Bold,Italic,Underline,StrikeOut,ScaleX,ScaleY,Spacing,Angle,BorderStyle,Outline,Shadow,
+/// Alignment,MarginL,MarginR,MarginV,Encoding` - 23 comma-delimited fields after the `Style: ` tag,
+/// of which only Fontname/Fontsize/Outline/Shadow/MarginV are touched here. Lines that don't match
+/// that shape (or no overrides are set at all) are passed through unchanged.
+pub fn apply_subtitle_style(ass: &str, overrides: &SubtitleStyleOverrides) -> String {
+    if overrides.is_empty() {
+        return ass.to_string();
+    }
+
+    let mut out = String::new();
+    for line in ass.lines() {
+        if let Some(rest) = line.strip_prefix("Style: ") {
+            let mut fields: Vec<String> = rest.split(',').map(|f| f.to_string()).collect();
+            if fields.len() == 23 {
+                if let Some(font_name) = &overrides.font_name {
+                    fields[1] = font_name.clone();
+                }
+                if let Some(font_size) = overrides.font_size {
+                    fields[2] = font_size.to_string();
+                }
+                if let Some(outline) = overrides.outline {
+                    fields[16] = outline.to_string();
+                }
+                if let Some(shadow) = overrides.shadow {
+                    fields[17] = shadow.to_string();
+                }
+                if let Some(margin_v) = overrides.margin_v {
+                    fields[21] = margin_v.to_string();
+                }
+                out.push_str("Style: ");
+                out.push_str(&fields.join(","));
+                out.push('\n');
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Clone)]
+struct Event {
+    start_cs: u32,
+    end_cs: u32,
+    text: String,
+    /// ASS `\an1`-`\an9` numpad alignment of the first override block that carries one, if any.
+    alignment: Option<u8>,
+}
+
+/// Converts a raw `.ass` subtitle payload (as downloaded from Crunchyroll) into `format`. Returns
+/// the input unchanged for [`SubtitleFormat::Ass`]. `fps` is only used by
+/// [`SubtitleFormat::Scc`], to place each caption on the right SCC frame number.
+pub fn convert_subtitle(ass: &str, format: SubtitleFormat, fps: f64) -> String {
+    match format {
+        SubtitleFormat::Ass => ass.to_string(),
+        SubtitleFormat::Srt => render_srt(&merge_overlapping(parse_events(ass))),
+        SubtitleFormat::Vtt => render_vtt(&merge_overlapping(parse_events(ass))),
+        SubtitleFormat::Scc => render_scc(&merge_overlapping(parse_events(ass)), fps),
+    }
+}
+
+/// Re-encodes a sidecar subtitle's content into `charset` (as given to `--subtitle-charset`,
+/// e.g. "utf-8" or "windows-1252") before it's written to disk, for players/tools that don't
+/// assume UTF-8. Characters the target charset can't represent are written as HTML decimal
+/// numeric character references (`&#NNNN;`), which is `encoding_rs`'s lossy-encode behavior.
+pub fn encode_subtitle_charset(content: &str, charset: &str) -> Result<Vec<u8>, String> {
+    let encoding = Encoding::for_label(charset.as_bytes())
+        .ok_or_else(|| format!("'{}' is not a known charset", charset))?;
+    let (bytes, _, _) = encoding.encode(content);
+    Ok(bytes.into_owned())
+}
+
+/// Shifts every `Dialogue:` event's start/end by `-shift`, as computed by the per-track `--merge
+/// sync` alignment offset, so a language whose audio (and therefore subtitles) had its beginning
+/// trimmed away stays aligned with the rest of the merged tracks. Events that would end at or
+/// before zero are dropped; a start that lands before zero is clamped to zero instead of kept
+/// negative. Everything besides `Dialogue:` lines (styles, fonts, script info) is passed through
+/// unchanged.
+pub fn shift_subtitle_events(ass: &str, shift: TimeDelta) -> String {
+    let dialogue_re = Regex::new(
+        r#"^(?P<prefix>Dialogue:\s*\d+,)(?P<start>\d+:\d{2}:\d{2}\.\d{2}),(?P<end>\d+:\d{2}:\d{2}\.\d{2}),(?P<suffix>.*)$"#,
+    )
+    .unwrap();
+    let shift_cs = shift.num_milliseconds() / 10;
+
+    let mut out = String::new();
+    for line in ass.lines() {
+        let Some(capture) = dialogue_re.captures(line) else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+
+        let end_cs = parse_ass_timestamp(&capture["end"]) as i64 - shift_cs;
+        if end_cs <= 0 {
+            continue;
+        }
+        let start_cs = (parse_ass_timestamp(&capture["start"]) as i64 - shift_cs).max(0);
+
+        out.push_str(&capture["prefix"]);
+        out.push_str(&format_ass_timestamp(start_cs as u32));
+        out.push(',');
+        out.push_str(&format_ass_timestamp(end_cs as u32));
+        out.push(',');
+        out.push_str(&capture["suffix"]);
+        out.push('\n');
+    }
+    out
+}
+
+/// Every `Dialogue:` event's `(start_cs, end_cs)` in file order (centiseconds, i.e. the 10ms grid
+/// `--subtitle-sync`'s VAD alignment works on), for correlating a track against an audio timeline.
+/// Matches the same `Dialogue:` lines [`shift_subtitle_events_per_line`] indexes, so the `i`-th
+/// interval belongs to the line that `shifts_cs[i]` is applied to.
+pub fn subtitle_cue_intervals_cs(ass: &str) -> Vec<(u32, u32)> {
+    let dialogue_re = Regex::new(
+        r#"^Dialogue:\s*\d+,(?P<start>\d+:\d{2}:\d{2}\.\d{2}),(?P<end>\d+:\d{2}:\d{2}\.\d{2}),"#,
+    )
+    .unwrap();
+    ass.lines()
+        .filter_map(|line| dialogue_re.captures(line))
+        .map(|c| (parse_ass_timestamp(&c["start"]), parse_ass_timestamp(&c["end"])))
+        .collect()
+}
+
+/// Per-line counterpart to [`shift_subtitle_events`]: shifts the `i`-th `Dialogue:` event by
+/// `shifts_cs[i]` centiseconds instead of applying one shift to the whole track, so a
+/// `--subtitle-sync` split alignment (different offsets for different parts of the episode, e.g.
+/// around an ad break) can be applied in one pass. Extra entries in `shifts_cs` are ignored; a
+/// `Dialogue:` line with no corresponding entry is left unshifted. Same start/end clamping rules as
+/// [`shift_subtitle_events`].
+pub fn shift_subtitle_events_per_line(ass: &str, shifts_cs: &[i64]) -> String {
+    let dialogue_re = Regex::new(
+        r#"^(?P<prefix>Dialogue:\s*\d+,)(?P<start>\d+:\d{2}:\d{2}\.\d{2}),(?P<end>\d+:\d{2}:\d{2}\.\d{2}),(?P<suffix>.*)$"#,
+    )
+    .unwrap();
+
+    let mut out = String::new();
+    let mut index = 0;
+    for line in ass.lines() {
+        let Some(capture) = dialogue_re.captures(line) else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+        let shift_cs = shifts_cs.get(index).copied().unwrap_or(0);
+        index += 1;
+
+        let end_cs = parse_ass_timestamp(&capture["end"]) as i64 + shift_cs;
+        if end_cs <= 0 {
+            continue;
+        }
+        let start_cs = (parse_ass_timestamp(&capture["start"]) as i64 + shift_cs).max(0);
+
+        out.push_str(&capture["prefix"]);
+        out.push_str(&format_ass_timestamp(start_cs as u32));
+        out.push(',');
+        out.push_str(&format_ass_timestamp(end_cs as u32));
+        out.push(',');
+        out.push_str(&capture["suffix"]);
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_events(ass: &str) -> Vec<Event> {
+    // Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text - only Start/End/Text matter here
+    let dialogue_re = Regex::new(
+        r#"^Dialogue:\s*\d+,(?P<start>\d+:\d{2}:\d{2}\.\d{2}),(?P<end>\d+:\d{2}:\d{2}\.\d{2}),[^,]*,[^,]*,\d+,\d+,\d+,[^,]*,(?P<text>.*)$"#,
+    )
+    .unwrap();
+    let alignment_re = Regex::new(r"\\an(?P<code>[1-9])").unwrap();
+    let override_re = Regex::new(r"\{[^}]*}").unwrap();
+
+    let mut events = vec![];
+    for line in ass.lines() {
+        let Some(capture) = dialogue_re.captures(line) else {
+            continue;
+        };
+        let raw_text = &capture["text"];
+        let alignment = alignment_re
+            .captures(raw_text)
+            .and_then(|c| c["code"].parse().ok());
+        let text = override_re
+            .replace_all(raw_text, "")
+            .replace("\\N", "\n")
+            .replace("\\n", "\n")
+            .replace("\\h", " ");
+
+        events.push(Event {
+            start_cs: parse_ass_timestamp(&capture["start"]),
+            end_cs: parse_ass_timestamp(&capture["end"]),
+            text,
+            alignment,
+        });
+    }
+    events.sort_by_key(|e| e.start_cs);
+    events
+}
+
+/// SRT/VTT players don't support overlapping cues as well as ASS does, so adjacent events whose
+/// timespans overlap are merged into a single cue spanning both, with their text stacked.
+fn merge_overlapping(events: Vec<Event>) -> Vec<Event> {
+    let mut merged: Vec<Event> = vec![];
+    for event in events {
+        if let Some(last) = merged.last_mut() {
+            if event.start_cs < last.end_cs {
+                last.end_cs = last.end_cs.max(event.end_cs);
+                last.text = format!("{}\n{}", last.text, event.text);
+                continue;
+            }
+        }
+        merged.push(event);
+    }
+    merged
+}
+
+/// Parses an ASS "H:MM:SS.cc" timestamp into centiseconds.
+fn parse_ass_timestamp(raw: &str) -> u32 {
+    let (hms, centis) = raw.split_once('.').unwrap_or((raw, "0"));
+    let mut parts = hms.split(':');
+    let hours: u32 = parts.next().unwrap_or("0").parse().unwrap_or(0);
+    let minutes: u32 = parts.next().unwrap_or("0").parse().unwrap_or(0);
+    let seconds: u32 = parts.next().unwrap_or("0").parse().unwrap_or(0);
+    let centis: u32 = centis.parse().unwrap_or(0);
+    ((hours * 3600 + minutes * 60 + seconds) * 100) + centis
+}
+
+fn format_srt_timestamp(cs: u32) -> String {
+    let ms = (cs % 100) * 10;
+    let total_seconds = cs / 100;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        total_seconds / 3600,
+        (total_seconds / 60) % 60,
+        total_seconds % 60,
+        ms
+    )
+}
+
+fn format_vtt_timestamp(cs: u32) -> String {
+    format_srt_timestamp(cs).replace(',', ".")
+}
+
+/// Formats centiseconds back into an ASS "H:MM:SS.cc" timestamp.
+fn format_ass_timestamp(cs: u32) -> String {
+    let centis = cs % 100;
+    let total_seconds = cs / 100;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        total_seconds / 3600,
+        (total_seconds / 60) % 60,
+        total_seconds % 60,
+        centis
+    )
+}
+
+fn render_srt(events: &[Event]) -> String {
+    let mut out = String::new();
+    for (i, event) in events.iter().enumerate() {
+        out.push_str(&format!("{}\n", i + 1));
+        out.push_str(&format!(
+            "{} --> {}\n",
+            format_srt_timestamp(event.start_cs),
+            format_srt_timestamp(event.end_cs)
+        ));
+        out.push_str(&event.text);
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// CEA-608 control code byte pairs (pre-parity), channel 1. Transmitted twice in succession per
+/// CEA-608's error-resilience requirement - see [`control_code`].
+const RCL: (u8, u8) = (0x14, 0x20); // Resume Caption Loading: starts a pop-on caption
+const EOC: (u8, u8) = (0x14, 0x2F); // End Of Caption: flips the back buffer onto screen
+const EDM: (u8, u8) = (0x14, 0x2C); // Erase Displayed Memory
+const ENM: (u8, u8) = (0x14, 0x2E); // Erase Non-displayed Memory
+
+/// Preamble Address Codes (white text, no underline, no indent) for the two bottom rows pop-on
+/// captions are placed on here. CEA-608 defines 15 rows and several indent/color/underline
+/// variants; this only ever emits plain bottom-aligned captions, so the rest of the table isn't
+/// implemented.
+const PAC_ROW_14: (u8, u8) = (0x14, 0x50);
+const PAC_ROW_15: (u8, u8) = (0x14, 0x70);
+
+/// Sets the CEA-608 odd-parity bit (bit 7) on a 7-bit value, as every transmitted byte requires.
+fn parity(b: u8) -> u8 {
+    let b = b & 0x7F;
+    if b.count_ones() % 2 == 0 {
+        b | 0x80
+    } else {
+        b
+    }
+}
+
+/// Formats a control code pair, doubled, as the hex the `.scc` format expects (e.g. "9420 9420"
+/// for RCL) - CEA-608 requires every control code to be sent twice in succession so a single
+/// dropped frame can't lose it.
+fn control_code(code: (u8, u8)) -> String {
+    let pair = format!("{:02x}{:02x}", parity(code.0), parity(code.1));
+    format!("{} {}", pair, pair)
+}
+
+/// Encodes up to two ASCII characters into one CEA-608 byte pair (odd parity set on each byte).
+/// Characters outside CEA-608's basic (printable ASCII) character set are replaced with a space
+/// rather than silently producing an invalid pair - this doesn't implement the spec's extended
+/// character codes.
+fn text_pair(a: u8, b: u8) -> String {
+    let to_608 = |c: u8| if c.is_ascii_graphic() || c == b' ' { c } else { b' ' };
+    format!("{:02x}{:02x}", parity(to_608(a)), parity(to_608(b)))
+}
+
+/// Packs `line` into space-padded CEA-608 byte pairs (two characters per code, as the format
+/// requires; an odd-length line is padded with a trailing space).
+fn text_codes(line: &str) -> Vec<String> {
+    let bytes: Vec<u8> = line.bytes().collect();
+    bytes
+        .chunks(2)
+        .map(|chunk| text_pair(chunk[0], *chunk.get(1).unwrap_or(&b' ')))
+        .collect()
+}
+
+/// Converts a centisecond timestamp into the `HH:MM:SS:FF` timecode SCC files use, deriving the
+/// frame number from `fps`.
+fn frame_timecode(cs: u32, fps: f64) -> String {
+    let total_seconds = cs / 100;
+    let sub_second_cs = cs % 100;
+    let frame = ((sub_second_cs as f64 / 100.0) * fps).round() as u32;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        total_seconds / 3600,
+        (total_seconds / 60) % 60,
+        total_seconds % 60,
+        frame
+    )
+}
+
+/// Renders `events` as a pop-on Scenarist SCC (CEA-608) caption track: each caption resumes
+/// caption loading (`RCL`) into the back buffer, positions up to its last two lines with a
+/// preamble address code per line, writes the text, then flips the buffer visible (`EOC`) at the
+/// caption's start time, and erases it (`EDM`+`ENM`) at its end time.
+fn render_scc(events: &[Event], fps: f64) -> String {
+    let mut out = String::from("Scenarist_SCC V1.0\n\n");
+
+    for event in events {
+        let lines: Vec<&str> = event.text.lines().rev().take(2).collect();
+        let rows = if lines.len() < 2 {
+            &[PAC_ROW_15][..]
+        } else {
+            &[PAC_ROW_14, PAC_ROW_15][..]
+        };
+
+        let mut open_codes = vec![control_code(RCL)];
+        for (row, line) in rows.iter().zip(lines.iter().rev()) {
+            open_codes.push(control_code(*row));
+            open_codes.extend(text_codes(line));
+        }
+        open_codes.push(control_code(EOC));
+
+        out.push_str(&format!(
+            "{}\t{}\n\n",
+            frame_timecode(event.start_cs, fps),
+            open_codes.join(" ")
+        ));
+        out.push_str(&format!(
+            "{}\t{} {}\n\n",
+            frame_timecode(event.end_cs, fps),
+            control_code(EDM),
+            control_code(ENM)
+        ));
+    }
+
+    out
+}
+
+fn render_vtt(events: &[Event]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for event in events {
+        out.push_str(&format!(
+            "{} --> {}{}\n",
+            format_vtt_timestamp(event.start_cs),
+            format_vtt_timestamp(event.end_cs),
+            event
+                .alignment
+                .map(|a| format!(" {}", vtt_position_cue(a)))
+                .unwrap_or_default()
+        ));
+        out.push_str(&event.text);
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Maps an ASS numpad alignment code (`\an1`-`\an9`) to the closest VTT cue settings. ASS numbers
+/// the grid bottom-to-top (1-3 bottom, 4-6 middle, 7-9 top) and left-to-right within a row.
+fn vtt_position_cue(an_code: u8) -> String {
+    let line = match an_code {
+        7..=9 => "line:0%",
+        4..=6 => "line:50%",
+        _ => "line:100%",
+    };
+    let align = match an_code % 3 {
+        1 => "align:start",
+        2 => "align:center",
+        _ => "align:end",
+    };
+    format!("{} {}", line, align)
+}
@@ -0,0 +1,156 @@
+use crate::utils::os::ffmpeg_command;
+use crate::utils::subtitle_export::{shift_subtitle_events_per_line, subtitle_cue_intervals_cs};
+use anyhow::{bail, Result};
+use std::path::Path;
+
+/// Grid resolution voice activity and subtitle cues are both rasterized onto, in centiseconds.
+/// Matches the centisecond precision `.ass` timestamps already have, so no extra rounding is
+/// introduced when cue intervals are read off [`subtitle_cue_intervals_cs`].
+const GRID_CS: i64 = 1;
+/// Bounded sweep range for the global (and per-line-group) offset search, in centiseconds (±45s).
+const MAX_SHIFT_CS: i64 = 4500;
+/// Subtracted from a line group's score for every new group the split DP opens, so a new offset is
+/// only introduced when it recovers more overlap than this from the previous group's offset.
+const SPLIT_PENALTY: f64 = 25.0;
+
+/// Aligns `ass` against the voice activity detected in `audio_path`, returning the re-timed
+/// subtitle text. `split` additionally lets different parts of the episode (e.g. around an ad
+/// break) pick up their own offset instead of a single global shift; a net-zero shift is a no-op
+/// and a shift that would push a cue negative is clamped to 0 (handled by
+/// [`shift_subtitle_events_per_line`]).
+pub fn sync_subtitle_to_audio(ass: &str, audio_path: &Path, split: bool) -> Result<String> {
+    let vad = voice_activity_timeline(audio_path)?;
+    let cues = subtitle_cue_intervals_cs(ass);
+    if vad.is_empty() || cues.is_empty() {
+        return Ok(ass.to_string());
+    }
+
+    let active_prefix = prefix_sum(&vad);
+    let overlap = |start_cs: u32, end_cs: u32, delta: i64| -> f64 {
+        let shifted_start = start_cs as i64 + delta;
+        let shifted_end = end_cs as i64 + delta;
+        overlap_count(&active_prefix, shifted_start, shifted_end) as f64
+    };
+
+    let shifts_cs: Vec<i64> = if split {
+        split_align(&cues, overlap)
+    } else {
+        let (best_delta, _) = best_offset(&cues, overlap);
+        vec![best_delta * GRID_CS; cues.len()]
+    };
+
+    if shifts_cs.iter().all(|s| *s == 0) {
+        return Ok(ass.to_string());
+    }
+
+    Ok(shift_subtitle_events_per_line(ass, &shifts_cs))
+}
+
+/// Sweeps every candidate delta in `±MAX_SHIFT_CS` and returns the one maximizing the combined
+/// voice-activity overlap of `cues`, alongside its score. O(cues × range), as the cue count is
+/// small relative to the bin range this resolves to.
+fn best_offset(cues: &[(u32, u32)], overlap: impl Fn(u32, u32, i64) -> f64) -> (i64, f64) {
+    let mut best_delta = 0;
+    let mut best_score = f64::MIN;
+    for delta in -MAX_SHIFT_CS..=MAX_SHIFT_CS {
+        let score: f64 = cues.iter().map(|(s, e)| overlap(*s, *e, delta)).sum();
+        if score > best_score {
+            best_score = score;
+            best_delta = delta;
+        }
+    }
+    (best_delta, best_score)
+}
+
+/// DP over cues in time order: each cue either keeps the previous cue's group offset or opens a
+/// new group with its own best offset (minus [`SPLIT_PENALTY`]), whichever scores higher so far.
+fn split_align(cues: &[(u32, u32)], overlap: impl Fn(u32, u32, i64) -> f64) -> Vec<i64> {
+    let mut scores = Vec::with_capacity(cues.len());
+    let mut offsets = Vec::with_capacity(cues.len());
+
+    for (i, (start_cs, end_cs)) in cues.iter().enumerate() {
+        let (local_delta, local_score) = best_offset(&[(*start_cs, *end_cs)], &overlap);
+
+        if i == 0 {
+            scores.push(local_score);
+            offsets.push(local_delta);
+            continue;
+        }
+
+        let prev_offset = offsets[i - 1];
+        let continue_score = scores[i - 1] + overlap(*start_cs, *end_cs, prev_offset);
+        let new_group_score = scores[i - 1] - SPLIT_PENALTY + local_score;
+
+        if continue_score >= new_group_score {
+            scores.push(continue_score);
+            offsets.push(prev_offset);
+        } else {
+            scores.push(new_group_score);
+            offsets.push(local_delta);
+        }
+    }
+
+    offsets.iter().map(|o| o * GRID_CS).collect()
+}
+
+fn prefix_sum(active: &[bool]) -> Vec<u32> {
+    let mut sums = Vec::with_capacity(active.len() + 1);
+    sums.push(0);
+    for &is_active in active {
+        sums.push(sums.last().unwrap() + is_active as u32);
+    }
+    sums
+}
+
+/// Count of active bins in `[start, end)`, clamped to the bounds of `prefix` (an interval that
+/// falls fully outside the timeline after shifting just contributes zero overlap).
+fn overlap_count(prefix: &[u32], start: i64, end: i64) -> u32 {
+    let len = prefix.len() as i64 - 1;
+    let start = start.clamp(0, len);
+    let end = end.clamp(0, len);
+    if end <= start {
+        return 0;
+    }
+    prefix[end as usize] - prefix[start as usize]
+}
+
+/// Derives a boolean voice-activity timeline from `audio_path` on the same 10ms grid
+/// [`subtitle_cue_intervals_cs`] uses: decodes to mono 16kHz PCM, frames it into 10ms windows, and
+/// marks a window active when its short-time energy exceeds an adaptive threshold derived from the
+/// track's own noise floor (its 10th-percentile window energy).
+fn voice_activity_timeline(audio_path: &Path) -> Result<Vec<bool>> {
+    let output = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-i", audio_path.to_string_lossy().to_string().as_str()])
+        .args(["-ac", "1", "-ar", "16000", "-f", "s16le", "-"])
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(output.stderr.as_slice()));
+    }
+
+    let samples: Vec<i16> = output
+        .stdout
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    if samples.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // 16000 samples/s * 0.01s (10ms, one grid step) = 160 samples per window
+    let window_samples = 160;
+    let mut energies: Vec<f64> = samples
+        .chunks(window_samples)
+        .map(|window| {
+            window.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / window.len() as f64
+        })
+        .collect();
+
+    let mut sorted = energies.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = sorted[sorted.len() / 10];
+    let threshold = noise_floor * 3.0 + 1.0;
+
+    Ok(energies.drain(..).map(|e| e > threshold).collect())
+}
@@ -1,19 +1,23 @@
 use std::{
     cmp,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
     ops::Not,
     path::Path,
-    process::Command,
+    process::Stdio,
+    sync::Mutex,
+    thread,
 };
 
 use chrono::TimeDelta;
 use crunchyroll_rs::Locale;
-use log::debug;
+use log::{debug, warn};
 use tempfile::TempPath;
 
 use anyhow::{bail, Result};
 
 use super::fmt::format_time_delta;
+use super::os::ffmpeg_command;
 
 pub struct SyncAudio {
     pub format_id: usize,
@@ -28,32 +32,49 @@ struct TimeRange {
     end: f64,
 }
 
+/// A format's computed sync offset alongside how much it can be trusted, see `sync_audios`'
+/// `max_offset_stddev_ms` parameter.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncOffset {
+    pub offset: TimeDelta,
+    /// Number of refinement iterations (`sync_audios`' `sync_precision`-derived range) that found
+    /// a confident match for this format.
+    pub runs: u32,
+    /// Standard deviation, in milliseconds, of this format's offset across those iterations - a
+    /// track whose alignment keeps landing somewhere different every run is a sign the match
+    /// itself is unreliable, not just noisy.
+    pub stddev_ms: f64,
+    /// Mean Hamming distance, in bits out of 32, between matched fingerprint frames at the
+    /// winning alignment, averaged across iterations - lower is a tighter match.
+    pub bit_error_rate: f64,
+}
+
 pub fn sync_audios(
     available_audios: &Vec<SyncAudio>,
     sync_tolerance: u32,
     sync_precision: u32,
-) -> Result<Option<HashMap<usize, TimeDelta>>> {
+    max_offset_stddev_ms: u32,
+) -> Result<Option<HashMap<usize, SyncOffset>>> {
     let mut result: HashMap<usize, TimeDelta> = HashMap::new();
 
     let mut sync_audios = vec![];
-    let mut chromaprints = HashMap::new();
     let mut formats = HashSet::new();
+    let mut initial_jobs = vec![];
     for audio in available_audios {
         if formats.contains(&audio.format_id) {
             continue;
         }
         formats.insert(audio.format_id);
         sync_audios.push((audio.format_id, &audio.path));
-        chromaprints.insert(
+        initial_jobs.push((
             audio.format_id,
-            generate_chromaprint(
-                &audio.path,
-                &TimeDelta::zero(),
-                &TimeDelta::zero(),
-                &TimeDelta::zero(),
-            )?,
-        );
+            &*audio.path,
+            TimeDelta::zero(),
+            TimeDelta::zero(),
+            TimeDelta::zero(),
+        ));
     }
+    let mut chromaprints = parallel_generate_chromaprints(initial_jobs)?;
     sync_audios.sort_by_key(|sync_audio| chromaprints.get(&sync_audio.0).unwrap().len());
 
     let base_audio = sync_audios.remove(0);
@@ -61,23 +82,29 @@ pub fn sync_audios(
     let mut start = f64::MAX;
     let mut end = f64::MIN;
     let mut initial_offsets = HashMap::new();
+    // Formats whose audio doesn't confidently match the base one (e.g. a heavily recut episode) -
+    // they're left out of `initial_offsets`/`sync_audios` below and keep their own full video track
+    // instead of being forced into a possibly-wrong offset.
+    let mut unsynced = vec![];
     for audio in &sync_audios {
         debug!(
             "Initial comparison of format {} to {}",
             audio.0, &base_audio.0
         );
 
-        let (lhs_ranges, rhs_ranges) = compare_chromaprints(
+        let (lhs_ranges, rhs_ranges, _) = compare_chromaprints(
             chromaprints.get(&base_audio.0).unwrap(),
             chromaprints.get(&audio.0).unwrap(),
             sync_tolerance,
         );
         if lhs_ranges.is_empty() || rhs_ranges.is_empty() {
-            bail!(
-                "Failed to sync videos, couldn't find matching audio parts between format {} and {}",
-                base_audio.0 + 1,
-                audio.0 + 1
+            warn!(
+                "Could not confidently sync format {} to {}, keeping it as a separate video track",
+                audio.0 + 1,
+                base_audio.0 + 1
             );
+            unsynced.push(audio.0);
+            continue;
         }
         let lhs_range = lhs_ranges[0];
         let rhs_range = rhs_ranges[0];
@@ -109,27 +136,38 @@ pub fn sync_audios(
     let start = TimeDelta::milliseconds((start * 1000.0) as i64 - 20000);
     let end = TimeDelta::milliseconds((end * 1000.0) as i64 + 20000);
 
-    for sync_audio in &sync_audios {
-        let chromaprint = generate_chromaprint(
-            sync_audio.1,
-            &start,
-            &end,
-            initial_offsets.get(&sync_audio.0).unwrap(),
-        )?;
-        chromaprints.insert(sync_audio.0, chromaprint);
-    }
+    let windowed_jobs: Vec<_> = sync_audios
+        .iter()
+        .filter(|a| !unsynced.contains(&a.0))
+        .map(|sync_audio| {
+            (
+                sync_audio.0,
+                &**sync_audio.1,
+                start,
+                end,
+                initial_offsets.get(&sync_audio.0).copied().unwrap(),
+            )
+        })
+        .collect();
+    chromaprints.extend(parallel_generate_chromaprints(windowed_jobs)?);
+
+    // The refinement loop below only ever re-fingerprints `base_audio` (every other track's
+    // chromaprint was already fixed above), so its window is decoded to raw PCM exactly once here
+    // and reused for every sub-offset instead of asking ffmpeg to re-decode the original, compressed
+    // file on each of the `2 * sync_precision + 1` iterations.
+    let base_pcm_window = decode_pcm_window(base_audio.1, &start, &end)?;
 
     let mut runs: HashMap<usize, i64> = HashMap::new();
-    let iterator_range_limits: i64 = 2 ^ sync_precision as i64;
+    let mut offset_samples: HashMap<usize, Vec<i64>> = HashMap::new();
+    let mut bit_error_rate_samples: HashMap<usize, Vec<f64>> = HashMap::new();
+    let iterator_range_limits = refinement_iteration_range_limits(sync_precision);
     for i in -iterator_range_limits..=iterator_range_limits {
         let base_offset = TimeDelta::milliseconds(
             ((0.128 / iterator_range_limits as f64 * i as f64) * 1000.0) as i64,
         );
-        chromaprints.insert(
-            base_audio.0,
-            generate_chromaprint(base_audio.1, &start, &end, &base_offset)?,
-        );
-        for audio in &sync_audios {
+        let shifted_pcm = shift_pcm(&base_pcm_window, &base_offset);
+        chromaprints.insert(base_audio.0, chromaprint_from_pcm(&shifted_pcm)?);
+        for audio in sync_audios.iter().filter(|a| !unsynced.contains(&a.0)) {
             let initial_offset = initial_offsets.get(&audio.0).copied().unwrap();
             let offset = find_offset(
                 (&base_audio.0, chromaprints.get(&base_audio.0).unwrap()),
@@ -139,10 +177,9 @@ pub fn sync_audios(
                 &start,
                 sync_tolerance,
             );
-            if offset.is_none() {
+            let Some((offset, bit_error_rate)) = offset else {
                 continue;
-            }
-            let offset = offset.unwrap();
+            };
 
             result.insert(
                 audio.0,
@@ -154,22 +191,63 @@ pub fn sync_audios(
                     .unwrap(),
             );
             runs.insert(audio.0, runs.get(&audio.0).copied().unwrap_or_default() + 1);
+            offset_samples
+                .entry(audio.0)
+                .or_default()
+                .push(offset.num_milliseconds());
+            bit_error_rate_samples
+                .entry(audio.0)
+                .or_default()
+                .push(bit_error_rate);
         }
     }
-    let mut result: HashMap<usize, TimeDelta> = result
-        .iter()
-        .map(|(format_id, offset)| {
-            (
-                *format_id,
-                TimeDelta::milliseconds(
-                    offset.num_milliseconds() / runs.get(format_id).copied().unwrap(),
+
+    let mut synced_result: HashMap<usize, SyncOffset> = HashMap::new();
+    for (format_id, total_offset) in &result {
+        let run_count = runs.get(format_id).copied().unwrap();
+        let samples = offset_samples.get(format_id).unwrap();
+        let mean_offset_ms = samples.iter().sum::<i64>() as f64 / samples.len() as f64;
+        let variance = samples
+            .iter()
+            .map(|sample| (*sample as f64 - mean_offset_ms).powi(2))
+            .sum::<f64>()
+            / samples.len() as f64;
+        let stddev_ms = variance.sqrt();
+        let mean_bit_error_rate = bit_error_rate_samples.get(format_id).unwrap().iter().sum::<f64>()
+            / run_count as f64;
+
+        if stddev_ms > max_offset_stddev_ms as f64 {
+            bail!(
+                "Sync offset for format {} varied by {stddev_ms:.0}ms across {run_count} runs, \
+                 which is above the {max_offset_stddev_ms}ms threshold, refusing to use an \
+                 unreliable offset",
+                format_id + 1
+            );
+        }
+
+        synced_result.insert(
+            *format_id,
+            SyncOffset {
+                offset: TimeDelta::milliseconds(
+                    total_offset.num_milliseconds() / run_count,
                 ),
-            )
-        })
-        .collect();
-    result.insert(base_audio.0, TimeDelta::milliseconds(0));
+                runs: run_count as u32,
+                stddev_ms,
+                bit_error_rate: mean_bit_error_rate,
+            },
+        );
+    }
+    synced_result.insert(
+        base_audio.0,
+        SyncOffset {
+            offset: TimeDelta::zero(),
+            runs: 0,
+            stddev_ms: 0.0,
+            bit_error_rate: 0.0,
+        },
+    );
 
-    Ok(Some(result))
+    Ok(Some(synced_result))
 }
 
 fn find_offset(
@@ -179,19 +257,21 @@ fn find_offset(
     rhs_shift: &TimeDelta,
     start: &TimeDelta,
     sync_tolerance: u32,
-) -> Option<TimeDelta> {
-    let (lhs_ranges, rhs_ranges) = compare_chromaprints(lhs.1, rhs.1, sync_tolerance);
+) -> Option<(TimeDelta, f64)> {
+    let (lhs_ranges, rhs_ranges, bit_error_rates) =
+        compare_chromaprints(lhs.1, rhs.1, sync_tolerance);
     if lhs_ranges.is_empty() || rhs_ranges.is_empty() {
         return None;
     }
     let lhs_range = lhs_ranges[0];
     let rhs_range = rhs_ranges[0];
+    let bit_error_rate = bit_error_rates[0];
     let offset = rhs_range.end - lhs_range.end;
     let offset = TimeDelta::milliseconds((offset * 1000.0) as i64)
         .checked_add(lhs_shift)?
         .checked_sub(rhs_shift)?;
     debug!(
-        "Found offset of {}ms ({} - {} {}s) ({} - {} {}s) for format {} to {}",
+        "Found offset of {}ms ({} - {} {}s) ({} - {} {}s) for format {} to {} (bit error rate {:.2})",
         offset.num_milliseconds(),
         lhs_range.start + start.num_milliseconds() as f64 / 1000.0,
         lhs_range.end + start.num_milliseconds() as f64 / 1000.0,
@@ -200,9 +280,149 @@ fn find_offset(
         rhs_range.end + start.num_milliseconds() as f64 / 1000.0,
         rhs_range.end - rhs_range.start,
         rhs.0,
-        lhs.0
+        lhs.0,
+        bit_error_rate
     );
-    Some(offset)
+    Some((offset, bit_error_rate))
+}
+
+/// Sample rate the refinement pass' raw PCM window is decoded to, see [`decode_pcm_window`]. Fixed
+/// rather than inherited from the source so every shifted re-fingerprint produced by
+/// [`chromaprint_from_pcm`] operates on the same byte layout.
+const REFINEMENT_PCM_SAMPLE_RATE: u32 = 44100;
+
+/// Runs [`generate_chromaprint`] for each job across a small thread pool (bounded by
+/// [`thread::available_parallelism`]), since ffmpeg's own decode is what dominates runtime here and
+/// every job is otherwise independent. This is what turns per-track full-length/windowed
+/// fingerprinting from a serial chain of ffmpeg invocations into one that runs in wall-clock time
+/// closer to that of a single track for the handful of dubs a typical episode has.
+fn parallel_generate_chromaprints(
+    jobs: Vec<(usize, &Path, TimeDelta, TimeDelta, TimeDelta)>,
+) -> Result<HashMap<usize, Vec<u32>>> {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(jobs.len().max(1));
+    let queue = Mutex::new(VecDeque::from(jobs));
+    let results: Mutex<HashMap<usize, Result<Vec<u32>>>> = Mutex::new(HashMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let job = queue.lock().unwrap().pop_front();
+                let Some((format_id, path, start, end, offset)) = job else {
+                    break;
+                };
+                let chromaprint = generate_chromaprint(path, &start, &end, &offset);
+                results.lock().unwrap().insert(format_id, chromaprint);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|(format_id, chromaprint)| chromaprint.map(|c| (format_id, c)))
+        .collect()
+}
+
+/// How far the refinement loop in [`sync_audios`] searches around the initial offset, in units of
+/// `0.128 / limit` seconds: the loop runs `i` from `-limit` to `limit` inclusive, so this is
+/// `(2 * sync_precision) + 1` total iterations, monotonically increasing with `sync_precision` as
+/// its doc comment promises.
+fn refinement_iteration_range_limits(sync_precision: u32) -> i64 {
+    sync_precision as i64
+}
+
+/// Decodes `[start, end]` of `input_file` to raw interleaved 16-bit stereo PCM at
+/// [`REFINEMENT_PCM_SAMPLE_RATE`], once, so the refinement loop in [`sync_audios`] can re-fingerprint
+/// the base track at a new sub-offset every iteration via [`shift_pcm`]/[`chromaprint_from_pcm`]
+/// instead of asking ffmpeg to re-decode the original, compressed source each time.
+fn decode_pcm_window(input_file: &Path, start: &TimeDelta, end: &TimeDelta) -> Result<Vec<u8>> {
+    let mut command = ffmpeg_command();
+    command
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-ss", format_time_delta(start).as_str()])
+        .args(["-to", format_time_delta(end).as_str()])
+        .args(["-i", input_file.to_string_lossy().to_string().as_str()])
+        .args(["-ac", "2"])
+        .args(["-ar", REFINEMENT_PCM_SAMPLE_RATE.to_string().as_str()])
+        .args(["-f", "s16le"])
+        .arg("-");
+
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(output.stderr.as_slice()));
+    }
+    Ok(output.stdout)
+}
+
+/// Shifts a raw interleaved 16-bit stereo PCM buffer (as produced by [`decode_pcm_window`]) by
+/// `offset`, in-process, with the same sign convention [`generate_chromaprint`] uses for its
+/// `offset`: a positive offset makes the window start that much earlier (the front is padded with
+/// silence, as no audio before the decoded window is at hand), a negative one drops that much audio
+/// off the front (it starts later).
+fn shift_pcm(pcm: &[u8], offset: &TimeDelta) -> Vec<u8> {
+    const FRAME_BYTES: usize = 4; // 2 channels * 16-bit samples
+
+    let shift_frames = offset.num_milliseconds() * REFINEMENT_PCM_SAMPLE_RATE as i64 / 1000;
+    let shift_bytes = shift_frames.unsigned_abs() as usize * FRAME_BYTES;
+
+    if shift_frames >= 0 {
+        let mut shifted = vec![0u8; shift_bytes];
+        shifted.extend_from_slice(pcm);
+        shifted
+    } else {
+        pcm.get(shift_bytes.min(pcm.len())..)
+            .unwrap_or_default()
+            .to_vec()
+    }
+}
+
+/// Re-encodes an already-decoded raw PCM buffer into a chromaprint via ffmpeg's chromaprint muxer -
+/// the same fingerprint format [`generate_chromaprint`] produces - without ever touching the
+/// original, compressed source file again: ffmpeg only has to parse a raw sample stream here, not
+/// decode a codec, so this stays cheap even called once per sub-offset in [`sync_audios`]' refinement
+/// loop.
+fn chromaprint_from_pcm(pcm: &[u8]) -> Result<Vec<u32>> {
+    let mut child = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-f", "s16le"])
+        .args(["-ar", REFINEMENT_PCM_SAMPLE_RATE.to_string().as_str()])
+        .args(["-ac", "2"])
+        .args(["-i", "-"])
+        .args(["-f", "chromaprint"])
+        .args(["-fp_format", "raw"])
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child.stdin.take().unwrap().write_all(pcm)?;
+    let extract_output = child.wait_with_output()?;
+
+    if !extract_output.status.success() {
+        bail!(
+            "{}",
+            String::from_utf8_lossy(extract_output.stderr.as_slice())
+        );
+    }
+    let raw_chromaprint = extract_output.stdout.as_slice();
+    let length = raw_chromaprint.len();
+    if length % 4 != 0 {
+        bail!("chromaprint bytes should be a multiple of 4");
+    }
+    let mut chromaprint = Vec::with_capacity(length / 4);
+    for i in 0..length / 4 {
+        chromaprint.push(as_u32_le(
+            raw_chromaprint[i * 4..i * 4 + 4].try_into().unwrap(),
+        ));
+    }
+    Ok(chromaprint)
 }
 
 fn generate_chromaprint(
@@ -218,7 +438,7 @@ fn generate_chromaprint(
         offset_argument = offset;
     };
 
-    let mut command = Command::new("ffmpeg");
+    let mut command = ffmpeg_command();
     command
         .arg("-hide_banner")
         .arg("-y")
@@ -258,29 +478,60 @@ fn generate_chromaprint(
     Ok(chromaprint)
 }
 
+/// Minimum share of overlapping frames the shift histogram's peak bin must hold before it's
+/// trusted to seed [`find_time_ranges`] directly, see [`compare_chromaprints`]. Below this, the
+/// old exhaustive scan over every `possible_shifts` candidate runs instead, since a flat histogram
+/// usually means the sample is genuinely ambiguous rather than cleanly aligned at one shift.
+const SHIFT_HISTOGRAM_CONFIDENCE_THRESHOLD: f64 = 0.15;
+
 fn compare_chromaprints(
     lhs_chromaprint: &Vec<u32>,
     rhs_chromaprint: &Vec<u32>,
     sync_tolerance: u32,
-) -> (Vec<TimeRange>, Vec<TimeRange>) {
+) -> (Vec<TimeRange>, Vec<TimeRange>, Vec<f64>) {
     let lhs_inverse_index = create_inverse_index(lhs_chromaprint);
     let rhs_inverse_index = create_inverse_index(rhs_chromaprint);
 
+    // constellation-offset alignment: every fingerprint value shared between both tracks (within
+    // the same +/-2 fuzzy tolerance used for the lookup itself) votes for its positional shift,
+    // `rhs_pos - lhs_pos`, also bumping the two neighboring bins to absorb that same fuzziness. A
+    // genuine alignment produces one sharp peak; a periodic false match (a shared intro jingle or
+    // recap music matching at several shifts) spreads its votes out instead, which the old
+    // "longest contiguous range per shift" heuristic had no way to tell apart from a real match.
     let mut possible_shifts = HashSet::new();
+    let mut shift_histogram: HashMap<i32, u32> = HashMap::new();
+    let mut overlapping_frames: u32 = 0;
     for lhs_pair in lhs_inverse_index {
         let original_point = lhs_pair.0;
         for i in -2..=2 {
             let modified_point = (original_point as i32 + i) as u32;
             if rhs_inverse_index.contains_key(&modified_point) {
                 let rhs_index = rhs_inverse_index.get(&modified_point).copied().unwrap();
-                possible_shifts.insert(rhs_index as i32 - lhs_pair.1 as i32);
+                let shift = rhs_index as i32 - lhs_pair.1 as i32;
+                possible_shifts.insert(shift);
+                overlapping_frames += 1;
+                for bin in shift - 1..=shift + 1 {
+                    *shift_histogram.entry(bin).or_default() += 1;
+                }
             }
         }
     }
 
-    let mut all_lhs_time_ranges = vec![];
-    let mut all_rhs_time_ranges = vec![];
-    for shift_amount in possible_shifts {
+    let peak_shift = shift_histogram
+        .iter()
+        .max_by_key(|(_, count)| **count)
+        .filter(|(_, count)| {
+            **count as f64 / overlapping_frames.max(1) as f64 >= SHIFT_HISTOGRAM_CONFIDENCE_THRESHOLD
+        })
+        .map(|(shift, _)| *shift);
+
+    let shifts_to_try: Vec<i32> = match peak_shift {
+        Some(shift) => vec![shift],
+        None => possible_shifts.into_iter().collect(),
+    };
+
+    let mut candidates: Vec<(TimeRange, TimeRange, f64)> = vec![];
+    for shift_amount in shifts_to_try {
         let time_range_pair = find_time_ranges(
             lhs_chromaprint,
             rhs_chromaprint,
@@ -290,7 +541,7 @@ fn compare_chromaprints(
         if time_range_pair.is_none() {
             continue;
         }
-        let (mut lhs_time_ranges, mut rhs_time_ranges) = time_range_pair.unwrap();
+        let (mut lhs_time_ranges, mut rhs_time_ranges, bit_error_rate) = time_range_pair.unwrap();
         let mut lhs_time_ranges: Vec<TimeRange> = lhs_time_ranges
             .drain(..)
             .filter(|time_range| {
@@ -313,15 +564,23 @@ fn compare_chromaprints(
             continue;
         }
 
-        all_lhs_time_ranges.push(lhs_time_ranges[0]);
-        all_rhs_time_ranges.push(rhs_time_ranges[0]);
+        candidates.push((lhs_time_ranges[0], rhs_time_ranges[0], bit_error_rate));
+    }
+    candidates.sort_by(|a, b| {
+        (a.0.end - a.0.start).total_cmp(&(b.0.end - b.0.start))
+    });
+    candidates.reverse();
+
+    let mut all_lhs_time_ranges = vec![];
+    let mut all_rhs_time_ranges = vec![];
+    let mut all_bit_error_rates = vec![];
+    for (lhs_range, rhs_range, bit_error_rate) in candidates {
+        all_lhs_time_ranges.push(lhs_range);
+        all_rhs_time_ranges.push(rhs_range);
+        all_bit_error_rates.push(bit_error_rate);
     }
-    all_lhs_time_ranges.sort_by(|a, b| (a.end - a.start).total_cmp(&(b.end - b.start)));
-    all_lhs_time_ranges.reverse();
-    all_rhs_time_ranges.sort_by(|a, b| (a.end - a.start).total_cmp(&(b.end - b.start)));
-    all_rhs_time_ranges.reverse();
 
-    (all_lhs_time_ranges, all_rhs_time_ranges)
+    (all_lhs_time_ranges, all_rhs_time_ranges, all_bit_error_rates)
 }
 
 fn create_inverse_index(chromaprint: &Vec<u32>) -> HashMap<u32, usize> {
@@ -337,7 +596,7 @@ fn find_time_ranges(
     rhs_chromaprint: &[u32],
     shift_amount: i32,
     sync_tolerance: u32,
-) -> Option<(Vec<TimeRange>, Vec<TimeRange>)> {
+) -> Option<(Vec<TimeRange>, Vec<TimeRange>, f64)> {
     let mut lhs_shift: i32 = 0;
     let mut rhs_shift: i32 = 0;
     if shift_amount < 0 {
@@ -348,6 +607,8 @@ fn find_time_ranges(
 
     let mut lhs_matching_timestamps = vec![];
     let mut rhs_matching_timestamps = vec![];
+    let mut total_difference: u64 = 0;
+    let mut matches: u64 = 0;
     let upper_limit =
         cmp::min(lhs_chromaprint.len(), rhs_chromaprint.len()) as i32 - shift_amount.abs();
 
@@ -362,6 +623,8 @@ fn find_time_ranges(
             continue;
         }
 
+        total_difference += difference as u64;
+        matches += 1;
         lhs_matching_timestamps.push(lhs_position as f64 * 0.128);
         rhs_matching_timestamps.push(rhs_position as f64 * 0.128);
     }
@@ -372,8 +635,9 @@ fn find_time_ranges(
     lhs_time_ranges.as_ref()?;
     let lhs_time_ranges = lhs_time_ranges.unwrap();
     let rhs_time_ranges = timestamps_to_ranges(rhs_matching_timestamps).unwrap();
+    let bit_error_rate = total_difference as f64 / matches.max(1) as f64;
 
-    Some((lhs_time_ranges, rhs_time_ranges))
+    Some((lhs_time_ranges, rhs_time_ranges, bit_error_rate))
 }
 
 fn timestamps_to_ranges(mut timestamps: Vec<f64>) -> Option<Vec<TimeRange>> {
@@ -415,3 +679,20 @@ fn as_u32_le(array: &[u8; 4]) -> u32 {
         | ((array[2] as u32) << 16)
         | ((array[3] as u32) << 24)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::refinement_iteration_range_limits;
+
+    #[test]
+    fn refinement_range_limits_grow_with_precision() {
+        let limits: Vec<i64> = (1..=8).map(refinement_iteration_range_limits).collect();
+        for window in limits.windows(2) {
+            assert!(
+                window[1] > window[0],
+                "range limits must strictly increase with sync_precision: {:?}",
+                limits
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::utils::os::{ffmpeg_command, tempfile};
+use anyhow::{bail, Result};
+use futures_util::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use log::LevelFilter;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use tempfile::TempPath;
+
+/// Downscaled frame size scene cuts are detected on; small enough that even a long episode decodes
+/// and diffs in a fraction of the time a full-resolution pass would take.
+const ANALYSIS_WIDTH: u32 = 64;
+const ANALYSIS_HEIGHT: u32 = 36;
+/// Frame rate scene-cut analysis is resampled to, independent of the source's real fps.
+const ANALYSIS_FPS: f64 = 5.0;
+/// A cut is never placed closer than this to the previous one, so a single noisy frame can't carve
+/// off a near-empty chunk.
+const MIN_SCENE_LEN_SECS: f64 = 2.0;
+/// A cut is forced at this distance from the previous one even without a detected scene change, so
+/// one long static scene doesn't become a single giant (unparallelizable) chunk.
+const MAX_SCENE_LEN_SECS: f64 = 10.0;
+/// Mean per-pixel luma difference (0..=1) between consecutive analysis frames above which a cut is
+/// recorded.
+const SCENE_CUT_THRESHOLD: f64 = 0.08;
+
+/// Re-encodes `src` with `encoder` by splitting it into scene-aligned chunks and encoding them
+/// concurrently (bounded by `std::thread::available_parallelism`, the same way the rest of this
+/// pipeline sizes its worker pools), then losslessly concatenating the results back together.
+/// Falls back to a single whole-file encode if no internal scene cuts are found. `ffmpeg_threads`
+/// is forwarded to every chunk's encoder the same way it already is for the plain remux path.
+pub async fn reencode_by_scene(
+    src: &Path,
+    encoder: &str,
+    ffmpeg_threads: Option<usize>,
+    progress_label: &str,
+) -> Result<TempPath> {
+    let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    let cuts = detect_scene_cuts(src)?;
+    let bounds = chunk_bounds(&cuts);
+
+    let chunks: Vec<TempPath> = bounds
+        .iter()
+        .map(|(start, end)| split_chunk(src, *start, *end))
+        .collect::<Result<_>>()?;
+
+    let progress = if log::max_level() == LevelFilter::Info {
+        let pb = ProgressBar::new(chunks.len() as u64)
+            .with_style(
+                ProgressStyle::with_template(":: {msg} [{wide_bar}] {pos}/{len} chunks")
+                    .unwrap()
+                    .progress_chars("##-"),
+            )
+            .with_message(progress_label.to_string())
+            .with_finish(ProgressFinish::Abandon);
+        Some(pb)
+    } else {
+        None
+    };
+
+    let encoded: Vec<Result<TempPath>> = stream::iter(chunks.iter().map(|chunk| {
+        let chunk_path = chunk.to_path_buf();
+        let progress = progress.clone();
+        async move {
+            let result =
+                tokio::task::spawn_blocking(move || encode_chunk(&chunk_path, encoder, ffmpeg_threads))
+                    .await
+                    .unwrap();
+            if let Some(pb) = &progress {
+                pb.inc(1)
+            }
+            result
+        }
+    }))
+    .buffer_unordered(workers.max(1))
+    .collect()
+    .await;
+    let encoded: Vec<TempPath> = encoded.into_iter().collect::<Result<_>>()?;
+
+    if encoded.len() == 1 {
+        return Ok(encoded.into_iter().next().unwrap());
+    }
+    concat_chunks(&encoded)
+}
+
+/// Timestamps (seconds, relative to `src`'s start) scene cuts were detected at.
+fn detect_scene_cuts(src: &Path) -> Result<Vec<f64>> {
+    let frame_bytes = (ANALYSIS_WIDTH * ANALYSIS_HEIGHT) as usize;
+
+    let output = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-i", src.to_string_lossy().to_string().as_str()])
+        .args([
+            "-vf",
+            &format!(
+                "scale={}:{},fps={},format=gray",
+                ANALYSIS_WIDTH, ANALYSIS_HEIGHT, ANALYSIS_FPS
+            ),
+        ])
+        .args(["-f", "rawvideo", "-pix_fmt", "gray", "-"])
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let frames: Vec<&[u8]> = output.stdout.chunks_exact(frame_bytes).collect();
+    if frames.len() < 2 {
+        return Ok(vec![]);
+    }
+
+    let mut cuts = vec![];
+    let mut last_cut_frame = 0usize;
+    for (i, frame) in frames.iter().enumerate().skip(1) {
+        let diff: u64 = frame
+            .iter()
+            .zip(frames[i - 1])
+            .map(|(a, b)| (*a as i32 - *b as i32).unsigned_abs() as u64)
+            .sum();
+        let normalized = diff as f64 / (frame_bytes as f64 * 255.0);
+        let since_last_secs = (i - last_cut_frame) as f64 / ANALYSIS_FPS;
+
+        let detected = normalized > SCENE_CUT_THRESHOLD && since_last_secs >= MIN_SCENE_LEN_SECS;
+        let forced = since_last_secs >= MAX_SCENE_LEN_SECS;
+        if detected || forced {
+            cuts.push(i as f64 / ANALYSIS_FPS);
+            last_cut_frame = i;
+        }
+    }
+    Ok(cuts)
+}
+
+/// Turns a list of interior cut timestamps into `(start, end)` chunk ranges; the final chunk's
+/// `end` is `None` so it is extracted up to whatever the real end of the file is, rather than
+/// trusting the analysis pass' (resampled, slightly imprecise) duration.
+fn chunk_bounds(cuts: &[f64]) -> Vec<(f64, Option<f64>)> {
+    let mut bounds = vec![];
+    let mut start = 0.0;
+    for cut in cuts {
+        bounds.push((start, Some(*cut)));
+        start = *cut;
+    }
+    bounds.push((start, None));
+    bounds
+}
+
+/// Stream-copies `[start, end)` of `src` into its own file, snapping to the nearest keyframe at or
+/// before `start` the same way any other `-ss`-before`-i` cut in this codebase does.
+fn split_chunk(src: &Path, start: f64, end: Option<f64>) -> Result<TempPath> {
+    let (_file, path) = tempfile(".mp4")?.into_parts();
+
+    let mut command = ffmpeg_command();
+    command.arg("-hide_banner").arg("-y");
+    if start > 0.0 {
+        command.args(["-ss", &start.to_string()]);
+    }
+    command.args(["-i", src.to_string_lossy().to_string().as_str()]);
+    if let Some(end) = end {
+        command.args(["-t", &(end - start).to_string()]);
+    }
+    command
+        .args(["-c", "copy"])
+        .args(["-avoid_negative_ts", "make_zero"])
+        .arg(path.to_string_lossy().to_string());
+
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(path)
+}
+
+fn encode_chunk(chunk: &Path, encoder: &str, ffmpeg_threads: Option<usize>) -> Result<TempPath> {
+    let (_file, path) = tempfile(".mp4")?.into_parts();
+
+    let mut command = ffmpeg_command();
+    command
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-i", chunk.to_string_lossy().to_string().as_str()])
+        .args(["-c:v", encoder]);
+    if let Some(threads) = ffmpeg_threads {
+        command.args(["-threads", threads.to_string().as_str()]);
+    }
+    command
+        .args(["-c:a", "copy"])
+        .arg(path.to_string_lossy().to_string());
+
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(path)
+}
+
+/// Losslessly joins already-encoded chunks back into a single file via ffmpeg's concat demuxer, in
+/// the order they were produced in.
+fn concat_chunks(chunks: &[TempPath]) -> Result<TempPath> {
+    let (mut list_file, list_path) = tempfile(".txt")?.into_parts();
+    for chunk in chunks {
+        writeln!(list_file, "file '{}'", chunk.to_string_lossy())?;
+    }
+    drop(list_file);
+
+    let (_file, path) = tempfile(".mp4")?.into_parts();
+    let output = ffmpeg_command()
+        .arg("-hide_banner")
+        .arg("-y")
+        .args(["-f", "concat", "-safe", "0"])
+        .args(["-i", list_path.to_string_lossy().to_string().as_str()])
+        .args(["-c", "copy"])
+        .arg(path.to_string_lossy().to_string())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(path)
+}
@@ -1,12 +1,172 @@
+use crate::utils::parse::parse_resolution;
 use anyhow::{bail, Result};
 use crunchyroll_rs::media::{Resolution, Stream, StreamData};
 use crunchyroll_rs::Locale;
+use std::fmt::{Display, Formatter};
+
+/// Which adaptive streaming protocol to request a [`Stream`]'s variants through. Mirrors the
+/// `--stream-protocol` flag.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StreamProtocol {
+    #[default]
+    Hls,
+    Dash,
+}
+
+impl StreamProtocol {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "hls" => StreamProtocol::Hls,
+            "dash" => StreamProtocol::Dash,
+            _ => return Err(format!("'{}' is not a valid stream protocol", s)),
+        })
+    }
+}
+
+impl Display for StreamProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            StreamProtocol::Hls => "hls",
+            StreamProtocol::Dash => "dash",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How to pick a video variant when `--resolution` isn't `best`/`worst` and no variant matches its
+/// height exactly. Mirrors the `--resolution-strategy` flag.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ResolutionStrategy {
+    /// Only an exact height match is accepted; if none exists the episode is dropped. The only
+    /// behavior before `--resolution-strategy` existed.
+    #[default]
+    Exact,
+    /// The variant whose height is closest to the requested one, ties broken towards the
+    /// higher-bandwidth variant.
+    Nearest,
+    /// The highest-bandwidth variant whose `bandwidth` stays under the given bits/second ceiling,
+    /// falling back to the lowest-bandwidth variant if none qualify.
+    MaxBitrate(u64),
+    /// Like `MaxBitrate`, but the ceiling is derived from a total byte budget spread evenly over
+    /// a known duration in seconds (`total_bytes * 8 / duration_secs`).
+    Budget(u64, f64),
+}
+
+impl ResolutionStrategy {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let lower = s.to_lowercase();
+        match lower.as_str() {
+            "exact" => return Ok(ResolutionStrategy::Exact),
+            "nearest" => return Ok(ResolutionStrategy::Nearest),
+            _ => {}
+        }
+        if let Some(cap) = lower.strip_prefix("max-bitrate:") {
+            return Ok(ResolutionStrategy::MaxBitrate(cap.parse().map_err(|_| {
+                format!("'{}' is not a valid bitrate in bits/second", cap)
+            })?));
+        }
+        if let Some(rest) = lower.strip_prefix("budget:") {
+            let (bytes, secs) = rest.split_once(':').ok_or_else(|| {
+                format!("'{}' is not a valid 'budget:<bytes>:<seconds>' value", s)
+            })?;
+            return Ok(ResolutionStrategy::Budget(
+                bytes
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a valid byte count", bytes))?,
+                secs.parse()
+                    .map_err(|_| format!("'{}' is not a valid duration in seconds", secs))?,
+            ));
+        }
+        Err(format!("'{}' is not a valid resolution strategy", s))
+    }
+
+    /// The effective bits/second ceiling for `MaxBitrate`/`Budget`, or `None` for the other
+    /// strategies.
+    fn bitrate_cap(&self) -> Option<u64> {
+        match self {
+            ResolutionStrategy::MaxBitrate(cap) => Some(*cap),
+            ResolutionStrategy::Budget(total_bytes, duration) if *duration > 0.0 => {
+                Some((*total_bytes as f64 * 8.0 / duration) as u64)
+            }
+            ResolutionStrategy::Budget(..) => None,
+            _ => None,
+        }
+    }
+}
+
+/// One step of a `--resolution` preference chain (e.g. `best<=720p,480p,worst`). Mirrors a single
+/// `--resolution` value, plus the two capped "best" sentinels.
+#[derive(Clone, Copy, Debug)]
+pub enum ResolutionPreference {
+    /// An exact target (including the `best`/`worst` sentinels), resolved the same way a lone
+    /// `--resolution` always has been: via `--resolution-strategy` if nothing matches it exactly.
+    Exact(Resolution),
+    /// `best<=H`: the highest-bandwidth variant whose height doesn't exceed `H`.
+    BestAtMost(u64),
+    /// `best>=H`: the highest-bandwidth variant whose height is at least `H`.
+    BestAtLeast(u64),
+}
+
+impl ResolutionPreference {
+    /// Parse a comma-separated `--resolution` preference chain, tried in order by
+    /// [`stream_data_from_stream`] until one resolves to an actual available rendition.
+    pub fn parse_chain(s: &str) -> Result<Vec<Self>, String> {
+        s.split(',').map(Self::parse).collect()
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        let trimmed = s.trim().to_lowercase();
+        if let Some(height) = trimmed.strip_prefix("best<=") {
+            return Ok(Self::BestAtMost(Self::parse_height(height)?));
+        }
+        if let Some(height) = trimmed.strip_prefix("best>=") {
+            return Ok(Self::BestAtLeast(Self::parse_height(height)?));
+        }
+        parse_resolution(trimmed).map(Self::Exact).map_err(|e| e.to_string())
+    }
+
+    fn parse_height(s: &str) -> Result<u64, String> {
+        s.strip_suffix('p')
+            .unwrap_or(s)
+            .parse()
+            .map_err(|_| format!("'{}' is not a valid resolution height", s))
+    }
+}
+
+impl Display for ResolutionPreference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolutionPreference::Exact(resolution) => write!(f, "{}", resolution),
+            ResolutionPreference::BestAtMost(height) => write!(f, "best<={}p", height),
+            ResolutionPreference::BestAtLeast(height) => write!(f, "best>={}p", height),
+        }
+    }
+}
+
+/// Format a `--resolution` preference chain back the way it was written, for error/log messages.
+pub fn format_resolution_preferences(preferences: &[ResolutionPreference]) -> String {
+    preferences
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
 
 pub async fn stream_data_from_stream(
     stream: &Stream,
-    resolution: &Resolution,
+    resolution_preferences: &[ResolutionPreference],
+    resolution_strategy: ResolutionStrategy,
     hardsub_subtitle: Option<Locale>,
+    protocol: StreamProtocol,
 ) -> Result<Option<(StreamData, StreamData, bool)>> {
+    // crunchyroll-rs' `Stream::stream_data` only ever resolves HLS variants in this version of the
+    // crate, so there's nothing to branch on yet; this still validates and threads the flag through
+    // so the rest of the pipeline (resume cache keys, `DownloadFormat`, ...) doesn't need to change
+    // again once a DASH-capable `Stream` method shows up upstream
+    if protocol == StreamProtocol::Dash {
+        bail!("'--stream-protocol dash' is not supported yet: the crunchyroll-rs version this is built against only exposes HLS stream data")
+    }
+
     let (hardsub_locale, mut contains_hardsub) = if hardsub_subtitle.is_some() {
         (hardsub_subtitle, true)
     } else {
@@ -35,12 +195,54 @@ pub async fn stream_data_from_stream(
     videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
     audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
 
-    let video_variant = match resolution.height {
-        u64::MAX => Some(videos.into_iter().next().unwrap()),
-        u64::MIN => Some(videos.into_iter().last().unwrap()),
-        _ => videos
-            .into_iter()
-            .find(|v| resolution.height == v.resolution().unwrap().height),
-    };
+    // try every preference in order, falling through to the next one if the current one doesn't
+    // resolve to an actual rendition (e.g. an exact height that no variant offers under `Exact`)
+    let video_variant = resolution_preferences.iter().find_map(|preference| {
+        match preference {
+            ResolutionPreference::BestAtMost(height) => videos
+                .iter()
+                .filter(|v| v.resolution().unwrap().height <= *height)
+                .max_by_key(|v| v.resolution().unwrap().height)
+                .cloned(),
+            // `videos` is sorted by descending bandwidth, so the first match is the
+            // highest-bandwidth one that qualifies
+            ResolutionPreference::BestAtLeast(height) => videos
+                .iter()
+                .find(|v| v.resolution().unwrap().height >= *height)
+                .cloned(),
+            ResolutionPreference::Exact(resolution) => match resolution.height {
+                u64::MAX => videos.first().cloned(),
+                u64::MIN => videos.last().cloned(),
+                _ => match resolution_strategy {
+                    ResolutionStrategy::Exact => videos
+                        .iter()
+                        .find(|v| resolution.height == v.resolution().unwrap().height)
+                        .cloned(),
+                    ResolutionStrategy::Nearest => {
+                        let target = resolution.height as i64;
+                        videos
+                            .iter()
+                            .min_by(|a, b| {
+                                let distance_a =
+                                    (target - a.resolution().unwrap().height as i64).abs();
+                                let distance_b =
+                                    (target - b.resolution().unwrap().height as i64).abs();
+                                distance_a
+                                    .cmp(&distance_b)
+                                    .then(b.bandwidth.cmp(&a.bandwidth))
+                            })
+                            .cloned()
+                    }
+                    ResolutionStrategy::MaxBitrate(_) | ResolutionStrategy::Budget(..) => {
+                        // `videos` is already sorted by descending bandwidth, so the first one
+                        // under the cap is the highest-bandwidth one that qualifies
+                        let cap = resolution_strategy.bitrate_cap();
+                        cap.and_then(|cap| videos.iter().find(|v| v.bandwidth <= cap).cloned())
+                            .or_else(|| videos.last().cloned())
+                    }
+                },
+            },
+        }
+    });
     Ok(video_variant.map(|v| (v, audios.first().unwrap().clone(), contains_hardsub)))
 }